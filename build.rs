@@ -5,9 +5,39 @@ use bindgen::RustTarget;
 #[cfg(feature = "java_wrapper")]
 use std::fmt;
 
+#[path = "codegen/message_codegen.rs"]
+mod message_codegen;
+#[path = "codegen/error_code_codegen.rs"]
+mod error_code_codegen;
+
+include!("codegen/max_payload_size.rs");
+
 fn main() {
     //hack to always run the build script
     println!("cargo:rerun-if-changed=None");
+
+    //generate the messages of every language from messages.toml
+    #[cfg_attr(not(any(feature = "java_wrapper", feature = "cpp_wrapper")), allow(unused_variables))]
+    let messages = {
+        let crate_root = env::var("CARGO_MANIFEST_DIR")
+            .expect("no CARGO_MANIFEST_DIR, but cargo should provide it");
+        let crate_root_path = PathBuf::from(crate_root);
+        let out_path = PathBuf::from(env::var("OUT_DIR").expect("no OUT_DIR, but cargo should provide it"));
+        let messages = message_codegen::load_schema(&crate_root_path.join("messages.toml"), MAX_PAYLOAD_SIZE);
+
+        fs::write(out_path.join("swordfish_messages.rs"), message_codegen::generate_rust(&messages))
+            .expect("Failed to write swordfish_messages.rs");
+        fs::write(out_path.join("swordfish_messages_python.rs"), message_codegen::generate_python(&messages))
+            .expect("Failed to write swordfish_messages_python.rs");
+
+        //the firmware uses the committed include/swordfish_messages.h, a test checks it is the same as this one
+        fs::write(out_path.join("swordfish_messages.h"), message_codegen::generate_c_header(&messages, MAX_PAYLOAD_SIZE))
+            .expect("Failed to write swordfish_messages.h");
+
+        fs::write(out_path.join("error_codes.rs"), error_code_codegen::generate_rust(&crate_root_path.join("error_codes.toml")))
+            .expect("Failed to write error_codes.rs");
+        messages
+    };
     
     #[cfg(any(feature = "python_wrapper"))]
     {
@@ -33,9 +63,15 @@ fn main() {
 
         let crate_root_path = PathBuf::from(crate_root);
         let ffi_source_folder = crate_root_path.join("src").join("ffi");
-        let interface_filepath = ffi_source_folder.join("interface.rs");
         let out_dir = env::var("OUT_DIR").expect("no OUT_DIR, but cargo should provide it");
 
+        //the message classes are generated from messages.toml and appended to the hand written interface
+        let interface_filepath = Path::new(&out_dir).join("interface.rs");
+        let mut interface = fs::read_to_string(ffi_source_folder.join("interface.rs"))
+            .expect("Failed to read interface.rs");
+        interface.push_str(&message_codegen::generate_foreign_classes(&messages));
+        fs::write(&interface_filepath, interface).expect("Failed to write interface.rs");
+
         //write executable_path to a file so it can be read by the cargo-make task after the build process was done
         //this is a stupid way to do it, but I couldn't find a better way
         let out_path = PathBuf::from(&out_dir);
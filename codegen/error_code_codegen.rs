@@ -0,0 +1,39 @@
+//generates a constant per error code of error_codes.toml, used by build.rs
+use serde::Deserialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct ErrorCodeFile {
+    #[serde(default)]
+    code: Vec<ErrorCodeDef>,
+}
+
+#[derive(Deserialize)]
+struct ErrorCodeDef {
+    code: u16,
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+//"busy" becomes `pub const BUSY: u16 = 4;`, included by src/swordfish_error.rs
+pub fn generate_rust(path: &Path) -> String {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let file: ErrorCodeFile = toml::from_str(&text)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+
+    let mut out = String::from("//generated by build.rs from error_codes.toml, do not edit\n");
+    for info in file.code.iter() {
+        if !info.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            panic!("error code name {} is not snake_case", info.name);
+        }
+        if !info.description.is_empty() {
+            writeln!(out, "//{}", info.description).unwrap();
+        }
+        writeln!(out, "pub const {}: u16 = {};", info.name.to_uppercase(), info.code).unwrap();
+    }
+    out
+}
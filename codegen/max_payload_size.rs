@@ -0,0 +1,2 @@
+//the largest payload of a frame, shared by build.rs (to check messages.toml) and the library
+pub const MAX_PAYLOAD_SIZE: usize = 245;
@@ -0,0 +1,669 @@
+//generates the swordfish messages for every language from messages.toml, used by build.rs
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const HEADER_COMMENT: &str = "generated by build.rs from messages.toml, do not edit";
+
+#[derive(Deserialize)]
+struct Schema {
+    message: Vec<MessageDef>,
+}
+
+#[derive(Deserialize)]
+pub struct MessageDef {
+    pub name: String,
+    pub opcode: u8,
+    pub category: String,
+    pub response: Option<u8>,
+    #[serde(default)]
+    pub fields: Vec<FieldDef>,
+}
+
+#[derive(Deserialize)]
+pub struct FieldDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub since: Option<u8>,
+}
+
+//-----------------------------field types-----------------------------------
+#[derive(Clone, Copy)]
+pub struct Scalar {
+    pub rust: &'static str,
+    pub c: &'static str,
+    pub size: usize,
+    pub is_float: bool,
+}
+
+const SCALARS: [Scalar; 10] = [
+    Scalar { rust: "u8", c: "uint8_t", size: 1, is_float: false },
+    Scalar { rust: "u16", c: "uint16_t", size: 2, is_float: false },
+    Scalar { rust: "u32", c: "uint32_t", size: 4, is_float: false },
+    Scalar { rust: "u64", c: "uint64_t", size: 8, is_float: false },
+    Scalar { rust: "i8", c: "int8_t", size: 1, is_float: false },
+    Scalar { rust: "i16", c: "int16_t", size: 2, is_float: false },
+    Scalar { rust: "i32", c: "int32_t", size: 4, is_float: false },
+    Scalar { rust: "i64", c: "int64_t", size: 8, is_float: false },
+    Scalar { rust: "f32", c: "float", size: 4, is_float: true },
+    Scalar { rust: "f64", c: "double", size: 8, is_float: true },
+];
+
+pub enum FieldType {
+    Scalar(Scalar),
+    Array(Scalar, usize),
+    //variable length, sent as a u8 length prefix followed by at most N bytes/items
+    String(usize),
+    Vec(Scalar, usize),
+}
+
+impl FieldType {
+    fn parse(message: &str, field: &str, ty: &str) -> FieldType {
+        let ty = ty.trim();
+        let scalar = |name: &str| {
+            SCALARS
+                .iter()
+                .find(|s| s.rust == name.trim())
+                .copied()
+                .unwrap_or_else(|| panic!("{}.{}: unknown field type {}", message, field, name))
+        };
+        let bound = |len: &str| {
+            let len: usize = len
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("{}.{}: bad length {}", message, field, len));
+            if len == 0 || len > u8::MAX as usize {
+                panic!("{}.{}: variable length fields hold between 1 and 255 items", message, field);
+            }
+            len
+        };
+        if let Some(len) = ty.strip_prefix("string<").and_then(|t| t.strip_suffix('>')) {
+            FieldType::String(bound(len))
+        } else if let Some(len) = ty.strip_prefix("bytes<").and_then(|t| t.strip_suffix('>')) {
+            FieldType::Vec(scalar("u8"), bound(len))
+        } else if let Some(inner) = ty.strip_prefix("vec<").and_then(|t| t.strip_suffix('>')) {
+            let (element, len) = inner
+                .split_once(',')
+                .unwrap_or_else(|| panic!("{}.{}: vectors are written as vec<type, max_len>", message, field));
+            FieldType::Vec(scalar(element), bound(len))
+        } else if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            let (element, len) = inner
+                .split_once(';')
+                .unwrap_or_else(|| panic!("{}.{}: arrays are written as [type; len]", message, field));
+            let len: usize = len
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("{}.{}: bad array length {}", message, field, len));
+            //Default is only implemented for arrays of up to 32 elements
+            if len == 0 || len > 32 {
+                panic!("{}.{}: array length must be between 1 and 32", message, field);
+            }
+            FieldType::Array(scalar(element), len)
+        } else {
+            FieldType::Scalar(scalar(ty))
+        }
+    }
+
+    fn rust_type(&self) -> String {
+        match self {
+            FieldType::Scalar(s) => s.rust.to_string(),
+            FieldType::Array(s, len) => format!("[{}; {}]", s.rust, len),
+            FieldType::String(len) => format!("crate::BoundedString<{}>", len),
+            FieldType::Vec(s, len) => format!("crate::BoundedVec<{}, {}>", s.rust, len),
+        }
+    }
+
+    //type used by the constructor/setter of the ffi accessors
+    fn rust_param_type(&self) -> String {
+        match self {
+            FieldType::Scalar(s) => s.rust.to_string(),
+            FieldType::Array(s, _) | FieldType::Vec(s, _) => format!("&[{}]", s.rust),
+            FieldType::String(_) => "&str".to_string(),
+        }
+    }
+
+    //type returned by the getter of the ffi accessors
+    fn rust_getter_type(&self) -> String {
+        match self {
+            FieldType::Scalar(s) => s.rust.to_string(),
+            FieldType::Array(s, _) | FieldType::Vec(s, _) => format!("Vec<{}>", s.rust),
+            FieldType::String(_) => "String".to_string(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            FieldType::Scalar(s) => s.size,
+            FieldType::Array(s, len) => s.size * len,
+            FieldType::String(len) => 1 + len,
+            FieldType::Vec(s, len) => 1 + s.size * len,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        match self {
+            FieldType::Scalar(s) | FieldType::Array(s, _) | FieldType::Vec(s, _) => s.is_float,
+            FieldType::String(_) => false,
+        }
+    }
+
+    fn is_variable_length(&self) -> bool {
+        matches!(self, FieldType::String(_) | FieldType::Vec(_, _))
+    }
+}
+
+impl MessageDef {
+    fn field_types(&self) -> Vec<FieldType> {
+        self.fields
+            .iter()
+            .map(|f| FieldType::parse(&self.name, &f.name, &f.ty))
+            .collect()
+    }
+
+    fn ffi_class_name(&self) -> String {
+        format!("{}Message", self.name)
+    }
+
+    fn has_variable_length_fields(&self) -> bool {
+        self.field_types().iter().any(|t| t.is_variable_length())
+    }
+}
+
+pub fn load_schema(path: &Path, max_payload_size: usize) -> Vec<MessageDef> {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let schema: Schema = toml::from_str(&text)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+
+    let mut opcodes = HashSet::new();
+    let mut names = HashSet::new();
+    for msg in schema.message.iter() {
+        if !opcodes.insert(msg.opcode) {
+            panic!("{}: opcode {} is used by more than one message", msg.name, msg.opcode);
+        }
+        if !names.insert(msg.name.clone()) {
+            panic!("message {} is defined more than once", msg.name);
+        }
+        if !["bounce", "param", "operation", "response", "stream"].contains(&msg.category.as_str()) {
+            panic!("{}: unknown category {}", msg.name, msg.category);
+        }
+        if msg.response.is_some() && msg.category != "operation" {
+            panic!("{}: only operations have a response opcode", msg.name);
+        }
+        let mut last_since = 1;
+        for field in msg.fields.iter() {
+            let since = field.since.unwrap_or(1);
+            if since == 0 || since < last_since {
+                panic!("{}.{}: fields must be ordered by their since version, starting at 1", msg.name, field.name);
+            }
+            last_since = since;
+        }
+        let payload_size: usize = msg.field_types().iter().map(|t| t.size()).sum();
+        if payload_size > max_payload_size {
+            panic!(
+                "{}: payload can be {} bytes, the maximum is {}",
+                msg.name, payload_size, max_payload_size
+            );
+        }
+    }
+    for msg in schema.message.iter() {
+        if let Some(response) = msg.response {
+            if !opcodes.contains(&response) {
+                panic!("{}: response opcode {} is not defined", msg.name, response);
+            }
+        }
+    }
+    schema.message
+}
+
+//-----------------------------rust-----------------------------------
+pub fn generate_rust(messages: &[MessageDef]) -> String {
+    let mut out = String::new();
+    writeln!(out, "//{}", HEADER_COMMENT).unwrap();
+    for msg in messages {
+        let types = msg.field_types();
+        let eq = if types.iter().any(|t| t.is_float()) { "" } else { "Eq, " };
+        let response = match msg.response {
+            Some(r) => format!(", response = {}", r),
+            None => String::new(),
+        };
+
+        writeln!(out, "\n//--------------{}------------------//", msg.name).unwrap();
+        //fixed size messages keep the layout of the c header, variable length ones can not
+        //(deriving Clone on a packed struct needs Copy)
+        let clone = if types.iter().any(|t| t.is_variable_length()) {
+            "Clone, "
+        } else {
+            writeln!(out, "#[repr(C, packed(1))]").unwrap();
+            "Clone, Copy, "
+        };
+        writeln!(out, "#[derive(Debug, {}PartialEq, {}Default, SwordFishMessage)]", clone, eq).unwrap();
+        writeln!(out, "#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]").unwrap();
+        writeln!(
+            out,
+            "#[swordfish(opcode = {}, category = \"{}\"{})]",
+            msg.opcode, msg.category, response
+        )
+        .unwrap();
+        writeln!(out, "pub struct {} {{", msg.name).unwrap();
+        for (field, ty) in msg.fields.iter().zip(types.iter()) {
+            if let Some(since) = field.since {
+                writeln!(out, "    #[swordfish(since = {})]", since).unwrap();
+            }
+            writeln!(out, "    pub {}: {},", field.name, ty.rust_type()).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+
+        //accessors, used by the ffi wrappers
+        writeln!(out, "impl {} {{", msg.name).unwrap();
+        let params: Vec<String> = msg
+            .fields
+            .iter()
+            .zip(types.iter())
+            .map(|(f, t)| format!("{}: {}", f.name, t.rust_param_type()))
+            .collect();
+        if msg.fields.len() > 7 {
+            writeln!(out, "    #[allow(clippy::too_many_arguments)]").unwrap();
+        }
+        //strings and vectors that do not fit are an error, so the constructor of their messages can fail
+        if msg.has_variable_length_fields() {
+            writeln!(out, "    pub fn new({}) -> anyhow::Result<Self> {{", params.join(", ")).unwrap();
+        } else {
+            writeln!(out, "    pub fn new({}) -> Self {{", params.join(", ")).unwrap();
+        }
+        if msg.fields.is_empty() {
+            writeln!(out, "        {}::default()", msg.name).unwrap();
+        } else {
+            writeln!(out, "        let mut msg = {}::default();", msg.name).unwrap();
+            for (field, ty) in msg.fields.iter().zip(types.iter()) {
+                let fallible = if ty.is_variable_length() { "?" } else { "" };
+                writeln!(out, "        msg.set_{0}({0}){1};", field.name, fallible).unwrap();
+            }
+            if msg.has_variable_length_fields() {
+                writeln!(out, "        Ok(msg)").unwrap();
+            } else {
+                writeln!(out, "        msg").unwrap();
+            }
+        }
+        writeln!(out, "    }}").unwrap();
+        for (field, ty) in msg.fields.iter().zip(types.iter()) {
+            //fields are copied out before use, references into packed structs are not allowed
+            match ty {
+                FieldType::Scalar(_) => {
+                    writeln!(
+                        out,
+                        "    pub fn get_{0}(&self) -> {1} {{self.{0}}}",
+                        field.name,
+                        ty.rust_getter_type()
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "    pub fn set_{0}(&mut self, {0}: {1}) {{self.{0} = {0}}}",
+                        field.name,
+                        ty.rust_param_type()
+                    )
+                    .unwrap();
+                }
+                FieldType::Array(_, len) => {
+                    writeln!(
+                        out,
+                        "    pub fn get_{0}(&self) -> {1} {{let {0} = self.{0}; {0}.to_vec()}}",
+                        field.name,
+                        ty.rust_getter_type()
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "    pub fn set_{0}(&mut self, {0}: {1}) {{",
+                        field.name,
+                        ty.rust_param_type()
+                    )
+                    .unwrap();
+                    writeln!(out, "        let mut arr: {} = Default::default();", ty.rust_type())
+                        .unwrap();
+                    writeln!(out, "        let len = std::cmp::min({}.len(), {});", field.name, len)
+                        .unwrap();
+                    writeln!(out, "        arr[..len].copy_from_slice(&{}[..len]);", field.name)
+                        .unwrap();
+                    writeln!(out, "        self.{} = arr;", field.name).unwrap();
+                    writeln!(out, "    }}").unwrap();
+                }
+                FieldType::String(_) | FieldType::Vec(_, _) => {
+                    let (to_owned, bounded) = match ty {
+                        FieldType::String(_) => ("to_string", "crate::BoundedString"),
+                        _ => ("to_vec", "crate::BoundedVec"),
+                    };
+                    writeln!(
+                        out,
+                        "    pub fn get_{0}(&self) -> {1} {{self.{0}.{2}()}}",
+                        field.name,
+                        ty.rust_getter_type(),
+                        to_owned
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "    pub fn set_{0}(&mut self, {0}: {1}) -> anyhow::Result<()> {{self.{0} = {2}::new({0})?; Ok(())}}",
+                        field.name,
+                        ty.rust_param_type(),
+                        bounded
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
+
+//-----------------------------python-----------------------------------
+pub fn generate_python(messages: &[MessageDef]) -> String {
+    let mut out = String::new();
+    writeln!(out, "//{}", HEADER_COMMENT).unwrap();
+    for msg in messages {
+        let class = msg.ffi_class_name();
+        let rust = format!("Rust{}", class);
+        let types = msg.field_types();
+        let py_type = |t: &FieldType| match t {
+            FieldType::Scalar(s) => s.rust.to_string(),
+            _ => t.rust_getter_type(),
+        };
+        let py_arg = |name: &str, t: &FieldType| match t {
+            FieldType::Scalar(_) => name.to_string(),
+            _ => format!("&{}", name),
+        };
+
+        writeln!(out, "\nuse swordfish_messages::{} as {};", msg.name, rust).unwrap();
+        writeln!(out, "#[pyclass]").unwrap();
+        writeln!(out, "pub struct {}({});", class, rust).unwrap();
+        writeln!(out, "#[pymethods]").unwrap();
+        writeln!(out, "impl {} {{", class).unwrap();
+
+        let params: Vec<String> = msg
+            .fields
+            .iter()
+            .zip(types.iter())
+            .map(|(f, t)| format!("{}: {}", f.name, py_type(t)))
+            .collect();
+        let args: Vec<String> = msg
+            .fields
+            .iter()
+            .zip(types.iter())
+            .map(|(f, t)| py_arg(&f.name, t))
+            .collect();
+        writeln!(out, "    #[new]").unwrap();
+        if msg.fields.len() > 7 {
+            writeln!(out, "    #[allow(clippy::too_many_arguments)]").unwrap();
+        }
+        if msg.has_variable_length_fields() {
+            writeln!(out, "    fn new({}) -> PyResult<Self> {{", params.join(", ")).unwrap();
+            writeln!(
+                out,
+                "        {}::new({}).map({}).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))",
+                rust,
+                args.join(", "),
+                class
+            )
+            .unwrap();
+        } else {
+            writeln!(out, "    fn new({}) -> Self {{", params.join(", ")).unwrap();
+            writeln!(out, "        {}({}::new({}))", class, rust, args.join(", ")).unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "    #[staticmethod]").unwrap();
+        writeln!(out, "    fn make_empty() -> Self {{").unwrap();
+        writeln!(out, "        {}({}::default())", class, rust).unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "    fn to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage {{").unwrap();
+        writeln!(out, "        SwordFishConcentratedMessage(self.0.to_concentrated(counter))").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "    #[staticmethod]").unwrap();
+        writeln!(
+            out,
+            "    fn from_concentrated(msg: &SwordFishConcentratedMessage) -> Option<{}> {{",
+            class
+        )
+        .unwrap();
+        writeln!(out, "        match {}::from_concentrated(&msg.0) {{", rust).unwrap();
+        writeln!(out, "            Ok(msg) => Some({}(msg)),", class).unwrap();
+        writeln!(out, "            Err(_) => None,").unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "    fn print(&self) {{").unwrap();
+        writeln!(out, "        self.0.print();").unwrap();
+        writeln!(out, "    }}").unwrap();
+        for (field, ty) in msg.fields.iter().zip(types.iter()) {
+            writeln!(
+                out,
+                "    fn get_{0}(&self) -> {1} {{self.0.get_{0}()}}",
+                field.name,
+                py_type(ty)
+            )
+            .unwrap();
+            if ty.is_variable_length() {
+                writeln!(
+                    out,
+                    "    fn set_{0}(&mut self, {0}: {1}) -> PyResult<()> {{self.0.set_{0}({2}).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))}}",
+                    field.name,
+                    py_type(ty),
+                    py_arg(&field.name, ty)
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    out,
+                    "    fn set_{0}(&mut self, {0}: {1}) {{self.0.set_{0}({2})}}",
+                    field.name,
+                    py_type(ty),
+                    py_arg(&field.name, ty)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    writeln!(out, "\npub fn add_message_classes(m: &Bound<'_, PyModule>) -> PyResult<()> {{").unwrap();
+    for msg in messages {
+        writeln!(out, "    m.add_class::<{}>()?;", msg.ffi_class_name()).unwrap();
+    }
+    writeln!(out, "    Ok(())").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+//-----------------------------flapigen (c++/java)-----------------------------------
+#[cfg(any(feature = "java_wrapper", feature = "cpp_wrapper"))]
+pub fn generate_foreign_classes(messages: &[MessageDef]) -> String {
+    let mut out = String::new();
+    writeln!(out, "\n//-------------------------------SwordFish Messages----------------------------------").unwrap();
+    writeln!(out, "//{}", HEADER_COMMENT).unwrap();
+    for msg in messages {
+        let class = msg.ffi_class_name();
+        let types = msg.field_types();
+        let params: Vec<String> = msg
+            .fields
+            .iter()
+            .zip(types.iter())
+            .map(|(f, t)| format!("{} : {}", f.name, t.rust_param_type()))
+            .collect();
+
+        writeln!(out, "\n//--------------{}------------------//", msg.name).unwrap();
+        writeln!(out, "use swordfish_messages::{} as {};", msg.name, class).unwrap();
+        //the foreign languages get the errors of the fallible constructor and setters as strings
+        if msg.has_variable_length_fields() {
+            let args: Vec<&str> = msg.fields.iter().map(|f| f.name.as_str()).collect();
+            writeln!(out, "impl {} {{", class).unwrap();
+            writeln!(out, "    pub fn ffi_new({}) -> Result<{}, String> {{", params.join(", ").replace(" : ", ": "), class).unwrap();
+            writeln!(out, "        {}::new({}).map_err(|e| e.to_string())", class, args.join(", ")).unwrap();
+            writeln!(out, "    }}").unwrap();
+            for (field, ty) in msg.fields.iter().zip(types.iter()).filter(|(_, t)| t.is_variable_length()) {
+                writeln!(
+                    out,
+                    "    pub fn ffi_set_{0}(&mut self, {0}: {1}) -> Result<(), String> {{self.set_{0}({0}).map_err(|e| e.to_string())}}",
+                    field.name,
+                    ty.rust_param_type()
+                )
+                .unwrap();
+            }
+            writeln!(out, "}}").unwrap();
+        }
+        writeln!(out, "foreign_class!(").unwrap();
+        writeln!(out, "    class {} {{", class).unwrap();
+        writeln!(out, "        self_type {};", class).unwrap();
+        //flapigen can not generate a c++ constructor that fails, these messages are made with create()
+        if msg.has_variable_length_fields() {
+            writeln!(out, "        private constructor = empty;").unwrap();
+            writeln!(
+                out,
+                "        fn {0}::ffi_new({1}) -> Result<{0}, String>; alias create;",
+                class,
+                params.join(", ")
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                out,
+                "        constructor {0}::new({1}) -> {0};",
+                class,
+                params.join(", ")
+            )
+            .unwrap();
+        }
+        writeln!(out, "        fn make_empty() -> {0} {{{0}::default()}}", class).unwrap();
+        writeln!(out, "        fn {}::print(&self);", class).unwrap();
+        writeln!(
+            out,
+            "        fn {}::to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage;",
+            class
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "        fn {0}::from_concentrated(concenrated_msg: &SwordFishConcentratedMessage) -> Option<{0}> {{",
+            class
+        )
+        .unwrap();
+        writeln!(out, "            match {}::from_concentrated(concenrated_msg) {{", class).unwrap();
+        writeln!(out, "                Ok(msg) => Some(msg),").unwrap();
+        writeln!(out, "                Err(_) => None,").unwrap();
+        writeln!(out, "            }}").unwrap();
+        writeln!(out, "        }}").unwrap();
+        for (field, ty) in msg.fields.iter().zip(types.iter()) {
+            writeln!(
+                out,
+                "        fn {0}::get_{1}(&self) -> {2};",
+                class,
+                field.name,
+                ty.rust_getter_type()
+            )
+            .unwrap();
+            if ty.is_variable_length() {
+                writeln!(
+                    out,
+                    "        fn {0}::ffi_set_{1}(&mut self, {1}: {2}) -> Result<(), String>; alias set_{1};",
+                    class,
+                    field.name,
+                    ty.rust_param_type()
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    out,
+                    "        fn {0}::set_{1}(&mut self, {1}: {2});",
+                    class,
+                    field.name,
+                    ty.rust_param_type()
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, ");").unwrap();
+    }
+    out
+}
+
+//-----------------------------c header-----------------------------------
+fn to_snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub fn generate_c_header(messages: &[MessageDef], max_payload_size: usize) -> String {
+    let mut out = String::new();
+    writeln!(out, "// {}", HEADER_COMMENT).unwrap();
+    writeln!(out, "#ifndef SWORDFISH_MESSAGES_H").unwrap();
+    writeln!(out, "#define SWORDFISH_MESSAGES_H\n").unwrap();
+    writeln!(out, "#include <stdint.h>\n").unwrap();
+    writeln!(out, "#define SWORDFISH_MAX_PAYLOAD_SIZE {}\n", max_payload_size).unwrap();
+    for msg in messages {
+        writeln!(
+            out,
+            "#define SWORDFISH_OPCODE_{} {}",
+            to_snake_case(&msg.name).to_uppercase(),
+            msg.opcode
+        )
+        .unwrap();
+    }
+    writeln!(out, "\n#pragma pack(push, 1)").unwrap();
+    for msg in messages {
+        let snake = to_snake_case(&msg.name);
+        let mut comment = format!("{}, opcode {}, category {}", msg.name, msg.opcode, msg.category);
+        if let Some(response) = msg.response {
+            write!(comment, ", response opcode {}", response).unwrap();
+        }
+        writeln!(out, "\n// {}", comment).unwrap();
+        if msg.fields.is_empty() {
+            writeln!(out, "// no payload").unwrap();
+            continue;
+        }
+        if msg.field_types().iter().any(|t| t.is_variable_length()) {
+            writeln!(
+                out,
+                "// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout"
+            )
+            .unwrap();
+        }
+        writeln!(out, "typedef struct {{").unwrap();
+        for (field, ty) in msg.fields.iter().zip(msg.field_types().iter()) {
+            if let Some(since) = field.since {
+                writeln!(out, "    // since version {}", since).unwrap();
+            }
+            match ty {
+                FieldType::Scalar(s) => writeln!(out, "    {} {};", s.c, field.name).unwrap(),
+                FieldType::Array(s, len) => {
+                    writeln!(out, "    {} {}[{}];", s.c, field.name, len).unwrap()
+                }
+                //on the wire only the first <name>_len bytes/items follow the length byte
+                FieldType::String(len) => {
+                    writeln!(out, "    uint8_t {}_len;", field.name).unwrap();
+                    writeln!(out, "    char {}[{}];", field.name, len).unwrap();
+                }
+                FieldType::Vec(s, len) => {
+                    writeln!(out, "    uint8_t {}_len;", field.name).unwrap();
+                    writeln!(out, "    {} {}[{}];", s.c, field.name, len).unwrap();
+                }
+            }
+        }
+        writeln!(out, "}} swordfish_{}_t;", snake).unwrap();
+    }
+    writeln!(out, "\n#pragma pack(pop)\n").unwrap();
+    writeln!(out, "#endif // SWORDFISH_MESSAGES_H").unwrap();
+    out
+}
@@ -29,6 +29,7 @@ int main() {
     //we need to move the payload to conform to rust borrow checker (I guess)
     swordfish_com::RustSlice<const uint8_t> payload_cpp = swordfish_com::RustSlice<const uint8_t>(payload, 10);
     swordfish_com::SwordFishConcentratedMessage concentrated_message_cpp = swordfish_com::SwordFishConcentratedMessage(counter, opcode, std::move(payload_cpp));
+    std::cout << concentrated_message_cpp.to_pretty_string().to_std_string() << std::endl;
 
     //create a ping message
     swordfish_com::PingMessage ping_message = swordfish_com::PingMessage();
@@ -49,7 +50,7 @@ int main() {
     swordfish_com::SwordFishComm swordfish_comm = swordfish_com::SwordFishComm(probable_swordfish_port);
     std::cout << "Tx counter: " << swordfish_comm.get_tx_counter() << std::endl;
     std::cout << "Rx counter: " << swordfish_comm.get_rx_counter() << std::endl;
-    swordfish_com::SwordFishConcentratedMessage concentrated_send = ping_message.to_concentrated(0);
+    swordfish_com::SwordFishConcentratedMessage concentrated_send = ping_message.to_concentrated(swordfish_comm.next_tx_counter());
     std::optional<swordfish_com::SwordFishConcentratedMessage> concentrated_answer = swordfish_comm.send_msg(std::move(concentrated_send));
     if (concentrated_answer.has_value()) {
         std::optional<swordfish_com::PingMessage> ping_answer = swordfish_com::PingMessage::from_concentrated(concentrated_answer.value());
@@ -0,0 +1,194 @@
+// generated by build.rs from messages.toml, do not edit
+#ifndef SWORDFISH_MESSAGES_H
+#define SWORDFISH_MESSAGES_H
+
+#include <stdint.h>
+
+#define SWORDFISH_MAX_PAYLOAD_SIZE 245
+
+#define SWORDFISH_OPCODE_PING 0
+#define SWORDFISH_OPCODE_ECHO 1
+#define SWORDFISH_OPCODE_VERSION_DATA 2
+#define SWORDFISH_OPCODE_TIME_SYNC 4
+#define SWORDFISH_OPCODE_DFU_ENTER_BOOTLOADER 64
+#define SWORDFISH_OPCODE_DFU_ERASE 65
+#define SWORDFISH_OPCODE_DFU_WRITE_BLOCK 66
+#define SWORDFISH_OPCODE_DFU_VERIFY 67
+#define SWORDFISH_OPCODE_DFU_COMMIT 68
+#define SWORDFISH_OPCODE_DFU_GET_STATE 69
+#define SWORDFISH_OPCODE_DFU_STATUS 70
+#define SWORDFISH_OPCODE_PARAM_READ 80
+#define SWORDFISH_OPCODE_PARAM_WRITE 81
+#define SWORDFISH_OPCODE_DEVICE_LOG 90
+#define SWORDFISH_OPCODE_STREAM_START 96
+#define SWORDFISH_OPCODE_STREAM_STOP 97
+#define SWORDFISH_OPCODE_IMU_SAMPLE 100
+#define SWORDFISH_OPCODE_CALIBRATE 110
+#define SWORDFISH_OPCODE_SELF_TEST 111
+#define SWORDFISH_OPCODE_OPERATION_PROGRESS 112
+#define SWORDFISH_OPCODE_OPERATION_RESULT 113
+#define SWORDFISH_OPCODE_OPERATION_ABORT 114
+#define SWORDFISH_OPCODE_NACK 127
+
+#pragma pack(push, 1)
+
+// Ping, opcode 0, category bounce
+// no payload
+
+// Echo, opcode 1, category bounce
+// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout
+typedef struct {
+    uint8_t text_len;
+    char text[32];
+    uint8_t data_len;
+    uint8_t data[32];
+    uint8_t samples_len;
+    int16_t samples[8];
+} swordfish_echo_t;
+
+// VersionData, opcode 2, category bounce
+typedef struct {
+    uint8_t version;
+    uint8_t subversion;
+    uint32_t mcu_type;
+    uint8_t uuid[8];
+} swordfish_version_data_t;
+
+// TimeSync, opcode 4, category bounce
+typedef struct {
+    uint64_t host_time;
+    uint64_t device_time;
+} swordfish_time_sync_t;
+
+// DfuEnterBootloader, opcode 64, category operation, response opcode 70
+typedef struct {
+    uint32_t image_size;
+} swordfish_dfu_enter_bootloader_t;
+
+// DfuErase, opcode 65, category operation, response opcode 70
+typedef struct {
+    uint32_t address;
+    uint32_t length;
+} swordfish_dfu_erase_t;
+
+// DfuWriteBlock, opcode 66, category operation, response opcode 70
+// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout
+typedef struct {
+    uint32_t address;
+    uint8_t data_len;
+    uint8_t data[128];
+} swordfish_dfu_write_block_t;
+
+// DfuVerify, opcode 67, category operation, response opcode 70
+typedef struct {
+    uint32_t address;
+    uint32_t length;
+} swordfish_dfu_verify_t;
+
+// DfuCommit, opcode 68, category operation, response opcode 70
+typedef struct {
+    uint32_t image_size;
+    uint32_t crc32;
+} swordfish_dfu_commit_t;
+
+// DfuGetState, opcode 69, category operation, response opcode 70
+// no payload
+
+// DfuStatus, opcode 70, category response
+typedef struct {
+    uint8_t request;
+    uint8_t status;
+    uint8_t state;
+    uint32_t written;
+    uint32_t crc32;
+} swordfish_dfu_status_t;
+
+// ParamRead, opcode 80, category param
+typedef struct {
+    uint16_t id;
+    uint8_t status;
+    uint64_t value;
+} swordfish_param_read_t;
+
+// ParamWrite, opcode 81, category param
+typedef struct {
+    uint16_t id;
+    uint8_t status;
+    uint64_t value;
+} swordfish_param_write_t;
+
+// DeviceLog, opcode 90, category response
+// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout
+typedef struct {
+    uint8_t level;
+    uint16_t module_id;
+    uint32_t timestamp;
+    uint8_t text_len;
+    char text[200];
+} swordfish_device_log_t;
+
+// StreamStart, opcode 96, category bounce
+typedef struct {
+    uint8_t stream;
+    uint16_t rate_hz;
+} swordfish_stream_start_t;
+
+// StreamStop, opcode 97, category bounce
+typedef struct {
+    uint8_t stream;
+} swordfish_stream_stop_t;
+
+// ImuSample, opcode 100, category stream
+typedef struct {
+    uint32_t sequence;
+    int16_t accel[3];
+    int16_t gyro[3];
+} swordfish_imu_sample_t;
+
+// Calibrate, opcode 110, category operation
+typedef struct {
+    uint8_t sensor;
+} swordfish_calibrate_t;
+
+// SelfTest, opcode 111, category operation
+typedef struct {
+    uint32_t tests;
+} swordfish_self_test_t;
+
+// OperationProgress, opcode 112, category response
+// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout
+typedef struct {
+    uint8_t operation;
+    uint8_t percent;
+    uint16_t stage;
+    uint8_t text_len;
+    char text[64];
+} swordfish_operation_progress_t;
+
+// OperationResult, opcode 113, category response
+// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout
+typedef struct {
+    uint8_t operation;
+    uint8_t status;
+    uint8_t data_len;
+    uint8_t data[64];
+} swordfish_operation_result_t;
+
+// OperationAbort, opcode 114, category bounce
+typedef struct {
+    uint8_t operation;
+} swordfish_operation_abort_t;
+
+// Nack, opcode 127, category response
+// variable length: a field sent as <name>_len followed by only <name>_len items, so this is not the wire layout
+typedef struct {
+    uint8_t opcode;
+    uint16_t counter;
+    uint16_t code;
+    uint8_t detail_len;
+    char detail[64];
+} swordfish_nack_t;
+
+#pragma pack(pop)
+
+#endif // SWORDFISH_MESSAGES_H
@@ -40,9 +40,9 @@ public class Main {
         SwordFishComm swordfish_comm = new SwordFishComm(probable_swordfish_port);
         System.out.println("Tx counter: " + swordfish_comm.get_tx_counter());
         System.out.println("Rx counter: " + swordfish_comm.get_rx_counter());
-        java.util.Optional<SwordFishConcentratedMessage> concentrated_answer = swordfish_comm.send_msg(ping_message.to_concentrated(0));
+        java.util.Optional<SwordFishConcentratedMessage> concentrated_answer = swordfish_comm.send_msg(ping_message.to_concentrated(swordfish_comm.next_tx_counter()));
         if (concentrated_answer.isPresent()) {
-            concentrated_answer.get().print();
+            System.out.println(concentrated_answer.get().to_pretty_string());
         }
         System.out.println("Tx counter: " + swordfish_comm.get_tx_counter());
         System.out.println("Rx counter: " + swordfish_comm.get_rx_counter());        
@@ -27,8 +27,8 @@ if __name__ == "__main__":
     comm = swordfish_com.SwordFishComm(swordfish_com.find_probable_swordfish_port())
     print(f"Tx counter: {comm.get_tx_counter()}")
     print(f"Rx counter: {comm.get_rx_counter()}")
-    answer = comm.send_msg(ping_concentrated)
+    answer = comm.send_msg(ping_msg.to_concentrated(comm.next_tx_counter()))
     print(f"Tx counter: {comm.get_tx_counter()}")
     print(f"Rx counter: {comm.get_rx_counter()}")
-    answer.print()
+    print(answer.to_pretty_string(comm.registry()))
 
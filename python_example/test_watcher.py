@@ -0,0 +1,28 @@
+import subprocess
+import sys
+
+import swordfish_com
+
+# the callback sleeps without the gil, the watcher is dropped meanwhile and has to be joined
+# while its thread waits for the gil to return from the callback. a deadlock ends in the timeout
+DROP_RUNNING_WATCHER = """
+import gc, time
+import swordfish_com
+def slow(event, port):
+    time.sleep(0.2)
+watcher = swordfish_com.DeviceWatcher(slow, [swordfish_com.DeviceMatcher("any", port_name="*")])
+time.sleep(0.1)
+del watcher
+gc.collect()
+"""
+
+
+def test_dropping_a_running_watcher():
+    if not swordfish_com.list_ports():
+        print("no serial port, the callback does not run")
+    subprocess.run([sys.executable, "-c", DROP_RUNNING_WATCHER], check=True, timeout=10)
+
+
+if __name__ == "__main__":
+    test_dropping_a_running_watcher()
+    print("ok")
@@ -16,7 +16,7 @@ foreign_class!(
         }
         fn get_serial_ports() -> String {
             let result = match swordfish_comm::get_serial_ports() {
-                Some(ports) => ports,
+                Some(ports) => swordfish_comm::serial_ports_text(&ports),
                 None => "".to_string(),
             };
             result
@@ -35,9 +35,31 @@ foreign_class!(
             swordfish_comm
         }
         // fn SwordFishComm::change_message_rx_callback(&self, opcode: u8, callback: Box<dyn Fn(SwordFishConcentratedMessage) + Send>);
+        fn SwordFishComm::ffi_replay(path: &str, speed: f64, sync_on_tx: bool) -> Result<SwordFishComm, String>; alias replay;
         fn SwordFishComm::send_msg(&self, msg: SwordFishConcentratedMessage) -> Option<SwordFishConcentratedMessage>;
+        fn SwordFishComm::ffi_request(&self, msg: SwordFishConcentratedMessage, timeout_ms: u32) -> Result<SwordFishConcentratedMessage, String>; alias request;
+        fn SwordFishComm::ffi_describe_error_code(code: u16) -> String; alias describe_error_code;
         fn SwordFishComm::get_tx_counter(&self) -> usize;
+        fn SwordFishComm::next_tx_counter(&self) -> u16;
         fn SwordFishComm::get_rx_counter(&self) -> usize;
+        fn SwordFishComm::ffi_register_message(&self, descriptor: &MessageDescriptor) -> bool; alias register_message;
+        fn SwordFishComm::registry(&self) -> MessageRegistry;
+        fn SwordFishComm::ffi_recent_device_logs(&self, max: usize) -> String; alias recent_device_logs;
+        fn SwordFishComm::ffi_clear_device_logs(&self); alias clear_device_logs;
+        fn SwordFishComm::ffi_set_device_log_rate_limit(&self, max_per_second: u32); alias set_device_log_rate_limit;
+        fn SwordFishComm::ffi_set_clock_sync_period(&self, period_ms: u32); alias set_clock_sync_period;
+        fn SwordFishComm::ffi_device_to_host_time(&self, device_us: u64) -> Option<i64>; alias device_to_host_time;
+        fn SwordFishComm::ffi_start_operation(&self, msg: SwordFishConcentratedMessage, timeout_ms: u32) -> Option<OperationHandle>; alias start_operation;
+        fn SwordFishComm::ffi_start_stream(&self, opcode: u8, rate_hz: u16) -> bool; alias start_stream;
+        fn SwordFishComm::ffi_stop_stream(&self, opcode: u8) -> bool; alias stop_stream;
+        fn SwordFishComm::ffi_drain_bytes(&self, opcode: u8, max: usize) -> Vec<u8>; alias drain_bytes;
+        fn SwordFishComm::ffi_stream_received(&self, opcode: u8) -> u64; alias stream_received;
+        fn SwordFishComm::ffi_stream_overflowed(&self, opcode: u8) -> u64; alias stream_overflowed;
+        fn SwordFishComm::ffi_set_stream_capacity(&self, opcode: u8, capacity: usize) -> bool; alias set_stream_capacity;
+        fn SwordFishComm::ffi_start_capture(&self, path: &str) -> bool; alias start_capture;
+        fn SwordFishComm::ffi_stop_capture(&self) -> u64; alias stop_capture;
+        fn SwordFishComm::ffi_start_recording(&self, path: &str) -> bool; alias start_recording;
+        fn SwordFishComm::ffi_stop_recording(&self) -> u64; alias stop_recording;
     }
 
 );
@@ -46,7 +68,11 @@ foreign_class!(
 use swordfish_concentrated_message::SwordFishConcentratedMessage as SwordFishConcentratedMessage;
 impl SwordFishConcentratedMessage {
     pub fn print(&self) {
-        println!("{:?}", self);
+        println!("{}", self);
+    }
+    //the name of the opcode, the counter, the checksum, the fields and a hexdump, see swordfish_format
+    pub fn to_pretty_string(&self) -> String {
+        self.to_string()
     }
 }
 
@@ -55,21 +81,182 @@ foreign_class!(
         self_type SwordFishConcentratedMessage;
         constructor SwordFishConcentratedMessage::new(counter: u16, opcode: u8, payload: &[u8]) -> SwordFishConcentratedMessage;
         fn SwordFishConcentratedMessage::print(&self);
+        fn SwordFishConcentratedMessage::to_pretty_string(&self) -> String;
     }
 );
 
-//-------------------------------SwordFish Messages----------------------------------
-//--------------Ping------------------//
-use swordfish_messages::Ping as PingMessage;
+//-------------------------------Dynamic Messages-----------------------------------
+use swordfish_dynamic::MessageDescriptor as MessageDescriptor;
+use swordfish_dynamic::DynamicMessage as DynamicMessage;
+impl MessageDescriptor {
+    //category is "bounce", "param", "operation", "response" or "stream"
+    pub fn ffi_new(name: &str, opcode: u8, category: &str) -> Result<MessageDescriptor, String> {
+        let category = SwordFishMessageCategory::from_name(category)
+            .ok_or_else(|| format!("Unknown message category {}", category))?;
+        Ok(MessageDescriptor::new(name, opcode, category))
+    }
+    //field_type is spelled like in messages.toml, "u16", "f32", "[u8; 8]"
+    pub fn ffi_add_field(&mut self, name: &str, field_type: &str, offset: usize, big_endian: bool) -> bool {
+        let endianness = if big_endian { swordfish_dynamic::Endianness::Big } else { swordfish_dynamic::Endianness::Little };
+        match swordfish_dynamic::DynamicFieldType::parse(field_type) {
+            Ok(field_type) => self.add_field(name, field_type, offset, endianness).is_ok(),
+            Err(_) => false,
+        }
+    }
+    pub fn ffi_append_field(&mut self, name: &str, field_type: &str, big_endian: bool) -> bool {
+        let offset = self.payload_length();
+        self.ffi_add_field(name, field_type, offset, big_endian)
+    }
+    pub fn ffi_set_response_opcode(&mut self, response_opcode: u8) -> bool {
+        self.set_response_opcode(response_opcode).is_ok()
+    }
+}
+
+impl DynamicMessage {
+    pub fn ffi_new(descriptor: &MessageDescriptor) -> DynamicMessage {
+        DynamicMessage::new(std::sync::Arc::new(descriptor.clone()))
+    }
+    pub fn ffi_get_integer(&self, name: &str) -> Option<i64> {self.get_integer(name).ok()}
+    pub fn ffi_set_integer(&mut self, name: &str, value: i64) -> bool {self.set_integer(name, value).is_ok()}
+    pub fn ffi_get_float(&self, name: &str) -> Option<f64> {self.get_float(name).ok()}
+    pub fn ffi_set_float(&mut self, name: &str, value: f64) -> bool {self.set_float(name, value).is_ok()}
+    pub fn ffi_get_bytes(&self, name: &str) -> Vec<u8> {
+        match self.get(name) {
+            Ok(swordfish_dynamic::DynamicValue::Bytes(bytes)) => bytes,
+            _ => Vec::new(),
+        }
+    }
+    pub fn ffi_set_bytes(&mut self, name: &str, value: &[u8]) -> bool {
+        self.set(name, swordfish_dynamic::DynamicValue::Bytes(value.to_vec())).is_ok()
+    }
+    pub fn print(&self) {
+        println!("{:?}", self);
+    }
+}
+
+impl SwordFishComm {
+    //one "LEVEL module_id timestamp_ms: text" line per entry, oldest first
+    pub fn ffi_recent_device_logs(&self, max: usize) -> String {
+        self.device_log()
+            .recent(max)
+            .iter()
+            .map(|entry| format!("{} {} {}ms: {}\n", entry.level, entry.module_id, entry.timestamp, entry.text))
+            .collect()
+    }
+    pub fn ffi_clear_device_logs(&self) {
+        self.device_log().clear()
+    }
+    //0 turns the rate limit off
+    pub fn ffi_set_device_log_rate_limit(&self, max_per_second: u32) {
+        self.device_log().set_rate_limit(if max_per_second == 0 { None } else { Some(max_per_second) })
+    }
+    //a TimeSync round trip every period_ms, 0 stops them
+    pub fn ffi_set_clock_sync_period(&self, period_ms: u32) {
+        let period = if period_ms == 0 { None } else { Some(std::time::Duration::from_millis(period_ms as u64)) };
+        self.clock_sync().set_period(period)
+    }
+    //us since the unix epoch, empty before the first time sync
+    pub fn ffi_device_to_host_time(&self, device_us: u64) -> Option<i64> {
+        self.device_to_host_time(device_us)
+            .and_then(|host| host.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_micros() as i64)
+    }
+    //the error is the text of the SwordFishError, e.g. the rejection of the device with its error code
+    pub fn ffi_request(&self, msg: SwordFishConcentratedMessage, timeout_ms: u32) -> Result<SwordFishConcentratedMessage, String> {
+        self.request_with_timeout(msg, std::time::Duration::from_millis(timeout_ms as u64))
+            .map_err(|e| e.to_string())
+    }
+    pub fn ffi_describe_error_code(code: u16) -> String {
+        swordfish_error::describe_error_code(code)
+    }
+    //empty if the opcode is not an operation or is already running
+    pub fn ffi_start_operation(&self, msg: SwordFishConcentratedMessage, timeout_ms: u32) -> Option<OperationHandle> {
+        self.start_operation(msg, std::time::Duration::from_millis(timeout_ms as u64)).ok()
+    }
+    pub fn ffi_start_stream(&self, opcode: u8, rate_hz: u16) -> bool {
+        self.start_stream(opcode, rate_hz).is_ok()
+    }
+    pub fn ffi_stop_stream(&self, opcode: u8) -> bool {
+        self.stop_stream(opcode).is_ok()
+    }
+    //false if the opcode is not a stream
+    pub fn ffi_set_stream_capacity(&self, opcode: u8, capacity: usize) -> bool {
+        self.set_stream_capacity(opcode, capacity).is_ok()
+    }
+    //the payloads of at most max buffered messages back to back, each payload_size bytes
+    pub fn ffi_drain_bytes(&self, opcode: u8, max: usize) -> Vec<u8> {
+        let stream = match self.stream_buffer(opcode) {
+            Some(stream) => stream,
+            None => return Vec::new(),
+        };
+        let stride = self.registry().get(opcode).map_or(0, |info| info.payload_size);
+        let mut out = vec![0u8; std::cmp::min(max, stream.len()) * stride];
+        let n = stream.drain_payloads(max, stride, &mut out);
+        out.truncate(n * stride);
+        out
+    }
+    pub fn ffi_stream_received(&self, opcode: u8) -> u64 {
+        self.stream_buffer(opcode).map_or(0, |stream| stream.stats().received)
+    }
+    pub fn ffi_stream_overflowed(&self, opcode: u8) -> u64 {
+        self.stream_buffer(opcode).map_or(0, |stream| stream.stats().overflowed)
+    }
+    pub fn ffi_register_message(&self, descriptor: &MessageDescriptor) -> bool {
+        self.register_message(descriptor).is_ok()
+    }
+    pub fn ffi_start_capture(&self, path: &str) -> bool {
+        self.start_capture(std::path::Path::new(path)).is_ok()
+    }
+    //the number of frames captured, 0 if the capture could not be written
+    pub fn ffi_stop_capture(&self) -> u64 {
+        self.stop_capture().unwrap_or(0)
+    }
+    pub fn ffi_start_recording(&self, path: &str) -> bool {
+        self.start_recording(std::path::Path::new(path)).is_ok()
+    }
+    //the number of chunks recorded, 0 if the recording could not be written
+    pub fn ffi_stop_recording(&self) -> u64 {
+        self.stop_recording().unwrap_or(0)
+    }
+    //a comm that plays a session recorded with start_recording back instead of talking to a board,
+    //with sync_on_tx the answers wait for their request to be sent again
+    pub fn ffi_replay(path: &str, speed: f64, sync_on_tx: bool) -> Result<SwordFishComm, String> {
+        let session = swordfish_session::Session::load(std::path::Path::new(path)).map_err(|e| e.to_string())?;
+        let replay = swordfish_session::ReplayTransport::new(session)
+            .speed(speed)
+            .map_err(|e| e.to_string())?
+            .sync_on_tx(sync_on_tx);
+        Ok(SwordFishComm::from_transport(Box::new(replay)))
+    }
+}
+
+foreign_class!(
+    class MessageDescriptor {
+        self_type MessageDescriptor;
+        //an unknown category is an error, and flapigen can not generate a c++ constructor that fails
+        private constructor = empty;
+        fn MessageDescriptor::ffi_new(name: &str, opcode: u8, category: &str) -> Result<MessageDescriptor, String>; alias create;
+        fn MessageDescriptor::ffi_add_field(&mut self, name: &str, field_type: &str, offset: usize, big_endian: bool) -> bool; alias add_field;
+        fn MessageDescriptor::ffi_append_field(&mut self, name: &str, field_type: &str, big_endian: bool) -> bool; alias append_field;
+        fn MessageDescriptor::ffi_set_response_opcode(&mut self, response_opcode: u8) -> bool; alias set_response_opcode;
+        fn MessageDescriptor::payload_length(&self) -> usize;
+    }
+);
 
 foreign_class!(
-    class PingMessage {
-        self_type PingMessage;
-        constructor new() -> PingMessage {PingMessage::default()}
-        fn PingMessage::print(&self);
-        fn PingMessage::to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage;
-        fn PingMessage::from_concentrated(concenrated_msg: &SwordFishConcentratedMessage) -> Option<PingMessage> {
-            match PingMessage::from_concentrated(concenrated_msg) {
+    class DynamicMessage {
+        self_type DynamicMessage;
+        constructor DynamicMessage::ffi_new(descriptor: &MessageDescriptor) -> DynamicMessage;
+        fn DynamicMessage::ffi_get_integer(&self, name: &str) -> Option<i64>; alias get_integer;
+        fn DynamicMessage::ffi_set_integer(&mut self, name: &str, value: i64) -> bool; alias set_integer;
+        fn DynamicMessage::ffi_get_float(&self, name: &str) -> Option<f64>; alias get_float;
+        fn DynamicMessage::ffi_set_float(&mut self, name: &str, value: f64) -> bool; alias set_float;
+        fn DynamicMessage::ffi_get_bytes(&self, name: &str) -> Vec<u8>; alias get_bytes;
+        fn DynamicMessage::ffi_set_bytes(&mut self, name: &str, value: &[u8]) -> bool; alias set_bytes;
+        fn DynamicMessage::print(&self);
+        fn DynamicMessage::to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage;
+        fn DynamicMessage::from_concentrated(descriptor: &MessageDescriptor, concenrated_msg: &SwordFishConcentratedMessage) -> Option<DynamicMessage> {
+            match DynamicMessage::from_concentrated(std::sync::Arc::new(descriptor.clone()), concenrated_msg) {
                 Ok(msg) => Some(msg),
                 Err(_) => None,
             }
@@ -77,62 +264,232 @@ foreign_class!(
     }
 );
 
-//----------------VersionData----------------//
-use swordfish_messages::VersionData as VersionDataMessage;
-impl VersionDataMessage {
-    pub fn new(version : u8, subversion : u8, mcu_type : u32, uuid : &[u8]) -> Self {
-        VersionDataMessage {
-            version,
-            subversion,
-            mcu_type,
-            uuid: {
-                let mut uuid_arr = [0; 8];
-                let len = std::cmp::min(uuid.len(), 8);
-                uuid_arr[..len].copy_from_slice(&uuid[..len]);
-                uuid_arr
-            }
+//-------------------------------Long-running operations-----------------------------
+use swordfish_operation::OperationHandle as OperationHandle;
+use swordfish_operation::{OperationError, OperationOutcome};
+fn outcome_code(outcome: &OperationOutcome) -> i32 {
+    match outcome {
+        Ok(_) => 0,
+        Err(OperationError::Failed { status, .. }) => *status as i32,
+        Err(OperationError::Timeout) => -1,
+        Err(OperationError::Cancelled) => -2,
+        Err(OperationError::Rejected(_)) => -4,
+    }
+}
+impl OperationHandle {
+    //0 on success, the device status when it failed, -1 on timeout, -2 when cancelled and -4 when rejected
+    pub fn ffi_wait(&self) -> i32 {
+        outcome_code(&self.wait())
+    }
+    //like ffi_wait, -3 while it runs
+    pub fn ffi_result_code(&self) -> i32 {
+        self.try_result().map_or(-3, |outcome| outcome_code(&outcome))
+    }
+    //the data of the OperationResult, also when it failed
+    pub fn ffi_result_data(&self) -> Vec<u8> {
+        match self.try_result() {
+            Some(Ok(result)) => result.get_data(),
+            Some(Err(OperationError::Failed { data, .. })) => data,
+            _ => Vec::new(),
         }
     }
-    pub fn get_version(&self) -> u8 {self.version}
-    pub fn get_subversion(&self) -> u8 {self.subversion}
-    pub fn get_mcu_type(&self) -> u32 {self.mcu_type}
-    pub fn get_uuid(&self) -> Vec<u8> {self.uuid.to_vec()}
-    pub fn set_version(&mut self, version: u8) {self.version = version}
-    pub fn set_subversion(&mut self, subversion: u8) {self.subversion = subversion}
-    pub fn set_mcu_type(&mut self, mcu_type: u32) {self.mcu_type = mcu_type}
-    pub fn set_uuid(&mut self, uuid: &[u8]) {
-        self.uuid = [0; 8];
-        let len = std::cmp::min(uuid.len(), 8);
-        self.uuid[..len].copy_from_slice(&uuid[..len]);
+    pub fn ffi_percent(&self) -> u8 {
+        self.latest_progress().map_or(0, |progress| progress.get_percent())
     }
+    pub fn ffi_progress_text(&self) -> String {
+        self.latest_progress().map(|progress| progress.get_text()).unwrap_or_default()
+    }
+}
+
+foreign_class!(
+    class OperationHandle {
+        self_type OperationHandle;
+        private constructor = empty;
+        fn OperationHandle::opcode(&self) -> u8;
+        fn OperationHandle::is_finished(&self) -> bool;
+        fn OperationHandle::cancel(&self) -> bool;
+        fn OperationHandle::ffi_wait(&self) -> i32; alias wait;
+        fn OperationHandle::ffi_result_code(&self) -> i32; alias result_code;
+        fn OperationHandle::ffi_result_data(&self) -> Vec<u8>; alias result_data;
+        fn OperationHandle::ffi_percent(&self) -> u8; alias percent;
+        fn OperationHandle::ffi_progress_text(&self) -> String; alias progress_text;
+    }
+);
 
+//-------------------------------Port discovery-------------------------------------
+use swordfish_discovery::PortInfo as PortInfo;
+use swordfish_discovery::DiscoveryConfig as DiscoveryConfig;
+impl PortInfo {
+    //-1 when the port is not usb
+    pub fn ffi_vid(&self) -> i32 {self.vid.map_or(-1, |vid| vid as i32)}
+    pub fn ffi_pid(&self) -> i32 {self.pid.map_or(-1, |pid| pid as i32)}
+    //empty when unknown
+    pub fn ffi_port_name(&self) -> String {self.port_name.clone()}
+    pub fn ffi_serial_number(&self) -> String {self.serial_number.clone().unwrap_or_default()}
+    pub fn ffi_manufacturer(&self) -> String {self.manufacturer.clone().unwrap_or_default()}
+    pub fn ffi_product(&self) -> String {self.product.clone().unwrap_or_default()}
+    pub fn ffi_matcher(&self) -> String {self.matcher.clone().unwrap_or_default()}
+    pub fn ffi_confirmed(&self) -> bool {self.confirmed}
+    //"usb", "bluetooth", "pci" or "unknown"
+    pub fn ffi_kind(&self) -> String {format!("{:?}", self.kind).to_lowercase()}
+    //every serial port, matched or not
+    pub fn ffi_list_ports() -> Vec<PortInfo> {swordfish_discovery::list_ports()}
+    //true if a swordfish device answers a Ping on the port within timeout_ms
+    pub fn ffi_probe(port_name: &str, timeout_ms: u32) -> bool {
+        swordfish_discovery::probe(port_name, std::time::Duration::from_millis(timeout_ms as u64))
+    }
 }
 
+impl DiscoveryConfig {
+    //vid/pid of -1 and empty globs match anything
+    pub fn ffi_add_matcher(&mut self, name: &str, vid: i32, pid: i32, manufacturer: &str, product: &str, serial_number: &str, port_name: &str) {
+        let glob = |pattern: &str| if pattern.is_empty() { None } else { Some(pattern.to_string()) };
+        self.matchers.push(swordfish_discovery::DeviceMatcher {
+            name: name.to_string(),
+            vid: u16::try_from(vid).ok(),
+            pid: u16::try_from(pid).ok(),
+            manufacturer: glob(manufacturer),
+            product: glob(product),
+            serial_number: glob(serial_number),
+            port_name: glob(port_name),
+        });
+    }
+    //0 turns probing off
+    pub fn ffi_set_probe(&mut self, timeout_ms: u32) {
+        self.probe = if timeout_ms == 0 { None } else { Some(std::time::Duration::from_millis(timeout_ms as u64)) };
+    }
+    pub fn ffi_discover(&self) -> Vec<PortInfo> {
+        swordfish_discovery::discover_with(self)
+    }
+}
 
 foreign_class!(
-    class VersionDataMessage
-    {
-        self_type VersionDataMessage;
-        constructor VersionDataMessage::new(version : u8, subversion : u8, mcu_type : u32, uuid : &[u8]) -> VersionDataMessage;
-        fn make_empty() -> VersionDataMessage {VersionDataMessage::default()}
-        fn VersionDataMessage::print(&self);
-        fn VersionDataMessage::to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage;
-        fn VersionDataMessage::from_concentrated(concenrated_msg: &SwordFishConcentratedMessage) -> Option<VersionDataMessage> {
-            match VersionDataMessage::from_concentrated(concenrated_msg) {
-                Ok(msg) => Some(msg),
-                Err(_) => None,
-            }
+    class PortInfo {
+        self_type PortInfo;
+        private constructor = empty;
+        fn PortInfo::ffi_port_name(&self) -> String; alias port_name;
+        fn PortInfo::ffi_vid(&self) -> i32; alias vid;
+        fn PortInfo::ffi_pid(&self) -> i32; alias pid;
+        fn PortInfo::ffi_serial_number(&self) -> String; alias serial_number;
+        fn PortInfo::ffi_manufacturer(&self) -> String; alias manufacturer;
+        fn PortInfo::ffi_product(&self) -> String; alias product;
+        fn PortInfo::ffi_matcher(&self) -> String; alias matcher;
+        fn PortInfo::ffi_confirmed(&self) -> bool; alias confirmed;
+        fn PortInfo::ffi_kind(&self) -> String; alias kind;
+        fn PortInfo::ffi_list_ports() -> Vec<PortInfo>; alias list_ports;
+        fn PortInfo::ffi_probe(port_name: &str, timeout_ms: u32) -> bool; alias probe;
+    }
+);
+
+foreign_class!(
+    class DiscoveryConfig {
+        self_type DiscoveryConfig;
+        //the default usb-uart matchers
+        constructor DiscoveryConfig::default() -> DiscoveryConfig;
+        fn DiscoveryConfig::empty() -> DiscoveryConfig;
+        fn DiscoveryConfig::ffi_add_matcher(&mut self, name: &str, vid: i32, pid: i32, manufacturer: &str, product: &str, serial_number: &str, port_name: &str); alias add_matcher;
+        fn DiscoveryConfig::ffi_set_probe(&mut self, timeout_ms: u32); alias set_probe;
+        fn DiscoveryConfig::ffi_discover(&self) -> Vec<PortInfo>; alias discover;
+    }
+);
+
+//-------------------------------Device watcher-------------------------------------
+use swordfish_watcher::DeviceEvent as DeviceEvent;
+impl DeviceEvent {
+    pub fn ffi_port(&self) -> PortInfo {
+        self.port().clone()
+    }
+}
+
+foreign_class!(
+    class DeviceEvent {
+        self_type DeviceEvent;
+        private constructor = empty;
+        fn DeviceEvent::is_added(&self) -> bool;
+        fn DeviceEvent::ffi_port(&self) -> PortInfo; alias port;
+    }
+);
+
+//the events of a swordfish_watcher::DeviceWatcher, queued until they are polled
+pub struct DeviceEventQueue {
+    _watcher: swordfish_watcher::DeviceWatcher,
+    events: std::sync::Mutex<std::sync::mpsc::Receiver<DeviceEvent>>,
+}
+impl DeviceEventQueue {
+    pub fn new(config: &DiscoveryConfig) -> DeviceEventQueue {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let watcher = swordfish_watcher::DeviceWatcher::new(
+            config.clone(),
+            Box::new(move |event| {
+                let _ = sender.send(event.clone());
+            }),
+        );
+        DeviceEventQueue {
+            _watcher: watcher,
+            events: std::sync::Mutex::new(receiver),
         }
-        //specific getters
-        fn VersionDataMessage::get_version(&self) -> u8;
-        fn VersionDataMessage::get_subversion(&self) -> u8;
-        fn VersionDataMessage::get_mcu_type(&self) -> u32;
-        fn VersionDataMessage::get_uuid(&self) -> Vec<u8>;
-        //specific setters
-        fn VersionDataMessage::set_version(&mut self, version: u8);
-        fn VersionDataMessage::set_subversion(&mut self, subversion: u8);
-        fn VersionDataMessage::set_mcu_type(&mut self, mcu_type: u32);
-        fn VersionDataMessage::set_uuid(&mut self, uuid: &[u8]);
+    }
+    //the next event, empty when none arrives within timeout_ms
+    pub fn poll_event(&self, timeout_ms: u32) -> Option<DeviceEvent> {
+        self.events
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .recv_timeout(std::time::Duration::from_millis(timeout_ms as u64))
+            .ok()
+    }
+}
+
+foreign_class!(
+    class DeviceWatcher {
+        self_type DeviceEventQueue;
+        constructor DeviceEventQueue::new(config: &DiscoveryConfig) -> DeviceEventQueue;
+        fn DeviceEventQueue::poll_event(&self, timeout_ms: u32) -> Option<DeviceEvent>;
+    }
+);
+
+//-------------------------------Message Registry-----------------------------------
+use swordfish_registry::MessageRegistry as MessageRegistry;
+impl MessageRegistry {
+    //unknown opcodes give an empty name/category and a size of 0
+    pub fn ffi_name(&self, opcode: u8) -> String {
+        self.get(opcode).map(|info| info.name.clone()).unwrap_or_default()
+    }
+    pub fn ffi_category(&self, opcode: u8) -> String {
+        self.get(opcode).map(|info| info.category.name().to_string()).unwrap_or_default()
+    }
+    pub fn ffi_payload_size(&self, opcode: u8) -> usize {
+        self.get(opcode).map(|info| info.payload_size).unwrap_or(0)
+    }
+    //the full description of one message (response opcode, fields, ...) as json, empty for unknown opcodes
+    pub fn ffi_message_json(&self, opcode: u8) -> String {
+        match self.get(opcode) {
+            Some(info) => serde_json::to_string_pretty(info).expect("the registry only holds plain data, this should never fail"),
+            None => String::new(),
+        }
+    }
+    //the wireshark lua plugin for captures, see SwordFishComm::start_capture
+    pub fn ffi_wireshark_dissector(&self) -> String {
+        swordfish_capture::generate_dissector(self)
+    }
+    //like SwordFishConcentratedMessage::to_pretty_string, with the runtime messages of this registry
+    pub fn ffi_to_pretty_string(&self, msg: &SwordFishConcentratedMessage) -> String {
+        swordfish_format::FrameFormatter::new(self).format(msg)
+    }
+}
+
+foreign_class!(
+    class MessageRegistry {
+        self_type MessageRegistry;
+        constructor MessageRegistry::new() -> MessageRegistry;
+        fn MessageRegistry::opcodes(&self) -> Vec<u8>;
+        fn MessageRegistry::ffi_name(&self, opcode: u8) -> String; alias name;
+        fn MessageRegistry::ffi_category(&self, opcode: u8) -> String; alias category;
+        fn MessageRegistry::ffi_payload_size(&self, opcode: u8) -> usize; alias payload_size;
+        fn MessageRegistry::ffi_message_json(&self, opcode: u8) -> String; alias message_json;
+        fn MessageRegistry::to_json(&self) -> String;
+        fn MessageRegistry::ffi_wireshark_dissector(&self) -> String; alias wireshark_dissector;
+        fn MessageRegistry::ffi_to_pretty_string(&self, msg: &SwordFishConcentratedMessage) -> String; alias to_pretty_string;
     }
 );
 
+//the message classes (PingMessage, VersionDataMessage, ...) are generated from messages.toml by build.rs
@@ -5,7 +5,7 @@ use swordfish_concentrated_message::SwordFishConcentratedMessage as RustSwordFis
 #[pyfunction]
 fn get_serial_ports() -> String {
     let result = match swordfish_comm::get_serial_ports() {
-        Some(ports) => ports,
+        Some(ports) => swordfish_comm::serial_ports_text(&ports),
         None => "".to_string(),
     };
     result
@@ -30,32 +30,498 @@ impl SwordFishConcentratedMessage {
         ))
     }
     fn print(&self) {
-        println!("{:?}", self.0);
+        println!("{}", self.0);
+    }
+    //the name of the opcode, the counter, the checksum, the fields and a hexdump.
+    //the registry of SwordFishComm.registry() also names the runtime messages
+    #[pyo3(signature = (registry=None))]
+    fn to_pretty_string(&self, registry: Option<PyRef<'_, MessageRegistry>>) -> String {
+        match registry {
+            Some(registry) => swordfish_format::FrameFormatter::new(&registry.0).format(&self.0),
+            None => self.0.to_string(),
+        }
     }
 }
 
-use swordfish_messages::Ping as RustPingMessage;
+//the message classes (PingMessage, VersionDataMessage, ...) are generated from messages.toml by build.rs
+include!(concat!(env!("OUT_DIR"), "/swordfish_messages_python.rs"));
+
+use pyo3::exceptions::PyValueError;
+use swordfish_dynamic::DynamicMessage as RustDynamicMessage;
+use swordfish_dynamic::MessageDescriptor as RustMessageDescriptor;
+use swordfish_dynamic::{DynamicFieldType, DynamicValue, Endianness};
+
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
 #[pyclass]
-pub struct PingMessage(RustPingMessage);
+#[derive(Clone)]
+pub struct MessageDescriptor(RustMessageDescriptor);
 #[pymethods]
-impl PingMessage {
+impl MessageDescriptor {
+    //category is "bounce", "param", "operation", "response" or "stream"
     #[new]
-    fn new() -> Self {
-        PingMessage(RustPingMessage::default())
+    #[pyo3(signature = (name, opcode, category, response_opcode=None))]
+    fn new(name: &str, opcode: u8, category: &str, response_opcode: Option<u8>) -> PyResult<Self> {
+        let category = SwordFishMessageCategory::from_name(category)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown message category {}", category)))?;
+        let mut descriptor = RustMessageDescriptor::new(name, opcode, category);
+        if let Some(response_opcode) = response_opcode {
+            descriptor.set_response_opcode(response_opcode).map_err(to_py_err)?;
+        }
+        Ok(MessageDescriptor(descriptor))
     }
-    fn to_concentrated(&self, counter : u16) -> SwordFishConcentratedMessage {
-        let tmp : RustSwordFishConcentratedMessage = self.0.to_concentrated(counter);
-        SwordFishConcentratedMessage(tmp)
+    //field_type is spelled like in messages.toml, "u16", "f32", "[u8; 8]"
+    //without an offset the field is placed after the last one
+    #[pyo3(signature = (name, field_type, offset=None, big_endian=false))]
+    fn add_field(&mut self, name: &str, field_type: &str, offset: Option<usize>, big_endian: bool) -> PyResult<()> {
+        let field_type = DynamicFieldType::parse(field_type).map_err(to_py_err)?;
+        let endianness = if big_endian { Endianness::Big } else { Endianness::Little };
+        let offset = offset.unwrap_or_else(|| self.0.payload_length());
+        self.0.add_field(name, field_type, offset, endianness).map_err(to_py_err)
+    }
+    fn payload_length(&self) -> usize {
+        self.0.payload_length()
+    }
+}
+
+#[pyclass]
+pub struct DynamicMessage(RustDynamicMessage);
+#[pymethods]
+impl DynamicMessage {
+    #[new]
+    fn new(descriptor: &MessageDescriptor) -> Self {
+        DynamicMessage(RustDynamicMessage::new(std::sync::Arc::new(descriptor.0.clone())))
+    }
+    //ints, floats and bytes depending on the field type
+    fn get(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        let value = match self.0.get(name).map_err(to_py_err)? {
+            DynamicValue::Bytes(bytes) => pyo3::types::PyBytes::new_bound(py, &bytes).into_py(py),
+            DynamicValue::F32(_) | DynamicValue::F64(_) => self.0.get_float(name).map_err(to_py_err)?.into_py(py),
+            _ => self.0.get_integer(name).map_err(to_py_err)?.into_py(py),
+        };
+        Ok(value)
+    }
+    fn set(&mut self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let field_type = match self.0.descriptor().field(name) {
+            Some(field) => field.field_type,
+            None => return Err(PyValueError::new_err(format!("Unknown field {}", name))),
+        };
+        let result = match field_type {
+            DynamicFieldType::Bytes(_) => self.0.set(name, DynamicValue::Bytes(value.extract::<Vec<u8>>()?)),
+            DynamicFieldType::F32 | DynamicFieldType::F64 => self.0.set_float(name, value.extract::<f64>()?),
+            _ => self.0.set_integer(name, value.extract::<i64>()?),
+        };
+        result.map_err(to_py_err)
+    }
+    fn to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage {
+        SwordFishConcentratedMessage(self.0.to_concentrated(counter))
     }
     #[staticmethod]
-    fn from_concentrated(msg: &SwordFishConcentratedMessage) -> Option<PingMessage> {
-        match RustPingMessage::from_concentrated(&msg.0) {
-            Ok(ping_message) => Some(PingMessage(ping_message)),
+    fn from_concentrated(descriptor: &MessageDescriptor, concenrated_msg: &SwordFishConcentratedMessage) -> Option<DynamicMessage> {
+        match RustDynamicMessage::from_concentrated(std::sync::Arc::new(descriptor.0.clone()), &concenrated_msg.0) {
+            Ok(msg) => Some(DynamicMessage(msg)),
             Err(_) => None,
         }
     }
     fn print(&self) {
-        self.0.print();
+        println!("{:?}", self.0);
+    }
+}
+
+use swordfish_registry::FieldLayout as RustFieldLayout;
+use swordfish_registry::MessageInfo as RustMessageInfo;
+use swordfish_registry::MessageRegistry as RustMessageRegistry;
+
+#[pyclass]
+#[derive(Clone)]
+pub struct FieldLayout(RustFieldLayout);
+#[pymethods]
+impl FieldLayout {
+    #[getter]
+    fn name(&self) -> String {
+        self.0.name.clone()
+    }
+    #[getter]
+    fn type_name(&self) -> String {
+        self.0.type_name.clone()
+    }
+    #[getter]
+    fn offset(&self) -> Option<usize> {
+        self.0.offset
+    }
+    #[getter]
+    fn size(&self) -> usize {
+        self.0.size
+    }
+    #[getter]
+    fn variable_length(&self) -> bool {
+        self.0.variable_length
+    }
+    #[getter]
+    fn big_endian(&self) -> bool {
+        self.0.big_endian
+    }
+    #[getter]
+    fn since(&self) -> u8 {
+        self.0.since
+    }
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct MessageInfo(RustMessageInfo);
+#[pymethods]
+impl MessageInfo {
+    #[getter]
+    fn name(&self) -> String {
+        self.0.name.clone()
+    }
+    #[getter]
+    fn opcode(&self) -> u8 {
+        self.0.opcode
+    }
+    #[getter]
+    fn category(&self) -> &'static str {
+        self.0.category.name()
+    }
+    #[getter]
+    fn response_opcode(&self) -> Option<u8> {
+        self.0.response_opcode()
+    }
+    #[getter]
+    fn payload_size(&self) -> usize {
+        self.0.payload_size
+    }
+    #[getter]
+    fn version(&self) -> u8 {
+        self.0.version
+    }
+    #[getter]
+    fn fields(&self) -> Vec<FieldLayout> {
+        self.0.fields.iter().cloned().map(FieldLayout).collect()
+    }
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[pyclass]
+pub struct MessageRegistry(RustMessageRegistry);
+#[pymethods]
+impl MessageRegistry {
+    //the messages compiled into the library, SwordFishComm.registry() also lists the registered runtime messages
+    #[new]
+    fn new() -> Self {
+        MessageRegistry(RustMessageRegistry::new())
+    }
+    fn opcodes(&self) -> Vec<u8> {
+        self.0.opcodes()
+    }
+    fn get(&self, opcode: u8) -> Option<MessageInfo> {
+        self.0.get(opcode).cloned().map(MessageInfo)
+    }
+    fn find(&self, name: &str) -> Option<MessageInfo> {
+        self.0.find(name).cloned().map(MessageInfo)
+    }
+    fn to_json(&self) -> String {
+        self.0.to_json()
+    }
+    //the wireshark lua plugin for captures, see SwordFishComm.start_capture
+    fn wireshark_dissector(&self) -> String {
+        swordfish_capture::generate_dissector(&self.0)
+    }
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<MessageRegistry> {
+        RustMessageRegistry::from_json(json).map(MessageRegistry).map_err(to_py_err)
+    }
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+}
+
+use swordfish_device_log::DeviceLogEntry as RustDeviceLogEntry;
+
+#[pyclass]
+pub struct DeviceLogEntry(RustDeviceLogEntry);
+#[pymethods]
+impl DeviceLogEntry {
+    #[getter]
+    fn level(&self) -> String {
+        self.0.level.to_string()
+    }
+    #[getter]
+    fn module_id(&self) -> u16 {
+        self.0.module_id
+    }
+    //ms since the device booted
+    #[getter]
+    fn timestamp(&self) -> u32 {
+        self.0.timestamp
+    }
+    #[getter]
+    fn text(&self) -> String {
+        self.0.text.clone()
+    }
+    //host time, seconds since the unix epoch
+    #[getter]
+    fn received(&self) -> f64 {
+        self.0
+            .received
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+use swordfish_error::SwordFishError;
+use swordfish_operation::OperationError;
+
+//raised when the device answers with a Nack, args are (text, opcode, counter, code, detail)
+pyo3::create_exception!(swordfish_com, DeviceRejectedError, pyo3::exceptions::PyException);
+
+fn swordfish_error_to_py(e: SwordFishError) -> PyErr {
+    match &e {
+        SwordFishError::DeviceRejected { opcode, counter, code, detail } => {
+            DeviceRejectedError::new_err((e.to_string(), *opcode, *counter, *code, detail.clone()))
+        }
+        SwordFishError::Timeout { .. } => pyo3::exceptions::PyTimeoutError::new_err(e.to_string()),
+        SwordFishError::NoAnswerExpected { .. } | SwordFishError::UnknownOpcode { .. } => PyValueError::new_err(e.to_string()),
+    }
+}
+
+#[pyfunction]
+fn describe_error_code(code: u16) -> String {
+    swordfish_error::describe_error_code(code)
+}
+use swordfish_operation::OperationHandle as RustOperationHandle;
+use swordfish_operation::OperationOutcome;
+
+fn outcome_to_py(outcome: OperationOutcome) -> PyResult<OperationResultMessage> {
+    match outcome {
+        Ok(result) => Ok(OperationResultMessage(result)),
+        Err(OperationError::Timeout) => Err(pyo3::exceptions::PyTimeoutError::new_err("The operation timed out")),
+        Err(OperationError::Rejected(e)) => Err(swordfish_error_to_py(e)),
+        Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(e.to_string())),
+    }
+}
+
+#[pyclass]
+pub struct OperationHandle(RustOperationHandle);
+#[pymethods]
+impl OperationHandle {
+    fn opcode(&self) -> u8 {
+        self.0.opcode()
+    }
+    fn progress(&self) -> Vec<OperationProgressMessage> {
+        self.0.progress().into_iter().map(OperationProgressMessage).collect()
+    }
+    fn latest_progress(&self) -> Option<OperationProgressMessage> {
+        self.0.latest_progress().map(OperationProgressMessage)
+    }
+    fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+    fn set_timeout(&self, timeout_ms: u64) {
+        self.0.set_timeout(std::time::Duration::from_millis(timeout_ms))
+    }
+    //the OperationResultMessage, raises TimeoutError or RuntimeError (failed or cancelled)
+    fn wait(&self, py: Python<'_>) -> PyResult<OperationResultMessage> {
+        outcome_to_py(py.allow_threads(|| self.0.wait()))
+    }
+    fn cancel(&self) -> bool {
+        self.0.cancel()
+    }
+    //callback(OperationProgressMessage), called from the read thread
+    fn on_progress(&self, callback: PyObject) {
+        self.0.on_progress(Box::new(move |progress| {
+            Python::with_gil(|py| {
+                if let Err(e) = callback.call1(py, (OperationProgressMessage(progress.clone()),)) {
+                    e.print(py);
+                }
+            })
+        }))
+    }
+    //callback(result, error), error is None or the message of the failure
+    fn on_complete(&self, callback: PyObject) {
+        self.0.on_complete(Box::new(move |outcome| {
+            Python::with_gil(|py| {
+                let args = match outcome {
+                    Ok(result) => (Some(OperationResultMessage(result.clone())), None),
+                    Err(e) => (None, Some(e.to_string())),
+                };
+                if let Err(e) = callback.call1(py, args) {
+                    e.print(py);
+                }
+            })
+        }))
+    }
+}
+
+use swordfish_discovery::DeviceMatcher as RustDeviceMatcher;
+use swordfish_discovery::DiscoveryConfig;
+use swordfish_discovery::PortInfo as RustPortInfo;
+
+#[pyclass]
+pub struct PortInfo(RustPortInfo);
+#[pymethods]
+impl PortInfo {
+    #[getter]
+    fn port_name(&self) -> String {
+        self.0.port_name.clone()
+    }
+    //"usb", "bluetooth", "pci" or "unknown"
+    #[getter]
+    fn kind(&self) -> String {
+        format!("{:?}", self.0.kind).to_lowercase()
+    }
+    #[getter]
+    fn vid(&self) -> Option<u16> {
+        self.0.vid
+    }
+    #[getter]
+    fn pid(&self) -> Option<u16> {
+        self.0.pid
+    }
+    #[getter]
+    fn serial_number(&self) -> Option<String> {
+        self.0.serial_number.clone()
+    }
+    #[getter]
+    fn manufacturer(&self) -> Option<String> {
+        self.0.manufacturer.clone()
+    }
+    #[getter]
+    fn product(&self) -> Option<String> {
+        self.0.product.clone()
+    }
+    #[getter]
+    fn matcher(&self) -> Option<String> {
+        self.0.matcher.clone()
+    }
+    #[getter]
+    fn confirmed(&self) -> bool {
+        self.0.confirmed
+    }
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+//the string fields are globs with * and ?
+#[pyclass]
+#[derive(Clone)]
+pub struct DeviceMatcher(RustDeviceMatcher);
+#[pymethods]
+impl DeviceMatcher {
+    #[new]
+    #[pyo3(signature = (name, vid=None, pid=None, manufacturer=None, product=None, serial_number=None, port_name=None))]
+    fn new(
+        name: &str,
+        vid: Option<u16>,
+        pid: Option<u16>,
+        manufacturer: Option<String>,
+        product: Option<String>,
+        serial_number: Option<String>,
+        port_name: Option<String>,
+    ) -> Self {
+        DeviceMatcher(RustDeviceMatcher {
+            name: name.to_string(),
+            vid,
+            pid,
+            manufacturer,
+            product,
+            serial_number,
+            port_name,
+        })
+    }
+    fn matches(&self, port: &PortInfo) -> bool {
+        self.0.matches(&port.0)
+    }
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+//the matching ports, with the default usb-uart matchers when matchers is None.
+//probe_ms sends a Ping and keeps the ports that answer
+#[pyfunction]
+#[pyo3(signature = (matchers=None, probe_ms=None))]
+fn discover(py: Python<'_>, matchers: Option<Vec<DeviceMatcher>>, probe_ms: Option<u64>) -> Vec<PortInfo> {
+    let mut config = match matchers {
+        Some(matchers) => DiscoveryConfig {
+            matchers: matchers.into_iter().map(|matcher| matcher.0).collect(),
+            probe: None,
+        },
+        None => DiscoveryConfig::default(),
+    };
+    config.probe = probe_ms.map(std::time::Duration::from_millis);
+    py.allow_threads(|| swordfish_discovery::discover_with(&config))
+        .into_iter()
+        .map(PortInfo)
+        .collect()
+}
+#[pyfunction]
+fn list_ports() -> Vec<PortInfo> {
+    swordfish_discovery::list_ports().into_iter().map(PortInfo).collect()
+}
+#[pyfunction]
+#[pyo3(signature = (port_name, timeout_ms=100))]
+fn probe(py: Python<'_>, port_name: &str, timeout_ms: u64) -> bool {
+    py.allow_threads(|| swordfish_discovery::probe(port_name, std::time::Duration::from_millis(timeout_ms)))
+}
+
+use swordfish_watcher::DeviceWatcher as RustDeviceWatcher;
+
+//calls callback(event, port) from its own thread, event is "added" or "removed" and port a PortInfo.
+//matchers default to the usb-uart bridges of swordfish boards, like discover
+#[pyclass]
+pub struct DeviceWatcher(Option<RustDeviceWatcher>);
+#[pymethods]
+impl DeviceWatcher {
+    #[new]
+    #[pyo3(signature = (callback, matchers=None))]
+    fn new(callback: PyObject, matchers: Option<Vec<DeviceMatcher>>) -> Self {
+        let config = match matchers {
+            Some(matchers) => DiscoveryConfig {
+                matchers: matchers.into_iter().map(|matcher| matcher.0).collect(),
+                probe: None,
+            },
+            None => DiscoveryConfig::default(),
+        };
+        let watcher = RustDeviceWatcher::new(
+            config,
+            Box::new(move |event| {
+                Python::with_gil(|py| {
+                    let name = if event.is_added() { "added" } else { "removed" };
+                    if let Err(e) = callback.call1(py, (name, PortInfo(event.port().clone()))) {
+                        e.print(py);
+                    }
+                })
+            }),
+        );
+        DeviceWatcher(Some(watcher))
+    }
+    fn stop(&mut self, py: Python<'_>) {
+        if let Some(mut watcher) = self.0.take() {
+            py.allow_threads(|| watcher.stop());
+        }
+    }
+}
+
+//the watcher thread may be waiting for the gil to run the callback, so it is joined without holding it
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        if let Some(mut watcher) = self.0.take() {
+            Python::with_gil(|py| py.allow_threads(|| watcher.stop()));
+        }
     }
 }
 
@@ -68,26 +534,187 @@ impl SwordFishComm {
     fn new(port_name: &str) -> Self {
         SwordFishComm(RustSwordFishComm::new(port_name).expect("Failed to create SwordFishComm"))
     }
+    //a comm that plays a session recorded with start_recording back instead of talking to a board,
+    //with sync_on_tx the answers wait for their request to be sent again
+    #[staticmethod]
+    #[pyo3(signature = (path, speed=1.0, sync_on_tx=false))]
+    fn replay(path: &str, speed: f64, sync_on_tx: bool) -> PyResult<Self> {
+        let session = swordfish_session::Session::load(std::path::Path::new(path)).map_err(to_py_err)?;
+        let replay = swordfish_session::ReplayTransport::new(session)
+            .speed(speed)
+            .map_err(to_py_err)?
+            .sync_on_tx(sync_on_tx);
+        Ok(SwordFishComm(RustSwordFishComm::from_transport(Box::new(replay))))
+    }
     fn send_msg(&self, msg: &SwordFishConcentratedMessage) -> Option<SwordFishConcentratedMessage> {
         match self.0.send_msg(msg.0) {
             Some(msg) => Some(SwordFishConcentratedMessage(msg)),
             None => None,
         }
     }
+    //the answer, raises DeviceRejectedError when the device sends a Nack and TimeoutError without an answer
+    #[pyo3(signature = (msg, timeout_ms=200))]
+    fn request(&self, py: Python<'_>, msg: &SwordFishConcentratedMessage, timeout_ms: u64) -> PyResult<SwordFishConcentratedMessage> {
+        py.allow_threads(|| self.0.request_with_timeout(msg.0, std::time::Duration::from_millis(timeout_ms)))
+            .map(SwordFishConcentratedMessage)
+            .map_err(swordfish_error_to_py)
+    }
     fn get_tx_counter(&self) -> usize {
         self.0.get_tx_counter()
     }
+    fn next_tx_counter(&self) -> u16 {
+        self.0.next_tx_counter()
+    }
     fn get_rx_counter(&self) -> usize {
         self.0.get_rx_counter()
     }
+    fn register_message(&self, descriptor: &MessageDescriptor) -> PyResult<()> {
+        self.0.register_message(&descriptor.0).map_err(to_py_err)
+    }
+    fn registry(&self) -> MessageRegistry {
+        MessageRegistry(self.0.registry())
+    }
+    #[pyo3(signature = (max=256))]
+    fn recent_device_logs(&self, max: usize) -> Vec<DeviceLogEntry> {
+        self.0.device_log().recent(max).into_iter().map(DeviceLogEntry).collect()
+    }
+    fn clear_device_logs(&self) {
+        self.0.device_log().clear()
+    }
+    //None forwards every device log to the log crate
+    fn set_device_log_rate_limit(&self, max_per_second: Option<u32>) {
+        self.0.device_log().set_rate_limit(max_per_second)
+    }
+    fn set_device_log_module_name(&self, module_id: u16, name: &str) {
+        self.0.device_log().set_module_name(module_id, name)
+    }
+    //a TimeSync round trip every period_ms, 0 stops them
+    fn set_clock_sync_period(&self, period_ms: u64) {
+        let period = if period_ms == 0 { None } else { Some(std::time::Duration::from_millis(period_ms)) };
+        self.0.clock_sync().set_period(period)
+    }
+    //(offset_us, drift_ppm, delay_us) of the device clock, None before the first time sync
+    fn clock_estimate(&self) -> Option<(f64, f64, u64)> {
+        self.0
+            .clock_sync()
+            .estimate()
+            .map(|estimate| (estimate.offset_us, estimate.drift_ppm, estimate.delay_us))
+    }
+    //seconds since the unix epoch, like time.time()
+    fn device_to_host_time(&self, device_us: u64) -> Option<f64> {
+        self.0
+            .device_to_host_time(device_us)
+            .and_then(|host| host.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs_f64())
+    }
+    #[pyo3(signature = (msg, timeout_ms=2000))]
+    fn start_operation(&self, msg: &SwordFishConcentratedMessage, timeout_ms: u64) -> PyResult<OperationHandle> {
+        self.0
+            .start_operation(msg.0, std::time::Duration::from_millis(timeout_ms))
+            .map(OperationHandle)
+            .map_err(to_py_err)
+    }
+    fn running_operations(&self) -> Vec<u8> {
+        self.0.running_operations()
+    }
+    fn start_stream(&self, opcode: u8, rate_hz: u16) -> PyResult<()> {
+        self.0.start_stream(opcode, rate_hz).map_err(to_py_err)
+    }
+    fn stop_stream(&self, opcode: u8) -> PyResult<()> {
+        self.0.stop_stream(opcode).map_err(to_py_err)
+    }
+    //the payloads of at most max buffered messages back to back, each payload_size bytes
+    #[pyo3(signature = (opcode, max=1024))]
+    fn drain_bytes<'py>(&self, py: Python<'py>, opcode: u8, max: usize) -> PyResult<Bound<'py, pyo3::types::PyBytes>> {
+        let stream = self
+            .0
+            .stream_buffer(opcode)
+            .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("Opcode {} is not a stream", opcode)))?;
+        let stride = self.0.registry().get(opcode).map_or(0, |info| info.payload_size);
+        //drained straight into the bytes object, copied again only if another reader took some records meanwhile
+        let size = std::cmp::min(max, stream.len()) * stride;
+        let mut n = 0;
+        let bytes = pyo3::types::PyBytes::new_bound_with(py, size, |out| {
+            n = stream.drain_payloads(max, stride, out);
+            Ok(())
+        })?;
+        if n * stride < size {
+            return Ok(pyo3::types::PyBytes::new_bound(py, &bytes.as_bytes()[..n * stride]));
+        }
+        Ok(bytes)
+    }
+    //drain_bytes viewed as a numpy array, dtype is usually a structured dtype matching the message fields
+    #[pyo3(signature = (opcode, dtype, max=1024))]
+    fn drain_array(&self, py: Python<'_>, opcode: u8, dtype: &Bound<'_, PyAny>, max: usize) -> PyResult<PyObject> {
+        let bytes = self.drain_bytes(py, opcode, max)?;
+        let numpy = py.import_bound("numpy")?;
+        Ok(numpy.call_method1("frombuffer", (bytes, dtype))?.unbind())
+    }
+    //(received, overflowed, buffered, capacity)
+    fn stream_stats(&self, opcode: u8) -> Option<(u64, u64, usize, usize)> {
+        self.0.stream_buffer(opcode).map(|stream| {
+            let stats = stream.stats();
+            (stats.received, stats.overflowed, stats.buffered, stats.capacity)
+        })
+    }
+    fn set_stream_capacity(&self, opcode: u8, capacity: usize) -> PyResult<()> {
+        self.0
+            .set_stream_capacity(opcode, capacity)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+    //the frames, bytes and failures of the link since the comm was created
+    fn link_stats(&self) -> std::collections::HashMap<&'static str, u64> {
+        let stats = self.0.link_stats();
+        std::collections::HashMap::from([
+            ("tx_frames", stats.tx_frames),
+            ("rx_frames", stats.rx_frames),
+            ("tx_bytes", stats.tx_bytes),
+            ("rx_bytes", stats.rx_bytes),
+            ("bad_frames", stats.bad_frames),
+            ("write_errors", stats.write_errors),
+            ("timeouts", stats.timeouts),
+            ("rejected", stats.rejected),
+        ])
+    }
+    //records every frame sent and received to a pcapng file until stop_capture
+    fn start_capture(&self, path: &str) -> PyResult<()> {
+        self.0.start_capture(std::path::Path::new(path)).map_err(to_py_err)
+    }
+    //returns the number of frames captured
+    fn stop_capture(&self) -> PyResult<u64> {
+        self.0.stop_capture().map_err(to_py_err)
+    }
+    //records the raw bytes sent and received with their timing until stop_recording, see replay
+    fn start_recording(&self, path: &str) -> PyResult<()> {
+        self.0.start_recording(std::path::Path::new(path)).map_err(to_py_err)
+    }
+    //returns the number of chunks recorded
+    fn stop_recording(&self) -> PyResult<u64> {
+        self.0.stop_recording().map_err(to_py_err)
+    }
 }
 
 #[pymodule]
 fn swordfish_com(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(get_serial_ports, m)?)?;
     m.add_function(wrap_pyfunction!(find_probable_swordfish_port, m)?)?;
+    m.add_function(wrap_pyfunction!(describe_error_code, m)?)?;
+    m.add_function(wrap_pyfunction!(discover, m)?)?;
+    m.add_function(wrap_pyfunction!(list_ports, m)?)?;
+    m.add_function(wrap_pyfunction!(probe, m)?)?;
+    m.add_class::<PortInfo>()?;
+    m.add_class::<DeviceMatcher>()?;
+    m.add_class::<DeviceWatcher>()?;
+    m.add("DeviceRejectedError", m.py().get_type_bound::<DeviceRejectedError>())?;
     m.add_class::<SwordFishComm>()?;
     m.add_class::<SwordFishConcentratedMessage>()?;
-    m.add_class::<PingMessage>()?;
+    m.add_class::<MessageDescriptor>()?;
+    m.add_class::<DynamicMessage>()?;
+    m.add_class::<FieldLayout>()?;
+    m.add_class::<MessageInfo>()?;
+    m.add_class::<MessageRegistry>()?;
+    m.add_class::<DeviceLogEntry>()?;
+    m.add_class::<OperationHandle>()?;
+    add_message_classes(m)?;
     Ok(())
 }
@@ -1,9 +1,42 @@
+#[cfg(feature = "bridge")]
+pub mod swordfish_bridge;
+pub mod swordfish_capture;
+pub mod swordfish_clock_sync;
 pub mod swordfish_comm;
 mod swordfish_concentrated_message;
+#[cfg(unix)]
+pub mod swordfish_daemon;
+pub mod swordfish_device_log;
+pub mod swordfish_dfu;
+pub mod swordfish_discovery;
+pub mod swordfish_dynamic;
+pub mod swordfish_error;
+pub mod swordfish_format;
 pub mod swordfish_messages;
+pub mod swordfish_operation;
+pub mod swordfish_params;
+#[cfg(target_os = "linux")]
+pub mod swordfish_pty;
+pub mod swordfish_registry;
+pub mod swordfish_session;
+#[cfg(feature = "simulator")]
+pub mod swordfish_simulator;
+pub mod swordfish_splitter;
+pub mod swordfish_stream;
+pub mod swordfish_transport;
+pub mod swordfish_util;
+pub mod swordfish_watcher;
+pub mod swordfish_wire;
 pub use swordfish_concentrated_message::SwordFishConcentratedMessage;
 pub use swordfish_concentrated_message::TOTAL_MESSAGE_SIZE as CONCENTRATED_MESSAGE_TOTAL_SIZE;
+pub use swordfish_concentrated_message::MAX_PAYLOAD_SIZE;
+pub use swordfish_wire::{BoundedString, BoundedVec};
+pub use swordfish_registry::{FieldLayout, FieldType, FieldValue, MessageInfo, MessageRegistry};
 mod ffi;
+pub use swordfish_derive::SwordFishMessage;
+
+//lets the code generated by the derive macro refer to ::swordfish_com from inside this crate as well
+extern crate self as swordfish_com;
 
 //---------------------Buckets and Catagories---------------------
 use std::sync::{Condvar, Mutex};
@@ -14,6 +47,36 @@ pub enum SwordFishMessageCategory {
     Param,                 //message is sent to swordfish to set/get parameters
     Operation(Option<u8>), //message is sent to swordfish to perform an operation, the u8 is the opcode of the response msg
     Response,              //message that is sent from swordfish as a response to an operation,
+    Stream,                //message that swordfish pushes at a high rate, buffered until it is drained
+}
+
+impl SwordFishMessageCategory {
+    //the names used by the derive macro and messages.toml
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bounce" => Some(SwordFishMessageCategory::Bounce),
+            "param" => Some(SwordFishMessageCategory::Param),
+            "operation" => Some(SwordFishMessageCategory::Operation(None)),
+            "response" => Some(SwordFishMessageCategory::Response),
+            "stream" => Some(SwordFishMessageCategory::Stream),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SwordFishMessageCategory::Bounce => "bounce",
+            SwordFishMessageCategory::Param => "param",
+            SwordFishMessageCategory::Operation(_) => "operation",
+            SwordFishMessageCategory::Response => "response",
+            SwordFishMessageCategory::Stream => "stream",
+        }
+    }
+
+    //the device answers with the counter of the request, so an answer to an earlier request can be told apart
+    pub fn echoes_counter(&self) -> bool {
+        matches!(self, SwordFishMessageCategory::Bounce | SwordFishMessageCategory::Param)
+    }
 }
 
 pub struct SwordFishMessageBucket {
@@ -21,6 +84,26 @@ pub struct SwordFishMessageBucket {
     pub on_rx_callback: Mutex<Option<Box<dyn FnMut(SwordFishConcentratedMessage) + Send>>>,
     pub catagory: SwordFishMessageCategory,
     pub condvar: Condvar,
+    pub waiting_counter: Mutex<Option<u16>>, //counter of the request waiting for the message, locked after message
+    pub stream: Option<std::sync::Arc<swordfish_stream::StreamBuffer>>, //only for the Stream category
+}
+
+//every message that derives SwordFishMessage submits one of these, see create_swordfish_messages_hashmap
+pub struct SwordFishMessageRegistration {
+    pub name: &'static str,
+    pub opcode: u8,
+    pub category: SwordFishMessageCategory,
+    pub version: u8,
+    pub fields: &'static [SwordFishFieldInfo],
+    pub payload_length: fn() -> usize,
+}
+inventory::collect!(SwordFishMessageRegistration);
+
+#[doc(hidden)]
+pub mod __private {
+    //used by the SwordFishMessage derive macro, not part of the public api
+    pub use anyhow;
+    pub use inventory;
 }
 
 impl SwordFishMessageBucket {
@@ -30,22 +113,39 @@ impl SwordFishMessageBucket {
             on_rx_callback: Mutex::new(None),
             catagory,
             condvar: Condvar::new(),
+            waiting_counter: Mutex::new(None),
+            stream: match catagory {
+                SwordFishMessageCategory::Stream => Some(std::sync::Arc::new(swordfish_stream::StreamBuffer::default())),
+                _ => None,
+            },
         }
     }
 }
 
 //---------------------SwordFishMessageTrait---------------------
-use crate::swordfish_concentrated_message::MAX_PAYLOAD_SIZE;
 use anyhow::{anyhow, Result};
 use inline_colorization::{color_red, color_reset};
 use std::mem::{self, MaybeUninit};
 
+//describes one field of a message, provided by the derive macro
+#[derive(Debug, Clone, Copy)]
+pub struct SwordFishFieldInfo {
+    pub name: &'static str,
+    pub field_type: fn() -> swordfish_registry::FieldType,
+    pub max_size: usize,       //bytes on the wire, at most for variable length fields
+    pub variable_length: bool, //strings and vectors with a length prefix
+    pub since: u8, //message version that added the field, older devices do not send it
+}
+
 pub trait SwordFishMessageTrait
 where
     Self: Sized + Default + std::fmt::Debug,
 {
     const OPCODE: u8;
     const CATEGORY: SwordFishMessageCategory;
+    //the newest `since` of the message fields
+    const VERSION: u8 = 1;
+    const FIELDS: &'static [SwordFishFieldInfo] = &[];
 
     fn print(&self) {
         println!("  Opcode: {}", Self::OPCODE);
@@ -53,17 +153,41 @@ where
         println!("      {:?}", self);
     }
 
+    //for derived messages this is the largest payload the message can have
     fn get_payload_length() -> usize {
         return std::mem::size_of::<Self>();
     }
 
+    //by default the payload is the memory of the (packed) struct,
+    //the derive macro overrides these with a field-wise encoding, see swordfish_wire
+    fn encode_payload(&self) -> Vec<u8> {
+        let length = std::mem::size_of::<Self>();
+        let payload =
+            unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, length) };
+        payload.to_vec()
+    }
+
+    fn decode_payload(payload: &[u8]) -> Result<Self> {
+        if payload.len() != std::mem::size_of::<Self>() {
+            return Err(anyhow!(
+                "Wrong length, expected {}, got {}",
+                std::mem::size_of::<Self>(),
+                payload.len()
+            ));
+        }
+        let mut uninit = MaybeUninit::<Self>::uninit();
+        let ptr = uninit.as_mut_ptr() as *mut u8;
+        unsafe {
+            ptr.copy_from_nonoverlapping(payload.as_ptr(), mem::size_of::<Self>());
+            Ok(uninit.assume_init())
+        }
+    }
+
     fn to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage {
-        let length = Self::get_payload_length();
-        if length > MAX_PAYLOAD_SIZE {
+        let payload = self.encode_payload();
+        if payload.len() > MAX_PAYLOAD_SIZE {
             panic!("{color_red}Payload too large, this should never happen{color_reset}");
         }
-        let payload =
-            unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, length) };
         SwordFishConcentratedMessage::new(counter, Self::OPCODE, &payload)
     }
     fn from_concentrated(concenrated_msg: &SwordFishConcentratedMessage) -> Result<Self> {
@@ -73,22 +197,14 @@ where
                 Self::OPCODE,
                 concenrated_msg.opcode
             ));
-        } else if concenrated_msg.length as usize != std::mem::size_of::<Self>() {
+        } else if concenrated_msg.length as usize > MAX_PAYLOAD_SIZE {
             return Err(anyhow!(
-                "Wrong length, expected {}, got {}",
-                std::mem::size_of::<Self>(),
+                "Wrong length, maximum is {}, got {}",
+                MAX_PAYLOAD_SIZE,
                 concenrated_msg.length
             ));
         } else {
-            let mut uninit = MaybeUninit::<Self>::uninit();
-            let ptr = uninit.as_mut_ptr() as *mut u8;
-            unsafe {
-                ptr.copy_from_nonoverlapping(
-                    concenrated_msg.payload.as_ptr(),
-                    mem::size_of::<Self>(),
-                );
-                Ok(uninit.assume_init())
-            }
+            Self::decode_payload(&concenrated_msg.payload[..concenrated_msg.length as usize])
         }
     }
 }
@@ -0,0 +1,640 @@
+//a board in the lab rack used from another machine. the bridge owns the serial port and serves it over tcp:
+//  let bridge = SwordFishBridge::spawn(TcpListener::bind("0.0.0.0:5757")?, Box::new(port), Some(token), "/dev/ttyUSB0")?;
+//and the remote side opens it like a port, with the same api, callbacks and wrappers:
+//  let comm = SwordFishComm::new("tcp://token@lab-pc:5757")?;
+//a session starts with a handshake of text lines, where both sides prove they know the token:
+//  bridge: swordfish-bridge <version> <nonce>               the nonces are 32 random bytes in hex
+//  client: hello <version> <nonce> <proof>                  hmac-sha256(token, "client" nonces)
+//  bridge: ok <proof> <device>  or  error <reason>          hmac-sha256(token, "server" nonces), the bridge closes on error
+//then both directions carry records keyed by hmac-sha256(token, "session" nonces), so the token never crosses the network:
+//  <length u16 le> <bytes> <tag>                            the first 16 bytes of the hmac of the direction, the number
+//                                                           of the record in it and the bytes
+//a record that was changed, dropped, replayed or sent back ends the session. the bytes are not encrypted.
+//without a token the keys come from an empty one, which only guards against mistakes, not against an attacker.
+//the bridge serves one session at a time, the frames of several clients would mix on the port
+//only frames with a good checksum are forwarded, a client cannot put garbage on the port
+use crate::swordfish_splitter::{FrameSplitter, Segment};
+use crate::swordfish_transport::SwordFishTransport;
+use crate::swordfish_util::{from_hex, read_line, to_hex};
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+
+pub const BRIDGE_PROTOCOL_VERSION: u32 = 2;
+pub const DEFAULT_BRIDGE_PORT: u16 = 5757;
+//the environment variable the token is taken from when a tcp:// port has none
+pub const BRIDGE_TOKEN_VARIABLE: &str = "SWORDFISH_BRIDGE_TOKEN";
+
+const GREETING: &str = "swordfish-bridge";
+const NONCE_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+const MAX_RECORD_SIZE: usize = 4096;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+//connections that did not finish their handshake, more are closed right away
+const MAX_PENDING_HANDSHAKES: usize = 8;
+//frames of the device waiting for a slow client, more are dropped
+const CLIENT_QUEUE_SIZE: usize = 256;
+//how often the threads of the bridge look if they should stop
+const POLL_PERIOD: Duration = Duration::from_millis(20);
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn keyed(key: &[u8], label: &[u8], server_nonce: &[u8], client_nonce: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac takes keys of any size");
+    mac.update(label);
+    mac.update(server_nonce);
+    mac.update(client_nonce);
+    mac
+}
+
+fn proof(token: &str, label: &[u8], server_nonce: &[u8], client_nonce: &[u8]) -> Vec<u8> {
+    keyed(token.as_bytes(), label, server_nonce, client_nonce).finalize().into_bytes().to_vec()
+}
+
+fn verify(token: &str, label: &[u8], server_nonce: &[u8], client_nonce: &[u8], proof: &[u8]) -> bool {
+    keyed(token.as_bytes(), label, server_nonce, client_nonce).verify_slice(proof).is_ok()
+}
+
+fn random_nonce() -> Result<[u8; NONCE_SIZE]> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    getrandom::getrandom(&mut nonce).map_err(|e| anyhow!("No random nonce: {}", e))?;
+    Ok(nonce)
+}
+
+//---------------------Records---------------------
+//one direction of a session, the records are numbered so none can be dropped, replayed or reordered
+struct RecordStream {
+    key: [u8; 32],
+    direction: &'static [u8],
+    sequence: u64,
+}
+
+impl RecordStream {
+    //the bridge to the client and the client to the bridge
+    fn session(token: &str, server_nonce: &[u8], client_nonce: &[u8]) -> (RecordStream, RecordStream) {
+        let key: [u8; 32] = keyed(token.as_bytes(), b"session", server_nonce, client_nonce).finalize().into_bytes().into();
+        let stream = |direction| RecordStream { key, direction, sequence: 0 };
+        (stream(b"to client"), stream(b"to bridge"))
+    }
+
+    fn mac(&self, bytes: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac takes keys of any size");
+        mac.update(self.direction);
+        mac.update(&self.sequence.to_le_bytes());
+        mac.update(bytes);
+        mac
+    }
+
+    //at most MAX_RECORD_SIZE bytes
+    fn seal(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let tag = self.mac(bytes).finalize().into_bytes();
+        self.sequence += 1;
+        let mut record = Vec::with_capacity(2 + bytes.len() + TAG_SIZE);
+        record.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        record.extend_from_slice(bytes);
+        record.extend_from_slice(&tag[..TAG_SIZE]);
+        record
+    }
+
+    //takes the first record off the buffer, None while it is not complete
+    fn open(&mut self, buffer: &mut Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+        let length = u16::from_le_bytes([buffer[0], buffer[1]]) as usize;
+        if length > MAX_RECORD_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The record is too long"));
+        }
+        if buffer.len() < 2 + length + TAG_SIZE {
+            return Ok(None);
+        }
+        let record: Vec<u8> = buffer.drain(..2 + length + TAG_SIZE).collect();
+        let (bytes, tag) = record[2..].split_at(length);
+        if self.mac(bytes).verify_truncated_left(tag).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "A record of the session was tampered with"));
+        }
+        self.sequence += 1;
+        Ok(Some(bytes.to_vec()))
+    }
+}
+
+//"tcp://token@host:port", the token can also come from SWORDFISH_BRIDGE_TOKEN. None when it is not a tcp port
+pub fn parse_bridge_url(port_name: &str) -> Option<(String, Option<String>)> {
+    let rest = port_name.strip_prefix("tcp://")?;
+    let (token, address) = match rest.rsplit_once('@') {
+        Some((token, address)) => (Some(token.to_string()), address),
+        None => (std::env::var(BRIDGE_TOKEN_VARIABLE).ok(), rest),
+    };
+    let address = match address.contains(':') {
+        true => address.to_string(),
+        false => format!("{}:{}", address, DEFAULT_BRIDGE_PORT),
+    };
+    Some((address, token))
+}
+
+//the port name without the token of a tcp:// port, for logs and error messages
+pub fn redact_port_url(port_name: &str) -> String {
+    match port_name.strip_prefix("tcp://").and_then(|rest| rest.rsplit_once('@')) {
+        Some((_, address)) => format!("tcp://***@{}", address),
+        None => port_name.to_string(),
+    }
+}
+
+//---------------------TcpTransport---------------------
+//the client side of a bridge session, for SwordFishComm::from_transport
+pub struct TcpTransport {
+    stream: TcpStream,
+    device: String,
+    to_bridge: RecordStream,
+    to_client: RecordStream,
+    incoming: Vec<u8>, //records not complete yet
+    pending: Vec<u8>,
+}
+
+impl TcpTransport {
+    pub fn connect(address: &str, token: Option<&str>) -> Result<Self> {
+        let token = token.unwrap_or("");
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("{} has no address", address))?;
+        let stream = TcpStream::connect_timeout(&address, HANDSHAKE_TIMEOUT)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let greeting = read_line(&mut reader)?;
+        let server_nonce = match greeting.split(' ').collect::<Vec<_>>().as_slice() {
+            [GREETING, version, nonce] => {
+                let version: u32 = version.parse().map_err(|_| anyhow!("Bad bridge version {}", version))?;
+                if version != BRIDGE_PROTOCOL_VERSION {
+                    return Err(anyhow!("The bridge speaks version {}, this client needs {}", version, BRIDGE_PROTOCOL_VERSION));
+                }
+                from_hex(nonce)?
+            }
+            _ => return Err(anyhow!("{} is not a swordfish bridge", address)),
+        };
+        let client_nonce = random_nonce()?;
+        let proof = proof(token, b"client", &server_nonce, &client_nonce);
+        let hello = format!("hello {} {} {}\n", BRIDGE_PROTOCOL_VERSION, to_hex(&client_nonce), to_hex(&proof));
+        (&stream).write_all(hello.as_bytes())?;
+
+        let answer = read_line(&mut reader)?;
+        let device = match answer.split_once(' ') {
+            Some(("ok", rest)) => {
+                let (proof, device) = rest.split_once(' ').unwrap_or((rest, ""));
+                //whoever answers without the token is not the bridge
+                if !verify(token, b"server", &server_nonce, &client_nonce, &from_hex(proof).unwrap_or_default()) {
+                    return Err(anyhow!("The bridge at {} could not prove it knows the token", address));
+                }
+                device.to_string()
+            }
+            Some(("error", reason)) => return Err(anyhow!("The bridge refused the session: {}", reason)),
+            _ => return Err(anyhow!("Unexpected answer from the bridge: {}", answer)),
+        };
+        let (to_client, to_bridge) = RecordStream::session(token, &server_nonce, &client_nonce);
+        //the first records may have followed the answer into the buffer of the reader
+        let incoming = reader.buffer().to_vec();
+        stream.set_read_timeout(Some(Duration::from_millis(1)))?;
+        Ok(TcpTransport {
+            stream,
+            device,
+            to_bridge,
+            to_client,
+            incoming,
+            pending: Vec::new(),
+        })
+    }
+
+    //the port the bridge serves
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            if let Some(bytes) = self.to_client.open(&mut self.incoming)? {
+                self.pending = bytes;
+                continue;
+            }
+            let mut buffer = [0u8; 4096];
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::BrokenPipe.into()), //the bridge closed the session
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                //a socket says WouldBlock when its read times out, a transport says TimedOut
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(io::ErrorKind::TimedOut.into()),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Err(io::ErrorKind::BrokenPipe.into()),
+                Err(e) => return Err(e),
+            }
+        }
+        let n = std::cmp::min(buf.len(), self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = std::cmp::min(buf.len(), MAX_RECORD_SIZE);
+        self.stream.write_all(&self.to_bridge.seal(&buf[..n]))?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+//---------------------SwordFishBridge---------------------
+struct Session {
+    id: u64,
+    peer: SocketAddr,
+    stream: TcpStream,
+    to_client: SyncSender<Vec<u8>>,
+    dropped: u64, //frames the client was too slow for
+}
+
+//what serve_client needs once the client was told ok
+struct OpenedSession {
+    id: u64,
+    to_client: RecordStream,
+    to_bridge: RecordStream,
+    frames: Receiver<Vec<u8>>,
+}
+
+struct BridgeShared {
+    token: Option<String>,
+    device_name: String,
+    session: Mutex<Option<Session>>,
+    sessions: AtomicU64,
+    handshakes: AtomicUsize,
+    alive: AtomicBool,
+    device_alive: AtomicBool,
+}
+
+impl BridgeShared {
+    fn session(&self) -> std::sync::MutexGuard<'_, Option<Session>> {
+        self.session
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+    }
+
+    //the handshake of a new connection, the session is installed before the client is told ok
+    fn open_session(&self, stream: &TcpStream, peer: SocketAddr) -> Result<OpenedSession> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        //a client that does not read must not stall its writer forever
+        stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let token = self.token.as_deref().unwrap_or("");
+        let server_nonce = random_nonce()?;
+        let mut writer = stream;
+        writer.write_all(format!("{} {} {}\n", GREETING, BRIDGE_PROTOCOL_VERSION, to_hex(&server_nonce)).as_bytes())?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let hello = read_line(&mut reader)?;
+        let answer = match hello.split(' ').collect::<Vec<_>>().as_slice() {
+            ["hello", version, client_nonce, proof] => match version.parse::<u32>() {
+                Ok(BRIDGE_PROTOCOL_VERSION) => {
+                    let client_nonce = from_hex(client_nonce).unwrap_or_default();
+                    let proof = from_hex(proof).unwrap_or_default();
+                    match client_nonce.len() == NONCE_SIZE && verify(token, b"client", &server_nonce, &client_nonce, &proof) {
+                        true => Ok(client_nonce),
+                        false => Err("bad token".to_string()),
+                    }
+                }
+                _ => Err(format!("version {} is not supported, this bridge speaks {}", version, BRIDGE_PROTOCOL_VERSION)),
+            },
+            _ => Err("bad hello".to_string()),
+        };
+        let client_nonce = match answer {
+            Ok(client_nonce) => client_nonce,
+            Err(reason) => {
+                let _ = writer.write_all(format!("error {}\n", reason).as_bytes());
+                return Err(anyhow!("{}", reason));
+            }
+        };
+
+        let mut session = self.session();
+        if let Some(current) = session.as_ref() {
+            let reason = format!("busy, {} has the device", current.peer);
+            let _ = writer.write_all(format!("error {}\n", reason).as_bytes());
+            return Err(anyhow!("{}", reason));
+        }
+        let id = self.sessions.fetch_add(1, Ordering::Relaxed) + 1;
+        let server_proof = proof(token, b"server", &server_nonce, &client_nonce);
+        writer.write_all(format!("ok {} {}\n", to_hex(&server_proof), self.device_name).as_bytes())?;
+        let (to_client, frames) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+        *session = Some(Session {
+            id,
+            peer,
+            stream: stream.try_clone()?,
+            to_client,
+            dropped: 0,
+        });
+        let (to_client, to_bridge) = RecordStream::session(token, &server_nonce, &client_nonce);
+        Ok(OpenedSession {
+            id,
+            to_client,
+            to_bridge,
+            frames,
+        })
+    }
+
+    fn close_session(&self, id: u64) {
+        let mut session = self.session();
+        if session.as_ref().is_some_and(|session| session.id == id) {
+            if let Some(session) = session.take() {
+                let _ = session.stream.shutdown(Shutdown::Both);
+                match session.dropped {
+                    0 => log::info!("Session {} of {} closed", id, session.peer),
+                    dropped => log::info!("Session {} of {} closed, {} frames were dropped for it", id, session.peer, dropped),
+                }
+            }
+        }
+    }
+}
+
+//sends the frames of the device to the client, until the session is closed
+fn write_client(mut stream: TcpStream, mut to_client: RecordStream, frames: Receiver<Vec<u8>>) {
+    for frame in frames {
+        if stream.write_all(&to_client.seal(&frame)).is_err() {
+            //the reader of the session sees it and closes the session
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+//reads the frames of a client and hands them to the device thread
+fn serve_client(shared: Arc<BridgeShared>, mut stream: TcpStream, peer: SocketAddr, to_device: Sender<Vec<u8>>) {
+    let opened = shared.open_session(&stream, peer);
+    shared.handshakes.fetch_sub(1, Ordering::Relaxed);
+    let OpenedSession {
+        id,
+        to_client,
+        mut to_bridge,
+        frames,
+    } = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            log::warn!("Refused {}: {}", peer, e);
+            return;
+        }
+    };
+    log::info!("Session {} of {} opened", id, peer);
+    let writer = match stream.try_clone() {
+        Ok(writer_stream) => spawn(move || write_client(writer_stream, to_client, frames)),
+        Err(e) => {
+            log::error!("{}", e);
+            shared.close_session(id);
+            return;
+        }
+    };
+    if let Err(e) = stream.set_read_timeout(Some(POLL_PERIOD)) {
+        log::error!("{}", e);
+    }
+    let mut splitter = FrameSplitter::for_host_frames();
+    let mut incoming = Vec::new();
+    let mut buffer = [0u8; 4096];
+    'session: while shared.alive.load(Ordering::Relaxed) && shared.device_alive.load(Ordering::Relaxed) {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => incoming.extend_from_slice(&buffer[..n]),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(_) => break,
+        };
+        loop {
+            let bytes = match to_bridge.open(&mut incoming) {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("Closing the session of {}: {}", peer, e);
+                    break 'session;
+                }
+            };
+            for segment in splitter.push(&bytes) {
+                match segment {
+                    Segment::Frame(frame, Some(_)) => {
+                        if to_device.send(frame).is_err() {
+                            break 'session;
+                        }
+                    }
+                    Segment::Frame(frame, None) => log::warn!("Dropping a frame of {} with a bad checksum: {}", peer, to_hex(&frame)),
+                    Segment::Bytes(bytes) => log::warn!("Dropping {} bytes of {} outside a frame", bytes.len(), peer),
+                }
+            }
+        }
+    }
+    shared.close_session(id);
+    let _ = writer.join();
+}
+
+//writes the frames of the client to the device and queues the frames of the device for the client
+fn run_device(shared: &BridgeShared, mut device: Box<dyn SwordFishTransport>, from_clients: Receiver<Vec<u8>>) -> io::Result<()> {
+    let mut splitter = FrameSplitter::new();
+    let mut buffer = [0u8; 4096];
+    while shared.alive.load(Ordering::Relaxed) {
+        for frame in from_clients.try_iter() {
+            device.write_all(&frame)?;
+            device.flush()?;
+        }
+        let n = match device.read(&mut buffer) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e),
+        };
+        for segment in splitter.push(&buffer[..n]) {
+            let frame = match segment {
+                Segment::Frame(frame, Some(_)) => frame,
+                _ => continue, //the board's own noise, SwordFishComm would drop it too
+            };
+            //nobody listens without a session, a client that is not done with the last frames misses this one
+            if let Some(session) = shared.session().as_mut() {
+                if let Err(TrySendError::Full(_)) = session.to_client.try_send(frame) {
+                    session.dropped += 1;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct SwordFishBridge {
+    shared: Arc<BridgeShared>,
+    local_addr: SocketAddr,
+    accept_handle: Option<JoinHandle<()>>,
+    device_handle: Option<JoinHandle<()>>,
+}
+
+impl SwordFishBridge {
+    //serves the device to the clients of the listener. without a token any client is accepted.
+    //the reads of the device should time out when there is no data, see swordfish_transport
+    pub fn spawn(
+        listener: TcpListener,
+        device: Box<dyn SwordFishTransport>,
+        token: Option<String>,
+        device_name: &str,
+    ) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let shared = Arc::new(BridgeShared {
+            token,
+            device_name: device_name.to_string(),
+            session: Mutex::new(None),
+            sessions: AtomicU64::new(0),
+            handshakes: AtomicUsize::new(0),
+            alive: AtomicBool::new(true),
+            device_alive: AtomicBool::new(true),
+        });
+        let (to_device, from_clients) = mpsc::channel();
+
+        let device_shared = shared.clone();
+        let device_handle = spawn(move || {
+            if let Err(e) = run_device(&device_shared, device, from_clients) {
+                log::error!("The device of the bridge failed: {}", e);
+            }
+            device_shared.device_alive.store(false, Ordering::Relaxed);
+            if let Some(session) = device_shared.session().take() {
+                let _ = session.stream.shutdown(Shutdown::Both);
+            }
+        });
+
+        let accept_shared = shared.clone();
+        let accept_handle = spawn(move || {
+            let mut clients: Vec<JoinHandle<()>> = Vec::new();
+            while accept_shared.alive.load(Ordering::Relaxed) && accept_shared.device_alive.load(Ordering::Relaxed) {
+                clients.retain(|client| !client.is_finished());
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        if accept_shared.handshakes.load(Ordering::Relaxed) >= MAX_PENDING_HANDSHAKES {
+                            log::warn!("Closing {}, {} other connections are in their handshake", peer, MAX_PENDING_HANDSHAKES);
+                            continue;
+                        }
+                        if let Err(e) = stream.set_nonblocking(false) {
+                            log::error!("{}", e);
+                            continue;
+                        }
+                        accept_shared.handshakes.fetch_add(1, Ordering::Relaxed);
+                        let (shared, to_device) = (accept_shared.clone(), to_device.clone());
+                        clients.push(spawn(move || serve_client(shared, stream, peer, to_device)));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_PERIOD),
+                    Err(e) => {
+                        log::error!("The bridge could not accept: {}", e);
+                        std::thread::sleep(POLL_PERIOD);
+                    }
+                }
+            }
+            accept_shared.alive.store(false, Ordering::Relaxed);
+            for client in clients {
+                let _ = client.join();
+            }
+        });
+
+        Ok(SwordFishBridge {
+            shared,
+            local_addr,
+            accept_handle: Some(accept_handle),
+            device_handle: Some(device_handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    //the client of the current session
+    pub fn client(&self) -> Option<SocketAddr> {
+        self.shared.session().as_ref().map(|session| session.peer)
+    }
+
+    //how many sessions were opened since the start
+    pub fn sessions(&self) -> u64 {
+        self.shared.sessions.load(Ordering::Relaxed)
+    }
+
+    //false once the device was disconnected
+    pub fn is_running(&self) -> bool {
+        self.shared.device_alive.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SwordFishBridge {
+    fn drop(&mut self) {
+        self.shared.alive.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.accept_handle.take() {
+            handle.join().expect("The accept thread of the bridge could not be joined");
+        }
+        if let Some(handle) = self.device_handle.take() {
+            handle.join().expect("The device thread of the bridge could not be joined");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bridge_urls_carry_the_token() {
+        assert_eq!(
+            parse_bridge_url("tcp://secret@lab:6000"),
+            Some(("lab:6000".to_string(), Some("secret".to_string())))
+        );
+        assert_eq!(parse_bridge_url("tcp://10.0.0.2").unwrap().0, "10.0.0.2:5757");
+        assert_eq!(parse_bridge_url("/dev/ttyUSB0"), None);
+        assert_eq!(redact_port_url("tcp://secret@lab:6000"), "tcp://***@lab:6000");
+        assert_eq!(redact_port_url("tcp://lab:6000"), "tcp://lab:6000");
+        assert_eq!(redact_port_url("/dev/ttyUSB0"), "/dev/ttyUSB0");
+    }
+
+    #[test]
+    fn the_proof_depends_on_token_and_nonces() {
+        let (server, client) = ([7u8; NONCE_SIZE], [9u8; NONCE_SIZE]);
+        let client_proof = proof("token", b"client", &server, &client);
+        assert!(verify("token", b"client", &server, &client, &client_proof));
+        assert!(!verify("other", b"client", &server, &client, &client_proof));
+        assert!(!verify("token", b"client", &[8u8; NONCE_SIZE], &client, &client_proof));
+        //a proof of the client can not be sent back as the one of the server
+        assert!(!verify("token", b"server", &server, &client, &client_proof));
+    }
+
+    #[test]
+    fn records_are_checked_in_order() {
+        let (server, client) = ([7u8; NONCE_SIZE], [9u8; NONCE_SIZE]);
+        let (mut sealer, _) = RecordStream::session("token", &server, &client);
+        let (mut opener, mut other_direction) = RecordStream::session("token", &server, &client);
+        let first = sealer.seal(&[1, 2, 3]);
+        let second = sealer.seal(&[4]);
+
+        let mut buffer = first[..4].to_vec();
+        assert_eq!(opener.open(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(&first[4..]);
+        buffer.extend_from_slice(&second);
+        assert_eq!(opener.open(&mut buffer).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(opener.open(&mut buffer).unwrap(), Some(vec![4]));
+        assert!(buffer.is_empty());
+
+        //replayed, sent back the other way, changed
+        assert!(opener.open(&mut second.clone()).is_err());
+        assert!(other_direction.open(&mut first.clone()).is_err());
+        let mut changed = sealer.seal(&[5]);
+        changed[2] ^= 1;
+        assert!(opener.open(&mut changed).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+//records the frames SwordFishComm sends and receives to a pcapng file, to be opened with wireshark:
+//  comm.start_capture(Path::new("session.pcapng"))?;
+//  ...
+//  comm.stop_capture()?;
+//the link type is LINKTYPE_USER0. every packet starts with a 2 byte pseudo header (direction, flags)
+//followed by the frame as it was on the wire. frames that failed the checksum are kept, flagged in the pseudo header
+//and in the packet flags. generate_dissector writes the wireshark lua plugin that decodes them
+use crate::swordfish_registry::{FieldType, MessageRegistry};
+use crate::swordfish_dynamic::DynamicFieldType;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+pub const LINKTYPE_USER0: u16 = 147;
+//bytes before the frame in every packet
+pub const PSEUDO_HEADER_SIZE: usize = 2;
+//bit of the pseudo header flags
+pub const FLAG_BAD_CHECKSUM: u8 = 0x01;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const EPB_FLAGS: u16 = 2;
+//epb_flags: bits 0-1 are the direction, bit 24 is a crc error
+const EPB_INBOUND: u32 = 1;
+const EPB_OUTBOUND: u32 = 2;
+const EPB_CRC_ERROR: u32 = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Tx, //host to device
+    Rx, //device to host
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureFrame {
+    pub direction: Direction,
+    pub host_us: u64, //see swordfish_clock_sync::host_now_us
+    pub bad_checksum: bool,
+    pub bytes: Vec<u8>, //the frame from the sync word to the checksum
+}
+
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+fn push_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    block.extend_from_slice(value);
+    block.resize(block.len() + padding(value.len()), 0);
+}
+
+//the body between the block type and the trailing length
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let total_length = (12 + body.len()) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&total_length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&total_length.to_le_bytes())
+}
+
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+    frames: u64,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    //writes the section header and the interface of the frames
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let mut section = Vec::new();
+        section.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        section.extend_from_slice(&1u16.to_le_bytes());
+        section.extend_from_slice(&0u16.to_le_bytes());
+        section.extend_from_slice(&(-1i64).to_le_bytes()); //section length not known
+        push_option(&mut section, SHB_USERAPPL, concat!("swordfish_com ", env!("CARGO_PKG_VERSION")).as_bytes());
+        push_option(&mut section, OPT_END, &[]);
+        write_block(&mut writer, SECTION_HEADER_BLOCK, &section)?;
+
+        let mut interface = Vec::new();
+        interface.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+        interface.extend_from_slice(&0u16.to_le_bytes());
+        interface.extend_from_slice(&0u32.to_le_bytes()); //no snap length
+        push_option(&mut interface, IF_NAME, b"swordfish");
+        push_option(&mut interface, IF_TSRESOL, &[6]); //microseconds
+        push_option(&mut interface, OPT_END, &[]);
+        write_block(&mut writer, INTERFACE_DESCRIPTION_BLOCK, &interface)?;
+        Ok(PcapngWriter { writer, frames: 0 })
+    }
+
+    pub fn write_frame(&mut self, frame: &CaptureFrame) -> io::Result<()> {
+        let mut packet = vec![
+            match frame.direction {
+                Direction::Tx => 0,
+                Direction::Rx => 1,
+            },
+            if frame.bad_checksum { FLAG_BAD_CHECKSUM } else { 0 },
+        ];
+        packet.extend_from_slice(&frame.bytes);
+
+        let mut block = Vec::with_capacity(packet.len() + 48);
+        block.extend_from_slice(&0u32.to_le_bytes()); //interface id
+        block.extend_from_slice(&((frame.host_us >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(frame.host_us as u32).to_le_bytes());
+        block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        block.extend_from_slice(&packet);
+        block.resize(block.len() + padding(packet.len()), 0);
+        let mut flags = match frame.direction {
+            Direction::Tx => EPB_OUTBOUND,
+            Direction::Rx => EPB_INBOUND,
+        };
+        if frame.bad_checksum {
+            flags |= EPB_CRC_ERROR;
+            push_option(&mut block, OPT_COMMENT, b"bad checksum");
+        }
+        push_option(&mut block, EPB_FLAGS, &flags.to_le_bytes());
+        push_option(&mut block, OPT_END, &[]);
+        write_block(&mut self.writer, ENHANCED_PACKET_BLOCK, &block)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+//the frames of a capture written by PcapngWriter, blocks of other types are skipped
+pub fn read_frames<R: Read>(mut reader: R) -> Result<Vec<CaptureFrame>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let u32_at = |offset: usize| -> Result<u32> {
+        let word = bytes
+            .get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("Capture truncated at byte {}", offset))?;
+        Ok(u32::from_le_bytes(word.try_into().expect("the slice has 4 bytes")))
+    };
+    if u32_at(0)? != SECTION_HEADER_BLOCK || u32_at(8)? != BYTE_ORDER_MAGIC {
+        return Err(anyhow!("Not a little endian pcapng capture"));
+    }
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let block_type = u32_at(offset)?;
+        let total_length = u32_at(offset + 4)? as usize;
+        if total_length < 12 || offset + total_length > bytes.len() {
+            return Err(anyhow!("Bad block length {} at byte {}", total_length, offset));
+        }
+        if block_type == ENHANCED_PACKET_BLOCK {
+            let host_us = ((u32_at(offset + 12)? as u64) << 32) | u32_at(offset + 16)? as u64;
+            let captured = u32_at(offset + 20)? as usize;
+            let packet = bytes
+                .get(offset + 28..offset + 28 + captured)
+                .filter(|packet| packet.len() >= PSEUDO_HEADER_SIZE)
+                .ok_or_else(|| anyhow!("Bad packet at byte {}", offset))?;
+            frames.push(CaptureFrame {
+                direction: if packet[0] == 0 { Direction::Tx } else { Direction::Rx },
+                host_us,
+                bad_checksum: packet[1] & FLAG_BAD_CHECKSUM != 0,
+                bytes: packet[PSEUDO_HEADER_SIZE..].to_vec(),
+            });
+        }
+        offset += total_length;
+    }
+    Ok(frames)
+}
+
+//the capture of a SwordFishComm, fed by its read/write thread
+#[derive(Default)]
+pub struct FrameCapture {
+    active: AtomicBool,
+    writer: Mutex<Option<PcapngWriter<Box<dyn Write + Send>>>>,
+}
+
+impl FrameCapture {
+    //replaces the running capture, if any
+    pub fn start(&self, writer: Box<dyn Write + Send>) -> io::Result<()> {
+        let pcapng = PcapngWriter::new(writer)?;
+        let previous = self
+            .writer
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .replace(pcapng);
+        self.active.store(true, Ordering::Relaxed);
+        if let Some(mut previous) = previous {
+            previous.flush()?;
+        }
+        Ok(())
+    }
+
+    //returns the number of frames written
+    pub fn stop(&self) -> io::Result<u64> {
+        self.active.store(false, Ordering::Relaxed);
+        let pcapng = self
+            .writer
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .take();
+        match pcapng {
+            Some(mut pcapng) => {
+                pcapng.flush()?;
+                Ok(pcapng.frames())
+            }
+            None => Ok(0),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    //a capture that fails to write is stopped
+    pub fn record(&self, direction: Direction, host_us: u64, bytes: &[u8], bad_checksum: bool) {
+        if !self.is_active() {
+            return;
+        }
+        let mut writer = self
+            .writer
+            .lock()
+            .expect("Another thread holding the mutex panicked");
+        let pcapng = match writer.as_mut() {
+            Some(pcapng) => pcapng,
+            None => return,
+        };
+        let frame = CaptureFrame {
+            direction,
+            host_us,
+            bad_checksum,
+            bytes: bytes.to_vec(),
+        };
+        if let Err(e) = pcapng.write_frame(&frame) {
+            log::error!("Stopping the capture, could not write it: {}", e);
+            writer.take();
+            self.active.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+//---------------------wireshark dissector---------------------
+fn lua_protofield(field_type: &FieldType) -> &'static str {
+    match field_type {
+        FieldType::Number(number) => match number {
+            DynamicFieldType::U8 => "uint8",
+            DynamicFieldType::U16 => "uint16",
+            DynamicFieldType::U32 => "uint32",
+            DynamicFieldType::U64 => "uint64",
+            DynamicFieldType::I8 => "int8",
+            DynamicFieldType::I16 => "int16",
+            DynamicFieldType::I32 => "int32",
+            DynamicFieldType::I64 => "int64",
+            DynamicFieldType::F32 => "float",
+            DynamicFieldType::F64 => "double",
+            DynamicFieldType::Bytes(_) => "bytes",
+        },
+        FieldType::String(_) => "string",
+        FieldType::Array(item, _) | FieldType::Vec(item, _) if item.is_u8() => "bytes",
+        FieldType::Array(item, _) | FieldType::Vec(item, _) => lua_protofield(item),
+    }
+}
+
+//lua that adds one value of the field to tree and advances offset
+fn lua_decode(field_type: &FieldType, hf: &str, big_endian: bool, indent: &str, depth: usize, lua: &mut String) {
+    let add = if big_endian { "add" } else { "add_le" };
+    match field_type {
+        FieldType::Number(number) => {
+            let size = number.size();
+            lua.push_str(&format!("{indent}tree:{add}({hf}, tvb(offset, {size}))\n{indent}offset = offset + {size}\n"));
+        }
+        FieldType::Array(item, len) if item.is_u8() => {
+            lua.push_str(&format!("{indent}tree:add({hf}, tvb(offset, {len}))\n{indent}offset = offset + {len}\n"));
+        }
+        FieldType::Array(item, len) => {
+            lua.push_str(&format!("{indent}for _ = 1, {len} do\n"));
+            lua_decode(item, hf, big_endian, &format!("{indent}    "), depth + 1, lua);
+            lua.push_str(&format!("{indent}end\n"));
+        }
+        FieldType::String(_) => {
+            lua.push_str(&format!(
+                "{indent}local len{depth} = tvb(offset, 1):uint()\n\
+                 {indent}tree:add({hf}, tvb(offset, 1 + len{depth}), len{depth} > 0 and tvb(offset + 1, len{depth}):string() or \"\")\n\
+                 {indent}offset = offset + 1 + len{depth}\n"
+            ));
+        }
+        FieldType::Vec(item, _) if item.is_u8() => {
+            lua.push_str(&format!(
+                "{indent}local len{depth} = tvb(offset, 1):uint()\n\
+                 {indent}tree:add({hf}, tvb(offset, 1 + len{depth}), len{depth} > 0 and tvb(offset + 1, len{depth}):bytes() or ByteArray.new())\n\
+                 {indent}offset = offset + 1 + len{depth}\n"
+            ));
+        }
+        FieldType::Vec(item, _) => {
+            lua.push_str(&format!(
+                "{indent}local len{depth} = tvb(offset, 1):uint()\n{indent}offset = offset + 1\n{indent}for _ = 1, len{depth} do\n"
+            ));
+            lua_decode(item, hf, big_endian, &format!("{indent}    "), depth + 1, lua);
+            lua.push_str(&format!("{indent}end\n"));
+        }
+    }
+}
+
+//a wireshark lua plugin for captures made by FrameCapture, decoding the header,
+//the opcode name and the fields of every message of the registry.
+//copy it to the personal lua plugins folder (Help > About Wireshark > Folders)
+pub fn generate_dissector(registry: &MessageRegistry) -> String {
+    let mut opcodes = String::new();
+    let mut fields = String::new();
+    let mut decoders = String::new();
+    for info in registry.iter() {
+        opcodes.push_str(&format!("    [{}] = \"{}\",\n", info.opcode, info.name));
+        let mut decoder = format!("-- {} ({})\nmessages[{}] = function(tvb, tree)\n    local offset = 0\n", info.name, info.category.name(), info.opcode);
+        for field in &info.fields {
+            let field_type = match field.field_type() {
+                Ok(field_type) => field_type,
+                Err(e) => {
+                    log::warn!("{}.{} is left out of the dissector: {}", info.name, field.name, e);
+                    break;
+                }
+            };
+            let hf = format!("hf[\"{}.{}\"]", info.name, field.name);
+            fields.push_str(&format!(
+                "{} = ProtoField.{}(\"swordfish.{}.{}\", \"{}\")\n",
+                hf,
+                lua_protofield(&field_type),
+                info.name,
+                field.name,
+                field.name
+            ));
+            //fields that an older device does not send yet
+            decoder.push_str("    if offset >= tvb:len() then return end\n");
+            lua_decode(&field_type, &hf, field.big_endian, "    ", 0, &mut decoder);
+        }
+        decoder.push_str("end\n\n");
+        decoders.push_str(&decoder);
+    }
+    DISSECTOR_TEMPLATE
+        .replace("{opcodes}", &opcodes)
+        .replace("{fields}", &fields)
+        .replace("{decoders}", &decoders)
+}
+
+const DISSECTOR_TEMPLATE: &str = r#"-- swordfish protocol dissector, generated from the message registry by swordfish_capture::generate_dissector
+-- for captures with link type USER0 (147) made by SwordFishComm::start_capture
+local swordfish = Proto("swordfish", "SwordFish")
+
+local directions = { [0] = "host -> device", [1] = "device -> host" }
+local opcodes = {
+{opcodes}}
+
+local hf = {}
+hf.direction = ProtoField.uint8("swordfish.direction", "Direction", base.DEC, directions)
+hf.bad_checksum = ProtoField.bool("swordfish.bad_checksum", "Bad checksum", 8, nil, 0x01)
+hf.sync_word = ProtoField.uint32("swordfish.sync_word", "Sync word", base.HEX)
+hf.counter = ProtoField.uint16("swordfish.counter", "Counter")
+hf.opcode = ProtoField.uint8("swordfish.opcode", "Opcode", base.DEC, opcodes)
+hf.length = ProtoField.uint16("swordfish.length", "Length")
+hf.payload = ProtoField.bytes("swordfish.payload", "Payload")
+hf.checksum = ProtoField.uint8("swordfish.checksum", "Checksum", base.HEX)
+{fields}
+local field_list = {}
+for _, field in pairs(hf) do
+    field_list[#field_list + 1] = field
+end
+swordfish.fields = field_list
+
+local messages = {}
+{decoders}function swordfish.dissector(buffer, pinfo, tree)
+    if buffer:len() < 2 + 10 then
+        return 0
+    end
+    pinfo.cols.protocol = "SWORDFISH"
+    local direction = buffer(0, 1):uint()
+    local bad_checksum = buffer(1, 1):uint() % 2 == 1
+    local subtree = tree:add(swordfish, buffer())
+    subtree:add(hf.direction, buffer(0, 1))
+    subtree:add(hf.bad_checksum, buffer(1, 1))
+
+    local frame = buffer(2):tvb()
+    local counter = frame(4, 2):le_uint()
+    local opcode = frame(6, 1):uint()
+    local length = frame(7, 2):le_uint()
+    subtree:add_le(hf.sync_word, frame(0, 4))
+    subtree:add_le(hf.counter, frame(4, 2))
+    subtree:add(hf.opcode, frame(6, 1))
+    subtree:add_le(hf.length, frame(7, 2))
+
+    local info = (directions[direction] or "?") .. "  " .. (opcodes[opcode] or ("opcode " .. opcode)) .. " #" .. counter
+    if frame:len() < 10 + length then
+        pinfo.cols.info = info .. " [truncated]"
+        return buffer:len()
+    end
+    if length > 0 then
+        local payload_tree = subtree:add(hf.payload, frame(9, length))
+        local decode = messages[opcode]
+        if decode then
+            local ok, err = pcall(decode, frame(9, length):tvb(), payload_tree)
+            if not ok then
+                payload_tree:add_expert_info(PI_MALFORMED, PI_ERROR, tostring(err))
+            end
+        end
+    end
+    subtree:add(hf.checksum, frame(9 + length, 1))
+    if bad_checksum then
+        info = info .. " [bad checksum]"
+    end
+    pinfo.cols.info = info
+    return buffer:len()
+end
+
+DissectorTable.get("wtap_encap"):add(wtap_encaps and wtap_encaps.USER0 or wtap.USER0, swordfish)
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swordfish_messages::{Nack, VersionData};
+    use crate::SwordFishMessageTrait;
+
+    #[test]
+    fn frames_round_trip_through_pcapng() {
+        let tx = VersionData::default().to_concentrated(1).into_bytes().to_vec();
+        let frames = vec![
+            CaptureFrame { direction: Direction::Tx, host_us: 1_700_000_000_000_001, bad_checksum: false, bytes: tx },
+            CaptureFrame { direction: Direction::Rx, host_us: 1_700_000_000_000_900, bad_checksum: true, bytes: vec![0xde, 0xad, 0xbe] },
+        ];
+        let mut pcapng = PcapngWriter::new(Vec::new()).unwrap();
+        for frame in &frames {
+            pcapng.write_frame(frame).unwrap();
+        }
+        assert_eq!(pcapng.frames(), 2);
+        let bytes = pcapng.into_inner();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(read_frames(&bytes[..]).unwrap(), frames);
+    }
+
+    #[test]
+    fn dissector_decodes_every_message() {
+        let lua = generate_dissector(&MessageRegistry::new());
+        assert!(lua.contains(&format!("[{}] = \"VersionData\"", VersionData::OPCODE)));
+        assert!(lua.contains("hf[\"VersionData.uuid\"] = ProtoField.bytes(\"swordfish.VersionData.uuid\", \"uuid\")"));
+        assert!(lua.contains(&format!("messages[{}] = function(tvb, tree)", Nack::OPCODE)));
+        assert!(lua.contains("hf[\"Nack.detail\"] = ProtoField.string"));
+        assert!(!lua.contains("{opcodes}"));
+    }
+}
@@ -0,0 +1,254 @@
+//estimates the device clock from TimeSync round trips, like ntp does:
+//  t1 host sends, t2 device clock when it answers, t4 host receives
+//  offset = t2 - (t1 + t4) / 2, delay = t4 - t1
+//the round trips with the smallest delay have the least queuing in them, only those are used.
+//a line fitted through their offsets over host time gives the drift of the device oscillator
+//times are us, host times since the unix epoch (from a monotonic clock), device times since the device booted
+use crate::swordfish_messages::TimeSync;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_WINDOW: usize = 32;
+
+//wall clock time that does not jump, anchored the first time it is used
+pub fn host_now_us() -> u64 {
+    static ANCHOR: OnceLock<(Instant, u64)> = OnceLock::new();
+    let (instant, system_us) = ANCHOR.get_or_init(|| {
+        let system_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("the system clock is before 1970")
+            .as_micros() as u64;
+        (Instant::now(), system_us)
+    });
+    system_us + instant.elapsed().as_micros() as u64
+}
+
+pub fn host_us_to_system_time(host_us: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(host_us)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    pub host_send: u64,
+    pub device: u64,
+    pub host_receive: u64,
+}
+
+impl ClockSample {
+    pub fn delay(&self) -> u64 {
+        self.host_receive.saturating_sub(self.host_send)
+    }
+
+    fn host_mid(&self) -> f64 {
+        (self.host_send as f64 + self.host_receive as f64) / 2.0
+    }
+
+    fn offset(&self) -> f64 {
+        self.device as f64 - self.host_mid()
+    }
+}
+
+//device = host + offset_us + drift * (host - reference_host)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockEstimate {
+    pub offset_us: f64,
+    pub drift_ppm: f64,
+    pub reference_host: u64,
+    pub delay_us: u64, //the smallest round trip seen
+    pub samples: usize, //round trips used by the estimate
+}
+
+impl ClockEstimate {
+    pub fn host_to_device_us(&self, host_us: u64) -> f64 {
+        let drift = self.drift_ppm * 1e-6;
+        host_us as f64 + self.offset_us + drift * (host_us as f64 - self.reference_host as f64)
+    }
+
+    pub fn device_to_host_us(&self, device_us: u64) -> f64 {
+        let drift = self.drift_ppm * 1e-6;
+        (device_us as f64 - self.offset_us + drift * self.reference_host as f64) / (1.0 + drift)
+    }
+}
+
+struct ClockSyncState {
+    samples: VecDeque<ClockSample>,
+    window: usize,
+    estimate: Option<ClockEstimate>,
+    period: Option<Duration>,
+    last_request: Option<Instant>,
+}
+
+pub struct ClockSync {
+    state: Mutex<ClockSyncState>,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        ClockSync {
+            state: Mutex::new(ClockSyncState {
+                samples: VecDeque::with_capacity(DEFAULT_WINDOW),
+                window: DEFAULT_WINDOW,
+                estimate: None,
+                period: None,
+                last_request: None,
+            }),
+        }
+    }
+}
+
+impl ClockSync {
+    fn lock(&self) -> std::sync::MutexGuard<'_, ClockSyncState> {
+        self.state
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+    }
+
+    //SwordFishComm sends a TimeSync every period, None stops it
+    pub fn set_period(&self, period: Option<Duration>) {
+        let mut state = self.lock();
+        state.period = period;
+        state.last_request = None;
+    }
+
+    //the number of round trips kept, older ones are forgotten so the drift can follow temperature changes
+    pub fn set_window(&self, window: usize) {
+        let mut state = self.lock();
+        state.window = std::cmp::max(window, 1);
+        while state.samples.len() > state.window {
+            state.samples.pop_front();
+        }
+    }
+
+    //the TimeSync to send now, if one is due
+    pub fn due_request(&self) -> Option<TimeSync> {
+        let mut state = self.lock();
+        let period = state.period?;
+        if state.last_request.is_some_and(|last| last.elapsed() < period) {
+            return None;
+        }
+        state.last_request = Some(Instant::now());
+        Some(TimeSync::new(host_now_us(), 0))
+    }
+
+    //called with every TimeSync answer, host_receive is when it was read
+    pub fn handle_answer(&self, answer: &TimeSync, host_receive: u64) {
+        self.add_sample(ClockSample {
+            host_send: answer.get_host_time(),
+            device: answer.get_device_time(),
+            host_receive,
+        });
+    }
+
+    pub fn add_sample(&self, sample: ClockSample) {
+        if sample.host_receive < sample.host_send {
+            log::warn!("Ignoring time sync answer from the future");
+            return;
+        }
+        let mut state = self.lock();
+        if state.samples.len() == state.window {
+            state.samples.pop_front();
+        }
+        state.samples.push_back(sample);
+        state.estimate = estimate(&state.samples);
+    }
+
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        self.lock().estimate
+    }
+
+    //None until the first TimeSync round trip
+    pub fn device_to_host_time(&self, device_us: u64) -> Option<SystemTime> {
+        let host_us = self.estimate()?.device_to_host_us(device_us);
+        Some(host_us_to_system_time(host_us.max(0.0) as u64))
+    }
+
+    pub fn host_to_device_time(&self, host: SystemTime) -> Option<u64> {
+        let host_us = host.duration_since(UNIX_EPOCH).ok()?.as_micros() as u64;
+        Some(self.estimate()?.host_to_device_us(host_us).max(0.0) as u64)
+    }
+
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.samples.clear();
+        state.estimate = None;
+    }
+}
+
+fn estimate(samples: &VecDeque<ClockSample>) -> Option<ClockEstimate> {
+    let min_delay = samples.iter().map(|s| s.delay()).min()?;
+    //round trips that were not held up much longer than the best one
+    let limit = 2 * min_delay + 200;
+    let good: Vec<&ClockSample> = samples.iter().filter(|s| s.delay() <= limit).collect();
+    let reference = good.iter().map(|s| s.host_mid()).fold(f64::MIN, f64::max);
+
+    //least squares of offset over host time, relative to the latest sample to keep the numbers small
+    let n = good.len() as f64;
+    let mean_x = good.iter().map(|s| s.host_mid() - reference).sum::<f64>() / n;
+    let mean_y = good.iter().map(|s| s.offset()).sum::<f64>() / n;
+    let sxx: f64 = good.iter().map(|s| (s.host_mid() - reference - mean_x).powi(2)).sum();
+    let sxy: f64 = good
+        .iter()
+        .map(|s| (s.host_mid() - reference - mean_x) * (s.offset() - mean_y))
+        .sum();
+    //with a single round trip, or all of them at the same time, there is no drift to see
+    let drift = if good.len() > 1 && sxx > 0.0 { sxy / sxx } else { 0.0 };
+    Some(ClockEstimate {
+        offset_us: mean_y - drift * mean_x,
+        drift_ppm: drift * 1e6,
+        reference_host: reference as u64,
+        delay_us: min_delay,
+        samples: good.len(),
+    })
+}
+
+//when a message was received, see SwordFishComm::add_rx_hook
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RxTimestamp {
+    pub host: SystemTime, //when the read returned
+    //host time the device most likely sent it, corrected by half the round trip, None before the first time sync
+    pub corrected: Option<SystemTime>,
+    pub device_us: Option<u64>, //the device clock when it sent it
+}
+
+impl RxTimestamp {
+    pub fn new(clock_sync: &ClockSync, host_us: u64) -> Self {
+        let estimate = clock_sync.estimate();
+        let corrected_us = estimate.map(|estimate| host_us.saturating_sub(estimate.delay_us / 2));
+        RxTimestamp {
+            host: host_us_to_system_time(host_us),
+            corrected: corrected_us.map(host_us_to_system_time),
+            device_us: estimate
+                .zip(corrected_us)
+                .map(|(estimate, corrected_us)| estimate.host_to_device_us(corrected_us).max(0.0) as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_and_drift_from_jittery_round_trips() {
+        let clock_sync = ClockSync::default();
+        //the device started 5s before host time 1_000_000 and runs 50ppm fast
+        let device = |host: u64| ((host as f64 - 1_000_000.0) * (1.0 + 50e-6)) as u64 + 5_000_000;
+        for i in 0..20u64 {
+            let host_send = 1_000_000 + i * 100_000;
+            //every third round trip is held up on the way back
+            let delay = if i % 3 == 0 { 5_000 } else { 400 };
+            clock_sync.add_sample(ClockSample {
+                host_send,
+                device: device(host_send + 200),
+                host_receive: host_send + delay,
+            });
+        }
+        let estimate = clock_sync.estimate().unwrap();
+        assert!((estimate.drift_ppm - 50.0).abs() < 1.0, "{:?}", estimate);
+        assert_eq!(estimate.delay_us, 400);
+        let host = 2_500_000;
+        assert!((estimate.host_to_device_us(host) - device(host) as f64).abs() < 5.0);
+        assert!((estimate.device_to_host_us(device(host)) - host as f64).abs() < 5.0);
+    }
+}
@@ -1,84 +1,299 @@
 use crate::swordfish_concentrated_message::{
     SwordFishConcentratedMessage, SwordFishConcentratedMessageBufferBuilder,
 };
+#[cfg(feature = "bridge")]
+use crate::swordfish_bridge::{parse_bridge_url, TcpTransport};
+use crate::swordfish_capture::{Direction, FrameCapture};
+#[cfg(unix)]
+use crate::swordfish_daemon::{parse_daemon_url, UnixTransport};
+use crate::swordfish_clock_sync::{host_now_us, ClockSync, RxTimestamp};
+use crate::swordfish_device_log::DeviceLogForwarder;
+use crate::swordfish_discovery;
+use crate::swordfish_dynamic::MessageDescriptor;
+use crate::swordfish_error::SwordFishError;
+use crate::swordfish_messages::{DeviceLog, Nack, OperationProgress, OperationResult, StreamStart, StreamStop, TimeSync};
 use crate::swordfish_messages::create_swordfish_messages_hashmap;
-use crate::{SwordFishMessageBucket, SwordFishMessageCategory, CONCENTRATED_MESSAGE_TOTAL_SIZE};
+use crate::swordfish_operation::{OperationHandle, OperationTracker};
+use crate::swordfish_registry::{MessageInfo, MessageRegistry};
+use crate::swordfish_session::SessionRecorder;
+use crate::swordfish_stream::{StreamBuffer, StreamRecord};
+use crate::swordfish_transport::SwordFishTransport;
+use crate::{
+    SwordFishMessageBucket, SwordFishMessageCategory, SwordFishMessageTrait,
+    CONCENTRATED_MESSAGE_TOTAL_SIZE,
+};
 use inline_colorization::{color_red, color_reset};
 use log;
 use serialport::{DataBits, Parity, SerialPort, StopBits};
 use std::collections::HashMap;
-use std::io::Read;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread::{spawn, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-pub fn get_serial_ports() -> Option<String> {
+//every serial port of the system, None when they can not be listed
+pub fn get_serial_ports() -> Option<Vec<swordfish_discovery::PortInfo>> {
     match serialport::available_ports() {
-        Ok(ports) => {
-            let mut ports_string = String::new();
-            ports_string.push_str(&format!("Num of devices: {}\n", ports.len()));
-            for (i, port_info) in ports.iter().enumerate() {
-                let port_name = &port_info.port_name;
-                match &port_info.port_type {
-                    serialport::SerialPortType::UsbPort(info) => {
-                        ports_string.push_str(&format!("{} : USB Port : {}\n", i, port_name));
-                        ports_string.push_str(&format!("  - VID: 0x{:04x}\n", info.vid));
-                        ports_string.push_str(&format!("  - PID: 0x{:04x}\n", info.pid));
-                        ports_string.push_str(&format!("  - Serial Number: {}\n", info.serial_number.as_deref().unwrap_or("None")));
-                        ports_string.push_str(&format!("  - Manufacturer: {}\n", info.manufacturer.as_deref().unwrap_or("None")));
-                        ports_string.push_str(&format!("  - Product: {}\n", info.product.as_deref().unwrap_or("None")));
-                    }
-                    serialport::SerialPortType::BluetoothPort => {
-                        ports_string.push_str(&format!("{} : Bluetooth Port : {}\n", i, port_name));
-                    }
-                    serialport::SerialPortType::PciPort => {
-                        ports_string.push_str(&format!("{} : PCI Port : {}\n", i, port_name));
-                    }
-                    serialport::SerialPortType::Unknown => {
-                        ports_string.push_str(&format!("{} : Unknown Port : {}\n", i, port_name));
-                    }
-                }
-            }
-            return Some(ports_string);
-        }
+        Ok(ports) => Some(ports.iter().map(swordfish_discovery::PortInfo::from_serialport).collect()),
         Err(e) => {
             log::error!("Error listing serial ports: {:?}", e);
-            return None;
+            None
+        }
+    }
+}
+
+//the ports of get_serial_ports as text, for the bindings and the examples
+pub fn serial_ports_text(ports: &[swordfish_discovery::PortInfo]) -> String {
+    let mut ports_string = String::new();
+    ports_string.push_str(&format!("Num of devices: {}\n", ports.len()));
+    for (i, port) in ports.iter().enumerate() {
+        match port.kind {
+            swordfish_discovery::PortKind::Usb => {
+                ports_string.push_str(&format!("{} : USB Port : {}\n", i, port.port_name));
+                ports_string.push_str(&format!("  - VID: 0x{:04x}\n", port.vid.unwrap_or_default()));
+                ports_string.push_str(&format!("  - PID: 0x{:04x}\n", port.pid.unwrap_or_default()));
+                ports_string.push_str(&format!("  - Serial Number: {}\n", port.serial_number.as_deref().unwrap_or("None")));
+                ports_string.push_str(&format!("  - Manufacturer: {}\n", port.manufacturer.as_deref().unwrap_or("None")));
+                ports_string.push_str(&format!("  - Product: {}\n", port.product.as_deref().unwrap_or("None")));
+            }
+            swordfish_discovery::PortKind::Bluetooth => {
+                ports_string.push_str(&format!("{} : Bluetooth Port : {}\n", i, port.port_name));
+            }
+            swordfish_discovery::PortKind::Pci => {
+                ports_string.push_str(&format!("{} : PCI Port : {}\n", i, port.port_name));
+            }
+            swordfish_discovery::PortKind::Unknown => {
+                ports_string.push_str(&format!("{} : Unknown Port : {}\n", i, port.port_name));
+            }
         }
     }
+    ports_string
 }
 
+//the first port accepted by the default matchers, see swordfish_discovery::discover
 pub fn find_probable_swordfish_port() -> Option<String> {
-    match serialport::available_ports() {
-        Ok(ports) => {
-            for port_info in ports {
-                if let serialport::SerialPortType::UsbPort(info) = port_info.port_type {
-                    if info.manufacturer.as_deref() == Some("Silicon Labs")
-                        && info.vid == 0x10C4
-                        && info.pid == 0xEA60
-                    {
-                        return Some(port_info.port_name);
-                    } else if info.manufacturer.as_deref() == Some("FTDI")
-                        && info.vid == 0x0403
-                        && info.pid == 0x6015
-                    {
-                        return Some(port_info.port_name);
+    swordfish_discovery::discover()
+        .into_iter()
+        .next()
+        .map(|port| port.port_name)
+}
+
+//called by the read thread with every received message, before it goes to its bucket
+pub type RxHook = Box<dyn FnMut(&SwordFishConcentratedMessage, &RxTimestamp) + Send>;
+
+//called by the write thread with every message sent, with the host time in us it was sent at
+pub type TxHook = Box<dyn FnMut(&SwordFishConcentratedMessage, u64) + Send>;
+
+//what the link did since the comm was created, see SwordFishComm::link_stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub tx_frames: u64,
+    pub rx_frames: u64,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,     //everything read, garbage included
+    pub bad_frames: u64,   //dropped for a bad checksum
+    pub write_errors: u64,
+    pub timeouts: u64,     //requests that got no answer
+    pub rejected: u64,     //requests answered with a Nack
+}
+
+#[derive(Default)]
+struct LinkCounters {
+    tx_frames: AtomicU64,
+    rx_frames: AtomicU64,
+    tx_bytes: AtomicU64,
+    rx_bytes: AtomicU64,
+    bad_frames: AtomicU64,
+    write_errors: AtomicU64,
+    timeouts: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl LinkCounters {
+    fn add(counter: &AtomicU64, n: usize) {
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LinkStats {
+        LinkStats {
+            tx_frames: self.tx_frames.load(Ordering::Relaxed),
+            rx_frames: self.rx_frames.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            bad_frames: self.bad_frames.load(Ordering::Relaxed),
+            write_errors: self.write_errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+//what the read thread does with a message besides filling its bucket
+#[derive(Default)]
+struct RxServices {
+    device_log: DeviceLogForwarder,
+    clock_sync: ClockSync,
+    operations: OperationTracker,
+    rx_hooks: Mutex<Vec<(usize, RxHook)>>,
+    tx_hooks: Mutex<Vec<(usize, TxHook)>>,
+    next_hook_id: AtomicUsize,
+    link: LinkCounters,
+}
+
+//the bucket that receives the answer to a request with this opcode, None if it is not answered
+fn response_bucket(
+    messages_hashmap: &HashMap<u8, SwordFishMessageBucket>,
+    opcode: u8,
+) -> Option<&SwordFishMessageBucket> {
+    let bucket = messages_hashmap.get(&opcode)?;
+    match bucket.catagory {
+        SwordFishMessageCategory::Bounce | SwordFishMessageCategory::Param => Some(bucket),
+        SwordFishMessageCategory::Operation(Some(response_opcode)) => messages_hashmap.get(&response_opcode),
+        _ => None,
+    }
+}
+
+//the request whose answer the bucket takes, answers and nacks with another counter are dropped
+fn set_waiting_counter(bucket: &SwordFishMessageBucket, counter: Option<u16>) {
+    *bucket
+        .waiting_counter
+        .lock()
+        .expect("Another thread holding the mutex panicked") = counter;
+}
+
+//hands a received message to its bucket, run by the read thread
+fn dispatch_rx_message(
+    messages_hashmap: &RwLock<HashMap<u8, SwordFishMessageBucket>>,
+    services: &RxServices,
+    msg: SwordFishConcentratedMessage,
+    host_receive_us: u64,
+) {
+    if msg.opcode == DeviceLog::OPCODE {
+        match DeviceLog::from_concentrated(&msg) {
+            Ok(device_log_msg) => services.device_log.handle(&device_log_msg),
+            Err(e) => log::warn!("Bad device log message: {}", e),
+        }
+    } else if msg.opcode == TimeSync::OPCODE {
+        match TimeSync::from_concentrated(&msg) {
+            Ok(answer) => services.clock_sync.handle_answer(&answer, host_receive_us),
+            Err(e) => log::warn!("Bad time sync message: {}", e),
+        }
+    } else if msg.opcode == OperationProgress::OPCODE {
+        match OperationProgress::from_concentrated(&msg) {
+            Ok(progress) => services.operations.handle_progress(&progress, msg.counter),
+            Err(e) => log::warn!("Bad operation progress message: {}", e),
+        }
+    } else if msg.opcode == OperationResult::OPCODE {
+        match OperationResult::from_concentrated(&msg) {
+            Ok(result) => services.operations.handle_result(&result, msg.counter),
+            Err(e) => log::warn!("Bad operation result message: {}", e),
+        }
+    }
+
+    let timestamp = RxTimestamp::new(&services.clock_sync, host_receive_us);
+    for (_, rx_hook) in services
+        .rx_hooks
+        .lock()
+        .expect("Another thread holding the mutex panicked")
+        .iter_mut()
+    {
+        rx_hook(&msg, &timestamp);
+    }
+
+    let gaurd = messages_hashmap
+        .read()
+        .expect("we are only reading, this should work");
+    //a rejected request ends its operation, or wakes the request waiting in place of the answer
+    if msg.opcode == Nack::OPCODE {
+        match Nack::from_concentrated(&msg) {
+            Ok(nack) if services.operations.handle_nack(&nack) => {}
+            //a nack for an earlier request stays out of the bucket, it could replace the answer of the waiting one
+            Ok(nack) => match response_bucket(&gaurd, nack.get_opcode()) {
+                Some(response_bucket) => {
+                    let mut bucket_msg = response_bucket
+                        .message
+                        .lock()
+                        .expect("Another thread holding the mutex panicked");
+                    let waiting_counter = *response_bucket
+                        .waiting_counter
+                        .lock()
+                        .expect("Another thread holding the mutex panicked");
+                    if waiting_counter == Some(nack.get_counter()) {
+                        *bucket_msg = Some(msg);
+                        response_bucket.condvar.notify_one();
+                    } else {
+                        log::warn!("{} (no request #{} is waiting)", SwordFishError::from(&nack), nack.get_counter());
                     }
                 }
+                None => log::warn!("{}", SwordFishError::from(&nack)),
+            },
+            Err(e) => log::warn!("Bad nack message: {}", e),
+        }
+    }
+
+    let bucket = match gaurd.get(&msg.opcode) {
+        Some(bucket) => bucket,
+        None => {
+            log::warn!("Received message with unknown opcode {}", msg.opcode);
+            return;
+        }
+    };
+
+    //if message has an rx callback, do it
+    if let Some(rx_callback) = bucket
+        .on_rx_callback
+        .lock()
+        .expect("Another thread holding the mutex panicked")
+        .as_mut()
+    {
+        rx_callback(msg);
+    }
+
+    //if message category is bounce, param or response, place the message in the bucket and notify the waiting thread
+    match bucket.catagory {
+        SwordFishMessageCategory::Bounce
+        | SwordFishMessageCategory::Param
+        | SwordFishMessageCategory::Response => {
+            let mut bucket_msg = bucket
+                .message
+                .lock()
+                .expect("Another thread holding the mutex panicked");
+            let waiting_counter = *bucket
+                .waiting_counter
+                .lock()
+                .expect("Another thread holding the mutex panicked");
+            //a late answer to an earlier request would take the place of the one the waiting request gets
+            if bucket.catagory.echoes_counter() && waiting_counter.is_some_and(|counter| counter != msg.counter) {
+                log::debug!("Ignoring opcode {} #{}, the request waiting for it is #{}", msg.opcode, msg.counter, waiting_counter.unwrap_or_default());
+                return;
             }
-            None
+            *bucket_msg = Some(msg);
+            bucket.condvar.notify_one();
         }
-        Err(e) => {
-            log::error!(
-                "{}-{} : Error listing serial ports: {:?}",
-                file!(),
-                line!(),
-                e
-            );
-            None
+        //stream messages are buffered until the application drains them
+        SwordFishMessageCategory::Stream => {
+            if let Some(stream) = &bucket.stream {
+                stream.push(StreamRecord {
+                    msg,
+                    host_us: host_receive_us,
+                });
+            }
         }
+        //if message category is operation, place the message in the response bucket and notify the waiting thread
+        SwordFishMessageCategory::Operation(Some(response_opcode)) => {
+            if let Some(response_bucket) = gaurd.get(&response_opcode) {
+                let mut bucket_msg = response_bucket
+                    .message
+                    .lock()
+                    .expect("Another thread holding the mutex panicked");
+                *bucket_msg = Some(msg);
+                response_bucket.condvar.notify_one();
+            }
+        }
+        _ => {}
     }
 }
 
@@ -91,14 +306,36 @@ pub struct SwordFishComm {
     tx_counter: Arc<AtomicUsize>,
     rx_counter: Arc<AtomicUsize>,
     messages_hashmap: Arc<RwLock<HashMap<u8, SwordFishMessageBucket>>>,
+    registry: RwLock<MessageRegistry>,
+    rx_services: Arc<RxServices>,
+    capture: Arc<FrameCapture>,
+    recorder: Arc<SessionRecorder>,
+    owns_serial_port: bool,
 }
 
 impl SwordFishComm {
+    //a serial port, a board served by swordfish-bridge as "tcp://token@host:port"
+    //or by swordfish-daemon as "unix:///path/to/socket?subscribe=ImuSample"
     pub fn new(portpath: &str) -> Result<SwordFishComm, serialport::Error> {
+        #[cfg(feature = "bridge")]
+        if let Some((address, token)) = parse_bridge_url(portpath) {
+            return SwordFishComm::connect_tcp(&address, token.as_deref())
+                .map_err(|e| serialport::Error::new(serialport::ErrorKind::NoDevice, e.to_string()));
+        }
+        #[cfg(not(feature = "bridge"))]
+        if portpath.starts_with("tcp://") {
+            return Err(serialport::Error::new(
+                serialport::ErrorKind::InvalidInput,
+                "tcp:// ports need the bridge feature of swordfish_com",
+            ));
+        }
+        #[cfg(unix)]
+        if let Some((path, subscriptions)) = parse_daemon_url(portpath) {
+            return SwordFishComm::connect_unix(&path, &subscriptions)
+                .map_err(|e| serialport::Error::new(serialport::ErrorKind::NoDevice, e.to_string()));
+        }
         if INSTANCE_COUNTER.load(Ordering::SeqCst) > 0 {
             panic!("{color_red}Only one instance of SwordFishComm is allowed{color_reset}");
-        } else {
-            INSTANCE_COUNTER.fetch_add(1, Ordering::Relaxed);
         }
 
         let port_builder = serialport::new(portpath, 115200)
@@ -107,36 +344,97 @@ impl SwordFishComm {
             .data_bits(DataBits::Eight)
             .timeout(Duration::from_millis(0)); //non-blocking
 
+        let port: Box<dyn SerialPort> = port_builder.open()?;
+        INSTANCE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut swordfish_comm = SwordFishComm::from_transport(Box::new(port));
+        swordfish_comm.owns_serial_port = true;
+        return Ok(swordfish_comm);
+    }
+
+    //a session with a swordfish-bridge, the port belongs to the bridge so this does not count as the instance
+    #[cfg(feature = "bridge")]
+    pub fn connect_tcp(address: &str, token: Option<&str>) -> anyhow::Result<SwordFishComm> {
+        let transport = TcpTransport::connect(address, token)?;
+        log::info!("Connected to {} through the bridge at {}", transport.device(), address);
+        Ok(SwordFishComm::from_transport(Box::new(transport)))
+    }
+
+    //a session with a swordfish-daemon, that shares the board with the other programs of the machine.
+    //subscriptions are the streams to receive when another client started them, "all" for every one
+    #[cfg(unix)]
+    pub fn connect_unix(path: &Path, subscriptions: &[String]) -> anyhow::Result<SwordFishComm> {
+        let transport = UnixTransport::connect(path, subscriptions)?;
+        log::info!("Connected to {} through the daemon at {}", transport.device(), path.display());
+        Ok(SwordFishComm::from_transport(Box::new(transport)))
+    }
+
+    //runs the protocol over any byte stream, e.g. the simulator or a socket
+    //reads of the transport should time out when there is no data, see swordfish_transport
+    pub fn from_transport(mut port: Box<dyn SwordFishTransport>) -> SwordFishComm {
         let swordfish_messages_hashmap = Arc::new(RwLock::new(create_swordfish_messages_hashmap()));
 
         let (master_transmitter, slave_receiver) = mpsc::channel::<SwordFishConcentratedMessage>();
 
-        let mut port: Box<dyn SerialPort> = port_builder.open()?;
         let thread_alive = Arc::new(AtomicBool::new(true));
         let rx_counter = Arc::new(AtomicUsize::new(0));
         let tx_counter = Arc::new(AtomicUsize::new(0));
+        let rx_services = Arc::new(RxServices::default());
+        let capture = Arc::new(FrameCapture::default());
+        let recorder = Arc::new(SessionRecorder::default());
 
+        let rx_services_clone = rx_services.clone();
         let swordfish_messages_hashmap_clone = swordfish_messages_hashmap.clone();
         let thread_alive_clone = thread_alive.clone();
         let rx_counter_clone = Arc::clone(&rx_counter);
         let tx_counter_clone = Arc::clone(&tx_counter);
+        let capture_clone = capture.clone();
+        let recorder_clone = recorder.clone();
         let thread_handle = spawn(move || {
             let mut read_buffer = [0; CONCENTRATED_MESSAGE_TOTAL_SIZE];
             let mut concentrated_messsage_builder: SwordFishConcentratedMessageBufferBuilder =
                 SwordFishConcentratedMessageBufferBuilder::new();
+            let bad_frame_capture = capture_clone.clone();
+            let bad_frame_services = rx_services_clone.clone();
+            concentrated_messsage_builder.on_bad_frame(Box::new(move |frame| {
+                LinkCounters::add(&bad_frame_services.link.bad_frames, 1);
+                bad_frame_capture.record(Direction::Rx, host_now_us(), frame, true);
+            }));
             while thread_alive_clone.load(Ordering::Relaxed) {
-                //check if there is anything to write
-                if let Ok(msg) = slave_receiver.try_recv() {
+                rx_services_clone.operations.poll_timeouts();
+                //check if there is anything to write, or if it is time to sync the clocks
+                let tx_msg = slave_receiver.try_recv().ok().or_else(|| {
+                    rx_services_clone
+                        .clock_sync
+                        .due_request()
+                        .map(|request| request.to_concentrated(tx_counter_clone.fetch_add(1, Ordering::Relaxed) as u16))
+                });
+                if let Some(msg) = tx_msg {
                     let buffer = msg.into_bytes();
-                    match port.write(&buffer) {
-                        Ok(_n_bytes_written) => match port.flush() {
+                    match port.write_all(&buffer) {
+                        Ok(()) => match port.flush() {
                             Ok(_) => {
-                                tx_counter_clone.fetch_add(1, Ordering::Relaxed);
+                                let host_send_us = host_now_us();
+                                LinkCounters::add(&rx_services_clone.link.tx_frames, 1);
+                                LinkCounters::add(&rx_services_clone.link.tx_bytes, buffer.len());
+                                recorder_clone.record(Direction::Tx, host_send_us, &buffer);
+                                capture_clone.record(Direction::Tx, host_send_us, &buffer, false);
+                                for (_, tx_hook) in rx_services_clone
+                                    .tx_hooks
+                                    .lock()
+                                    .expect("Another thread holding the mutex panicked")
+                                    .iter_mut()
+                                {
+                                    tx_hook(&msg, host_send_us);
+                                }
+                            }
+                            Err(e) => {
+                                LinkCounters::add(&rx_services_clone.link.write_errors, 1);
+                                log::error!("{}-{} : {:?}", file!(), line!(), e);
                             }
-                            Err(e) => log::error!("{}-{} : {:?}", file!(), line!(), e),
                         },
                         Err(e) => {
                             //write error
+                            LinkCounters::add(&rx_services_clone.link.write_errors, 1);
                             log::error!("{}-{} : {:?}", file!(), line!(), e);
                             if e.kind() == std::io::ErrorKind::BrokenPipe {
                                 thread_alive_clone.store(false, Ordering::Relaxed);
@@ -149,54 +447,18 @@ impl SwordFishComm {
                 //check if there is anything to read
                 match port.read(&mut read_buffer) {
                     Ok(n_bytes_read) => {
-                        if let Some(msg) = concentrated_messsage_builder
-                            .append_buffer(&read_buffer[0..n_bytes_read])
-                        {
+                        let host_receive_us = host_now_us();
+                        LinkCounters::add(&rx_services_clone.link.rx_bytes, n_bytes_read);
+                        recorder_clone.record(Direction::Rx, host_receive_us, &read_buffer[0..n_bytes_read]);
+                        let mut next_msg = concentrated_messsage_builder.append_buffer(&read_buffer[0..n_bytes_read]);
+                        while let Some(msg) = next_msg {
                             rx_counter_clone.fetch_add(1, Ordering::Relaxed);
-                            let gaurd = swordfish_messages_hashmap_clone
-                                .read()
-                                .expect("we are only reading, this should work");
-                            let bucket = gaurd
-                                .get(&msg.opcode)
-                                .expect("Opcode not found in hashmap, this should never happen");
-
-                            //if message has an rx callback, do it
-                            if let Some(rx_callback) = bucket
-                                .on_rx_callback
-                                .lock()
-                                .expect("Another thread holding the mutex panicked")
-                                .as_mut()
-                            {
-                                rx_callback(msg);
-                            }
-
-                            //if message category is bounce or param, place the message in the bucket and notify the waiting thread
-                            match bucket.catagory {
-                                SwordFishMessageCategory::Bounce | SwordFishMessageCategory::Param => {
-                                    let mut bucket_msg = bucket
-                                        .message
-                                        .lock()
-                                        .expect("Another thread holding the mutex panicked");
-                                    *bucket_msg = Some(msg);
-                                    bucket.condvar.notify_one();
-                                }
-                                //if message category is operation, place the message in the response bucket and notify the waiting thread
-                                SwordFishMessageCategory::Operation(Some(response_opcode)) => {
-                                    let gaurd = swordfish_messages_hashmap_clone
-                                        .read()
-                                        .expect("we are only reading, this should work");
-                                    let response_bucket = gaurd.get(&response_opcode).expect(
-                                        "Opcode not found in hashmap, this should never happen",
-                                    );
-                                    let mut bucket_msg = response_bucket
-                                        .message
-                                        .lock()
-                                        .expect("Another thread holding the mutex panicked");
-                                    *bucket_msg = Some(msg);
-                                    response_bucket.condvar.notify_one();
-                                }
-                                _ => {}
+                            LinkCounters::add(&rx_services_clone.link.rx_frames, 1);
+                            if capture_clone.is_active() {
+                                capture_clone.record(Direction::Rx, host_receive_us, &msg.into_bytes(), false);
                             }
+                            dispatch_rx_message(&swordfish_messages_hashmap_clone, &rx_services_clone, msg, host_receive_us);
+                            next_msg = concentrated_messsage_builder.next_message();
                         }
                     }
                     Err(e) => {
@@ -212,75 +474,330 @@ impl SwordFishComm {
             }
         });
 
-        return Ok(SwordFishComm {
+        return SwordFishComm {
             thread_handle: Some(thread_handle),
             thread_alive: thread_alive,
             transmitter: master_transmitter,
             tx_counter: tx_counter,
             rx_counter: rx_counter,
             messages_hashmap: swordfish_messages_hashmap,
-        });
+            registry: RwLock::new(MessageRegistry::new()),
+            rx_services: rx_services,
+            capture,
+            recorder,
+            owns_serial_port: false,
+        };
     }
 
+    //the number of counters handed out by next_tx_counter, the next message gets this one
     pub fn get_tx_counter(&self) -> usize {
         self.tx_counter.load(Ordering::SeqCst)
     }
 
+    //the counter of a new message, no two messages (TimeSync and OperationAbort included) get the same one
+    pub fn next_tx_counter(&self) -> u16 {
+        self.tx_counter.fetch_add(1, Ordering::SeqCst) as u16
+    }
+
     pub fn get_rx_counter(&self) -> usize {
         self.rx_counter.load(Ordering::SeqCst)
     }
 
+    //the firmware log, forwarded to the log crate under swordfish::device
+    pub fn device_log(&self) -> &DeviceLogForwarder {
+        &self.rx_services.device_log
+    }
+
+    //the device clock, set_period starts the periodic TimeSync round trips
+    pub fn clock_sync(&self) -> &ClockSync {
+        &self.rx_services.clock_sync
+    }
+
+    //host time of a device timestamp (us since the device booted), None before the first time sync
+    pub fn device_to_host_time(&self, device_us: u64) -> Option<std::time::SystemTime> {
+        self.rx_services.clock_sync.device_to_host_time(device_us)
+    }
+
+    //sees every received message with its timestamps, returns the id for remove_rx_hook
+    pub fn add_rx_hook(&self, rx_hook: RxHook) -> usize {
+        let id = self.rx_services.next_hook_id.fetch_add(1, Ordering::Relaxed);
+        self.rx_services
+            .rx_hooks
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .push((id, rx_hook));
+        id
+    }
+
+    pub fn remove_rx_hook(&self, id: usize) {
+        self.rx_services
+            .rx_hooks
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .retain(|(hook_id, _)| *hook_id != id);
+    }
+
+    //sees every message once it was written to the port, returns the id for remove_tx_hook
+    pub fn add_tx_hook(&self, tx_hook: TxHook) -> usize {
+        let id = self.rx_services.next_hook_id.fetch_add(1, Ordering::Relaxed);
+        self.rx_services
+            .tx_hooks
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .push((id, tx_hook));
+        id
+    }
+
+    pub fn remove_tx_hook(&self, id: usize) {
+        self.rx_services
+            .tx_hooks
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .retain(|(hook_id, _)| *hook_id != id);
+    }
+
+    pub fn link_stats(&self) -> LinkStats {
+        self.rx_services.link.snapshot()
+    }
+
     pub fn send_msg(&self, msg: SwordFishConcentratedMessage) -> Option<SwordFishConcentratedMessage> {
+        self.send_msg_with_timeout(msg, Duration::from_millis(200))
+    }
+
+    //like send_msg, for operations that take longer than the default 200ms to answer (erasing flash, ...)
+    pub fn send_msg_with_timeout(
+        &self,
+        msg: SwordFishConcentratedMessage,
+        timeout: Duration,
+    ) -> Option<SwordFishConcentratedMessage> {
+        match self.request_with_timeout(msg, timeout) {
+            Ok(answer) => Some(answer),
+            Err(SwordFishError::NoAnswerExpected { .. }) => None,
+            Err(e @ (SwordFishError::DeviceRejected { .. } | SwordFishError::UnknownOpcode { .. })) => {
+                log::warn!("{}", e);
+                None
+            }
+            Err(e) => {
+                log::debug!("{}", e);
+                None
+            }
+        }
+    }
+
+    //like send_msg, with a typed error when the device rejects the request with a Nack
+    pub fn request(&self, msg: SwordFishConcentratedMessage) -> Result<SwordFishConcentratedMessage, SwordFishError> {
+        self.request_with_timeout(msg, Duration::from_millis(200))
+    }
+
+    pub fn request_with_timeout(
+        &self,
+        msg: SwordFishConcentratedMessage,
+        timeout: Duration,
+    ) -> Result<SwordFishConcentratedMessage, SwordFishError> {
         let gaurd = self
             .messages_hashmap
             .read()
             .expect("we are only reading, this should work");
-        let bucket = gaurd
-            .get(&msg.opcode)
-            .expect("Opcode not found in hashmap, this should never happen");
+        if !gaurd.contains_key(&msg.opcode) {
+            return Err(SwordFishError::UnknownOpcode { opcode: msg.opcode });
+        }
+        let response_bucket = match response_bucket(&gaurd, msg.opcode) {
+            Some(response_bucket) => response_bucket,
+            None => {
+                self.transmitter.send(msg).expect("Failed to send message");
+                return Err(SwordFishError::NoAnswerExpected { opcode: msg.opcode });
+            }
+        };
 
+        //the bucket is locked before sending, so the answer can not arrive before we wait for it
+        let mut response_msg = response_bucket
+            .message
+            .lock()
+            .expect("Another thread holding the mutex panicked");
+        //drop a late answer to an earlier request
+        response_msg.take();
+        set_waiting_counter(response_bucket, Some(msg.counter));
         self.transmitter.send(msg).expect("Failed to send message");
-
-        match bucket.catagory {
-            SwordFishMessageCategory::Bounce | SwordFishMessageCategory::Param => {
-                if let Ok(mut optional_response_msg) = bucket.condvar.wait_timeout(
-                    bucket
-                        .message
-                        .lock()
-                        .expect("Another thread holding the mutex panicked"),
-                    Duration::from_millis(200),
-                ) {
-                    if let Some(response_msg) = optional_response_msg.0.take() {
-                        return Some(response_msg);
-                    } else {
-                        println!("No response message");
-                    }
+        let echoes_counter = gaurd
+            .get(&msg.opcode)
+            .is_some_and(|bucket| bucket.catagory.echoes_counter());
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (mut optional_response_msg, _) = response_bucket
+                .condvar
+                .wait_timeout_while(response_msg, remaining, |response_msg| response_msg.is_none())
+                .expect("Another thread holding the mutex panicked");
+            match optional_response_msg.take() {
+                None => {
+                    LinkCounters::add(&self.rx_services.link.timeouts, 1);
+                    break Err(SwordFishError::Timeout { opcode: msg.opcode });
                 }
-                return None;
-            }
-            SwordFishMessageCategory::Operation(Some(response_opcode)) => {
-                let gaurd = self
-                    .messages_hashmap
-                    .read()
-                    .expect("we are only reading, this should work");
-                let response_bucket = gaurd
-                    .get(&response_opcode)
-                    .expect("Opcode not found in hashmap, this should never happen");
-                if let Ok(mut optional_response_msg) = bucket.condvar.wait_timeout(
-                    response_bucket
-                        .message
-                        .lock()
-                        .expect("Another thread holding the mutex panicked"),
-                    Duration::from_millis(200),
-                ) {
-                    if let Some(response_msg) = optional_response_msg.0.take() {
-                        return Some(response_msg);
+                Some(answer) if answer.opcode == Nack::OPCODE => match Nack::from_concentrated(&answer) {
+                    Ok(nack) if nack.get_opcode() == msg.opcode && nack.get_counter() == msg.counter => {
+                        LinkCounters::add(&self.rx_services.link.rejected, 1);
+                        break Err(SwordFishError::from(&nack));
                     }
+                    _ => log::debug!("Ignoring a nack of an earlier request"),
+                },
+                Some(answer) if echoes_counter && answer.counter != msg.counter => {
+                    log::debug!("Ignoring a late answer #{} to opcode {}", answer.counter, msg.opcode)
                 }
-                return None;
+                Some(answer) => break Ok(answer),
             }
-            _ => return None,
+            response_msg = optional_response_msg;
+        };
+        set_waiting_counter(response_bucket, None);
+        result
+    }
+
+    //adds an opcode that is not known at compile time, e.g. one described by a MessageDescriptor
+    pub fn register_message(&self, descriptor: &MessageDescriptor) -> anyhow::Result<()> {
+        let mut gaurd = self
+            .messages_hashmap
+            .write()
+            .expect("we are the only writers, this should work");
+        if gaurd.contains_key(&descriptor.opcode) {
+            return Err(anyhow::anyhow!(
+                "Opcode {} is already registered",
+                descriptor.opcode
+            ));
         }
+        self.registry
+            .write()
+            .expect("Another thread holding the lock panicked")
+            .register(MessageInfo::from(descriptor))?;
+        gaurd.insert(
+            descriptor.opcode,
+            SwordFishMessageBucket::new(descriptor.category),
+        );
+        Ok(())
+    }
+
+    //the compiled messages and the ones added with register_message
+    pub fn registry(&self) -> MessageRegistry {
+        self.registry
+            .read()
+            .expect("Another thread holding the lock panicked")
+            .clone()
+    }
+
+    //---------------------capture---------------------
+    //records every frame sent and received from now on to a pcapng file, see swordfish_capture
+    pub fn start_capture(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.start_capture_to(Box::new(std::io::BufWriter::new(file)))
+    }
+
+    pub fn start_capture_to(&self, writer: Box<dyn Write + Send>) -> anyhow::Result<()> {
+        Ok(self.capture.start(writer)?)
+    }
+
+    //returns the number of frames captured
+    pub fn stop_capture(&self) -> anyhow::Result<u64> {
+        Ok(self.capture.stop()?)
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_active()
+    }
+
+    //---------------------session recording---------------------
+    //records the raw bytes sent and received from now on with their timing, to be replayed
+    //with swordfish_session::ReplayTransport
+    pub fn start_recording(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.start_recording_to(Box::new(std::io::BufWriter::new(file)))
+    }
+
+    pub fn start_recording_to(&self, writer: Box<dyn Write + Send>) -> anyhow::Result<()> {
+        Ok(self.recorder.start(writer)?)
+    }
+
+    //returns the number of chunks recorded
+    pub fn stop_recording(&self) -> anyhow::Result<u64> {
+        Ok(self.recorder.stop()?)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_active()
+    }
+
+    //---------------------long-running operations---------------------
+    //sends an operation that the device answers with OperationProgress messages and an OperationResult,
+    //it times out when nothing arrives for timeout (see swordfish_operation::DEFAULT_OPERATION_TIMEOUT)
+    pub fn start_operation(&self, msg: SwordFishConcentratedMessage, timeout: Duration) -> anyhow::Result<OperationHandle> {
+        let is_operation = matches!(
+            self.messages_hashmap
+                .read()
+                .expect("we are only reading, this should work")
+                .get(&msg.opcode)
+                .map(|bucket| bucket.catagory),
+            Some(SwordFishMessageCategory::Operation(None))
+        );
+        if !is_operation {
+            return Err(anyhow::anyhow!("Opcode {} is not an operation without a response", msg.opcode));
+        }
+        //tracked before it is sent, so the first progress can not be missed
+        let handle = self.rx_services.operations.start_handle(
+            msg.opcode,
+            msg.counter,
+            timeout,
+            self.transmitter.clone(),
+            self.tx_counter.clone(),
+        )?;
+        self.transmitter.send(msg).expect("Failed to send message");
+        Ok(handle)
+    }
+
+    //the opcodes of the operations that have not ended yet
+    pub fn running_operations(&self) -> Vec<u8> {
+        self.rx_services.operations.running()
+    }
+
+    //---------------------streams---------------------
+    //asks the device to push the stream message with this opcode rate_hz times a second
+    pub fn start_stream(&self, opcode: u8, rate_hz: u16) -> anyhow::Result<()> {
+        self.stream_buffer(opcode)
+            .ok_or_else(|| anyhow::anyhow!("Opcode {} is not a stream", opcode))?;
+        let request = StreamStart::new(opcode, rate_hz);
+        let answer = self.request(request.to_concentrated(self.next_tx_counter()))?;
+        if StreamStart::from_concentrated(&answer)? != request {
+            return Err(anyhow::anyhow!("The device did not start stream {}", opcode));
+        }
+        Ok(())
+    }
+
+    pub fn stop_stream(&self, opcode: u8) -> anyhow::Result<()> {
+        let request = StreamStop::new(opcode);
+        self.request(request.to_concentrated(self.next_tx_counter()))?;
+        Ok(())
+    }
+
+    //None if the opcode is not a registered Stream message
+    pub fn stream_buffer(&self, opcode: u8) -> Option<Arc<StreamBuffer>> {
+        self.messages_hashmap
+            .read()
+            .expect("we are only reading, this should work")
+            .get(&opcode)
+            .and_then(|bucket| bucket.stream.clone())
+    }
+
+    //at most max buffered messages of the stream T, oldest first. empty if T is not a stream
+    pub fn drain<T: SwordFishMessageTrait>(&self, max: usize) -> Vec<T> {
+        match self.stream_buffer(T::OPCODE) {
+            Some(stream) => stream.drain::<T>(max),
+            None => Vec::new(),
+        }
+    }
+
+    //resizes the ring buffer of the stream, keeping the newest messages that fit
+    pub fn set_stream_capacity(&self, opcode: u8, capacity: usize) -> anyhow::Result<()> {
+        let stream = self
+            .stream_buffer(opcode)
+            .ok_or_else(|| anyhow::anyhow!("Opcode {} is not a stream", opcode))?;
+        stream.set_capacity(capacity);
+        Ok(())
     }
 
     pub fn change_message_rx_callback(
@@ -308,6 +825,16 @@ impl Drop for SwordFishComm {
                 .join()
                 .expect("The thread that handles reads could not be joined");
         }
+        if let Err(e) = self.capture.stop() {
+            log::error!("Could not finish the capture: {}", e);
+        }
+        if let Err(e) = self.recorder.stop() {
+            log::error!("Could not finish the recording: {}", e);
+        }
+        //the port is closed, another instance can open it
+        if self.owns_serial_port {
+            INSTANCE_COUNTER.fetch_sub(1, Ordering::Relaxed);
+        }
     }
 }
 
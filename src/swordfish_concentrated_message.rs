@@ -1,10 +1,14 @@
 use inline_colorization::{color_red, color_reset};
-pub const MAX_PAYLOAD_SIZE: usize = 245;
+include!("../codegen/max_payload_size.rs");
 pub const TOTAL_MESSAGE_SIZE: usize = 255;
 pub const HEADER_SIZE: usize = 9;
 const SYNC_WORD_TO_SWORDFISH_U32: u32 = 0xefbeadde;
 pub const SYNC_WORD_FROM_SWORDFISH: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
 pub const _SYNC_WORD_TO_SWORDFISH: [u8; 4] = [0xef, 0xbe, 0xad, 0xde];
+//the sync words a frame can start with, per direction. this crate writes SYNC_WORD_TO_SWORDFISH_U32 little endian,
+//the bytes of SYNC_WORD_FROM_SWORDFISH, a host program that writes it big endian sends _SYNC_WORD_TO_SWORDFISH
+pub const SYNC_WORDS_FROM_DEVICE: &[[u8; 4]] = &[SYNC_WORD_FROM_SWORDFISH];
+pub const SYNC_WORDS_FROM_HOST: &[[u8; 4]] = &[SYNC_WORD_FROM_SWORDFISH, _SYNC_WORD_TO_SWORDFISH];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SwordFishConcentratedMessage {
@@ -69,6 +73,44 @@ impl SwordFishConcentratedMessage {
         checksum
     }
 
+    //the checksum of the header and the valid payload bytes, what the checksum field should hold
+    pub fn expected_checksum(&self) -> u8 {
+        let length = std::cmp::min(self.length as usize, MAX_PAYLOAD_SIZE);
+        SwordFishConcentratedMessage::calculate_checksum(self.sync_word, self.counter, self.opcode, self.length, &self.payload[..length])
+    }
+
+    pub fn checksum_is_valid(&self) -> bool {
+        self.checksum == self.expected_checksum()
+    }
+
+    //one whole frame, from the sync word to the checksum. None if its length or its checksum is wrong
+    pub fn parse_frame(frame: &[u8]) -> Option<SwordFishConcentratedMessage> {
+        if frame.len() < HEADER_SIZE + 1 {
+            return None;
+        }
+        let sync_word = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        let counter = u16::from_le_bytes([frame[4], frame[5]]);
+        let opcode = frame[6];
+        let length = u16::from_le_bytes([frame[7], frame[8]]);
+        if length as usize > MAX_PAYLOAD_SIZE || frame.len() != HEADER_SIZE + length as usize + 1 {
+            return None;
+        }
+        let mut payload = [0; MAX_PAYLOAD_SIZE];
+        payload[..length as usize].copy_from_slice(&frame[HEADER_SIZE..HEADER_SIZE + length as usize]);
+        let checksum = frame[frame.len() - 1];
+        if SwordFishConcentratedMessage::calculate_checksum(sync_word, counter, opcode, length, &payload) != checksum {
+            return None;
+        }
+        Some(SwordFishConcentratedMessage {
+            sync_word,
+            counter,
+            opcode,
+            length,
+            payload,
+            checksum,
+        })
+    }
+
     pub fn into_bytes(self) -> Box<[u8]> {
         //future: fix this by building correct size of buffer to begin wtth
         let mut buffer = [0; TOTAL_MESSAGE_SIZE];
@@ -98,19 +140,167 @@ impl Default for SwordFishConcentratedMessage {
     }
 }
 
+//--------------serde------------------//
+//the frame field by field, with the payload cut to its length: hex in human readable formats like json, bytes in cbor.
+//the checksum is kept as it was, so a frame with a bad one comes back the same. a length past MAX_PAYLOAD_SIZE
+//is kept too, with the MAX_PAYLOAD_SIZE bytes the frame holds
+#[cfg(feature = "serde")]
+impl serde::Serialize for SwordFishConcentratedMessage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let payload = &self.payload[..std::cmp::min(self.length as usize, MAX_PAYLOAD_SIZE)];
+        let human_readable = serializer.is_human_readable();
+        let mut frame = serializer.serialize_struct("SwordFishConcentratedMessage", 6)?;
+        frame.serialize_field("sync_word", &self.sync_word)?;
+        frame.serialize_field("counter", &self.counter)?;
+        frame.serialize_field("opcode", &self.opcode)?;
+        frame.serialize_field("length", &self.length)?;
+        match human_readable {
+            true => frame.serialize_field("payload", &crate::swordfish_util::to_hex(payload))?,
+            false => frame.serialize_field("payload", &PayloadBytes(payload.to_vec()))?,
+        }
+        frame.serialize_field("checksum", &self.checksum)?;
+        frame.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SwordFishConcentratedMessage {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            sync_word: u32,
+            counter: u16,
+            opcode: u8,
+            length: u16,
+            payload: PayloadBytes,
+            checksum: u8,
+        }
+        let fields = Fields::deserialize(deserializer)?;
+        let PayloadBytes(bytes) = fields.payload;
+        if bytes.len() != std::cmp::min(fields.length as usize, MAX_PAYLOAD_SIZE) {
+            return Err(serde::de::Error::custom(format!(
+                "{} payload bytes for a length of {}, at most {} are kept",
+                bytes.len(),
+                fields.length,
+                MAX_PAYLOAD_SIZE
+            )));
+        }
+        let mut payload = [0; MAX_PAYLOAD_SIZE];
+        payload[..bytes.len()].copy_from_slice(&bytes);
+        Ok(SwordFishConcentratedMessage {
+            sync_word: fields.sync_word,
+            counter: fields.counter,
+            opcode: fields.opcode,
+            length: fields.length,
+            payload,
+            checksum: fields.checksum,
+        })
+    }
+}
+
+//a payload as bytes, read back from bytes, an array of numbers or a hex string
+#[cfg(feature = "serde")]
+struct PayloadBytes(Vec<u8>);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PayloadBytes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PayloadBytes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PayloadVisitor;
+        impl<'de> serde::de::Visitor<'de> for PayloadVisitor {
+            type Value = PayloadBytes;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "payload bytes or a hex string")
+            }
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<PayloadBytes, E> {
+                crate::swordfish_util::from_hex(text).map(PayloadBytes).map_err(E::custom)
+            }
+            fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<PayloadBytes, E> {
+                Ok(PayloadBytes(bytes.to_vec()))
+            }
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<PayloadBytes, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Ok(PayloadBytes(bytes))
+            }
+        }
+        //whatever the format holds, so json takes arrays as well as hex strings
+        deserializer.deserialize_any(PayloadVisitor)
+    }
+}
+
+//json and cbor of a frame that give back the same frame, counter, opcode and checksum included
+#[cfg(feature = "serde")]
+impl SwordFishConcentratedMessage {
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_cbor(&self) -> anyhow::Result<Vec<u8>> {
+        let mut cbor = Vec::new();
+        ciborium::into_writer(self, &mut cbor)?;
+        Ok(cbor)
+    }
+
+    pub fn from_cbor(cbor: &[u8]) -> anyhow::Result<Self> {
+        Ok(ciborium::from_reader(cbor)?)
+    }
+}
+
+pub type BadFrameCallback = Box<dyn FnMut(&[u8]) + Send>;
+
+//a noisy line drops many frames, they are dumped at debug level and counted in one warning per interval
+const BAD_CHECKSUM_WARNING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 pub struct SwordFishConcentratedMessageBufferBuilder {
     accumulated_buffer: [u8; TOTAL_MESSAGE_SIZE * 3],
     n_accum_bytes: usize,
+    on_bad_frame: Option<BadFrameCallback>,
+    bad_checksums: u64, //since the last warning
+    last_bad_checksum_warning: Option<std::time::Instant>,
+    sync_words: &'static [[u8; 4]],
 }
 
 impl SwordFishConcentratedMessageBufferBuilder {
+    //parses the frames of the device
     pub fn new() -> Self {
+        SwordFishConcentratedMessageBufferBuilder::with_sync_words(SYNC_WORDS_FROM_DEVICE)
+    }
+
+    //parses the frames of a host, like the device does (the simulator uses it)
+    pub fn for_host_frames() -> Self {
+        SwordFishConcentratedMessageBufferBuilder::with_sync_words(SYNC_WORDS_FROM_HOST)
+    }
+
+    pub fn with_sync_words(sync_words: &'static [[u8; 4]]) -> Self {
         SwordFishConcentratedMessageBufferBuilder {
             accumulated_buffer: [0; TOTAL_MESSAGE_SIZE * 3],
             n_accum_bytes: 0,
+            on_bad_frame: None,
+            bad_checksums: 0,
+            last_bad_checksum_warning: None,
+            sync_words,
         }
     }
 
+    //called with every frame that is dropped because of its checksum, e.g. to capture it
+    pub fn on_bad_frame(&mut self, callback: BadFrameCallback) {
+        self.on_bad_frame = Some(callback);
+    }
+
     pub fn append_buffer(&mut self, buffer: &[u8]) -> Option<SwordFishConcentratedMessage> {
         //copy buffer into accumulated buffer
         if self.n_accum_bytes + buffer.len() > self.accumulated_buffer.len() {
@@ -126,73 +316,153 @@ impl SwordFishConcentratedMessageBufferBuilder {
             .copy_from_slice(buffer);
         self.n_accum_bytes += buffer.len();
 
-        if self.accumulated_buffer.len() < HEADER_SIZE {
-            //nothing to look for
-            return None;
-        }
+        self.next_message()
+    }
 
-        let sync_word_window = self
-            .accumulated_buffer
-            .windows(4)
-            .position(|window| window == SYNC_WORD_FROM_SWORDFISH);
-        if let Some(start_pos) = sync_word_window {
-            let payload_length = u16::from_le_bytes([buffer[start_pos + 7], buffer[start_pos + 8]]);
-            if payload_length > MAX_PAYLOAD_SIZE as u16 {
-                //bad message, remove it
+    //a single read can hold several messages, call this until it returns None
+    pub fn next_message(&mut self) -> Option<SwordFishConcentratedMessage> {
+        loop {
+            let sync_word_window = self.accumulated_buffer[..self.n_accum_bytes]
+                .windows(4)
+                .position(|window| self.sync_words.iter().any(|sync_word| window == sync_word));
+            let start_pos = match sync_word_window {
+                Some(start_pos) => start_pos,
+                None => {
+                    //couldnt find sync word, keep the last bytes, they can be the start of one
+                    self.consume(self.n_accum_bytes.saturating_sub(SYNC_WORD_FROM_SWORDFISH.len() - 1));
+                    return None;
+                }
+            };
+            //drop the garbage before the sync word
+            self.consume(start_pos);
+
+            if self.n_accum_bytes < HEADER_SIZE {
+                //nothing to look for
                 return None;
             }
+            let payload_length =
+                u16::from_le_bytes([self.accumulated_buffer[7], self.accumulated_buffer[8]]);
+            if payload_length > MAX_PAYLOAD_SIZE as u16 {
+                //bad message, skip its sync word and look for the next one
+                self.consume(1);
+                continue;
+            }
             let msg_length = HEADER_SIZE + payload_length as usize + 1; //+1 for checksum
-            let msg_end = start_pos + msg_length;
-            let msg_range = start_pos..msg_end;
-
-            let msg_buffer = &self.accumulated_buffer[msg_range.clone()];
-            let sync_word =
-                u32::from_le_bytes([msg_buffer[0], msg_buffer[1], msg_buffer[2], msg_buffer[3]]);
-            let counter = u16::from_le_bytes([msg_buffer[4], msg_buffer[5]]);
-            let opcode = msg_buffer[6];
-
-            let payload = {
-                let mut p = [0; MAX_PAYLOAD_SIZE];
-                let payload_start = HEADER_SIZE;
-                let payload_end = HEADER_SIZE + payload_length as usize;
-                p[..payload_length as usize]
-                    .copy_from_slice(&msg_buffer[payload_start..payload_end]);
-                p
-            };
-
-            let checksum = msg_buffer[msg_buffer.len() - 1];
-            let calc_checksum = SwordFishConcentratedMessage::calculate_checksum(
-                sync_word,
-                counter,
-                opcode,
-                payload_length,
-                &payload,
-            );
-            if calc_checksum == checksum {
-                //remove msg_range.end bytes from accumulated_buffer by copying the rest of the buffer to the beginning
-                self.accumulated_buffer.copy_within(msg_range.end.., 0);
-                self.n_accum_bytes -= msg_range.end;
-                Some(SwordFishConcentratedMessage {
-                    sync_word: sync_word,
-                    counter: counter,
-                    opcode: opcode,
-                    length: payload_length,
-                    payload: payload,
-                    checksum: checksum,
-                })
-            } else {
-                //bad message, wrong checksum, remove it
+            if self.n_accum_bytes < msg_length {
+                //wait for the rest of the message
                 return None;
             }
-        } else {
-            //couldnt find sync word
-            if self.n_accum_bytes >= 2 * TOTAL_MESSAGE_SIZE {
-                //remove TOTAL_MESSAGE_SIZE bytes from buffer by shifting the rest of the buffer to the beginning
-                //this prevent accumulation of bad messages that will cause buffer overflow
-                self.accumulated_buffer.copy_within(TOTAL_MESSAGE_SIZE.., 0);
-                self.n_accum_bytes -= TOTAL_MESSAGE_SIZE;
+
+            let msg_buffer = &self.accumulated_buffer[..msg_length];
+            if let Some(msg) = SwordFishConcentratedMessage::parse_frame(msg_buffer) {
+                self.consume(msg_length);
+                return Some(msg);
+            } else {
+                //bad message, wrong checksum, skip its sync word
+                log::debug!(
+                    "Dropping message with opcode {}, bad checksum: {}",
+                    msg_buffer[6],
+                    crate::swordfish_util::to_hex(msg_buffer)
+                );
+                self.bad_checksums += 1;
+                if self
+                    .last_bad_checksum_warning
+                    .is_none_or(|last| last.elapsed() >= BAD_CHECKSUM_WARNING_INTERVAL)
+                {
+                    log::warn!("Dropped {} messages with a bad checksum", self.bad_checksums);
+                    self.bad_checksums = 0;
+                    self.last_bad_checksum_warning = Some(std::time::Instant::now());
+                }
+                if let Some(on_bad_frame) = self.on_bad_frame.as_mut() {
+                    on_bad_frame(msg_buffer);
+                }
+                self.consume(1);
             }
-            return None;
         }
     }
+
+    //removes n bytes from the start of accumulated_buffer by copying the rest of the buffer to the beginning
+    fn consume(&mut self, n: usize) {
+        self.accumulated_buffer.copy_within(n..self.n_accum_bytes, 0);
+        self.n_accum_bytes -= n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_back_to_back_messages() {
+        let first = SwordFishConcentratedMessage::new(1, 2, &[1, 2, 3]);
+        let second = SwordFishConcentratedMessage::new(2, 0, &[]);
+        let mut bytes = vec![0x55, 0xde, 0xad];
+        bytes.extend_from_slice(&first.into_bytes());
+        bytes.extend_from_slice(&second.into_bytes());
+
+        let mut builder = SwordFishConcentratedMessageBufferBuilder::new();
+        assert_eq!(builder.append_buffer(&bytes[..8]), None);
+        assert_eq!(builder.append_buffer(&bytes[8..]), Some(first));
+        assert_eq!(builder.next_message(), Some(second));
+        assert_eq!(builder.next_message(), None);
+    }
+
+    #[test]
+    fn bad_checksum_is_skipped() {
+        let good = SwordFishConcentratedMessage::new(3, 2, &[4, 5]);
+        let mut bad = good.into_bytes().to_vec();
+        *bad.last_mut().unwrap() ^= 0xff;
+        bad.extend_from_slice(&good.into_bytes());
+
+        let mut builder = SwordFishConcentratedMessageBufferBuilder::new();
+        assert_eq!(builder.append_buffer(&bad), Some(good));
+    }
+
+    #[test]
+    fn bad_checksums_are_counted_between_warnings() {
+        let good = SwordFishConcentratedMessage::new(3, 2, &[4, 5]);
+        let mut bad = good.into_bytes().to_vec();
+        *bad.last_mut().unwrap() ^= 0xff;
+
+        let mut builder = SwordFishConcentratedMessageBufferBuilder::new();
+        for _ in 0..3 {
+            assert_eq!(builder.append_buffer(&bad), None);
+        }
+        //the first one is warned about right away, the others wait for the next interval
+        assert_eq!(builder.bad_checksums, 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_and_cbor_round_trip() {
+        let mut frame = SwordFishConcentratedMessage::new(513, 2, &[1, 2, 0xff]);
+        let json = frame.to_json().unwrap();
+        assert!(json.contains("\"payload\":\"0102ff\""));
+        assert!(json.contains("\"counter\":513"));
+        assert_eq!(SwordFishConcentratedMessage::from_json(&json).unwrap(), frame);
+        assert_eq!(SwordFishConcentratedMessage::from_cbor(&frame.to_cbor().unwrap()).unwrap(), frame);
+
+        //a bad checksum is kept, a payload that does not match the length is refused
+        frame.checksum ^= 0xff;
+        assert_eq!(SwordFishConcentratedMessage::from_cbor(&frame.to_cbor().unwrap()).unwrap(), frame);
+        assert!(SwordFishConcentratedMessage::from_json(&json.replace("0102ff", "0102")).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn payloads_are_read_from_json_arrays() {
+        let frame = SwordFishConcentratedMessage::new(7, 2, &[1, 2, 0xff]);
+        let json = frame.to_json().unwrap().replace("\"0102ff\"", "[1,2,255]");
+        assert_eq!(SwordFishConcentratedMessage::from_json(&json).unwrap(), frame);
+        assert!(SwordFishConcentratedMessage::from_json(&json.replace("255", "256")).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn lengths_past_the_payload_round_trip() {
+        let mut frame = SwordFishConcentratedMessage::new(7, 2, &[0x5a; MAX_PAYLOAD_SIZE]);
+        frame.length = 300;
+        assert_eq!(SwordFishConcentratedMessage::from_json(&frame.to_json().unwrap()).unwrap(), frame);
+        assert_eq!(SwordFishConcentratedMessage::from_cbor(&frame.to_cbor().unwrap()).unwrap(), frame);
+    }
 }
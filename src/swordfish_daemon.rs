@@ -0,0 +1,854 @@
+//one board shared by the programs of a machine (the gui, a logger, the test runner). the daemon owns the port
+//and every program connects to its unix socket in place of the port:
+//  let daemon = SwordFishDaemon::spawn(UnixListener::bind(path)?, Box::new(port), DaemonConfig::default(), &registry, "/dev/ttyUSB0")?;
+//  let comm = SwordFishComm::new("unix:///run/user/1000/swordfish.sock?subscribe=ImuSample")?;
+//a session starts with a line each way, then carries swordfish frames as on the wire:
+//  daemon: swordfish-daemon <version>
+//  client: hello <version> [subscribe=<message>,<message>|all]
+//  daemon: ok <device>  or  error <reason>
+//the counters of the clients are rewritten to counters of the daemon on the way to the device, so the answers
+//(and the Nacks, whose payload holds the counter too) go back to the client that asked, with its own counter.
+//the messages nobody asked for (DeviceLog, ..) go to every client, stream samples to the clients that started
+//the stream or subscribed to it, and a stream stops when its last client stopped it or left.
+//an operation without a response (Calibrate) belongs to the client that started it until its OperationResult,
+//and the exclusive opcodes of DaemonConfig (the firmware update) lock the device for one client,
+//the others are answered with a busy Nack until it is released
+use crate::swordfish_error::codes;
+use crate::swordfish_messages::{
+    DfuCommit, DfuEnterBootloader, Nack, OperationAbort, OperationProgress, OperationResult, StreamStart, StreamStop,
+};
+use crate::swordfish_registry::MessageRegistry;
+use crate::swordfish_splitter::{FrameSplitter, Segment};
+use crate::swordfish_transport::SwordFishTransport;
+use crate::swordfish_util::read_line;
+use crate::{BoundedString, SwordFishConcentratedMessage, SwordFishMessageCategory, SwordFishMessageTrait, MAX_PAYLOAD_SIZE};
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DAEMON_PROTOCOL_VERSION: u32 = 1;
+
+const GREETING: &str = "swordfish-daemon";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+//a request the device did not answer in this time is given up, a late answer goes to nobody
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+//and its counter is free again after this
+const LATE_ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+//frames waiting for a slow client, more are dropped
+const CLIENT_QUEUE_SIZE: usize = 256;
+const POLL_PERIOD: Duration = Duration::from_millis(20);
+
+//$XDG_RUNTIME_DIR/swordfish.sock, or in a directory of the user in the temp dir (see ensure_private_directory)
+pub fn default_socket_path() -> PathBuf {
+    let directory = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(directory) => PathBuf::from(directory),
+        None => std::env::temp_dir().join(format!("swordfish-{}", unsafe { libc::getuid() })),
+    };
+    directory.join("swordfish.sock")
+}
+
+//creates the directory with mode 0700, or checks that the user owns it and the others can not use it,
+//so another user can not put a socket of their own at the default path first
+pub fn ensure_private_directory(directory: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    match std::fs::DirBuilder::new().mode(0o700).create(directory) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        result => result?,
+    }
+    let metadata = std::fs::symlink_metadata(directory)?;
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("not a directory only user {} can use", uid),
+        ));
+    }
+    Ok(())
+}
+
+//"unix:///path/to/socket?subscribe=ImuSample,90", "unix://" alone is the default socket. None when it is not a unix port
+pub fn parse_daemon_url(port_name: &str) -> Option<(PathBuf, Vec<String>)> {
+    let rest = port_name.strip_prefix("unix://")?;
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let path = match path.is_empty() {
+        true => default_socket_path(),
+        false => PathBuf::from(path),
+    };
+    let subscriptions = query
+        .split('&')
+        .filter_map(|item| item.strip_prefix("subscribe="))
+        .flat_map(|names| names.split(','))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some((path, subscriptions))
+}
+
+//---------------------UnixTransport---------------------
+//the client side of a daemon session, for SwordFishComm::from_transport
+pub struct UnixTransport {
+    stream: UnixStream,
+    device: String,
+    pending: Vec<u8>,
+}
+
+impl UnixTransport {
+    //subscriptions are message names or opcodes of streams, or "all"
+    pub fn connect(path: &Path, subscriptions: &[String]) -> Result<Self> {
+        let stream = UnixStream::connect(path).map_err(|e| anyhow!("Could not connect to {}: {}", path.display(), e))?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let greeting = read_line(&mut reader)?;
+        match greeting.split_once(' ') {
+            Some((GREETING, version)) if version.parse::<u32>().is_ok_and(|version| version >= DAEMON_PROTOCOL_VERSION) => {}
+            Some((GREETING, version)) => return Err(anyhow!("The daemon speaks version {}, this client needs {}", version, DAEMON_PROTOCOL_VERSION)),
+            _ => return Err(anyhow!("{} is not a swordfish daemon", path.display())),
+        }
+        let mut hello = format!("hello {}", DAEMON_PROTOCOL_VERSION);
+        if !subscriptions.is_empty() {
+            hello.push_str(&format!(" subscribe={}", subscriptions.join(",")));
+        }
+        (&stream).write_all(format!("{}\n", hello).as_bytes())?;
+        let answer = read_line(&mut reader)?;
+        let device = match answer.split_once(' ') {
+            Some(("ok", device)) => device.to_string(),
+            Some(("error", reason)) => return Err(anyhow!("The daemon refused the session: {}", reason)),
+            _ => return Err(anyhow!("Unexpected answer from the daemon: {}", answer)),
+        };
+        //the first frames may have followed the answer into the buffer of the reader
+        let pending = reader.buffer().to_vec();
+        stream.set_read_timeout(Some(Duration::from_millis(1)))?;
+        Ok(UnixTransport { stream, device, pending })
+    }
+
+    //the port the daemon serves
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+}
+
+impl Read for UnixTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.pending.is_empty() {
+            let n = std::cmp::min(buf.len(), self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            return Ok(n);
+        }
+        match self.stream.read(buf) {
+            Ok(0) => Err(io::ErrorKind::BrokenPipe.into()), //the daemon closed the session
+            Ok(n) => Ok(n),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => Err(io::ErrorKind::BrokenPipe.into()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Write for UnixTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+//---------------------DaemonConfig---------------------
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaemonConfig {
+    //a request with one of these opcodes locks the device for its client
+    pub exclusive: Vec<u8>,
+    //the answer to one of these unlocks it
+    pub release: Vec<u8>,
+    //the lock is also released when its client sent nothing for this long
+    pub lock_timeout: Duration,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            exclusive: vec![DfuEnterBootloader::OPCODE],
+            release: vec![DfuCommit::OPCODE],
+            lock_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+//what the daemon is doing, for a status display
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaemonStatus {
+    pub clients: Vec<u64>,
+    pub sessions: u64,
+    pub exclusive_owner: Option<u64>,
+    pub streams: BTreeMap<u8, Vec<u64>>, //the running streams and the clients that started them
+    pub operations: BTreeMap<u8, u64>,   //the running operations and their client
+}
+
+//---------------------Router---------------------
+enum ClientEvent {
+    Opened(u64, UnixStream, SyncSender<Vec<u8>>, Subscription),
+    Frame(u64, Box<SwordFishConcentratedMessage>),
+    Closed(u64),
+}
+
+#[derive(Default)]
+struct Subscription {
+    all: bool,
+    opcodes: BTreeSet<u8>,
+}
+
+struct Client {
+    stream: UnixStream, //to end the session
+    to_client: SyncSender<Vec<u8>>,
+    subscription: Subscription,
+    last_request: Instant,
+    dropped: u64, //frames the client was too slow for
+}
+
+//a run of an operation, its progress and result carry the counter the daemon gave the request
+struct RunningOperation {
+    client: u64,
+    counter: u16,        //of the client
+    device_counter: u16, //of the daemon
+}
+
+struct Pending {
+    client: Option<u64>, //None for the requests of the daemon itself and the ones given up
+    counter: u16,
+    opcode: u8,
+    answer: Option<u8>, //the opcode of the answer, None when only a Nack can come back
+    sent: Instant,
+}
+
+//the state of the daemon, only touched by its device thread
+struct Router {
+    registry: MessageRegistry,
+    config: DaemonConfig,
+    clients: BTreeMap<u64, Client>,
+    pending: HashMap<u16, Pending>,
+    next_counter: u16,
+    streams: BTreeMap<u8, BTreeSet<u64>>,
+    operations: BTreeMap<u8, RunningOperation>,
+    exclusive: Option<u64>,
+    to_device: Vec<SwordFishConcentratedMessage>,
+    dead: Vec<u64>,
+}
+
+impl Router {
+    fn new(registry: &MessageRegistry, config: DaemonConfig) -> Self {
+        Router {
+            registry: registry.clone(),
+            config,
+            clients: BTreeMap::new(),
+            pending: HashMap::new(),
+            next_counter: 1,
+            streams: BTreeMap::new(),
+            operations: BTreeMap::new(),
+            exclusive: None,
+            to_device: Vec::new(),
+            dead: Vec::new(),
+        }
+    }
+
+    //queued for the writer of the client, the device thread never waits for a client
+    fn send_to(&mut self, id: u64, msg: &SwordFishConcentratedMessage) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            match client.to_client.try_send(msg.into_bytes().to_vec()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => client.dropped += 1,
+                Err(TrySendError::Disconnected(_)) => self.dead.push(id),
+            }
+        }
+    }
+
+    fn reject(&mut self, id: u64, msg: &SwordFishConcentratedMessage, code: u16, detail: &str) {
+        let nack = Nack {
+            opcode: msg.opcode,
+            counter: msg.counter,
+            code,
+            detail: BoundedString::truncated(detail),
+        }
+        .to_concentrated(msg.counter);
+        self.send_to(id, &nack);
+    }
+
+    //sent with a counter of the daemon that no request is waiting on, which is returned
+    fn forward(&mut self, client: Option<u64>, msg: &SwordFishConcentratedMessage, answer: Option<u8>) -> u16 {
+        let mut counter = self.next_counter;
+        while self.pending.contains_key(&counter) {
+            counter = counter.wrapping_add(1);
+        }
+        self.next_counter = counter.wrapping_add(1);
+        self.pending.insert(
+            counter,
+            Pending {
+                client,
+                counter: msg.counter,
+                opcode: msg.opcode,
+                answer,
+                sent: Instant::now(),
+            },
+        );
+        let payload = &msg.payload[..std::cmp::min(msg.length as usize, MAX_PAYLOAD_SIZE)];
+        self.to_device.push(SwordFishConcentratedMessage::new(counter, msg.opcode, payload));
+        counter
+    }
+
+    fn on_client_frame(&mut self, id: u64, msg: SwordFishConcentratedMessage) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.last_request = Instant::now();
+        }
+        if self.exclusive.is_some_and(|owner| owner != id) {
+            return self.reject(id, &msg, codes::BUSY, "another client has the device");
+        }
+        let category = self.registry.get(msg.opcode).map(|info| info.category);
+        if self.config.exclusive.contains(&msg.opcode) {
+            self.exclusive = Some(id);
+        }
+        match (msg.opcode, category) {
+            (StreamStop::OPCODE, _) => {
+                if let Ok(stop) = StreamStop::from_concentrated(&msg) {
+                    let others = self
+                        .streams
+                        .get(&stop.get_stream())
+                        .is_some_and(|clients| clients.iter().any(|client| *client != id));
+                    if others {
+                        //the stream keeps running for the others, the device is not told
+                        if let Some(clients) = self.streams.get_mut(&stop.get_stream()) {
+                            clients.remove(&id);
+                        }
+                        return self.send_to(id, &msg);
+                    }
+                }
+                self.forward(Some(id), &msg, Some(msg.opcode));
+            }
+            (OperationAbort::OPCODE, _) => {
+                let operation = OperationAbort::from_concentrated(&msg).map(|abort| abort.get_operation());
+                if let Ok(operation) = operation {
+                    if self.operations.get(&operation).is_some_and(|running| running.client != id) {
+                        return self.reject(id, &msg, codes::NOT_PERMITTED, "another client runs this operation");
+                    }
+                }
+                self.forward(Some(id), &msg, Some(msg.opcode));
+            }
+            (_, Some(SwordFishMessageCategory::Operation(None))) => {
+                if self.operations.get(&msg.opcode).is_some_and(|running| running.client != id) {
+                    return self.reject(id, &msg, codes::BUSY, "another client runs this operation");
+                }
+                let device_counter = self.forward(Some(id), &msg, None);
+                self.operations.insert(
+                    msg.opcode,
+                    RunningOperation {
+                        client: id,
+                        counter: msg.counter,
+                        device_counter,
+                    },
+                );
+            }
+            (_, Some(SwordFishMessageCategory::Operation(Some(response)))) => {
+                self.forward(Some(id), &msg, Some(response));
+            }
+            //bounce, param, and opcodes the daemon does not know, the device answers with the same opcode or a Nack
+            _ => {
+                self.forward(Some(id), &msg, Some(msg.opcode));
+            }
+        }
+    }
+
+    fn answered(&mut self, pending: &Pending, answer: &SwordFishConcentratedMessage) {
+        let id = match pending.client {
+            Some(id) => id,
+            None => return,
+        };
+        if answer.opcode == Nack::OPCODE {
+            if self.operations.get(&pending.opcode).is_some_and(|running| running.client == id) {
+                self.operations.remove(&pending.opcode);
+            }
+            if self.exclusive == Some(id) && self.config.exclusive.contains(&pending.opcode) {
+                self.exclusive = None;
+            }
+        } else if self.config.release.contains(&pending.opcode) && self.exclusive == Some(id) {
+            self.exclusive = None;
+        } else if pending.opcode == StreamStart::OPCODE {
+            if let Ok(start) = StreamStart::from_concentrated(answer) {
+                self.streams.entry(start.get_stream()).or_default().insert(id);
+            }
+        } else if pending.opcode == StreamStop::OPCODE {
+            if let Ok(stop) = StreamStop::from_concentrated(answer) {
+                self.streams.remove(&stop.get_stream());
+            }
+        }
+    }
+
+    fn fan_out(&mut self, msg: &SwordFishConcentratedMessage, to: Vec<u64>) {
+        for id in to {
+            self.send_to(id, msg);
+        }
+    }
+
+    fn on_device_frame(&mut self, msg: SwordFishConcentratedMessage) {
+        let payload = &msg.payload[..std::cmp::min(msg.length as usize, MAX_PAYLOAD_SIZE)];
+        //a Nack holds the counter of the request in its payload too
+        if msg.opcode == Nack::OPCODE {
+            if let Ok(nack) = Nack::from_concentrated(&msg) {
+                let mine = self.pending.get(&nack.get_counter()).is_some_and(|pending| pending.opcode == nack.get_opcode());
+                if mine {
+                    let pending = self.pending.remove(&nack.get_counter()).expect("checked above");
+                    self.answered(&pending, &msg);
+                    let rewritten = Nack { counter: pending.counter, ..nack }.to_concentrated(pending.counter);
+                    if let Some(id) = pending.client {
+                        self.send_to(id, &rewritten);
+                    }
+                    return;
+                }
+            }
+        }
+        //the progress of an operation goes to the client that started it, with the counter of its request.
+        //what is left of an earlier run (the result of an aborted one) goes nowhere
+        if msg.opcode == OperationProgress::OPCODE || msg.opcode == OperationResult::OPCODE {
+            if let Some(operation) = payload.first().copied() {
+                if let Some(running) = self.operations.get(&operation) {
+                    if running.device_counter != msg.counter {
+                        log::debug!("Dropping the {} of an earlier run of operation {}", msg.opcode, operation);
+                        return;
+                    }
+                    let (client, counter) = (running.client, running.counter);
+                    if msg.opcode == OperationResult::OPCODE {
+                        self.operations.remove(&operation);
+                    }
+                    return self.send_to(client, &SwordFishConcentratedMessage::new(counter, msg.opcode, payload));
+                }
+            }
+        }
+        let answer = self.pending.get(&msg.counter).is_some_and(|pending| pending.answer == Some(msg.opcode));
+        if answer {
+            let pending = self.pending.remove(&msg.counter).expect("checked above");
+            self.answered(&pending, &msg);
+            if let Some(id) = pending.client {
+                self.send_to(id, &SwordFishConcentratedMessage::new(pending.counter, msg.opcode, payload));
+            }
+            return;
+        }
+        let is_stream = self.registry.get(msg.opcode).is_some_and(|info| info.category == SwordFishMessageCategory::Stream);
+        let to: Vec<u64> = match is_stream {
+            true => {
+                let started = self.streams.get(&msg.opcode).cloned().unwrap_or_default();
+                self.clients
+                    .iter()
+                    .filter(|(id, client)| {
+                        started.contains(id) || client.subscription.all || client.subscription.opcodes.contains(&msg.opcode)
+                    })
+                    .map(|(id, _)| *id)
+                    .collect()
+            }
+            //nobody asked for it, everybody gets it
+            false => self.clients.keys().copied().collect(),
+        };
+        self.fan_out(&msg, to);
+    }
+
+    //forgets a client, and stops the streams it was the last to use
+    fn close(&mut self, id: u64) {
+        let client = match self.clients.remove(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        let _ = client.stream.shutdown(std::net::Shutdown::Both);
+        match client.dropped {
+            0 => log::info!("Client {} left", id),
+            dropped => log::info!("Client {} left, {} frames were dropped for it", id, dropped),
+        }
+        for pending in self.pending.values_mut().filter(|pending| pending.client == Some(id)) {
+            pending.client = None;
+        }
+        if self.exclusive == Some(id) {
+            log::warn!("Client {} left while it had the device locked", id);
+            self.exclusive = None;
+        }
+        self.operations.retain(|_, running| running.client != id);
+        let mut unused = Vec::new();
+        for (stream, clients) in self.streams.iter_mut() {
+            if clients.remove(&id) && clients.is_empty() {
+                unused.push(*stream);
+            }
+        }
+        for stream in unused {
+            self.streams.remove(&stream);
+            self.forward(None, &StreamStop::new(stream).to_concentrated(0), Some(StreamStop::OPCODE));
+        }
+    }
+
+    //true when the lock was released
+    fn expire(&mut self) -> bool {
+        for pending in self.pending.values_mut() {
+            if pending.client.is_some() && pending.sent.elapsed() >= PENDING_TIMEOUT {
+                log::debug!("Request {} of client {:?} was not answered", pending.opcode, pending.client);
+                pending.client = None;
+            }
+        }
+        self.pending.retain(|_, pending| pending.sent.elapsed() < LATE_ANSWER_TIMEOUT);
+        let owner = match self.exclusive {
+            Some(owner) => owner,
+            None => return false,
+        };
+        let idle = self.clients.get(&owner).is_none_or(|client| client.last_request.elapsed() > self.config.lock_timeout);
+        if idle {
+            log::warn!("Released the lock of client {}, it was idle", owner);
+            self.exclusive = None;
+        }
+        idle
+    }
+
+    fn status(&self, sessions: u64) -> DaemonStatus {
+        DaemonStatus {
+            clients: self.clients.keys().copied().collect(),
+            sessions,
+            exclusive_owner: self.exclusive,
+            streams: self
+                .streams
+                .iter()
+                .map(|(stream, clients)| (*stream, clients.iter().copied().collect()))
+                .collect(),
+            operations: self.operations.iter().map(|(opcode, running)| (*opcode, running.client)).collect(),
+        }
+    }
+}
+
+//---------------------SwordFishDaemon---------------------
+fn handshake(stream: &UnixStream, registry: &MessageRegistry, device_name: &str) -> Result<Subscription> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut writer = stream;
+    writer.write_all(format!("{} {}\n", GREETING, DAEMON_PROTOCOL_VERSION).as_bytes())?;
+    let hello = read_line(&mut BufReader::new(stream.try_clone()?))?;
+    let mut refuse = |reason: String| {
+        let _ = writer.write_all(format!("error {}\n", reason).as_bytes());
+        anyhow!("{}", reason)
+    };
+    let mut words = hello.split(' ');
+    match (words.next(), words.next().map(str::parse::<u32>)) {
+        (Some("hello"), Some(Ok(version))) if (1..=DAEMON_PROTOCOL_VERSION).contains(&version) => {}
+        (Some("hello"), Some(Ok(version))) => {
+            return Err(refuse(format!("version {} is not supported, this daemon speaks {}", version, DAEMON_PROTOCOL_VERSION)))
+        }
+        _ => return Err(refuse("bad hello".to_string())),
+    }
+    let mut subscription = Subscription::default();
+    for name in words.filter_map(|word| word.strip_prefix("subscribe=")).flat_map(|names| names.split(',')) {
+        if name == "all" {
+            subscription.all = true;
+            continue;
+        }
+        let info = name.parse::<u8>().ok().and_then(|opcode| registry.get(opcode)).or_else(|| registry.find(name));
+        match info {
+            Some(info) => subscription.opcodes.insert(info.opcode),
+            None => return Err(refuse(format!("unknown message {}", name))),
+        };
+    }
+    writer.write_all(format!("ok {}\n", device_name).as_bytes())?;
+    Ok(subscription)
+}
+
+//the frames of a client, until it leaves
+fn serve_client(stream: UnixStream, id: u64, events: Sender<ClientEvent>, alive: Arc<AtomicBool>, registry: MessageRegistry, device_name: String) {
+    let subscription = match handshake(&stream, &registry, &device_name) {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            log::info!("Refused client {}: {}", id, e);
+            return;
+        }
+    };
+    let streams = stream.try_clone().and_then(|writer| {
+        writer.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+        Ok((writer, stream.try_clone()?))
+    });
+    let (writer_stream, router_stream) = match streams {
+        Ok(streams) => streams,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+    let (to_client, frames) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+    if events.send(ClientEvent::Opened(id, router_stream, to_client, subscription)).is_err() {
+        return;
+    }
+    let writer = spawn(move || write_client(writer_stream, frames));
+    let mut reader = stream;
+    let _ = reader.set_read_timeout(Some(POLL_PERIOD));
+    let mut splitter = FrameSplitter::for_host_frames();
+    let mut buffer = [0u8; 4096];
+    while alive.load(Ordering::Relaxed) {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(_) => break,
+        };
+        for segment in splitter.push(&buffer[..n]) {
+            match segment {
+                Segment::Frame(_, Some(msg)) => {
+                    let _ = events.send(ClientEvent::Frame(id, msg));
+                }
+                Segment::Frame(frame, None) => log::warn!("Dropping a frame of client {} with a bad checksum: {:02x?}", id, frame),
+                Segment::Bytes(bytes) => log::warn!("Dropping {} bytes of client {} outside a frame", bytes.len(), id),
+            }
+        }
+    }
+    let _ = events.send(ClientEvent::Closed(id));
+    let _ = writer.join();
+}
+
+//sends the frames the router queued for a client, until the router forgets it
+fn write_client(mut stream: UnixStream, frames: Receiver<Vec<u8>>) {
+    for frame in frames {
+        if stream.write_all(&frame).is_err() {
+            //the reader of the client sees it and the router forgets the client
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return;
+        }
+    }
+}
+
+fn run_device(
+    router: &mut Router,
+    mut device: Box<dyn SwordFishTransport>,
+    events: Receiver<ClientEvent>,
+    alive: &AtomicBool,
+    status: &Mutex<DaemonStatus>,
+) -> io::Result<()> {
+    let mut splitter = FrameSplitter::new();
+    let mut buffer = [0u8; 4096];
+    let mut sessions = 0;
+    while alive.load(Ordering::Relaxed) {
+        let mut changed = false;
+        for event in events.try_iter() {
+            changed = true;
+            match event {
+                ClientEvent::Opened(id, stream, to_client, subscription) => {
+                    sessions += 1;
+                    log::info!("Client {} connected", id);
+                    let client = Client {
+                        stream,
+                        to_client,
+                        subscription,
+                        last_request: Instant::now(),
+                        dropped: 0,
+                    };
+                    router.clients.insert(id, client);
+                }
+                ClientEvent::Frame(id, msg) => router.on_client_frame(id, *msg),
+                ClientEvent::Closed(id) => router.close(id),
+            }
+        }
+        for msg in std::mem::take(&mut router.to_device) {
+            device.write_all(&msg.into_bytes())?;
+            device.flush()?;
+        }
+        match device.read(&mut buffer) {
+            Ok(n) => {
+                for segment in splitter.push(&buffer[..n]) {
+                    if let Segment::Frame(_, Some(msg)) = segment {
+                        router.on_device_frame(*msg);
+                        changed = true;
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+        for id in std::mem::take(&mut router.dead) {
+            router.close(id);
+            changed = true;
+        }
+        changed |= router.expire();
+        if changed {
+            *status.lock().expect("Another thread holding the mutex panicked") = router.status(sessions);
+        }
+    }
+    Ok(())
+}
+
+pub struct SwordFishDaemon {
+    alive: Arc<AtomicBool>,
+    device_alive: Arc<AtomicBool>,
+    status: Arc<Mutex<DaemonStatus>>,
+    accept_handle: Option<JoinHandle<()>>,
+    device_handle: Option<JoinHandle<()>>,
+}
+
+impl SwordFishDaemon {
+    //serves the device to the clients of the listener.
+    //the reads of the device should time out when there is no data, see swordfish_transport
+    pub fn spawn(
+        listener: UnixListener,
+        device: Box<dyn SwordFishTransport>,
+        config: DaemonConfig,
+        registry: &MessageRegistry,
+        device_name: &str,
+    ) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let alive = Arc::new(AtomicBool::new(true));
+        let device_alive = Arc::new(AtomicBool::new(true));
+        let status = Arc::new(Mutex::new(DaemonStatus::default()));
+        let (events, received) = mpsc::channel();
+
+        let mut router = Router::new(registry, config);
+        let (device_thread_alive, device_thread_status, device_alive_clone) = (alive.clone(), status.clone(), device_alive.clone());
+        let device_handle = spawn(move || {
+            if let Err(e) = run_device(&mut router, device, received, &device_thread_alive, &device_thread_status) {
+                log::error!("The device of the daemon failed: {}", e);
+            }
+            device_alive_clone.store(false, Ordering::Relaxed);
+            //the clients see their session end
+            for client in router.clients.values() {
+                let _ = client.stream.shutdown(std::net::Shutdown::Both);
+            }
+        });
+
+        let (accept_alive, accept_device_alive) = (alive.clone(), device_alive.clone());
+        let (registry, device_name) = (registry.clone(), device_name.to_string());
+        let accept_handle = spawn(move || {
+            let mut clients: Vec<JoinHandle<()>> = Vec::new();
+            let mut next_id = 1;
+            while accept_alive.load(Ordering::Relaxed) && accept_device_alive.load(Ordering::Relaxed) {
+                clients.retain(|client| !client.is_finished());
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = stream.set_nonblocking(false) {
+                            log::error!("{}", e);
+                            continue;
+                        }
+                        let (events, alive, registry, device_name) =
+                            (events.clone(), accept_alive.clone(), registry.clone(), device_name.clone());
+                        let id = next_id;
+                        next_id += 1;
+                        clients.push(spawn(move || serve_client(stream, id, events, alive, registry, device_name)));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_PERIOD),
+                    Err(e) => {
+                        log::error!("The daemon could not accept: {}", e);
+                        std::thread::sleep(POLL_PERIOD);
+                    }
+                }
+            }
+            accept_alive.store(false, Ordering::Relaxed);
+            for client in clients {
+                let _ = client.join();
+            }
+        });
+
+        Ok(SwordFishDaemon {
+            alive,
+            device_alive,
+            status,
+            accept_handle: Some(accept_handle),
+            device_handle: Some(device_handle),
+        })
+    }
+
+    pub fn status(&self) -> DaemonStatus {
+        self.status
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .clone()
+    }
+
+    //false once the device was disconnected
+    pub fn is_running(&self) -> bool {
+        self.device_alive.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SwordFishDaemon {
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.accept_handle.take() {
+            handle.join().expect("The accept thread of the daemon could not be joined");
+        }
+        if let Some(handle) = self.device_handle.take() {
+            handle.join().expect("The device thread of the daemon could not be joined");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daemon_urls_carry_the_subscriptions() {
+        assert_eq!(
+            parse_daemon_url("unix:///tmp/sf.sock?subscribe=ImuSample,90"),
+            Some((PathBuf::from("/tmp/sf.sock"), vec!["ImuSample".to_string(), "90".to_string()]))
+        );
+        assert_eq!(parse_daemon_url("unix://"), Some((default_socket_path(), Vec::new())));
+        assert_eq!(parse_daemon_url("tcp://host"), None);
+    }
+
+    #[test]
+    fn the_socket_directory_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let directory = std::env::temp_dir().join(format!("swordfish_private_{}", std::process::id()));
+        ensure_private_directory(&directory).unwrap();
+        assert_eq!(std::fs::metadata(&directory).unwrap().permissions().mode() & 0o777, 0o700);
+        ensure_private_directory(&directory).unwrap();
+        std::fs::set_permissions(&directory, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(ensure_private_directory(&directory).is_err());
+        std::fs::remove_dir(&directory).unwrap();
+    }
+
+    //a client of the router, and what was queued for it
+    fn client(router: &mut Router, id: u64, queue: usize) -> Receiver<Vec<u8>> {
+        let (stream, _) = UnixStream::pair().unwrap();
+        let (to_client, frames) = mpsc::sync_channel(queue);
+        let client = Client {
+            stream,
+            to_client,
+            subscription: Subscription::default(),
+            last_request: Instant::now(),
+            dropped: 0,
+        };
+        router.clients.insert(id, client);
+        frames
+    }
+
+    #[test]
+    fn late_answers_go_to_nobody() {
+        use crate::swordfish_messages::Ping;
+        let mut router = Router::new(&MessageRegistry::new(), DaemonConfig::default());
+        let (asked, other) = (client(&mut router, 1, 8), client(&mut router, 2, 8));
+        router.on_client_frame(1, Ping::default().to_concentrated(7));
+        let sent = router.to_device.pop().unwrap();
+        let pending = router.pending.get_mut(&sent.counter).unwrap();
+        pending.sent = Instant::now().checked_sub(PENDING_TIMEOUT).unwrap();
+        router.expire();
+        router.on_device_frame(Ping::default().to_concentrated(sent.counter));
+        assert!(asked.try_recv().is_err());
+        assert!(other.try_recv().is_err());
+        assert!(router.pending.is_empty());
+    }
+
+    #[test]
+    fn a_slow_client_misses_frames() {
+        use crate::swordfish_messages::DeviceLog;
+        let mut router = Router::new(&MessageRegistry::new(), DaemonConfig::default());
+        let (slow, other) = (client(&mut router, 1, 1), client(&mut router, 2, 8));
+        for counter in 0..3 {
+            router.on_device_frame(DeviceLog::new(2, 1, 0, "log").unwrap().to_concentrated(counter));
+        }
+        assert_eq!(slow.try_iter().count(), 1);
+        assert_eq!(other.try_iter().count(), 3);
+        assert_eq!(router.clients[&1].dropped, 2);
+        assert!(router.dead.is_empty());
+    }
+}
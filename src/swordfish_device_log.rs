@@ -0,0 +1,236 @@
+//the firmware log, received as DeviceLog messages and forwarded to the log crate
+//under the swordfish::device target, so it ends up wherever the host logs go:
+//  [2024-01-01T00:00:00Z INFO  swordfish::device] [radio] 1234ms: pll locked
+//forwarding can be rate limited, the latest entries are kept in a ring buffer either way
+use crate::swordfish_messages::DeviceLog;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+pub const DEVICE_LOG_TARGET: &str = "swordfish::device";
+const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceLogEntry {
+    pub level: log::Level,
+    pub module_id: u16,
+    pub timestamp: u32, //ms since the device booted
+    pub text: String,
+    pub received: SystemTime,
+}
+
+//the level numbers of DeviceLog are the ones of log::Level
+fn level_from_u8(level: u8) -> log::Level {
+    match level {
+        0 | 1 => log::Level::Error,
+        2 => log::Level::Warn,
+        3 => log::Level::Info,
+        4 => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+struct DeviceLogState {
+    entries: VecDeque<DeviceLogEntry>,
+    capacity: usize,
+    forwarding: bool,
+    module_names: HashMap<u16, String>,
+    //token bucket, refilled with rate_limit tokens per second
+    rate_limit: Option<u32>,
+    tokens: f64,
+    last_refill: Instant,
+    dropped: u64,
+    dropped_since_report: u64,
+}
+
+pub struct DeviceLogForwarder {
+    state: Mutex<DeviceLogState>,
+}
+
+impl Default for DeviceLogForwarder {
+    fn default() -> Self {
+        DeviceLogForwarder {
+            state: Mutex::new(DeviceLogState {
+                entries: VecDeque::with_capacity(DEFAULT_CAPACITY),
+                capacity: DEFAULT_CAPACITY,
+                forwarding: true,
+                module_names: HashMap::new(),
+                rate_limit: None,
+                tokens: 0.0,
+                last_refill: Instant::now(),
+                dropped: 0,
+                dropped_since_report: 0,
+            }),
+        }
+    }
+}
+
+impl DeviceLogForwarder {
+    fn lock(&self) -> std::sync::MutexGuard<'_, DeviceLogState> {
+        self.state
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+    }
+
+    //called by the read thread of SwordFishComm
+    pub fn handle(&self, msg: &DeviceLog) {
+        let entry = DeviceLogEntry {
+            level: level_from_u8(msg.level),
+            module_id: msg.module_id,
+            timestamp: msg.timestamp,
+            text: msg.text.to_string(),
+            received: SystemTime::now(),
+        };
+        //decided under the lock, logged after releasing it: a logger that blocks or
+        //reads the device log back must not stall the other users of the forwarder
+        let (dropped_report, forward) = {
+            let mut state = self.lock();
+            let mut dropped_report = None;
+            let mut forward = None;
+            if state.forwarding && state.allow() {
+                if state.dropped_since_report > 0 {
+                    dropped_report = Some(state.dropped_since_report);
+                    state.dropped_since_report = 0;
+                }
+                let module = match state.module_names.get(&entry.module_id) {
+                    Some(name) => name.clone(),
+                    None => entry.module_id.to_string(),
+                };
+                forward = Some((module, entry.level, entry.timestamp, entry.text.clone()));
+            }
+            if state.capacity > 0 {
+                if state.entries.len() == state.capacity {
+                    state.entries.pop_front();
+                }
+                state.entries.push_back(entry);
+            }
+            (dropped_report, forward)
+        };
+
+        if let Some(dropped) = dropped_report {
+            log::warn!(target: DEVICE_LOG_TARGET, "{} device log messages dropped by the rate limit", dropped);
+        }
+        if let Some((module, level, timestamp, text)) = forward {
+            log::log!(target: DEVICE_LOG_TARGET, level, "[{}] {}ms: {}", module, timestamp, text);
+        }
+    }
+
+    //at most max_per_second messages reach the log crate, None to forward everything
+    pub fn set_rate_limit(&self, max_per_second: Option<u32>) {
+        let mut state = self.lock();
+        state.rate_limit = max_per_second;
+        state.tokens = max_per_second.unwrap_or(0) as f64;
+        state.last_refill = Instant::now();
+    }
+
+    //false keeps the entries in the ring buffer only
+    pub fn set_forwarding(&self, forwarding: bool) {
+        self.lock().forwarding = forwarding;
+    }
+
+    //the number of entries kept, 0 to keep none
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.lock();
+        state.capacity = capacity;
+        while state.entries.len() > capacity {
+            state.entries.pop_front();
+        }
+    }
+
+    //printed in place of the module id
+    pub fn set_module_name(&self, module_id: u16, name: &str) {
+        self.lock().module_names.insert(module_id, name.to_string());
+    }
+
+    //the latest max entries, oldest first
+    pub fn recent(&self, max: usize) -> Vec<DeviceLogEntry> {
+        let state = self.lock();
+        let skip = state.entries.len().saturating_sub(max);
+        state.entries.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    //messages not forwarded because of the rate limit, they are still in the ring buffer
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+}
+
+impl DeviceLogState {
+    fn allow(&mut self) -> bool {
+        let rate_limit = match self.rate_limit {
+            Some(rate_limit) => rate_limit as f64,
+            None => return true,
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = f64::min(rate_limit, self.tokens + elapsed * rate_limit);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            self.dropped += 1;
+            self.dropped_since_report += 1;
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device_log(text: &str) -> DeviceLog {
+        DeviceLog::new(2, 7, 1000, text).unwrap()
+    }
+
+    #[test]
+    fn ring_buffer_and_rate_limit() {
+        let forwarder = DeviceLogForwarder::default();
+        forwarder.set_capacity(3);
+        forwarder.set_rate_limit(Some(2));
+        for i in 0..5 {
+            forwarder.handle(&device_log(&format!("line {}", i)));
+        }
+        let recent = forwarder.recent(10);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].text, "line 2");
+        assert_eq!(recent[2].level, log::Level::Warn);
+        assert_eq!(forwarder.recent(1)[0].text, "line 4");
+        assert_eq!(forwarder.dropped(), 3);
+    }
+
+    //reads the forwarder back while logging, which deadlocked when the lock was held around log::log!
+    struct ReadingLogger;
+
+    static LOGGED_FORWARDER: std::sync::OnceLock<DeviceLogForwarder> = std::sync::OnceLock::new();
+
+    impl log::Log for ReadingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == DEVICE_LOG_TARGET
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                if let Some(forwarder) = LOGGED_FORWARDER.get() {
+                    forwarder.recent(1);
+                }
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn the_logger_can_use_the_forwarder() {
+        let forwarder = LOGGED_FORWARDER.get_or_init(DeviceLogForwarder::default);
+        log::set_logger(&ReadingLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        forwarder.handle(&device_log("line 0"));
+        assert_eq!(forwarder.recent(1)[0].text, "line 0");
+    }
+}
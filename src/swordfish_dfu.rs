@@ -0,0 +1,479 @@
+//firmware update over the swordfish link, so boards can be flashed without a separate vendor tool
+//the host walks the bootloader through the dfu messages of messages.toml:
+//  DfuEnterBootloader -> DfuErase -> DfuWriteBlock... -> DfuVerify -> DfuCommit (the device reboots)
+//every step is answered with DfuStatus. an interrupted update is resumed from the bytes already written
+//when their crc matches the image, and after the reboot the device is asked if it rolled back
+use crate::swordfish_comm::SwordFishComm;
+use crate::swordfish_messages::{
+    DfuCommit, DfuEnterBootloader, DfuErase, DfuGetState, DfuStatus, DfuVerify, DfuWriteBlock,
+};
+use crate::SwordFishMessageTrait;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+//the data field of DfuWriteBlock
+pub const DFU_BLOCK_SIZE: usize = 128;
+//the largest gap between the records of an intel hex file that is filled in, a file with data far apart
+//(e.g. an option byte area) is not a single image of the application slot
+pub const MAX_INTEL_HEX_GAP: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DfuState {
+    Application = 0, //running the application, the bootloader is not active
+    Bootloader = 1,
+    Erased = 2,
+    Writing = 3,
+    Verified = 4,
+    RolledBack = 5, //the committed image did not boot, the previous one is running again
+}
+
+impl DfuState {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DfuState::Application),
+            1 => Some(DfuState::Bootloader),
+            2 => Some(DfuState::Erased),
+            3 => Some(DfuState::Writing),
+            4 => Some(DfuState::Verified),
+            5 => Some(DfuState::RolledBack),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DfuStatusCode {
+    Ok = 0,
+    BadState = 1,   //the operation is not allowed in the current DfuState
+    BadAddress = 2, //outside of the application slot
+    TooLarge = 3,   //the image does not fit in the application slot
+    FlashError = 4, //writing to a region that was not erased
+    CrcMismatch = 5,
+}
+
+impl DfuStatusCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DfuStatusCode::Ok),
+            1 => Some(DfuStatusCode::BadState),
+            2 => Some(DfuStatusCode::BadAddress),
+            3 => Some(DfuStatusCode::TooLarge),
+            4 => Some(DfuStatusCode::FlashError),
+            5 => Some(DfuStatusCode::CrcMismatch),
+            _ => None,
+        }
+    }
+}
+
+//crc32 (ieee 802.3, the one of zlib and most mcu crc units)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+//--------------FirmwareImage------------------//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareImage {
+    pub base_address: u32,
+    pub data: Vec<u8>,
+}
+
+impl FirmwareImage {
+    //a raw binary has no addresses, base_address is where its first byte goes
+    pub fn from_bin(data: &[u8], base_address: u32) -> Self {
+        FirmwareImage {
+            base_address,
+            data: data.to_vec(),
+        }
+    }
+
+    //intel hex, gaps of up to MAX_INTEL_HEX_GAP between records are filled with 0xff (erased flash)
+    pub fn from_intel_hex(text: &str) -> Result<Self> {
+        let mut chunks: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut upper_address: u32 = 0;
+        let mut end_of_file = false;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if end_of_file {
+                return Err(anyhow!("Line {}: record after the end of file record", line_number + 1));
+            }
+            let record = parse_hex_record(line)
+                .map_err(|e| anyhow!("Line {}: {}", line_number + 1, e))?;
+            let (length, offset, record_type) = (record[0] as usize, u16::from_be_bytes([record[1], record[2]]), record[3]);
+            let data = &record[4..4 + length];
+            match record_type {
+                0x00 => chunks.push((upper_address + offset as u32, data.to_vec())),
+                0x01 => end_of_file = true,
+                0x02 if length == 2 => upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+                0x04 if length == 2 => upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+                //start address records do not matter for flashing
+                0x03 | 0x05 => {}
+                _ => {
+                    return Err(anyhow!(
+                        "Line {}: unsupported record type {:02x} with length {}",
+                        line_number + 1,
+                        record_type,
+                        length
+                    ))
+                }
+            }
+        }
+        if !end_of_file {
+            return Err(anyhow!("Missing end of file record"));
+        }
+
+        chunks.sort_by_key(|(address, _)| *address);
+        let base_address = match chunks.first() {
+            Some((base_address, _)) => *base_address,
+            None => return Err(anyhow!("The file has no data records")),
+        };
+        let mut end = 0;
+        for (address, data) in chunks.iter() {
+            let start = (*address - base_address) as usize;
+            if start > end + MAX_INTEL_HEX_GAP {
+                return Err(anyhow!(
+                    "The data at {:08x} is {} bytes after the previous record, more than the {} bytes filled in",
+                    address,
+                    start - end,
+                    MAX_INTEL_HEX_GAP
+                ));
+            }
+            end = std::cmp::max(end, start + data.len());
+        }
+        let mut image = vec![0xff; end];
+        for (address, data) in chunks {
+            let start = (address - base_address) as usize;
+            image[start..start + data.len()].copy_from_slice(&data);
+        }
+        Ok(FirmwareImage {
+            base_address,
+            data: image,
+        })
+    }
+
+    //.hex and .ihex files are intel hex, anything else is a raw binary placed at bin_base_address
+    pub fn load(path: &Path, bin_base_address: u32) -> Result<Self> {
+        let is_hex = matches!(
+            path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref(),
+            Some("hex") | Some("ihex")
+        );
+        if is_hex {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+            FirmwareImage::from_intel_hex(&text)
+        } else {
+            let data = std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+            Ok(FirmwareImage::from_bin(&data, bin_base_address))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn crc32(&self) -> u32 {
+        crc32(&self.data)
+    }
+}
+
+//one ":LLAAAATT<data>CC" line as bytes, checksum checked
+fn parse_hex_record(line: &str) -> Result<Vec<u8>> {
+    let hex = line
+        .strip_prefix(':')
+        .ok_or_else(|| anyhow!("record does not start with ':'"))?;
+    if !hex.is_ascii() || hex.len() % 2 != 0 || hex.len() < 10 {
+        return Err(anyhow!("bad record length"));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| anyhow!("bad hex digit: {}", e))?;
+    if bytes.len() != bytes[0] as usize + 5 {
+        return Err(anyhow!("record length does not match its byte count"));
+    }
+    if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+        return Err(anyhow!("bad checksum"));
+    }
+    Ok(bytes)
+}
+
+fn check_status(status: DfuStatus) -> Result<DfuStatus> {
+    match DfuStatusCode::from_u8(status.get_status()) {
+        Some(DfuStatusCode::Ok) => Ok(status),
+        Some(code) => Err(anyhow!("Opcode {} failed with {:?}", status.get_request(), code)),
+        None => Err(anyhow!("Opcode {} failed with status {}", status.get_request(), status.get_status())),
+    }
+}
+
+//--------------FirmwareUpdater------------------//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DfuStage {
+    EnterBootloader,
+    Erase,
+    Write,
+    Verify,
+    Commit,
+    RollbackCheck,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DfuProgress {
+    pub stage: DfuStage,
+    pub done: usize,  //bytes written for DfuStage::Write, 0 or 1 for the other stages
+    pub total: usize,
+}
+
+pub struct FirmwareUpdater<'a> {
+    comm: &'a SwordFishComm,
+    on_progress: Option<Box<dyn FnMut(DfuProgress) + Send + 'a>>,
+    timeout: Duration,
+    erase_timeout: Duration,
+    retries: usize,
+    reboot_timeout: Duration,
+}
+
+impl<'a> FirmwareUpdater<'a> {
+    pub fn new(comm: &'a SwordFishComm) -> Self {
+        FirmwareUpdater {
+            comm,
+            on_progress: None,
+            timeout: Duration::from_millis(500),
+            erase_timeout: Duration::from_secs(10),
+            retries: 3,
+            reboot_timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn on_progress(mut self, on_progress: impl FnMut(DfuProgress) + Send + 'a) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    //how long to wait for the answer of a single block, and how often a block is resent without one
+    pub fn timeout(mut self, timeout: Duration, retries: usize) -> Self {
+        self.timeout = timeout;
+        self.retries = retries;
+        self
+    }
+
+    pub fn erase_timeout(mut self, erase_timeout: Duration) -> Self {
+        self.erase_timeout = erase_timeout;
+        self
+    }
+
+    //how long the device may take to come back after DfuCommit
+    pub fn reboot_timeout(mut self, reboot_timeout: Duration) -> Self {
+        self.reboot_timeout = reboot_timeout;
+        self
+    }
+
+    pub fn state(&self) -> Result<DfuStatus> {
+        self.request(&DfuGetState::default(), self.timeout)
+    }
+
+    //flashes the image, resuming an interrupted update of the same image
+    pub fn update(&mut self, image: &FirmwareImage) -> Result<()> {
+        if image.is_empty() {
+            return Err(anyhow!("The firmware image is empty"));
+        }
+        let total = image.len();
+
+        let written = self.resumable_bytes(image)?;
+        if written > 0 {
+            log::info!("Resuming firmware update at byte {} of {}", written, total);
+        } else {
+            self.request(&DfuEnterBootloader::new(total as u32), self.timeout)?;
+            self.progress(DfuStage::EnterBootloader, 1, 1);
+            self.request(
+                &DfuErase::new(image.base_address, total as u32),
+                self.erase_timeout,
+            )?;
+            self.progress(DfuStage::Erase, 1, 1);
+        }
+
+        let mut offset = written;
+        self.progress(DfuStage::Write, offset, total);
+        while offset < total {
+            let end = std::cmp::min(offset + DFU_BLOCK_SIZE, total);
+            self.write_block(image, offset, end)?;
+            offset = end;
+            self.progress(DfuStage::Write, offset, total);
+        }
+
+        let status = self.request(&DfuVerify::new(image.base_address, total as u32), self.erase_timeout)?;
+        if status.get_crc32() != image.crc32() {
+            return Err(anyhow!(
+                "Verify failed, device crc {:08x}, image crc {:08x}",
+                status.get_crc32(),
+                image.crc32()
+            ));
+        }
+        self.progress(DfuStage::Verify, 1, 1);
+
+        //the device reboots, so a commit is not resent: a lost answer is found out by the rollback check
+        let commit = DfuCommit::new(total as u32, image.crc32());
+        match self.request_once(&commit, self.erase_timeout)? {
+            Some(status) => {
+                check_status(status)?;
+            }
+            None => log::warn!("No answer to DfuCommit, asking the device what it runs"),
+        }
+        self.progress(DfuStage::Commit, 1, 1);
+
+        self.check_rollback(image)?;
+        self.progress(DfuStage::RollbackCheck, 1, 1);
+        Ok(())
+    }
+
+    //bytes of a previous, interrupted update of this image that do not need to be written again
+    fn resumable_bytes(&mut self, image: &FirmwareImage) -> Result<usize> {
+        let status = self.state()?;
+        let written = status.get_written() as usize;
+        let resumable = matches!(
+            DfuState::from_u8(status.get_state()),
+            Some(DfuState::Writing)
+        ) && written <= image.len()
+            && written.is_multiple_of(DFU_BLOCK_SIZE)
+            && status.get_crc32() == crc32(&image.data[..written]);
+        Ok(if resumable { written } else { 0 })
+    }
+
+    //after the reboot the device has to run the new image, not the previous one
+    fn check_rollback(&mut self, image: &FirmwareImage) -> Result<()> {
+        let time0 = Instant::now();
+        loop {
+            if let Ok(status) = self.state() {
+                match DfuState::from_u8(status.get_state()) {
+                    Some(DfuState::Application) if status.get_crc32() == image.crc32() => return Ok(()),
+                    Some(DfuState::RolledBack) => {
+                        return Err(anyhow!("The new firmware did not boot, the device rolled back"))
+                    }
+                    _ => {}
+                }
+            }
+            if time0.elapsed() > self.reboot_timeout {
+                return Err(anyhow!("The device did not come back with the new firmware after the reboot"));
+            }
+            sleep(Duration::from_millis(50));
+        }
+    }
+
+    //writing flash twice fails, so a block whose answer was lost is only resent
+    //when the device says it was not written. the answer has to report the end of the block
+    fn write_block(&self, image: &FirmwareImage, offset: usize, end: usize) -> Result<()> {
+        let block = DfuWriteBlock::new(image.base_address + offset as u32, &image.data[offset..end])
+            .expect("blocks are at most DFU_BLOCK_SIZE bytes");
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                let status = self.state()?;
+                let written = status.get_written() as usize;
+                if written == end && status.get_crc32() == crc32(&image.data[..end]) {
+                    log::debug!("Block at byte {} was written, its answer was lost", offset);
+                    return Ok(());
+                }
+                if written != offset {
+                    return Err(anyhow!(
+                        "The device wrote {} bytes, expected {} before the block at byte {}",
+                        written,
+                        offset,
+                        offset
+                    ));
+                }
+            }
+            if let Some(status) = self.request_once(&block, self.timeout)? {
+                let status = check_status(status)?;
+                if status.get_written() as usize != end {
+                    return Err(anyhow!(
+                        "The device reports {} bytes written after the block ending at byte {}",
+                        status.get_written(),
+                        end
+                    ));
+                }
+                return Ok(());
+            }
+        }
+        Err(anyhow!("No answer for the block at byte {} after {} retries", offset, self.retries))
+    }
+
+    //for the requests that can be repeated without harm
+    fn request<T: SwordFishMessageTrait>(&self, msg: &T, timeout: Duration) -> Result<DfuStatus> {
+        for _ in 0..=self.retries {
+            if let Some(status) = self.request_once(msg, timeout)? {
+                return check_status(status);
+            }
+        }
+        Err(anyhow!("No answer for opcode {} after {} retries", T::OPCODE, self.retries))
+    }
+
+    //None when there is no answer in time, or only a late answer to an earlier request
+    fn request_once<T: SwordFishMessageTrait>(&self, msg: &T, timeout: Duration) -> Result<Option<DfuStatus>> {
+        let concentrated_msg = msg.to_concentrated(self.comm.next_tx_counter());
+        let answer = match self.comm.send_msg_with_timeout(concentrated_msg, timeout) {
+            Some(answer) => answer,
+            None => return Ok(None),
+        };
+        let status = DfuStatus::from_concentrated(&answer)?;
+        if status.get_request() != T::OPCODE || answer.counter != concentrated_msg.counter {
+            log::debug!("Ignoring a late DfuStatus for opcode {} #{}", status.get_request(), answer.counter);
+            return Ok(None);
+        }
+        Ok(Some(status))
+    }
+
+    fn progress(&mut self, stage: DfuStage, done: usize, total: usize) {
+        if let Some(on_progress) = self.on_progress.as_mut() {
+            on_progress(DfuProgress { stage, done, total });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intel_hex_with_gap_and_extended_address() {
+        let text = ":020000040800F2\n\
+                    :0400000001020304F2\n\
+                    :02000800AABB91\n\
+                    :00000001FF\n";
+        let image = FirmwareImage::from_intel_hex(text).unwrap();
+        assert_eq!(image.base_address, 0x0800_0000);
+        assert_eq!(image.data, vec![1, 2, 3, 4, 0xff, 0xff, 0xff, 0xff, 0xaa, 0xbb]);
+        assert!(FirmwareImage::from_intel_hex(":0400000001020304F3\n:00000001FF\n").is_err());
+        assert!(FirmwareImage::from_intel_hex(":0400000001020304F2\n").is_err());
+    }
+
+    #[test]
+    fn intel_hex_with_distant_records_is_refused() {
+        //0x08000000 and 0x1fff0000, filling the gap would take 400 MB
+        let text = ":020000040800F2\n\
+                    :0400000001020304F2\n\
+                    :020000041FFFDC\n\
+                    :02000000AABB99\n\
+                    :00000001FF\n";
+        let error = FirmwareImage::from_intel_hex(text).unwrap_err();
+        assert!(error.to_string().contains("1fff0000"));
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
@@ -0,0 +1,362 @@
+//finds the serial ports a swordfish is plugged into:
+//  for port in discover() { println!("{} {:?}", port.port_name, port.serial_number); }
+//a port is a candidate when one of the DeviceMatchers of the DiscoveryConfig accepts it
+//(usb vid/pid, manufacturer, serial number and port name globs). with probing on,
+//a Ping is sent to every candidate and only the ones that answer are kept
+use crate::swordfish_concentrated_message::SwordFishConcentratedMessageBufferBuilder;
+use crate::swordfish_messages::Ping;
+use crate::swordfish_transport::SwordFishTransport;
+use crate::{SwordFishMessageTrait, CONCENTRATED_MESSAGE_TOTAL_SIZE};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serialport::{DataBits, Parity, StopBits};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortKind {
+    Usb,
+    Bluetooth,
+    Pci,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortInfo {
+    pub port_name: String,
+    pub kind: PortKind,
+    //the usb descriptor, None for other kinds of ports
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    //the name of the matcher that accepted the port
+    pub matcher: Option<String>,
+    //answered a Ping, only set when probing
+    pub confirmed: bool,
+}
+
+impl PortInfo {
+    pub fn from_serialport(port: &serialport::SerialPortInfo) -> Self {
+        let mut info = PortInfo {
+            port_name: port.port_name.clone(),
+            kind: PortKind::Unknown,
+            vid: None,
+            pid: None,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+            matcher: None,
+            confirmed: false,
+        };
+        match &port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => {
+                info.kind = PortKind::Usb;
+                info.vid = Some(usb.vid);
+                info.pid = Some(usb.pid);
+                info.serial_number = usb.serial_number.clone();
+                info.manufacturer = usb.manufacturer.clone();
+                info.product = usb.product.clone();
+            }
+            serialport::SerialPortType::BluetoothPort => info.kind = PortKind::Bluetooth,
+            serialport::SerialPortType::PciPort => info.kind = PortKind::Pci,
+            serialport::SerialPortType::Unknown => {}
+        }
+        info
+    }
+}
+
+//every field that is set has to match, strings are globs with * and ?
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceMatcher {
+    pub name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    pub port_name: Option<String>,
+}
+
+impl DeviceMatcher {
+    pub fn new(name: &str) -> Self {
+        DeviceMatcher {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn usb(name: &str, vid: u16, pid: u16) -> Self {
+        DeviceMatcher {
+            vid: Some(vid),
+            pid: Some(pid),
+            ..DeviceMatcher::new(name)
+        }
+    }
+
+    pub fn manufacturer(mut self, pattern: &str) -> Self {
+        self.manufacturer = Some(pattern.to_string());
+        self
+    }
+
+    pub fn product(mut self, pattern: &str) -> Self {
+        self.product = Some(pattern.to_string());
+        self
+    }
+
+    pub fn serial_number(mut self, pattern: &str) -> Self {
+        self.serial_number = Some(pattern.to_string());
+        self
+    }
+
+    pub fn port_name(mut self, pattern: &str) -> Self {
+        self.port_name = Some(pattern.to_string());
+        self
+    }
+
+    pub fn matches(&self, port: &PortInfo) -> bool {
+        fn glob_field(pattern: &Option<String>, value: &Option<String>) -> bool {
+            match (pattern, value) {
+                (None, _) => true,
+                (Some(pattern), Some(value)) => glob_match(pattern, value),
+                (Some(_), None) => false,
+            }
+        }
+        self.vid.is_none_or(|vid| port.vid == Some(vid))
+            && self.pid.is_none_or(|pid| port.pid == Some(pid))
+            && glob_field(&self.manufacturer, &port.manufacturer)
+            && glob_field(&self.product, &port.product)
+            && glob_field(&self.serial_number, &port.serial_number)
+            && glob_field(&self.port_name, &Some(port.port_name.clone()))
+    }
+}
+
+//* matches any run of characters, ? a single one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    //where the last * was, and the text position it is matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    #[serde(rename = "matcher")]
+    pub matchers: Vec<DeviceMatcher>,
+    //send a Ping and keep only the ports that answer within this time
+    #[serde(with = "probe_ms")]
+    pub probe: Option<Duration>,
+}
+
+//the usb-uart bridges used on swordfish boards
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            matchers: vec![
+                DeviceMatcher::usb("cp210x", 0x10C4, 0xEA60).manufacturer("Silicon Labs"),
+                DeviceMatcher::usb("ft231x", 0x0403, 0x6015).manufacturer("FTDI"),
+            ],
+            probe: None,
+        }
+    }
+}
+
+mod probe_ms {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(probe: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match probe {
+            Some(probe) => serializer.serialize_u64(probe.as_millis() as u64),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
+
+impl DiscoveryConfig {
+    //no matchers, add them with with_matcher
+    pub fn empty() -> Self {
+        DiscoveryConfig {
+            matchers: Vec::new(),
+            probe: None,
+        }
+    }
+
+    pub fn with_matcher(mut self, matcher: DeviceMatcher) -> Self {
+        self.matchers.push(matcher);
+        self
+    }
+
+    pub fn with_probe(mut self, timeout: Duration) -> Self {
+        self.probe = Some(timeout);
+        self
+    }
+
+    //  probe = 100
+    //  [[matcher]]
+    //  name = "lab boards"
+    //  vid = 0x10C4
+    //  serial_number = "SF-*"
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        DiscoveryConfig::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+
+    //the name of the first matcher that accepts the port
+    pub fn match_port(&self, port: &PortInfo) -> Option<&str> {
+        self.matchers
+            .iter()
+            .find(|matcher| matcher.matches(port))
+            .map(|matcher| matcher.name.as_str())
+    }
+
+    //the ports accepted by a matcher, in the order they were listed, without probing
+    pub fn filter(&self, ports: Vec<PortInfo>) -> Vec<PortInfo> {
+        ports
+            .into_iter()
+            .filter_map(|mut port| {
+                port.matcher = Some(self.match_port(&port)?.to_string());
+                Some(port)
+            })
+            .collect()
+    }
+}
+
+//every serial port of the system, whether it is a swordfish or not
+pub fn list_ports() -> Vec<PortInfo> {
+    crate::swordfish_comm::get_serial_ports().unwrap_or_default()
+}
+
+//the swordfish ports with the default matchers
+pub fn discover() -> Vec<PortInfo> {
+    discover_with(&DiscoveryConfig::default())
+}
+
+pub fn discover_with(config: &DiscoveryConfig) -> Vec<PortInfo> {
+    let candidates = config.filter(list_ports());
+    let timeout = match config.probe {
+        Some(timeout) => timeout,
+        None => return candidates,
+    };
+    candidates
+        .into_iter()
+        .filter_map(|mut port| {
+            port.confirmed = probe(&port.port_name, timeout);
+            port.confirmed.then_some(port)
+        })
+        .collect()
+}
+
+//true if a swordfish on the port answers a Ping within the timeout.
+//fails for a port that is open, e.g. by a SwordFishComm
+pub fn probe(port_name: &str, timeout: Duration) -> bool {
+    let port = serialport::new(port_name, 115200)
+        .stop_bits(StopBits::One)
+        .parity(Parity::None)
+        .data_bits(DataBits::Eight)
+        .timeout(Duration::from_millis(1))
+        .open();
+    match port {
+        Ok(mut port) => probe_transport(&mut port, timeout),
+        Err(e) => {
+            log::debug!("Can not probe {}: {}", port_name, e);
+            false
+        }
+    }
+}
+
+pub fn probe_transport<T: SwordFishTransport + ?Sized>(port: &mut T, timeout: Duration) -> bool {
+    let ping = Ping::default().to_concentrated(0);
+    if port.write_all(&ping.into_bytes()).and_then(|_| port.flush()).is_err() {
+        return false;
+    }
+    let deadline = Instant::now() + timeout;
+    let mut read_buffer = [0; CONCENTRATED_MESSAGE_TOTAL_SIZE];
+    let mut builder = SwordFishConcentratedMessageBufferBuilder::new();
+    while Instant::now() < deadline {
+        let n_bytes_read = match port.read(&mut read_buffer) {
+            Ok(n_bytes_read) => n_bytes_read,
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut || e.kind() == std::io::ErrorKind::WouldBlock => continue,
+            Err(_) => return false,
+        };
+        let mut next_msg = builder.append_buffer(&read_buffer[..n_bytes_read]);
+        while let Some(msg) = next_msg {
+            if msg.opcode == Ping::OPCODE {
+                return true;
+            }
+            next_msg = builder.next_message();
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usb_port(port_name: &str, vid: u16, pid: u16, serial_number: &str) -> PortInfo {
+        PortInfo {
+            port_name: port_name.to_string(),
+            kind: PortKind::Usb,
+            vid: Some(vid),
+            pid: Some(pid),
+            serial_number: Some(serial_number.to_string()),
+            manufacturer: Some("Silicon Labs".to_string()),
+            product: None,
+            matcher: None,
+            confirmed: false,
+        }
+    }
+
+    #[test]
+    fn matchers_and_globs() {
+        assert!(glob_match("SF-*", "SF-0042"));
+        assert!(glob_match("/dev/ttyUSB?", "/dev/ttyUSB3"));
+        assert!(glob_match("*a*b", "xxaxxb"));
+        assert!(!glob_match("/dev/ttyUSB?", "/dev/ttyUSB12"));
+
+        let ports = vec![
+            usb_port("/dev/ttyUSB0", 0x10C4, 0xEA60, "SF-0001"),
+            usb_port("/dev/ttyUSB1", 0x10C4, 0xEA60, "OTHER"),
+            usb_port("/dev/ttyACM0", 0x2341, 0x0043, "SF-0002"),
+        ];
+        let found = DiscoveryConfig::default().filter(ports.clone());
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].matcher.as_deref(), Some("cp210x"));
+
+        let config = DiscoveryConfig::from_toml_str(
+            "[[matcher]]\nname = \"lab\"\nvid = 0x10C4\nserial_number = \"SF-*\"\n\n[[matcher]]\nname = \"acm\"\nport_name = \"/dev/ttyACM*\"\n",
+        )
+        .unwrap();
+        let found: Vec<String> = config.filter(ports).into_iter().map(|port| port.port_name).collect();
+        assert_eq!(found, vec!["/dev/ttyUSB0", "/dev/ttyACM0"]);
+    }
+}
@@ -0,0 +1,422 @@
+//messages described at runtime, for opcodes that have no rust struct (yet)
+//a MessageDescriptor lists the fields with their type, offset in the payload and endianness,
+//a DynamicMessage is a payload that is read and written through a descriptor
+use crate::swordfish_concentrated_message::MAX_PAYLOAD_SIZE;
+use crate::{SwordFishConcentratedMessage, SwordFishMessageCategory};
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicFieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Bytes(usize),
+}
+
+impl DynamicFieldType {
+    //same spelling as the field types of messages.toml, "u16", "f32", "[u8; 8]"
+    pub fn parse(name: &str) -> Result<DynamicFieldType> {
+        let name = name.trim();
+        let field_type = match name {
+            "u8" => DynamicFieldType::U8,
+            "u16" => DynamicFieldType::U16,
+            "u32" => DynamicFieldType::U32,
+            "u64" => DynamicFieldType::U64,
+            "i8" => DynamicFieldType::I8,
+            "i16" => DynamicFieldType::I16,
+            "i32" => DynamicFieldType::I32,
+            "i64" => DynamicFieldType::I64,
+            "f32" => DynamicFieldType::F32,
+            "f64" => DynamicFieldType::F64,
+            _ => {
+                let len = name
+                    .strip_prefix('[')
+                    .and_then(|t| t.strip_suffix(']'))
+                    .and_then(|t| t.split_once(';'))
+                    .filter(|(element, _)| element.trim() == "u8")
+                    .and_then(|(_, len)| len.trim().parse::<usize>().ok())
+                    .ok_or_else(|| anyhow!("Unknown field type {}", name))?;
+                DynamicFieldType::Bytes(len)
+            }
+        };
+        Ok(field_type)
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            DynamicFieldType::U8 | DynamicFieldType::I8 => 1,
+            DynamicFieldType::U16 | DynamicFieldType::I16 => 2,
+            DynamicFieldType::U32 | DynamicFieldType::I32 | DynamicFieldType::F32 => 4,
+            DynamicFieldType::U64 | DynamicFieldType::I64 | DynamicFieldType::F64 => 8,
+            DynamicFieldType::Bytes(len) => *len,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub field_type: DynamicFieldType,
+    pub offset: usize,
+    pub endianness: Endianness,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageDescriptor {
+    pub name: String,
+    pub opcode: u8,
+    pub category: SwordFishMessageCategory,
+    pub fields: Vec<FieldDescriptor>,
+}
+
+impl MessageDescriptor {
+    pub fn new(name: &str, opcode: u8, category: SwordFishMessageCategory) -> Self {
+        MessageDescriptor {
+            name: name.to_string(),
+            opcode,
+            category,
+            fields: Vec::new(),
+        }
+    }
+
+    //only operations have a response
+    pub fn set_response_opcode(&mut self, response_opcode: u8) -> Result<()> {
+        match self.category {
+            SwordFishMessageCategory::Operation(_) => {
+                self.category = SwordFishMessageCategory::Operation(Some(response_opcode));
+                Ok(())
+            }
+            other => Err(anyhow!("{}: {:?} messages have no response opcode", self.name, other)),
+        }
+    }
+
+    pub fn add_field(
+        &mut self,
+        name: &str,
+        field_type: DynamicFieldType,
+        offset: usize,
+        endianness: Endianness,
+    ) -> Result<()> {
+        if self.field(name).is_some() {
+            return Err(anyhow!("{}: field {} is defined twice", self.name, name));
+        }
+        //the offset and the size come from the wrappers as they were given, they may be anything
+        match offset.checked_add(field_type.size()) {
+            Some(end) if end <= MAX_PAYLOAD_SIZE => {}
+            Some(end) => {
+                return Err(anyhow!(
+                    "{}: field {} ends at byte {}, the maximum payload is {}",
+                    self.name,
+                    name,
+                    end,
+                    MAX_PAYLOAD_SIZE
+                ))
+            }
+            None => {
+                return Err(anyhow!(
+                    "{}: field {} at offset {} with {} bytes does not fit in the maximum payload of {}",
+                    self.name,
+                    name,
+                    offset,
+                    field_type.size(),
+                    MAX_PAYLOAD_SIZE
+                ))
+            }
+        }
+        self.fields.push(FieldDescriptor {
+            name: name.to_string(),
+            field_type,
+            offset,
+            endianness,
+        });
+        Ok(())
+    }
+
+    //adds the field right after the end of the payload, like the fields of a packed struct
+    pub fn append_field(&mut self, name: &str, field_type: DynamicFieldType, endianness: Endianness) -> Result<()> {
+        let offset = self.payload_length();
+        self.add_field(name, field_type, offset, endianness)
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldDescriptor> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn payload_length(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|f| f.offset + f.field_type.size())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicMessage {
+    descriptor: Arc<MessageDescriptor>,
+    payload: Vec<u8>,
+}
+
+macro_rules! read_number {
+    ($t:ty, $bytes:expr, $endianness:expr) => {{
+        let bytes = $bytes.try_into().expect("field size matches its type");
+        match $endianness {
+            Endianness::Little => <$t>::from_le_bytes(bytes),
+            Endianness::Big => <$t>::from_be_bytes(bytes),
+        }
+    }};
+}
+
+macro_rules! number_bytes {
+    ($value:expr, $endianness:expr) => {
+        match $endianness {
+            Endianness::Little => $value.to_le_bytes().to_vec(),
+            Endianness::Big => $value.to_be_bytes().to_vec(),
+        }
+    };
+}
+
+impl DynamicMessage {
+    //all fields start as zero
+    pub fn new(descriptor: Arc<MessageDescriptor>) -> Self {
+        let payload = vec![0; descriptor.payload_length()];
+        DynamicMessage { descriptor, payload }
+    }
+
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        &self.descriptor
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    fn field(&self, name: &str) -> Result<&FieldDescriptor> {
+        self.descriptor
+            .field(name)
+            .ok_or_else(|| anyhow!("{} has no field {}", self.descriptor.name, name))
+    }
+
+    pub fn get(&self, name: &str) -> Result<DynamicValue> {
+        let field = self.field(name)?;
+        let bytes = &self.payload[field.offset..field.offset + field.field_type.size()];
+        let e = field.endianness;
+        let value = match field.field_type {
+            DynamicFieldType::U8 => DynamicValue::U8(bytes[0]),
+            DynamicFieldType::I8 => DynamicValue::I8(bytes[0] as i8),
+            DynamicFieldType::U16 => DynamicValue::U16(read_number!(u16, bytes, e)),
+            DynamicFieldType::U32 => DynamicValue::U32(read_number!(u32, bytes, e)),
+            DynamicFieldType::U64 => DynamicValue::U64(read_number!(u64, bytes, e)),
+            DynamicFieldType::I16 => DynamicValue::I16(read_number!(i16, bytes, e)),
+            DynamicFieldType::I32 => DynamicValue::I32(read_number!(i32, bytes, e)),
+            DynamicFieldType::I64 => DynamicValue::I64(read_number!(i64, bytes, e)),
+            DynamicFieldType::F32 => DynamicValue::F32(read_number!(f32, bytes, e)),
+            DynamicFieldType::F64 => DynamicValue::F64(read_number!(f64, bytes, e)),
+            DynamicFieldType::Bytes(_) => DynamicValue::Bytes(bytes.to_vec()),
+        };
+        Ok(value)
+    }
+
+    //the value has to match the type of the field, byte fields take at most their length and are zero padded
+    pub fn set(&mut self, name: &str, value: DynamicValue) -> Result<()> {
+        let field = self.field(name)?.clone();
+        let e = field.endianness;
+        let bytes = match (field.field_type, value) {
+            (DynamicFieldType::U8, DynamicValue::U8(v)) => vec![v],
+            (DynamicFieldType::I8, DynamicValue::I8(v)) => vec![v as u8],
+            (DynamicFieldType::U16, DynamicValue::U16(v)) => number_bytes!(v, e),
+            (DynamicFieldType::U32, DynamicValue::U32(v)) => number_bytes!(v, e),
+            (DynamicFieldType::U64, DynamicValue::U64(v)) => number_bytes!(v, e),
+            (DynamicFieldType::I16, DynamicValue::I16(v)) => number_bytes!(v, e),
+            (DynamicFieldType::I32, DynamicValue::I32(v)) => number_bytes!(v, e),
+            (DynamicFieldType::I64, DynamicValue::I64(v)) => number_bytes!(v, e),
+            (DynamicFieldType::F32, DynamicValue::F32(v)) => number_bytes!(v, e),
+            (DynamicFieldType::F64, DynamicValue::F64(v)) => number_bytes!(v, e),
+            (DynamicFieldType::Bytes(len), DynamicValue::Bytes(v)) => {
+                if v.len() > len {
+                    return Err(anyhow!("Field {} holds {} bytes, got {}", name, len, v.len()));
+                }
+                let mut padded = v;
+                padded.resize(len, 0);
+                padded
+            }
+            (field_type, value) => {
+                return Err(anyhow!(
+                    "Field {} is {:?}, can not set it to {:?}",
+                    name,
+                    field_type,
+                    value
+                ))
+            }
+        };
+        self.payload[field.offset..field.offset + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    //integer fields as i64, u64 values above i64::MAX wrap around
+    pub fn get_integer(&self, name: &str) -> Result<i64> {
+        match self.get(name)? {
+            DynamicValue::U8(v) => Ok(v as i64),
+            DynamicValue::U16(v) => Ok(v as i64),
+            DynamicValue::U32(v) => Ok(v as i64),
+            DynamicValue::U64(v) => Ok(v as i64),
+            DynamicValue::I8(v) => Ok(v as i64),
+            DynamicValue::I16(v) => Ok(v as i64),
+            DynamicValue::I32(v) => Ok(v as i64),
+            DynamicValue::I64(v) => Ok(v),
+            other => Err(anyhow!("Field {} is not an integer: {:?}", name, other)),
+        }
+    }
+
+    //sets an integer field, fails if the value does not fit the field type
+    pub fn set_integer(&mut self, name: &str, value: i64) -> Result<()> {
+        let out_of_range = || anyhow!("{} does not fit in field {}", value, name);
+        let value = match self.field(name)?.field_type {
+            DynamicFieldType::U8 => DynamicValue::U8(value.try_into().map_err(|_| out_of_range())?),
+            DynamicFieldType::U16 => DynamicValue::U16(value.try_into().map_err(|_| out_of_range())?),
+            DynamicFieldType::U32 => DynamicValue::U32(value.try_into().map_err(|_| out_of_range())?),
+            DynamicFieldType::U64 => DynamicValue::U64(u64::try_from(value).map_err(|_| out_of_range())?),
+            DynamicFieldType::I8 => DynamicValue::I8(value.try_into().map_err(|_| out_of_range())?),
+            DynamicFieldType::I16 => DynamicValue::I16(value.try_into().map_err(|_| out_of_range())?),
+            DynamicFieldType::I32 => DynamicValue::I32(value.try_into().map_err(|_| out_of_range())?),
+            DynamicFieldType::I64 => DynamicValue::I64(value),
+            other => return Err(anyhow!("Field {} is not an integer: {:?}", name, other)),
+        };
+        self.set(name, value)
+    }
+
+    pub fn get_float(&self, name: &str) -> Result<f64> {
+        match self.get(name)? {
+            DynamicValue::F32(v) => Ok(v as f64),
+            DynamicValue::F64(v) => Ok(v),
+            other => Err(anyhow!("Field {} is not a float: {:?}", name, other)),
+        }
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f64) -> Result<()> {
+        match self.field(name)?.field_type {
+            DynamicFieldType::F32 => self.set(name, DynamicValue::F32(value as f32)),
+            DynamicFieldType::F64 => self.set(name, DynamicValue::F64(value)),
+            other => Err(anyhow!("Field {} is not a float: {:?}", name, other)),
+        }
+    }
+
+    pub fn to_concentrated(&self, counter: u16) -> SwordFishConcentratedMessage {
+        SwordFishConcentratedMessage::new(counter, self.descriptor.opcode, &self.payload)
+    }
+
+    //same rules as the derived messages: missing trailing bytes read as zero, extra bytes are ignored
+    pub fn from_concentrated(
+        descriptor: Arc<MessageDescriptor>,
+        concenrated_msg: &SwordFishConcentratedMessage,
+    ) -> Result<Self> {
+        if descriptor.opcode != concenrated_msg.opcode {
+            return Err(anyhow!(
+                "Wrong opcode, expected {}, got {}",
+                descriptor.opcode,
+                concenrated_msg.opcode
+            ));
+        }
+        let length = concenrated_msg.length as usize;
+        if length > MAX_PAYLOAD_SIZE {
+            return Err(anyhow!(
+                "Wrong length, maximum is {}, got {}",
+                MAX_PAYLOAD_SIZE,
+                length
+            ));
+        }
+        let mut msg = DynamicMessage::new(descriptor);
+        let n = std::cmp::min(length, msg.payload.len());
+        msg.payload[..n].copy_from_slice(&concenrated_msg.payload[..n]);
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swordfish_messages::VersionData;
+    use crate::SwordFishMessageTrait;
+
+    #[test]
+    fn describe_version_data_at_runtime() {
+        let mut descriptor = MessageDescriptor::new("VersionData", 2, SwordFishMessageCategory::Bounce);
+        descriptor.append_field("version", DynamicFieldType::U8, Endianness::Little).unwrap();
+        descriptor.append_field("subversion", DynamicFieldType::U8, Endianness::Little).unwrap();
+        descriptor.append_field("mcu_type", DynamicFieldType::U32, Endianness::Little).unwrap();
+        descriptor.append_field("uuid", DynamicFieldType::parse("[u8; 8]").unwrap(), Endianness::Little).unwrap();
+        let descriptor = Arc::new(descriptor);
+
+        let mut msg = DynamicMessage::new(descriptor.clone());
+        msg.set_integer("version", 1).unwrap();
+        msg.set_integer("mcu_type", 0x12345678).unwrap();
+        msg.set("uuid", DynamicValue::Bytes(vec![1, 2, 3])).unwrap();
+        assert!(msg.set_integer("subversion", 256).is_err());
+        assert!(msg.set_float("version", 1.0).is_err());
+
+        let typed = VersionData::from_concentrated(&msg.to_concentrated(0)).unwrap();
+        assert_eq!(typed, VersionData::new(1, 0, 0x12345678, &[1, 2, 3]));
+
+        let back = DynamicMessage::from_concentrated(descriptor, &typed.to_concentrated(0)).unwrap();
+        assert_eq!(back.get_integer("mcu_type").unwrap(), 0x12345678);
+        assert_eq!(back.get("uuid").unwrap(), DynamicValue::Bytes(vec![1, 2, 3, 0, 0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn big_endian_fields() {
+        let mut descriptor = MessageDescriptor::new("Sample", 200, SwordFishMessageCategory::Response);
+        descriptor.add_field("value", DynamicFieldType::U16, 1, Endianness::Big).unwrap();
+        assert!(descriptor.add_field("value", DynamicFieldType::U8, 0, Endianness::Big).is_err());
+        let mut msg = DynamicMessage::new(Arc::new(descriptor));
+        msg.set_integer("value", 0x0102).unwrap();
+        assert_eq!(msg.payload(), &[0, 1, 2]);
+    }
+
+    #[test]
+    fn negative_values_do_not_wrap_into_unsigned_fields() {
+        let mut descriptor = MessageDescriptor::new("Counter", 201, SwordFishMessageCategory::Response);
+        descriptor.append_field("total", DynamicFieldType::U64, Endianness::Little).unwrap();
+        let mut msg = DynamicMessage::new(Arc::new(descriptor));
+        assert!(msg.set_integer("total", -1).is_err());
+        msg.set_integer("total", i64::MAX).unwrap();
+        assert_eq!(msg.get("total").unwrap(), DynamicValue::U64(i64::MAX as u64));
+    }
+
+    #[test]
+    fn fields_past_the_end_of_memory_are_refused() {
+        let mut descriptor = MessageDescriptor::new("Huge", 202, SwordFishMessageCategory::Response);
+        assert!(descriptor.add_field("far", DynamicFieldType::U32, usize::MAX - 1, Endianness::Little).is_err());
+        let huge = DynamicFieldType::parse(&format!("[u8; {}]", usize::MAX)).unwrap();
+        assert!(descriptor.add_field("huge", huge, 1, Endianness::Little).is_err());
+        assert!(descriptor.fields.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//errors of a request that the caller may want to tell apart, e.g. to retry when the device is busy:
+//  match comm.request(msg) {
+//      Err(SwordFishError::DeviceRejected { code, .. }) if code == codes::BUSY => retry(),
+//      ...
+//  }
+//a rejected request is answered with a Nack (see messages.toml) instead of its answer.
+//the codes are listed in error_codes.toml, build.rs turns each into a constant of codes,
+//and rendered as text through the ErrorCodeRegistry
+use crate::swordfish_messages::Nack;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+
+//the codes shared by every firmware, e.g. codes::BUSY
+pub mod codes {
+    include!(concat!(env!("OUT_DIR"), "/error_codes.rs"));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ErrorCodeInfo {
+    pub code: u16,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorCodeRegistry {
+    codes: BTreeMap<u16, ErrorCodeInfo>,
+}
+
+//the codes shared by every firmware
+impl Default for ErrorCodeRegistry {
+    fn default() -> Self {
+        ErrorCodeRegistry::from_toml_str(include_str!("../error_codes.toml")).expect("error_codes.toml is not a valid error code list")
+    }
+}
+
+impl ErrorCodeRegistry {
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct ErrorCodeFile {
+            #[serde(default)]
+            code: Vec<ErrorCodeInfo>,
+        }
+        let file: ErrorCodeFile = toml::from_str(text)?;
+        let mut registry = ErrorCodeRegistry { codes: BTreeMap::new() };
+        for info in file.code {
+            registry.register(info)?;
+        }
+        Ok(registry)
+    }
+
+    //adds the codes of the file to the ones already known, none of them if one conflicts
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        let mut loaded = self.clone();
+        for info in ErrorCodeRegistry::from_toml_str(&text)?.codes.into_values() {
+            loaded.register(info)?;
+        }
+        *self = loaded;
+        Ok(())
+    }
+
+    pub fn register(&mut self, info: ErrorCodeInfo) -> Result<()> {
+        if let Some(existing) = self.codes.get(&info.code) {
+            return Err(anyhow!("Error code {} is already used by {}", info.code, existing.name));
+        }
+        if self.find(&info.name).is_some() {
+            return Err(anyhow!("Error code name {} is used twice", info.name));
+        }
+        self.codes.insert(info.code, info);
+        Ok(())
+    }
+
+    pub fn get(&self, code: u16) -> Option<&ErrorCodeInfo> {
+        self.codes.get(&code)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ErrorCodeInfo> {
+        self.codes.values().find(|info| info.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ErrorCodeInfo> {
+        self.codes.values()
+    }
+
+    //"busy (the device is busy with another operation)", or "error 0x1234" for an unknown code
+    pub fn describe(&self, code: u16) -> String {
+        match self.get(code) {
+            Some(info) if info.description.is_empty() => info.name.clone(),
+            Some(info) => format!("{} ({})", info.name, info.description),
+            None => format!("error 0x{:04x}", code),
+        }
+    }
+}
+
+//the registry used to render SwordFishError, starts with the codes of error_codes.toml
+pub fn error_codes() -> &'static RwLock<ErrorCodeRegistry> {
+    static ERROR_CODES: OnceLock<RwLock<ErrorCodeRegistry>> = OnceLock::new();
+    ERROR_CODES.get_or_init(|| RwLock::new(ErrorCodeRegistry::default()))
+}
+
+//the code of a name in error_codes(), None for a name that is not registered
+pub fn error_code(name: &str) -> Option<u16> {
+    error_codes()
+        .read()
+        .expect("Another thread holding the lock panicked")
+        .find(name)
+        .map(|info| info.code)
+}
+
+pub fn describe_error_code(code: u16) -> String {
+    error_codes()
+        .read()
+        .expect("Another thread holding the lock panicked")
+        .describe(code)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwordFishError {
+    //the device answered with a Nack
+    DeviceRejected {
+        opcode: u8,
+        counter: u16,
+        code: u16,
+        detail: String,
+    },
+    //no answer within the timeout
+    Timeout { opcode: u8 },
+    //the opcode is not answered (operation without a response, response, stream), use send_msg
+    NoAnswerExpected { opcode: u8 },
+    //neither generated, derived nor registered, nothing was sent
+    UnknownOpcode { opcode: u8 },
+}
+
+impl std::fmt::Display for SwordFishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SwordFishError::DeviceRejected { opcode, code, detail, .. } => {
+                write!(f, "The device rejected opcode {}: {}", opcode, describe_error_code(*code))?;
+                if !detail.is_empty() {
+                    write!(f, ": {}", detail)?;
+                }
+                Ok(())
+            }
+            SwordFishError::Timeout { opcode } => write!(f, "No answer for opcode {}", opcode),
+            SwordFishError::NoAnswerExpected { opcode } => write!(f, "Opcode {} is not answered by the device", opcode),
+            SwordFishError::UnknownOpcode { opcode } => write!(f, "Opcode {} is not a known message", opcode),
+        }
+    }
+}
+
+impl std::error::Error for SwordFishError {}
+
+impl From<&Nack> for SwordFishError {
+    fn from(nack: &Nack) -> Self {
+        SwordFishError::DeviceRejected {
+            opcode: nack.get_opcode(),
+            counter: nack.get_counter(),
+            code: nack.get_code(),
+            detail: nack.get_detail(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_render_as_text() {
+        let mut registry = ErrorCodeRegistry::default();
+        assert_eq!(registry.find("busy").unwrap().code, 4);
+        registry
+            .register(ErrorCodeInfo { code: 0x8001, name: "no_antenna".to_string(), description: String::new() })
+            .unwrap();
+        assert!(registry
+            .register(ErrorCodeInfo { code: 4, name: "other".to_string(), description: String::new() })
+            .is_err());
+        assert_eq!(registry.describe(0x8001), "no_antenna");
+        assert_eq!(registry.describe(0x8002), "error 0x8002");
+
+        let error = SwordFishError::DeviceRejected { opcode: 81, counter: 3, code: 4, detail: "flash write".to_string() };
+        assert_eq!(
+            error.to_string(),
+            "The device rejected opcode 81: busy (the device is busy with another operation): flash write"
+        );
+    }
+
+    #[test]
+    fn a_conflicting_file_loads_none_of_its_codes() {
+        let path = std::env::temp_dir().join(format!("swordfish_error_codes_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[[code]]\ncode = 0x8001\nname = \"no_antenna\"\n\n[[code]]\ncode = 4\nname = \"motor_stalled\"\n",
+        )
+        .unwrap();
+        let mut registry = ErrorCodeRegistry::default();
+        assert!(registry.load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(registry, ErrorCodeRegistry::default());
+        assert_eq!(registry.describe(0x8001), "error 0x8001");
+    }
+}
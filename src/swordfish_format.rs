@@ -0,0 +1,150 @@
+//frames for people: the name and category of the opcode, the counter, the length and whether the checksum is right,
+//then the decoded fields and a hexdump of the `length` valid payload bytes (never the padding after them).
+//Display uses FrameFormatter::default(), which knows the messages compiled into the library; pass
+//SwordFishComm::registry() to FrameFormatter::new to name the runtime messages too
+//  VersionData (opcode 2, bounce) #17, 14 bytes, checksum ok
+//    version    = 1
+//    subversion = 2
+//    mcu_type   = 4660
+//    uuid       = 626f6172642d3031
+//    0000  01 02 34 12 00 00 62 6f 61 72 64 2d 30 31        |..4...board-01|
+use crate::swordfish_concentrated_message::{SwordFishConcentratedMessage, MAX_PAYLOAD_SIZE};
+use crate::swordfish_registry::MessageRegistry;
+use crate::swordfish_util::{hexdump, to_hex};
+use std::fmt;
+use std::sync::OnceLock;
+
+fn compiled_registry() -> &'static MessageRegistry {
+    static REGISTRY: OnceLock<MessageRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(MessageRegistry::new)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameFormatter<'a> {
+    pub registry: &'a MessageRegistry,
+    //the decoded fields of known opcodes
+    pub fields: bool,
+    pub hexdump: bool,
+    pub bytes_per_line: usize,
+    //the header, the fields and the payload in hex on one line, for logs
+    pub single_line: bool,
+}
+
+impl Default for FrameFormatter<'static> {
+    fn default() -> Self {
+        FrameFormatter::new(compiled_registry())
+    }
+}
+
+impl<'a> FrameFormatter<'a> {
+    pub fn new(registry: &'a MessageRegistry) -> Self {
+        FrameFormatter {
+            registry,
+            fields: true,
+            hexdump: true,
+            bytes_per_line: 16,
+            single_line: false,
+        }
+    }
+
+    pub fn format(&self, msg: &SwordFishConcentratedMessage) -> String {
+        let mut text = String::new();
+        self.write(&mut text, msg).expect("writing to a String never fails");
+        text
+    }
+
+    pub fn write(&self, out: &mut dyn fmt::Write, msg: &SwordFishConcentratedMessage) -> fmt::Result {
+        let payload = &msg.payload[..std::cmp::min(msg.length as usize, MAX_PAYLOAD_SIZE)];
+        let info = self.registry.get(msg.opcode);
+        match info {
+            Some(info) => write!(out, "{} (opcode {}, {})", info.name, msg.opcode, info.category.name())?,
+            None => write!(out, "opcode {} (unknown)", msg.opcode)?,
+        }
+        write!(out, " #{}, {} bytes", msg.counter, msg.length)?;
+        if msg.length as usize > MAX_PAYLOAD_SIZE {
+            write!(out, " (at most {})", MAX_PAYLOAD_SIZE)?;
+        }
+        match msg.checksum_is_valid() {
+            true => write!(out, ", checksum ok")?,
+            false => write!(out, ", checksum bad 0x{:02x}, expected 0x{:02x}", msg.checksum, msg.expected_checksum())?,
+        }
+
+        let fields = match info {
+            Some(info) if self.fields => Some(info.decode_fields(payload)),
+            _ => None,
+        };
+        if self.single_line {
+            match fields {
+                Some(Ok(fields)) => fields.iter().try_for_each(|(name, value)| write!(out, " {}={}", name, value))?,
+                Some(Err(e)) => write!(out, " ({})", e)?,
+                None => {}
+            }
+            if self.hexdump && !payload.is_empty() {
+                write!(out, " payload={}", to_hex(payload))?;
+            }
+            return Ok(());
+        }
+        match fields {
+            Some(Ok(fields)) => {
+                let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+                for (name, value) in fields {
+                    write!(out, "\n  {:<width$} = {}", name, value, width = width)?;
+                }
+            }
+            Some(Err(e)) => write!(out, "\n  could not decode: {}", e)?,
+            None => {}
+        }
+        if self.hexdump {
+            for line in hexdump(payload, self.bytes_per_line).lines() {
+                write!(out, "\n  {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SwordFishConcentratedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        FrameFormatter::default().write(f, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swordfish_messages::VersionData;
+    use crate::SwordFishMessageTrait;
+
+    #[test]
+    fn known_frames_show_their_fields_and_valid_bytes() {
+        let frame = VersionData::new(1, 2, 0x1234, b"board-01").to_concentrated(17);
+        let text = frame.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "VersionData (opcode 2, bounce) #17, 14 bytes, checksum ok");
+        assert_eq!(lines[3], "  mcu_type   = 4660");
+        assert_eq!(lines[5], "  0000  01 02 34 12 00 00 62 6f 61 72 64 2d 30 31        |..4...board-01|");
+        assert_eq!(lines.len(), 6);
+
+        let formatter = FrameFormatter {
+            single_line: true,
+            hexdump: false,
+            ..FrameFormatter::default()
+        };
+        assert_eq!(
+            formatter.format(&frame),
+            "VersionData (opcode 2, bounce) #17, 14 bytes, checksum ok version=1 subversion=2 mcu_type=4660 uuid=626f6172642d3031"
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_and_bad_checksums() {
+        let mut frame = SwordFishConcentratedMessage::new(3, 249, &[0xaa; 20]);
+        frame.checksum ^= 1;
+        let text = frame.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("opcode 249 (unknown) #3, 20 bytes, checksum bad"));
+        assert!(lines[1].starts_with("  0000  aa aa"));
+        assert!(lines[2].starts_with("  0010  aa aa aa aa   "));
+        assert_eq!(lines.len(), 3);
+    }
+}
@@ -1,36 +1,19 @@
-use crate::{SwordFishMessageBucket, SwordFishMessageCategory, SwordFishMessageTrait};
+use crate::{SwordFishMessage, SwordFishMessageBucket, SwordFishMessageRegistration};
 use std::collections::HashMap;
 
-//--------------Ping------------------//
-#[repr(C, packed(1))]
-#[derive(Debug, Default)]
-pub struct Ping {}
-impl SwordFishMessageTrait for Ping {
-    const OPCODE: u8 = 0;
-    const CATEGORY: SwordFishMessageCategory = SwordFishMessageCategory::Bounce;
-}
-
-//----------------VersionData----------------//
-#[repr(C, packed(1))]
-#[derive(Debug, PartialEq, Eq, Default)] //partial Eq and Eq are needed for the tests
-pub struct VersionData {
-    pub version: u8,
-    pub subversion: u8,
-    pub mcu_type: u32,
-    pub uuid: [u8; 8],
-}
-impl SwordFishMessageTrait for VersionData {
-    const OPCODE: u8 = 2;
-    const CATEGORY: SwordFishMessageCategory = SwordFishMessageCategory::Bounce;
-}
+//the messages are generated from messages.toml by build.rs, edit the schema instead of this file
+include!(concat!(env!("OUT_DIR"), "/swordfish_messages.rs"));
 
+//every message deriving SwordFishMessage registers itself, no need to list them here.
+//the opcodes are unique, the derive makes the link fail otherwise
 pub fn create_swordfish_messages_hashmap() -> HashMap<u8, SwordFishMessageBucket> {
     let mut map = HashMap::new();
-    map.insert(Ping::OPCODE, SwordFishMessageBucket::new(Ping::CATEGORY));
-    map.insert(
-        VersionData::OPCODE,
-        SwordFishMessageBucket::new(VersionData::CATEGORY),
-    );
+    for registration in inventory::iter::<SwordFishMessageRegistration> {
+        map.insert(
+            registration.opcode,
+            SwordFishMessageBucket::new(registration.category),
+        );
+    }
     map
 }
 
@@ -38,6 +21,7 @@ pub fn create_swordfish_messages_hashmap() -> HashMap<u8, SwordFishMessageBucket
 mod tests {
     use super::*;
     use crate::swordfish_concentrated_message::SwordFishConcentratedMessageBufferBuilder;
+    use crate::SwordFishMessageTrait;
 
     #[test]
     fn to_from() {
@@ -50,4 +34,57 @@ mod tests {
         let output_version_data = VersionData::from_concentrated(&output_concenrated_msg).unwrap();
         assert_eq!(input_version_data, output_version_data);
     }
+
+    #[test]
+    fn derived_messages_are_registered() {
+        let map = create_swordfish_messages_hashmap();
+        //only the messages of the schema, test messages must not leak into the library
+        assert_eq!(map.len(), include_str!("../messages.toml").matches("\n[[message]]").count());
+        assert_eq!(map[&Ping::OPCODE].catagory, Ping::CATEGORY);
+        assert_eq!(map[&VersionData::OPCODE].catagory, VersionData::CATEGORY);
+    }
+
+    #[test]
+    fn variable_length_fields_are_bounded() {
+        let mut echo = Echo::new("über", &[1, 2, 3], &[-1, 300]).unwrap();
+        echo.set_samples(&[-5; 8]).unwrap();
+        //too long is an error, the field keeps its value
+        assert!(echo.set_text(&"x".repeat(33)).is_err());
+        assert!(echo.set_data(&[0; 33]).is_err());
+        assert!(Echo::new("", &[], &[0; 9]).is_err());
+
+        let frame = echo.to_concentrated(3);
+        //the length prefixes keep the frame short
+        assert_eq!(frame.length, 1 + 5 + 1 + 3 + 1 + 16);
+        let decoded = Echo::from_concentrated(&frame).unwrap();
+        assert_eq!(decoded, echo);
+        assert_eq!(decoded.get_text(), "über");
+        assert_eq!(decoded.get_data(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn the_committed_c_header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/swordfish_messages.h"));
+        assert!(
+            include_str!("../include/swordfish_messages.h") == generated,
+            "include/swordfish_messages.h does not match messages.toml, copy {} over it",
+            concat!(env!("OUT_DIR"), "/swordfish_messages.h")
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn messages_serialize_field_by_field() {
+        let version = VersionData::new(1, 2, 0x1234, &[0xab; 8]);
+        let json = serde_json::to_value(version).unwrap();
+        assert_eq!(json["mcu_type"], serde_json::json!(0x1234));
+        assert_eq!(json["uuid"], serde_json::json!(vec![0xab; 8]));
+        assert_eq!(serde_json::from_value::<VersionData>(json).unwrap(), version);
+
+        let nack = Nack::new(81, 3, 4, "flash write").unwrap();
+        let json = serde_json::to_string(&nack).unwrap();
+        assert!(json.contains("\"detail\":\"flash write\""));
+        assert_eq!(serde_json::from_str::<Nack>(&json).unwrap(), nack);
+        assert!(serde_json::from_str::<Nack>(&json.replace("flash write", &"x".repeat(65))).is_err());
+    }
 }
\ No newline at end of file
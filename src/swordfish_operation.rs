@@ -0,0 +1,405 @@
+//operations that take seconds (calibration, self test) and report how far they got:
+//the host sends the request, the device answers with OperationProgress messages while it runs
+//and one OperationResult at the end. the OperationHandle returned by SwordFishComm::start_operation
+//collects them and completes in whichever way suits the caller:
+//  let handle = comm.start_operation(Calibrate::new(0).to_concentrated(comm.next_tx_counter()), Duration::from_secs(2))?;
+//  handle.on_progress(Box::new(|p| println!("{}% {}", p.get_percent(), p.get_text())));
+//  let result = handle.wait()?;           //blocking
+//  handle.on_complete(Box::new(|outcome| ...));  //callback, from the read thread
+//  let result = handle.await?;            //async, OperationHandle is a Future
+//the timeout restarts with every progress message, so it only has to cover the longest silent stage.
+//the progress and result frames carry the counter of the request, so a late result of a cancelled run
+//can not end the next run of the same operation
+use crate::swordfish_concentrated_message::SwordFishConcentratedMessage;
+use crate::swordfish_error::SwordFishError;
+use crate::swordfish_messages::{Nack, OperationAbort, OperationProgress, OperationResult};
+use crate::SwordFishMessageTrait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_OPERATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationError {
+    Failed { status: u8, data: Vec<u8> }, //the device ended it with a non zero status
+    Timeout, //no progress or result within the timeout
+    Cancelled,
+    Rejected(SwordFishError), //the device answered the request with a Nack
+}
+
+impl std::fmt::Display for OperationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OperationError::Failed { status, .. } => write!(f, "The operation failed with status {}", status),
+            OperationError::Timeout => write!(f, "The operation timed out"),
+            OperationError::Cancelled => write!(f, "The operation was cancelled"),
+            OperationError::Rejected(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for OperationError {}
+
+pub type OperationOutcome = Result<OperationResult, OperationError>;
+pub type ProgressCallback = Box<dyn FnMut(&OperationProgress) + Send>;
+pub type CompletionCallback = Box<dyn FnOnce(&OperationOutcome) + Send>;
+
+struct OperationState {
+    progress: Vec<OperationProgress>,
+    outcome: Option<OperationOutcome>,
+    timeout: Duration,
+    deadline: Instant,
+    on_progress: Option<ProgressCallback>,
+    on_complete: Option<CompletionCallback>,
+    waker: Option<Waker>,
+}
+
+struct Operation {
+    opcode: u8,
+    counter: u16, //of the request
+    state: Mutex<OperationState>,
+    condvar: Condvar,
+}
+
+impl Operation {
+    fn lock(&self) -> MutexGuard<'_, OperationState> {
+        self.state
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+    }
+
+    fn progress(&self, progress: OperationProgress) {
+        let mut state = self.lock();
+        if state.outcome.is_some() {
+            return;
+        }
+        state.deadline = Instant::now() + state.timeout;
+        state.progress.push(progress.clone());
+        //the callback runs without the lock, so it can use the handle
+        let mut on_progress = state.on_progress.take();
+        drop(state);
+        if let Some(callback) = on_progress.as_mut() {
+            callback(&progress);
+        }
+        let mut state = self.lock();
+        if state.on_progress.is_none() {
+            state.on_progress = on_progress;
+        }
+    }
+
+    //the first outcome wins, returns false if the operation had already finished
+    fn finish(&self, outcome: OperationOutcome) -> bool {
+        let mut state = self.lock();
+        if state.outcome.is_some() {
+            return false;
+        }
+        state.outcome = Some(outcome.clone());
+        let waker = state.waker.take();
+        let on_complete = state.on_complete.take();
+        drop(state);
+        self.condvar.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        if let Some(callback) = on_complete {
+            callback(&outcome);
+        }
+        true
+    }
+
+    fn timed_out(&self, now: Instant) -> bool {
+        let state = self.lock();
+        state.outcome.is_none() && now >= state.deadline
+    }
+
+    fn is_finished(&self) -> bool {
+        self.lock().outcome.is_some()
+    }
+}
+
+//the running operations by opcode and counter of the request, fed by the read thread of SwordFishComm
+#[derive(Default)]
+pub struct OperationTracker {
+    running: Mutex<HashMap<(u8, u16), Arc<Operation>>>,
+}
+
+impl OperationTracker {
+    fn lock(&self) -> MutexGuard<'_, HashMap<(u8, u16), Arc<Operation>>> {
+        self.running
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+    }
+
+    //only one run per opcode at a time, like on the device
+    fn start(&self, opcode: u8, counter: u16, timeout: Duration) -> anyhow::Result<Arc<Operation>> {
+        let mut running = self.lock();
+        if running
+            .iter()
+            .any(|((running_opcode, _), operation)| *running_opcode == opcode && !operation.is_finished())
+        {
+            return Err(anyhow::anyhow!("Operation {} is already running", opcode));
+        }
+        let operation = Arc::new(Operation {
+            opcode,
+            counter,
+            state: Mutex::new(OperationState {
+                progress: Vec::new(),
+                outcome: None,
+                timeout,
+                deadline: Instant::now() + timeout,
+                on_progress: None,
+                on_complete: None,
+                waker: None,
+            }),
+            condvar: Condvar::new(),
+        });
+        running.insert((opcode, counter), operation.clone());
+        Ok(operation)
+    }
+
+    fn get(&self, opcode: u8, counter: u16) -> Option<Arc<Operation>> {
+        self.lock().get(&(opcode, counter)).cloned()
+    }
+
+    //counter is the one of the frame, the counter of the request
+    pub fn handle_progress(&self, progress: &OperationProgress, counter: u16) {
+        match self.get(progress.get_operation(), counter) {
+            Some(operation) => operation.progress(progress.clone()),
+            None => log::debug!("Progress of operation {} ({}) that is not running", progress.get_operation(), counter),
+        }
+    }
+
+    pub fn handle_result(&self, result: &OperationResult, counter: u16) {
+        let operation = match self.lock().remove(&(result.get_operation(), counter)) {
+            Some(operation) => operation,
+            None => {
+                log::debug!("Result of operation {} ({}) that is not running", result.get_operation(), counter);
+                return;
+            }
+        };
+        let outcome = match result.get_status() {
+            0 => Ok(result.clone()),
+            status => Err(OperationError::Failed {
+                status,
+                data: result.get_data(),
+            }),
+        };
+        operation.finish(outcome);
+    }
+
+    //true if the nack was for a running operation, which it ends
+    pub fn handle_nack(&self, nack: &Nack) -> bool {
+        let operation = match self.lock().remove(&(nack.get_opcode(), nack.get_counter())) {
+            Some(operation) => operation,
+            None => return false,
+        };
+        operation.finish(Err(OperationError::Rejected(SwordFishError::from(nack))))
+    }
+
+    //ends the operations that have been silent for longer than their timeout, called by the read thread
+    pub fn poll_timeouts(&self) {
+        let now = Instant::now();
+        let expired: Vec<Arc<Operation>> = {
+            let mut running = self.lock();
+            let expired = running
+                .values()
+                .filter(|operation| operation.timed_out(now))
+                .cloned()
+                .collect();
+            running.retain(|_, operation| !operation.is_finished() && !operation.timed_out(now));
+            expired
+        };
+        for operation in expired {
+            log::warn!("Operation {} timed out", operation.opcode);
+            operation.finish(Err(OperationError::Timeout));
+        }
+    }
+
+    pub fn running(&self) -> Vec<u8> {
+        self.lock()
+            .iter()
+            .filter(|(_, operation)| !operation.is_finished())
+            .map(|((opcode, _), _)| *opcode)
+            .collect()
+    }
+
+    pub(crate) fn start_handle(
+        &self,
+        opcode: u8,
+        counter: u16,
+        timeout: Duration,
+        transmitter: Sender<SwordFishConcentratedMessage>,
+        tx_counter: Arc<AtomicUsize>,
+    ) -> anyhow::Result<OperationHandle> {
+        Ok(OperationHandle {
+            operation: self.start(opcode, counter, timeout)?,
+            transmitter,
+            tx_counter,
+        })
+    }
+}
+
+pub struct OperationHandle {
+    operation: Arc<Operation>,
+    transmitter: Sender<SwordFishConcentratedMessage>,
+    tx_counter: Arc<AtomicUsize>,
+}
+
+impl OperationHandle {
+    //the opcode of the request
+    pub fn opcode(&self) -> u8 {
+        self.operation.opcode
+    }
+
+    //the counter of the request, the device answers with it
+    pub fn counter(&self) -> u16 {
+        self.operation.counter
+    }
+
+    //every progress message so far, oldest first
+    pub fn progress(&self) -> Vec<OperationProgress> {
+        self.operation.lock().progress.clone()
+    }
+
+    pub fn latest_progress(&self) -> Option<OperationProgress> {
+        self.operation.lock().progress.last().cloned()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.operation.is_finished()
+    }
+
+    //the outcome, None while it runs
+    pub fn try_result(&self) -> Option<OperationOutcome> {
+        self.operation.lock().outcome.clone()
+    }
+
+    //the time allowed between two progress messages, counted from now
+    pub fn set_timeout(&self, timeout: Duration) {
+        let mut state = self.operation.lock();
+        state.timeout = timeout;
+        state.deadline = Instant::now() + timeout;
+    }
+
+    //called from the read thread with every progress message
+    pub fn on_progress(&self, callback: ProgressCallback) {
+        self.operation.lock().on_progress = Some(callback);
+    }
+
+    //called once from the read thread when the operation ends, right away if it already has
+    pub fn on_complete(&self, callback: CompletionCallback) {
+        let mut state = self.operation.lock();
+        match state.outcome.clone() {
+            Some(outcome) => {
+                drop(state);
+                callback(&outcome);
+            }
+            None => state.on_complete = Some(callback),
+        }
+    }
+
+    //blocks until the operation ends
+    pub fn wait(&self) -> OperationOutcome {
+        let mut state = self.operation.lock();
+        loop {
+            if let Some(outcome) = &state.outcome {
+                return outcome.clone();
+            }
+            let now = Instant::now();
+            //the read thread normally ends it, this covers a comm that was dropped
+            if now >= state.deadline {
+                drop(state);
+                self.operation.finish(Err(OperationError::Timeout));
+                state = self.operation.lock();
+                continue;
+            }
+            let remaining = state.deadline - now;
+            state = self
+                .operation
+                .condvar
+                .wait_timeout(state, remaining)
+                .expect("Another thread holding the mutex panicked")
+                .0;
+        }
+    }
+
+    //sends OperationAbort and ends the operation with OperationError::Cancelled,
+    //returns false if it had already ended
+    pub fn cancel(&self) -> bool {
+        if self.operation.is_finished() {
+            return false;
+        }
+        let abort = OperationAbort::new(self.operation.opcode)
+            .to_concentrated(self.tx_counter.fetch_add(1, Ordering::Relaxed) as u16);
+        if self.transmitter.send(abort).is_err() {
+            log::warn!("Could not send the abort of operation {}", self.operation.opcode);
+        }
+        self.operation.finish(Err(OperationError::Cancelled))
+    }
+}
+
+impl Future for OperationHandle {
+    type Output = OperationOutcome;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.operation.lock();
+        match &state.outcome {
+            Some(outcome) => Poll::Ready(outcome.clone()),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_restarts_on_progress() {
+        let tracker = OperationTracker::default();
+        let (transmitter, _receiver) = std::sync::mpsc::channel();
+        let handle = tracker
+            .start_handle(110, 4, Duration::from_millis(50), transmitter, Arc::new(AtomicUsize::new(0)))
+            .unwrap();
+        assert!(tracker.start(110, 5, Duration::from_millis(50)).is_err());
+        for percent in [25, 50, 75] {
+            std::thread::sleep(Duration::from_millis(30));
+            tracker.handle_progress(&OperationProgress::new(110, percent, 0, "calibrating").unwrap(), 4);
+            tracker.poll_timeouts();
+        }
+        assert!(!handle.is_finished());
+        assert_eq!(handle.latest_progress().unwrap().get_percent(), 75);
+        std::thread::sleep(Duration::from_millis(60));
+        tracker.poll_timeouts();
+        assert_eq!(handle.try_result(), Some(Err(OperationError::Timeout)));
+        assert!(tracker.running().is_empty());
+    }
+
+    #[test]
+    fn results_of_an_earlier_run_are_ignored() {
+        let tracker = OperationTracker::default();
+        let (transmitter, _receiver) = std::sync::mpsc::channel();
+        let first = tracker
+            .start_handle(110, 1, DEFAULT_OPERATION_TIMEOUT, transmitter.clone(), Arc::new(AtomicUsize::new(0)))
+            .unwrap();
+        assert!(first.cancel());
+        let second = tracker
+            .start_handle(110, 2, DEFAULT_OPERATION_TIMEOUT, transmitter, Arc::new(AtomicUsize::new(0)))
+            .unwrap();
+        //the device ends the aborted run
+        tracker.handle_result(&OperationResult::new(110, 3, &[]).unwrap(), 1);
+        assert!(!tracker.handle_nack(&Nack::new(110, 1, 7, "").unwrap()));
+        assert!(!second.is_finished());
+        tracker.handle_result(&OperationResult::new(110, 0, &[9]).unwrap(), 2);
+        assert_eq!(second.wait().unwrap().get_data(), vec![9]);
+    }
+}
@@ -0,0 +1,484 @@
+//typed access to the device parameters, on top of the ParamRead/ParamWrite messages
+//the ParamTable (params.toml) gives every parameter an id, a type, a range, a unit and a default,
+//values are validated on the host before they are sent. a ParamSet is a named list of values,
+//saved to and loaded from toml files for provisioning:
+//  led_brightness = 80
+//  tx_power = -3.5
+use crate::swordfish_comm::SwordFishComm;
+use crate::swordfish_messages::{ParamRead, ParamWrite};
+use crate::SwordFishMessageTrait;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamStatus {
+    Ok = 0,
+    UnknownId = 1,
+    OutOfRange = 2,
+    ReadOnly = 3,
+}
+
+impl ParamStatus {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ParamStatus::Ok),
+            1 => Some(ParamStatus::UnknownId),
+            2 => Some(ParamStatus::OutOfRange),
+            3 => Some(ParamStatus::ReadOnly),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    F32,
+}
+
+impl ParamType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bool" => Some(ParamType::Bool),
+            "u8" => Some(ParamType::U8),
+            "u16" => Some(ParamType::U16),
+            "u32" => Some(ParamType::U32),
+            "i8" => Some(ParamType::I8),
+            "i16" => Some(ParamType::I16),
+            "i32" => Some(ParamType::I32),
+            "f32" => Some(ParamType::F32),
+            _ => None,
+        }
+    }
+
+    //the values an integer type can hold
+    fn integer_bounds(&self) -> Option<(i64, i64)> {
+        match self {
+            ParamType::U8 => Some((0, u8::MAX as i64)),
+            ParamType::U16 => Some((0, u16::MAX as i64)),
+            ParamType::U32 => Some((0, u32::MAX as i64)),
+            ParamType::I8 => Some((i8::MIN as i64, i8::MAX as i64)),
+            ParamType::I16 => Some((i16::MIN as i64, i16::MAX as i64)),
+            ParamType::I32 => Some((i32::MIN as i64, i32::MAX as i64)),
+            ParamType::Bool | ParamType::F32 => None,
+        }
+    }
+}
+
+//bool, integer or float, written to toml as such
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Bool(value) => write!(f, "{}", value),
+            ParamValue::Integer(value) => write!(f, "{}", value),
+            ParamValue::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<bool> for ParamValue {
+    fn from(value: bool) -> Self {
+        ParamValue::Bool(value)
+    }
+}
+
+impl From<i64> for ParamValue {
+    fn from(value: i64) -> Self {
+        ParamValue::Integer(value)
+    }
+}
+
+//the integer types of the parameters, so set_param("fan", 1200u16) needs no cast
+macro_rules! impl_from_integer {
+    ($($int:ty),*) => {
+        $(impl From<$int> for ParamValue {
+            fn from(value: $int) -> Self {
+                ParamValue::Integer(value as i64)
+            }
+        })*
+    };
+}
+impl_from_integer!(u8, u16, u32, i8, i16, i32);
+
+impl From<f64> for ParamValue {
+    fn from(value: f64) -> Self {
+        ParamValue::Float(value)
+    }
+}
+
+impl From<f32> for ParamValue {
+    fn from(value: f32) -> Self {
+        ParamValue::Float(value as f64)
+    }
+}
+
+//--------------ParamTable------------------//
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ParamDef {
+    pub id: u16,
+    pub name: String,
+    #[serde(rename = "type", deserialize_with = "deserialize_param_type")]
+    pub param_type: ParamType,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub unit: String,
+    pub default: ParamValue,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn deserialize_param_type<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<ParamType, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    ParamType::from_name(&name).ok_or_else(|| serde::de::Error::custom(format!("unknown parameter type \"{}\"", name)))
+}
+
+impl ParamDef {
+    //the value converted to the type of the parameter, an error if it does not fit the type or the range
+    pub fn validate(&self, value: ParamValue) -> Result<ParamValue> {
+        let value = match (self.param_type, value) {
+            (ParamType::Bool, ParamValue::Bool(_)) => value,
+            (ParamType::F32, ParamValue::Float(_)) => value,
+            (ParamType::F32, ParamValue::Integer(v)) => ParamValue::Float(v as f64),
+            (param_type, ParamValue::Integer(v)) if param_type != ParamType::Bool => {
+                let (low, high) = param_type.integer_bounds().expect("integer types have bounds");
+                if v < low || v > high {
+                    return Err(anyhow!("{} does not fit in a {:?}, got {}", self.name, param_type, v));
+                }
+                value
+            }
+            _ => return Err(anyhow!("{} is a {:?}, got {}", self.name, self.param_type, value)),
+        };
+        let number = match value {
+            ParamValue::Integer(v) => v as f64,
+            ParamValue::Float(v) => v,
+            ParamValue::Bool(_) => return Ok(value),
+        };
+        if self.min.is_some_and(|min| number < min) || self.max.is_some_and(|max| number > max) {
+            return Err(anyhow!(
+                "{} is out of range [{}, {}] {}, got {}",
+                self.name,
+                self.min.map_or("-".to_string(), |min| min.to_string()),
+                self.max.map_or("-".to_string(), |max| max.to_string()),
+                self.unit,
+                value
+            ));
+        }
+        Ok(value)
+    }
+
+    //the u64 of ParamRead/ParamWrite, value has to be validated first
+    pub fn to_raw(&self, value: ParamValue) -> u64 {
+        match value {
+            ParamValue::Bool(v) => v as u64,
+            ParamValue::Integer(v) => v as u64,
+            ParamValue::Float(v) => (v as f32).to_bits() as u64,
+        }
+    }
+
+    pub fn from_raw(&self, raw: u64) -> ParamValue {
+        match self.param_type {
+            ParamType::Bool => ParamValue::Bool(raw != 0),
+            ParamType::U8 => ParamValue::Integer(raw as u8 as i64),
+            ParamType::U16 => ParamValue::Integer(raw as u16 as i64),
+            ParamType::U32 => ParamValue::Integer(raw as u32 as i64),
+            ParamType::I8 => ParamValue::Integer(raw as i8 as i64),
+            ParamType::I16 => ParamValue::Integer(raw as i16 as i64),
+            ParamType::I32 => ParamValue::Integer(raw as i32 as i64),
+            ParamType::F32 => ParamValue::Float(f32::from_bits(raw as u32) as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamTable {
+    params: Vec<ParamDef>,
+}
+
+//the parameters of the firmware
+impl Default for ParamTable {
+    fn default() -> Self {
+        ParamTable::from_toml_str(include_str!("../params.toml")).expect("params.toml is not a valid parameter table")
+    }
+}
+
+impl ParamTable {
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct ParamFile {
+            #[serde(default)]
+            param: Vec<ParamDef>,
+        }
+        let file: ParamFile = toml::from_str(text)?;
+        let mut ids = std::collections::HashSet::new();
+        let mut names = std::collections::HashSet::new();
+        for param in file.param.iter() {
+            if !ids.insert(param.id) {
+                return Err(anyhow!("Parameter id {} is used twice", param.id));
+            }
+            if !names.insert(param.name.as_str()) {
+                return Err(anyhow!("Parameter name {} is used twice", param.name));
+            }
+            param
+                .validate(param.default)
+                .map_err(|e| anyhow!("Bad default value: {}", e))?;
+        }
+        Ok(ParamTable { params: file.param })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        ParamTable::from_toml_str(&text)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ParamDef> {
+        self.params.iter().find(|param| param.name == name)
+    }
+
+    pub fn get_by_id(&self, id: u16) -> Option<&ParamDef> {
+        self.params.iter().find(|param| param.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ParamDef> {
+        self.params.iter()
+    }
+
+    pub fn defaults(&self) -> ParamSet {
+        ParamSet {
+            values: self
+                .params
+                .iter()
+                .map(|param| (param.name.clone(), param.default))
+                .collect(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<&ParamDef> {
+        self.get(name).ok_or_else(|| anyhow!("Unknown parameter {}", name))
+    }
+}
+
+//--------------ParamSet------------------//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ParamSet {
+    pub values: BTreeMap<String, ParamValue>,
+}
+
+impl ParamSet {
+    pub fn get(&self, name: &str) -> Option<ParamValue> {
+        self.values.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, value: impl Into<ParamValue>) {
+        self.values.insert(name.to_string(), value.into());
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string(self).expect("a map of plain values, this should never fail")
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_toml_string()).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        ParamSet::from_toml_str(&text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamDiff {
+    pub name: String,
+    pub device: ParamValue,
+    pub profile: ParamValue,
+}
+
+//--------------ParamStore------------------//
+pub struct ParamStore<'a> {
+    comm: &'a SwordFishComm,
+    table: ParamTable,
+}
+
+impl<'a> ParamStore<'a> {
+    //with the parameter table of the firmware, see ParamTable::default
+    pub fn new(comm: &'a SwordFishComm) -> Self {
+        ParamStore::with_table(comm, ParamTable::default())
+    }
+
+    pub fn with_table(comm: &'a SwordFishComm, table: ParamTable) -> Self {
+        ParamStore { comm, table }
+    }
+
+    pub fn table(&self) -> &ParamTable {
+        &self.table
+    }
+
+    pub fn get_param(&self, name: &str) -> Result<ParamValue> {
+        let param = self.table.lookup(name)?;
+        let request = ParamRead::new(param.id, 0, 0);
+        let answer = self.exchange(&request, param)?;
+        Ok(param.from_raw(answer.get_value()))
+    }
+
+    pub fn set_param(&self, name: &str, value: impl Into<ParamValue>) -> Result<()> {
+        let param = self.table.lookup(name)?;
+        if param.read_only {
+            return Err(anyhow!("{} is read only", name));
+        }
+        let value = param.validate(value.into())?;
+        let request = ParamWrite::new(param.id, 0, param.to_raw(value));
+        let answer = self.exchange(&request, param)?;
+        if param.from_raw(answer.get_value()) != param.from_raw(param.to_raw(value)) {
+            return Err(anyhow!(
+                "{} was set to {}, the device kept {}",
+                name,
+                value,
+                param.from_raw(answer.get_value())
+            ));
+        }
+        Ok(())
+    }
+
+    //every parameter of the table
+    pub fn read_all(&self) -> Result<ParamSet> {
+        let mut set = ParamSet::default();
+        for param in self.table.iter() {
+            set.set(&param.name, self.get_param(&param.name)?);
+        }
+        Ok(set)
+    }
+
+    //the whole set is validated before the first write, read only parameters are skipped
+    pub fn write_all(&self, set: &ParamSet) -> Result<()> {
+        for (name, value) in set.values.iter() {
+            self.table.lookup(name)?.validate(*value)?;
+        }
+        for (name, value) in set.values.iter() {
+            if self.table.lookup(name)?.read_only {
+                log::info!("Skipping read only parameter {}", name);
+                continue;
+            }
+            self.set_param(name, *value)?;
+        }
+        Ok(())
+    }
+
+    //the parameters of the profile that have a different value on the device
+    pub fn diff(&self, profile: &ParamSet) -> Result<Vec<ParamDiff>> {
+        let mut diffs = Vec::new();
+        for (name, profile_value) in profile.values.iter() {
+            let param = self.table.lookup(name)?;
+            let profile_value = param.validate(*profile_value)?;
+            let device_value = self.get_param(name)?;
+            //compared as sent on the wire, a f32 does not round trip through f64 text
+            if param.to_raw(device_value) != param.to_raw(profile_value) {
+                diffs.push(ParamDiff {
+                    name: name.clone(),
+                    device: device_value,
+                    profile: profile_value,
+                });
+            }
+        }
+        Ok(diffs)
+    }
+
+    //ParamRead and ParamWrite share the fields, the answer has to be for the same parameter
+    fn exchange<T: SwordFishMessageTrait + ParamMessage>(&self, request: &T, param: &ParamDef) -> Result<T> {
+        let concentrated_msg = request.to_concentrated(self.comm.next_tx_counter());
+        let answer = self
+            .comm
+            .request(concentrated_msg)
+            .map_err(|e| anyhow!("Parameter {}: {}", param.name, e))?;
+        let answer = T::from_concentrated(&answer)?;
+        if answer.id() != param.id {
+            return Err(anyhow!("Answer for parameter id {}, expected {}", answer.id(), param.id));
+        }
+        match ParamStatus::from_u8(answer.status()) {
+            Some(ParamStatus::Ok) => Ok(answer),
+            Some(status) => Err(anyhow!("Device rejected parameter {}: {:?}", param.name, status)),
+            None => Err(anyhow!("Device rejected parameter {}: status {}", param.name, answer.status())),
+        }
+    }
+}
+
+trait ParamMessage {
+    fn id(&self) -> u16;
+    fn status(&self) -> u8;
+}
+
+impl ParamMessage for ParamRead {
+    fn id(&self) -> u16 {
+        self.get_id()
+    }
+    fn status(&self) -> u8 {
+        self.get_status()
+    }
+}
+
+impl ParamMessage for ParamWrite {
+    fn id(&self) -> u16 {
+        self.get_id()
+    }
+    fn status(&self) -> u8 {
+        self.get_status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_and_raw_values() {
+        let table = ParamTable::default();
+        let brightness = table.get("led_brightness").unwrap();
+        assert!(brightness.validate(ParamValue::Integer(101)).is_err());
+        assert!(brightness.validate(ParamValue::Bool(true)).is_err());
+        let offset = table.get("frequency_offset").unwrap();
+        assert_eq!(offset.from_raw(offset.to_raw(ParamValue::Integer(-5))), ParamValue::Integer(-5));
+        let power = table.get("tx_power").unwrap();
+        assert_eq!(power.validate(ParamValue::Integer(-3)).unwrap(), ParamValue::Float(-3.0));
+        assert_eq!(power.from_raw(power.to_raw(ParamValue::Float(-3.5))), ParamValue::Float(-3.5));
+    }
+
+    #[test]
+    fn values_from_the_integer_types() {
+        assert_eq!(ParamValue::from(200u8), ParamValue::Integer(200));
+        assert_eq!(ParamValue::from(-5i16), ParamValue::Integer(-5));
+        assert_eq!(ParamValue::from(u32::MAX), ParamValue::Integer(u32::MAX as i64));
+        assert_eq!(ParamValue::from(0.5f32), ParamValue::Float(0.5));
+        let brightness = ParamTable::default().get("led_brightness").unwrap().clone();
+        assert_eq!(brightness.validate(70u16.into()).unwrap(), ParamValue::Integer(70));
+    }
+
+    #[test]
+    fn param_set_toml_round_trip() {
+        let set = ParamTable::default().defaults();
+        let text = set.to_toml_string();
+        assert!(text.contains("led_brightness = 50"));
+        assert_eq!(ParamSet::from_toml_str(&text).unwrap(), set);
+    }
+}
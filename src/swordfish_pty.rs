@@ -0,0 +1,133 @@
+//a pseudo terminal, the master end used as a transport and the slave end opened by another program as a serial port:
+//  let pty = PtyTransport::open()?;
+//  println!("{}", pty.port_name()); //e.g. /dev/pts/5, for SwordFishComm::new or a vendor tool
+//the slave is kept open by the transport, so the master does not hang up when the other program closes its end,
+//and it is in raw mode, the bytes go through untouched
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+
+pub struct PtyTransport {
+    master: File,
+    _slave: File,
+    port_name: String,
+    read_timeout: Duration,
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn set_raw(fd: libc::c_int) -> io::Result<()> {
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    //SAFETY: tcgetattr fills the termios when it succeeds
+    let mut termios = unsafe {
+        check(libc::tcgetattr(fd, termios.as_mut_ptr()))?;
+        termios.assume_init()
+    };
+    //SAFETY: termios is a valid termios read from the fd
+    unsafe {
+        libc::cfmakeraw(&mut termios);
+        check(libc::tcsetattr(fd, libc::TCSANOW, &termios))?;
+    }
+    Ok(())
+}
+
+impl PtyTransport {
+    pub fn open() -> io::Result<Self> {
+        //SAFETY: the fds are owned by the Files as soon as they are opened, ptsname_r writes at most buf.len() bytes
+        unsafe {
+            let master_fd = check(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC))?;
+            let master = File::from_raw_fd(master_fd);
+            check(libc::grantpt(master_fd))?;
+            check(libc::unlockpt(master_fd))?;
+            let mut buf = [0 as libc::c_char; 128];
+            let error = libc::ptsname_r(master_fd, buf.as_mut_ptr(), buf.len());
+            if error != 0 {
+                return Err(io::Error::from_raw_os_error(error));
+            }
+            let port_name = std::ffi::CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned();
+            let slave = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NOCTTY)
+                .open(&port_name)?;
+            set_raw(slave.as_raw_fd())?;
+            Ok(PtyTransport {
+                master,
+                _slave: slave,
+                port_name,
+                read_timeout: Duration::from_millis(1),
+            })
+        }
+    }
+
+    //the path the other program opens
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = timeout;
+    }
+}
+
+impl Read for PtyTransport {
+    //times out like a serial port when nothing arrives in read_timeout
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        //SAFETY: poll_fd is one valid pollfd
+        let ready = check(unsafe { libc::poll(&mut poll_fd, 1, self.read_timeout.as_millis() as libc::c_int) })?;
+        if ready == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.master.read(buf)
+    }
+}
+
+impl Write for PtyTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_go_through_the_pty_untouched() {
+        let mut pty = PtyTransport::open().unwrap();
+        let mut other = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pty.port_name())
+            .unwrap();
+        //bytes a terminal would translate or eat
+        let bytes = [0xde, 0xad, b'\n', b'\r', 0x03, 0x11, 0x7f];
+        other.write_all(&bytes).unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0u8; 16];
+        pty.set_read_timeout(Duration::from_millis(100));
+        while received.len() < bytes.len() {
+            let n = pty.read(&mut buf).unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(received, bytes);
+        assert_eq!(pty.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
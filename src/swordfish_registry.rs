@@ -0,0 +1,784 @@
+//every message known to the host, with the names and layout needed by tools and UIs
+//MessageRegistry::new() lists the messages that derive SwordFishMessage,
+//runtime messages (see swordfish_dynamic) are added with register
+use crate::swordfish_concentrated_message::MAX_PAYLOAD_SIZE;
+use crate::swordfish_dynamic::{DynamicFieldType, Endianness, MessageDescriptor};
+use crate::swordfish_util::{from_hex, to_hex};
+use crate::{SwordFishMessageCategory, SwordFishMessageRegistration};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldLayout {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    //None after the first variable length field, its position depends on the payload
+    pub offset: Option<usize>,
+    //bytes on the wire, at most for variable length fields
+    pub size: usize,
+    pub variable_length: bool,
+    #[serde(default)]
+    pub big_endian: bool,
+    pub since: u8,
+}
+
+impl FieldLayout {
+    pub fn field_type(&self) -> Result<FieldType> {
+        FieldType::parse(&self.type_name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MessageInfoJson", into = "MessageInfoJson")]
+pub struct MessageInfo {
+    pub name: String,
+    pub opcode: u8,
+    pub category: SwordFishMessageCategory,
+    //the largest payload of the message
+    pub payload_size: usize,
+    pub version: u8,
+    pub fields: Vec<FieldLayout>,
+}
+
+impl MessageInfo {
+    pub fn response_opcode(&self) -> Option<u8> {
+        match self.category {
+            SwordFishMessageCategory::Operation(response_opcode) => response_opcode,
+            _ => None,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    //the value of every field, for tools that print messages they were not compiled with.
+    //fields that an older device does not send yet are left out
+    pub fn decode_fields(&self, payload: &[u8]) -> Result<Vec<(String, FieldValue)>> {
+        let mut input = payload;
+        let mut values = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            if let Some(offset) = field.offset {
+                input = payload.get(offset..).unwrap_or(&[]);
+            }
+            if input.is_empty() && field.since > 1 {
+                break;
+            }
+            let value = field
+                .field_type()
+                .and_then(|field_type| decode_value(&field_type, field.big_endian, &mut input))
+                .map_err(|e| anyhow!("Field {} of {}: {}", field.name, self.name, e))?;
+            values.push((field.name.clone(), value));
+        }
+        Ok(values)
+    }
+
+    //the payload of the message with these field values, the inverse of decode_fields.
+    //fields that are not given are zero (empty for strings and vecs)
+    pub fn encode_fields(&self, values: &[(String, FieldValue)]) -> Result<Vec<u8>> {
+        if let Some((name, _)) = values.iter().find(|(name, _)| self.field(name).is_none()) {
+            return Err(anyhow!("{} has no field {}", self.name, name));
+        }
+        let mut payload = Vec::with_capacity(self.payload_size);
+        for field in &self.fields {
+            if let Some(offset) = field.offset {
+                payload.resize(std::cmp::max(payload.len(), offset), 0);
+            }
+            let field_type = field.field_type()?;
+            let value = values.iter().find(|(name, _)| *name == field.name).map(|(_, value)| value);
+            match value {
+                Some(value) => encode_value(&field_type, field.big_endian, value, &mut payload),
+                None => encode_default(&field_type, &mut payload),
+            }
+            .map_err(|e| anyhow!("Field {} of {}: {}", field.name, self.name, e))?;
+        }
+        if payload.len() > MAX_PAYLOAD_SIZE {
+            return Err(anyhow!("The payload of {} is {} bytes, at most {}", self.name, payload.len(), MAX_PAYLOAD_SIZE));
+        }
+        Ok(payload)
+    }
+
+    //the values of a json object keyed by field name, for encode_fields. null is no field at all
+    pub fn fields_from_json(&self, object: &Value) -> Result<Vec<(String, FieldValue)>> {
+        let object = match object {
+            Value::Null => return Ok(Vec::new()),
+            Value::Object(object) => object,
+            _ => return Err(anyhow!("The fields of {} must be a json object, not {}", self.name, object)),
+        };
+        object
+            .iter()
+            .map(|(name, value)| {
+                let field = self.field(name).ok_or_else(|| anyhow!("{} has no field {}", self.name, name))?;
+                let value = field
+                    .field_type()
+                    .and_then(|field_type| field_type.value_from_json(value))
+                    .map_err(|e| anyhow!("Field {} of {}: {}", name, self.name, e))?;
+                Ok((name.clone(), value))
+            })
+            .collect()
+    }
+
+    //decode_fields as a json object keyed by field name, the form fields_from_json takes
+    pub fn decode_json(&self, payload: &[u8]) -> Result<Value> {
+        let fields: Map<String, Value> = self
+            .decode_fields(payload)?
+            .into_iter()
+            .map(|(name, value)| (name, serde_json::to_value(value).expect("a field value is plain data")))
+            .collect();
+        Ok(Value::Object(fields))
+    }
+
+    //the json schema of the fields object. fields that are left out are zero, so none is required
+    pub fn json_schema(&self) -> Value {
+        let properties: Map<String, Value> = self
+            .fields
+            .iter()
+            .map(|field| {
+                let schema = field.field_type().map_or(json!({}), |field_type| field_type.json_schema());
+                (field.name.clone(), schema)
+            })
+            .collect();
+        json!({
+            "title": self.name,
+            "type": "object",
+            "properties": properties,
+            "additionalProperties": false,
+            "x-opcode": self.opcode,
+            "x-category": self.category.name(),
+            "x-response-opcode": self.response_opcode(),
+        })
+    }
+
+    fn from_registration(registration: &SwordFishMessageRegistration) -> Self {
+        let mut offset = Some(0);
+        let fields = registration
+            .fields
+            .iter()
+            .map(|field| {
+                let layout = FieldLayout {
+                    name: field.name.to_string(),
+                    type_name: (field.field_type)().to_string(),
+                    offset,
+                    size: field.max_size,
+                    variable_length: field.variable_length,
+                    big_endian: false,
+                    since: field.since,
+                };
+                offset = match offset {
+                    Some(offset) if !field.variable_length => Some(offset + field.max_size),
+                    _ => None,
+                };
+                layout
+            })
+            .collect();
+        MessageInfo {
+            name: registration.name.to_string(),
+            opcode: registration.opcode,
+            category: registration.category,
+            payload_size: (registration.payload_length)(),
+            version: registration.version,
+            fields,
+        }
+    }
+}
+
+//the wire encoding of a field. derived messages get it from SwordFishWireField::field_type, layouts read from
+//json and runtime messages parse it from the name of the type ("u16", "[i16; 3]", "BoundedString<64>", ...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Number(DynamicFieldType), //never DynamicFieldType::Bytes, a u8 array is an Array
+    Array(Box<FieldType>, usize),
+    String(usize),              //u8 length prefix and at most n bytes of utf-8
+    Vec(Box<FieldType>, usize), //u8 length prefix and at most n items
+}
+
+//the name parse reads back, like the type is written in a message
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldType::Number(number) => f.write_str(&format!("{:?}", number).to_lowercase()),
+            FieldType::Array(item, len) => write!(f, "[{}; {}]", item, len),
+            FieldType::String(max) => write!(f, "BoundedString<{}>", max),
+            FieldType::Vec(item, max) => write!(f, "BoundedVec<{}, {}>", item, max),
+        }
+    }
+}
+
+//a decoded field, see MessageInfo::decode_fields
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Text(String),
+    #[serde(serialize_with = "serialize_hex")]
+    Bytes(Vec<u8>), //u8 arrays and byte vectors, hex in json
+    List(Vec<FieldValue>),
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Unsigned(value) => write!(f, "{}", value),
+            FieldValue::Signed(value) => write!(f, "{}", value),
+            FieldValue::Float(value) => write!(f, "{}", value),
+            FieldValue::Text(text) => write!(f, "{:?}", text),
+            FieldValue::Bytes(bytes) => write!(f, "{}", to_hex(bytes)),
+            FieldValue::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+fn serialize_hex<S: serde::Serializer>(bytes: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_hex(bytes))
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if input.len() < n {
+        return Err(anyhow!("Payload too short, expected {} more bytes, got {}", n, input.len()));
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+impl FieldType {
+    pub fn parse(type_name: &str) -> Result<FieldType> {
+        let type_name: String = type_name.chars().filter(|c| !c.is_whitespace()).collect();
+        let type_name = type_name
+            .trim_start_matches("crate::")
+            .trim_start_matches("swordfish_com::");
+        let unknown = || anyhow!("Unknown field type {}", type_name);
+        if let Some(array) = type_name.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            let (item, len) = array.rsplit_once(';').ok_or_else(unknown)?;
+            return Ok(FieldType::Array(Box::new(FieldType::parse(item)?), len.parse()?));
+        }
+        if let Some(len) = type_name.strip_prefix("BoundedString<").and_then(|t| t.strip_suffix('>')) {
+            return Ok(FieldType::String(len.parse()?));
+        }
+        if let Some(vector) = type_name.strip_prefix("BoundedVec<").and_then(|t| t.strip_suffix('>')) {
+            let (item, len) = vector.rsplit_once(',').ok_or_else(unknown)?;
+            return Ok(FieldType::Vec(Box::new(FieldType::parse(item)?), len.parse()?));
+        }
+        match DynamicFieldType::parse(type_name)? {
+            DynamicFieldType::Bytes(_) => Err(unknown()),
+            number => Ok(FieldType::Number(number)),
+        }
+    }
+
+    pub fn is_u8(&self) -> bool {
+        *self == FieldType::Number(DynamicFieldType::U8)
+    }
+
+    //a value typed by a person, spelled like FieldValue displays it: numbers in decimal or 0x hex,
+    //u8 arrays and vecs in hex, other arrays and vecs as [1, 2, 3], strings with or without quotes
+    pub fn parse_value(&self, text: &str) -> Result<FieldValue> {
+        let text = text.trim();
+        let number = match self {
+            FieldType::Number(number) => *number,
+            FieldType::String(_) => {
+                let unquoted = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text);
+                return Ok(FieldValue::Text(unquoted.to_string()));
+            }
+            FieldType::Array(item, _) | FieldType::Vec(item, _) if item.is_u8() => {
+                return Ok(FieldValue::Bytes(from_hex(text)?));
+            }
+            FieldType::Array(item, _) | FieldType::Vec(item, _) => {
+                let items = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')).unwrap_or(text);
+                return items
+                    .split(',')
+                    .filter(|item| !item.trim().is_empty())
+                    .map(|item_text| item.parse_value(item_text))
+                    .collect::<Result<Vec<FieldValue>>>()
+                    .map(FieldValue::List);
+            }
+        };
+        let invalid = |e: &dyn std::fmt::Display| anyhow!("Invalid {:?} value {:?}: {}", number, text, e);
+        let unsigned = |text: &str| match text.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => text.parse::<u64>(),
+        };
+        Ok(match number {
+            DynamicFieldType::F32 | DynamicFieldType::F64 => FieldValue::Float(text.parse().map_err(|e| invalid(&e))?),
+            DynamicFieldType::I8 | DynamicFieldType::I16 | DynamicFieldType::I32 | DynamicFieldType::I64 => {
+                match text.strip_prefix('-') {
+                    Some(magnitude) => {
+                        let magnitude = unsigned(magnitude).map_err(|e| invalid(&e))?;
+                        FieldValue::Signed(0i64.checked_sub_unsigned(magnitude).ok_or_else(|| invalid(&"out of range"))?)
+                    }
+                    None => FieldValue::Signed(unsigned(text).map_err(|e| invalid(&e))?.try_into().map_err(|e| invalid(&e))?),
+                }
+            }
+            _ => FieldValue::Unsigned(unsigned(text).map_err(|e| invalid(&e))?),
+        })
+    }
+
+    //a value of a json object: numbers, strings like parse_value takes them, and arrays of values.
+    //u8 arrays and vecs are a hex string or an array of bytes
+    pub fn value_from_json(&self, value: &Value) -> Result<FieldValue> {
+        match (self, value) {
+            (FieldType::String(_), Value::String(text)) => Ok(FieldValue::Text(text.clone())),
+            (_, Value::String(text)) => self.parse_value(text),
+            (FieldType::Number(_), Value::Number(number)) => self.parse_value(&number.to_string()),
+            (FieldType::Array(item, _) | FieldType::Vec(item, _), Value::Array(items)) if item.is_u8() => items
+                .iter()
+                .map(|byte| {
+                    byte.as_u64()
+                        .and_then(|byte| u8::try_from(byte).ok())
+                        .ok_or_else(|| anyhow!("Invalid byte {}", byte))
+                })
+                .collect::<Result<Vec<u8>>>()
+                .map(FieldValue::Bytes),
+            (FieldType::Array(item, _) | FieldType::Vec(item, _), Value::Array(items)) => items
+                .iter()
+                .map(|item_value| item.value_from_json(item_value))
+                .collect::<Result<Vec<FieldValue>>>()
+                .map(FieldValue::List),
+            (_, value) => Err(anyhow!("Invalid {} value {}", self.json_schema()["type"].as_str().unwrap_or("field"), value)),
+        }
+    }
+
+    //the schema of the values FieldValue serializes to, the range of integers included
+    pub fn json_schema(&self) -> Value {
+        match self {
+            FieldType::Number(DynamicFieldType::F32 | DynamicFieldType::F64) => json!({ "type": "number" }),
+            FieldType::Number(number @ (DynamicFieldType::I8 | DynamicFieldType::I16 | DynamicFieldType::I32 | DynamicFieldType::I64)) => {
+                let shift = 64 - 8 * number.size() as u32;
+                json!({ "type": "integer", "minimum": i64::MIN >> shift, "maximum": i64::MAX >> shift })
+            }
+            FieldType::Number(number) => {
+                json!({ "type": "integer", "minimum": 0, "maximum": u64::MAX >> (64 - 8 * number.size() as u32) })
+            }
+            //maxLength counts characters, the limit is in bytes of utf-8
+            FieldType::String(max) => json!({ "type": "string", "maxLength": max }),
+            FieldType::Array(item, len) if item.is_u8() => {
+                json!({ "type": "string", "pattern": format!("^([0-9a-fA-F]{{2}}){{{}}}$", len) })
+            }
+            FieldType::Vec(item, max) if item.is_u8() => {
+                json!({ "type": "string", "pattern": format!("^([0-9a-fA-F]{{2}}){{0,{}}}$", max) })
+            }
+            FieldType::Array(item, len) => {
+                json!({ "type": "array", "items": item.json_schema(), "minItems": len, "maxItems": len })
+            }
+            FieldType::Vec(item, max) => json!({ "type": "array", "items": item.json_schema(), "maxItems": max }),
+        }
+    }
+}
+
+fn encode_default(field_type: &FieldType, out: &mut Vec<u8>) -> Result<()> {
+    match field_type {
+        FieldType::Number(number) => out.resize(out.len() + number.size(), 0),
+        FieldType::Array(item, len) => {
+            for _ in 0..*len {
+                encode_default(item, out)?;
+            }
+        }
+        FieldType::String(_) | FieldType::Vec(_, _) => out.push(0),
+    }
+    Ok(())
+}
+
+fn encode_value(field_type: &FieldType, big_endian: bool, value: &FieldValue, out: &mut Vec<u8>) -> Result<()> {
+    let number = match (field_type, value) {
+        (FieldType::Number(number), _) => *number,
+        (FieldType::String(max), FieldValue::Text(text)) => {
+            if text.len() > *max {
+                return Err(anyhow!("{} bytes of text, at most {}", text.len(), max));
+            }
+            out.push(text.len() as u8);
+            out.extend_from_slice(text.as_bytes());
+            return Ok(());
+        }
+        (FieldType::Array(item, len), _) => {
+            let n = encode_items(item, *len, big_endian, value, out)?;
+            if n != *len {
+                return Err(anyhow!("{} items, expected {}", n, len));
+            }
+            return Ok(());
+        }
+        (FieldType::Vec(item, max), _) => {
+            let len_at = out.len();
+            out.push(0);
+            let n = encode_items(item, *max, big_endian, value, out)?;
+            out[len_at] = n as u8;
+            return Ok(());
+        }
+        (FieldType::String(_), other) => return Err(anyhow!("Expected text, got {}", other)),
+    };
+    let mut bytes = match number {
+        DynamicFieldType::F32 | DynamicFieldType::F64 => {
+            let v = match value {
+                FieldValue::Float(v) => *v,
+                FieldValue::Unsigned(v) => *v as f64,
+                FieldValue::Signed(v) => *v as f64,
+                other => return Err(anyhow!("Expected a number, got {}", other)),
+            };
+            match number {
+                DynamicFieldType::F32 => (v as f32).to_le_bytes().to_vec(),
+                _ => v.to_le_bytes().to_vec(),
+            }
+        }
+        _ => {
+            let v = match value {
+                FieldValue::Unsigned(v) => *v as i128,
+                FieldValue::Signed(v) => *v as i128,
+                other => return Err(anyhow!("Expected an integer, got {}", other)),
+            };
+            let bits = 8 * number.size() as u32;
+            let (min, max) = match number {
+                DynamicFieldType::I8 | DynamicFieldType::I16 | DynamicFieldType::I32 | DynamicFieldType::I64 => {
+                    (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+                }
+                _ => (0, (1i128 << bits) - 1),
+            };
+            if v < min || v > max {
+                return Err(anyhow!("{} does not fit in {:?}", v, number));
+            }
+            v.to_le_bytes()[..number.size()].to_vec()
+        }
+    };
+    if big_endian {
+        bytes.reverse();
+    }
+    out.extend_from_slice(&bytes);
+    Ok(())
+}
+
+//returns the number of items written, at most max
+fn encode_items(item: &FieldType, max: usize, big_endian: bool, value: &FieldValue, out: &mut Vec<u8>) -> Result<usize> {
+    let n = match value {
+        FieldValue::Bytes(bytes) if item.is_u8() => {
+            out.extend_from_slice(&bytes[..std::cmp::min(bytes.len(), max)]);
+            bytes.len()
+        }
+        FieldValue::List(items) => {
+            for value in items.iter().take(max) {
+                encode_value(item, big_endian, value, out)?;
+            }
+            items.len()
+        }
+        other => return Err(anyhow!("Expected a list, got {}", other)),
+    };
+    if n > max {
+        return Err(anyhow!("{} items, at most {}", n, max));
+    }
+    Ok(n)
+}
+
+fn decode_value(field_type: &FieldType, big_endian: bool, input: &mut &[u8]) -> Result<FieldValue> {
+    let number = match field_type {
+        FieldType::Number(number) => *number,
+        FieldType::Array(item, len) => return decode_items(item, *len, big_endian, input),
+        FieldType::Vec(item, _) => {
+            let len = take(input, 1)?[0] as usize;
+            return decode_items(item, len, big_endian, input);
+        }
+        FieldType::String(_) => {
+            let len = take(input, 1)?[0] as usize;
+            let text = std::str::from_utf8(take(input, len)?).map_err(|e| anyhow!("Invalid utf-8 in string field: {}", e))?;
+            return Ok(FieldValue::Text(text.to_string()));
+        }
+    };
+    let mut bytes = take(input, number.size())?.to_vec();
+    if big_endian {
+        bytes.reverse();
+    }
+    macro_rules! from_le {
+        ($t:ty) => {
+            <$t>::from_le_bytes(bytes[..].try_into().expect("take returns exactly the size of the number"))
+        };
+    }
+    Ok(match number {
+        DynamicFieldType::U8 => FieldValue::Unsigned(from_le!(u8) as u64),
+        DynamicFieldType::U16 => FieldValue::Unsigned(from_le!(u16) as u64),
+        DynamicFieldType::U32 => FieldValue::Unsigned(from_le!(u32) as u64),
+        DynamicFieldType::U64 => FieldValue::Unsigned(from_le!(u64)),
+        DynamicFieldType::I8 => FieldValue::Signed(from_le!(i8) as i64),
+        DynamicFieldType::I16 => FieldValue::Signed(from_le!(i16) as i64),
+        DynamicFieldType::I32 => FieldValue::Signed(from_le!(i32) as i64),
+        DynamicFieldType::I64 => FieldValue::Signed(from_le!(i64)),
+        DynamicFieldType::F32 => FieldValue::Float(from_le!(f32) as f64),
+        DynamicFieldType::F64 => FieldValue::Float(from_le!(f64)),
+        DynamicFieldType::Bytes(_) => FieldValue::Bytes(bytes),
+    })
+}
+
+fn decode_items(item: &FieldType, len: usize, big_endian: bool, input: &mut &[u8]) -> Result<FieldValue> {
+    if item.is_u8() {
+        return Ok(FieldValue::Bytes(take(input, len)?.to_vec()));
+    }
+    let items = (0..len)
+        .map(|_| decode_value(item, big_endian, input))
+        .collect::<Result<Vec<FieldValue>>>()?;
+    Ok(FieldValue::List(items))
+}
+
+impl From<&MessageDescriptor> for MessageInfo {
+    fn from(descriptor: &MessageDescriptor) -> Self {
+        let fields = descriptor
+            .fields
+            .iter()
+            .map(|field| FieldLayout {
+                name: field.name.clone(),
+                type_name: match field.field_type {
+                    DynamicFieldType::Bytes(len) => format!("[u8; {}]", len),
+                    other => format!("{:?}", other).to_lowercase(),
+                },
+                offset: Some(field.offset),
+                size: field.field_type.size(),
+                variable_length: false,
+                big_endian: field.endianness == Endianness::Big,
+                since: 1,
+            })
+            .collect();
+        MessageInfo {
+            name: descriptor.name.clone(),
+            opcode: descriptor.opcode,
+            category: descriptor.category,
+            payload_size: descriptor.payload_length(),
+            version: 1,
+            fields,
+        }
+    }
+}
+
+//the json form spells the category like messages.toml and keeps the response opcode in its own key
+#[derive(Serialize, Deserialize)]
+struct MessageInfoJson {
+    name: String,
+    opcode: u8,
+    category: String,
+    response_opcode: Option<u8>,
+    payload_size: usize,
+    version: u8,
+    fields: Vec<FieldLayout>,
+}
+
+impl From<MessageInfo> for MessageInfoJson {
+    fn from(info: MessageInfo) -> Self {
+        MessageInfoJson {
+            response_opcode: info.response_opcode(),
+            name: info.name,
+            opcode: info.opcode,
+            category: info.category.name().to_string(),
+            payload_size: info.payload_size,
+            version: info.version,
+            fields: info.fields,
+        }
+    }
+}
+
+impl TryFrom<MessageInfoJson> for MessageInfo {
+    type Error = anyhow::Error;
+    fn try_from(json: MessageInfoJson) -> Result<Self> {
+        let category = match SwordFishMessageCategory::from_name(&json.category) {
+            Some(SwordFishMessageCategory::Operation(_)) => {
+                SwordFishMessageCategory::Operation(json.response_opcode)
+            }
+            Some(category) => category,
+            None => return Err(anyhow!("Unknown message category {}", json.category)),
+        };
+        Ok(MessageInfo {
+            name: json.name,
+            opcode: json.opcode,
+            category,
+            payload_size: json.payload_size,
+            version: json.version,
+            fields: json.fields,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRegistry {
+    max_payload_size: usize,
+    messages: BTreeMap<u8, MessageInfo>,
+}
+
+impl Default for MessageRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageRegistry {
+    //the messages compiled into the library
+    pub fn new() -> Self {
+        let mut messages = BTreeMap::new();
+        for registration in inventory::iter::<SwordFishMessageRegistration> {
+            messages.insert(
+                registration.opcode,
+                MessageInfo::from_registration(registration),
+            );
+        }
+        MessageRegistry {
+            max_payload_size: MAX_PAYLOAD_SIZE,
+            messages,
+        }
+    }
+
+    pub fn register(&mut self, info: MessageInfo) -> Result<()> {
+        if self.messages.contains_key(&info.opcode) {
+            return Err(anyhow!("Opcode {} is already registered", info.opcode));
+        }
+        self.messages.insert(info.opcode, info);
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: u8) -> Option<&MessageInfo> {
+        self.messages.get(&opcode)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&MessageInfo> {
+        self.messages.values().find(|info| info.name == name)
+    }
+
+    pub fn opcodes(&self) -> Vec<u8> {
+        self.messages.keys().copied().collect()
+    }
+
+    //ordered by opcode
+    pub fn iter(&self) -> impl Iterator<Item = &MessageInfo> {
+        self.messages.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the registry only holds plain data, this should never fail")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    //a json schema with the fields object of every message in $defs, under its name
+    pub fn json_schema(&self) -> Value {
+        let definitions: Map<String, Value> = self.iter().map(|info| (info.name.clone(), info.json_schema())).collect();
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "swordfish messages",
+            "$defs": definitions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swordfish_messages::{Nack, VersionData};
+    use crate::SwordFishMessageTrait;
+
+    #[test]
+    fn compiled_messages_are_listed() {
+        let registry = MessageRegistry::new();
+        let info = registry.get(VersionData::OPCODE).unwrap();
+        assert_eq!(info.name, "VersionData");
+        assert_eq!(info.payload_size, VersionData::get_payload_length());
+        assert_eq!(registry.find("VersionData"), Some(info));
+        let uuid = info.field("uuid").unwrap();
+        assert_eq!(uuid.offset, Some(6));
+        assert_eq!(uuid.size, 8);
+    }
+
+    #[test]
+    fn fields_decode_by_layout() {
+        let registry = MessageRegistry::new();
+        let version = VersionData::new(1, 2, 0x1234, &[0xab; 8]);
+        let fields = registry
+            .get(VersionData::OPCODE)
+            .unwrap()
+            .decode_fields(&version.encode_payload())
+            .unwrap();
+        assert_eq!(fields[2], ("mcu_type".to_string(), FieldValue::Unsigned(0x1234)));
+        assert_eq!(fields[3].1.to_string(), "abababababababab");
+
+        let nack = Nack::new(81, 3, 4, "flash write").unwrap();
+        let fields = registry.get(Nack::OPCODE).unwrap().decode_fields(&nack.encode_payload()).unwrap();
+        assert_eq!(fields[3].1, FieldValue::Text("flash write".to_string()));
+        assert!(registry.get(Nack::OPCODE).unwrap().decode_fields(&[81]).is_err());
+    }
+
+    #[test]
+    fn typed_fields_encode_to_the_payload() {
+        let registry = MessageRegistry::new();
+        let info = registry.get(VersionData::OPCODE).unwrap();
+        let version = VersionData::new(1, 2, 0x1234, &[0xab; 8]);
+        let typed = |name: &str, text: &str| {
+            let value = info.field(name).unwrap().field_type().unwrap().parse_value(text).unwrap();
+            (name.to_string(), value)
+        };
+        let values = vec![typed("version", "1"), typed("subversion", "2"), typed("mcu_type", "0x1234"), typed("uuid", "abababababababab")];
+        assert_eq!(info.encode_fields(&values).unwrap(), version.encode_payload());
+        assert_eq!(info.encode_fields(&info.decode_fields(&version.encode_payload()).unwrap()).unwrap(), version.encode_payload());
+        assert!(info.encode_fields(&[typed("version", "256")]).is_err());
+        assert!(info.encode_fields(&[("colour".to_string(), FieldValue::Unsigned(1))]).is_err());
+
+        let info = registry.get(Nack::OPCODE).unwrap();
+        let nack = Nack::new(81, 3, 4, "flash write").unwrap();
+        let fields = info.decode_fields(&nack.encode_payload()).unwrap();
+        assert_eq!(info.encode_fields(&fields).unwrap(), nack.encode_payload());
+        let text = info.field("detail").unwrap().field_type().unwrap().parse_value("\"flash write\"").unwrap();
+        assert_eq!(text, FieldValue::Text("flash write".to_string()));
+        assert_eq!(FieldType::parse("[i16; 3]").unwrap().parse_value("[1, -2, 0x3]").unwrap(),
+            FieldValue::List(vec![FieldValue::Signed(1), FieldValue::Signed(-2), FieldValue::Signed(3)]));
+    }
+
+    #[test]
+    fn json_fields_encode_to_the_payload() {
+        let registry = MessageRegistry::new();
+        let info = registry.get(VersionData::OPCODE).unwrap();
+        let version = VersionData::new(1, 2, 0x1234, &[0xab; 8]);
+        let fields = json!({ "version": 1, "subversion": "2", "mcu_type": 4660, "uuid": "abababababababab" });
+        assert_eq!(info.encode_fields(&info.fields_from_json(&fields).unwrap()).unwrap(), version.encode_payload());
+        assert_eq!(info.decode_json(&version.encode_payload()).unwrap()["uuid"], json!("abababababababab"));
+        let bytes = info.fields_from_json(&json!({ "uuid": [171, 171, 171, 171, 171, 171, 171, 171] })).unwrap();
+        assert_eq!(bytes[0].1, FieldValue::Bytes(vec![0xab; 8]));
+        assert!(info.fields_from_json(&json!({ "version": 256 })).and_then(|fields| info.encode_fields(&fields)).is_err());
+        assert!(info.fields_from_json(&json!({ "version": 1.5 })).is_err());
+        assert!(info.fields_from_json(&json!({ "colour": 1 })).is_err());
+        assert!(info.fields_from_json(&json!([1])).is_err());
+
+        let nack = registry.get(Nack::OPCODE).unwrap().json_schema();
+        assert_eq!(nack["x-opcode"], json!(Nack::OPCODE));
+        assert_eq!(nack["properties"]["detail"]["type"], json!("string"));
+        let schema = registry.json_schema();
+        assert_eq!(schema["$defs"]["VersionData"]["properties"]["version"]["maximum"], json!(255));
+        assert_eq!(FieldType::parse("i16").unwrap().json_schema()["minimum"], json!(-32768));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut registry = MessageRegistry::new();
+        let mut descriptor = MessageDescriptor::new("Reset", 200, SwordFishMessageCategory::Operation(None));
+        descriptor.set_response_opcode(201).unwrap();
+        descriptor
+            .append_field("delay_ms", DynamicFieldType::U16, Endianness::Big)
+            .unwrap();
+        registry.register(MessageInfo::from(&descriptor)).unwrap();
+        assert!(registry.register(MessageInfo::from(&descriptor)).is_err());
+
+        let json = registry.to_json();
+        assert!(json.contains("\"response_opcode\": 201"));
+        assert_eq!(MessageRegistry::from_json(&json).unwrap(), registry);
+    }
+}
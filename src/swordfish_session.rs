@@ -0,0 +1,466 @@
+//records the raw byte streams of a SwordFishComm, with their timing, to reproduce a session without the board:
+//  comm.start_recording(Path::new("bug.session"))?;
+//  ...
+//  comm.stop_recording()?;
+//  let replay = ReplayTransport::new(Session::load(Path::new("bug.session"))?).speed(10.0)?;
+//  let comm = SwordFishComm::from_transport(Box::new(replay));
+//unlike a capture (see swordfish_capture) the chunks are kept as they were read from the port, not cut into frames,
+//so the parser sees the same split frames and garbage again.
+//the file is text, one chunk per line: "<us since the start> <tx|rx> <hex bytes>", lines starting with # are comments
+use crate::swordfish_capture::Direction;
+use crate::swordfish_clock_sync::host_now_us;
+use crate::swordfish_util::{from_hex, to_hex};
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+const SESSION_HEADER: &str = "# swordfish session 1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionChunk {
+    pub elapsed_us: u64, //since the recording started
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    pub started_us: u64, //host time of the start, see swordfish_clock_sync::host_now_us
+    pub chunks: Vec<SessionChunk>,
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk: &SessionChunk) -> io::Result<()> {
+    let direction = match chunk.direction {
+        Direction::Tx => "tx",
+        Direction::Rx => "rx",
+    };
+    writeln!(writer, "{} {} {}", chunk.elapsed_us, direction, to_hex(&chunk.bytes))
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Session> {
+        let file = std::fs::File::open(path).map_err(|e| anyhow!("Could not open {}: {}", path.display(), e))?;
+        Session::read_from(BufReader::new(file))
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Session> {
+        let mut session = Session::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(started) = comment.trim().strip_prefix("started ") {
+                    session.started_us = started.parse()?;
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(3, ' ');
+            let chunk = (|| -> Result<SessionChunk> {
+                let elapsed_us = parts.next().unwrap_or("").parse()?;
+                let direction = match parts.next() {
+                    Some("tx") => Direction::Tx,
+                    Some("rx") => Direction::Rx,
+                    other => return Err(anyhow!("Unknown direction {:?}", other)),
+                };
+                let bytes = from_hex(parts.next().unwrap_or(""))?;
+                Ok(SessionChunk { elapsed_us, direction, bytes })
+            })()
+            .map_err(|e| anyhow!("Line {}: {}", index + 1, e))?;
+            session.chunks.push(chunk);
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", SESSION_HEADER)?;
+        writeln!(writer, "# started {}", self.started_us)?;
+        for chunk in &self.chunks {
+            write_chunk(writer, chunk)?;
+        }
+        Ok(())
+    }
+
+    //the bytes of one direction, concatenated
+    pub fn stream(&self, direction: Direction) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.direction == direction)
+            .flat_map(|chunk| chunk.bytes.iter().copied())
+            .collect()
+    }
+}
+
+struct RecordingWriter {
+    writer: Box<dyn Write + Send>,
+    started_us: u64,
+    chunks: u64,
+}
+
+//the recording of a SwordFishComm, fed by its read/write thread
+#[derive(Default)]
+pub struct SessionRecorder {
+    active: AtomicBool,
+    writer: Mutex<Option<RecordingWriter>>,
+}
+
+impl SessionRecorder {
+    //replaces the running recording, if any
+    pub fn start(&self, mut writer: Box<dyn Write + Send>) -> io::Result<()> {
+        let started_us = host_now_us();
+        writeln!(writer, "{}", SESSION_HEADER)?;
+        writeln!(writer, "# started {}", started_us)?;
+        let previous = self
+            .writer
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .replace(RecordingWriter { writer, started_us, chunks: 0 });
+        self.active.store(true, Ordering::Relaxed);
+        if let Some(mut previous) = previous {
+            previous.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    //returns the number of chunks written
+    pub fn stop(&self) -> io::Result<u64> {
+        self.active.store(false, Ordering::Relaxed);
+        let recording = self
+            .writer
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+            .take();
+        match recording {
+            Some(mut recording) => {
+                recording.writer.flush()?;
+                Ok(recording.chunks)
+            }
+            None => Ok(0),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    //a recording that fails to write is stopped
+    pub fn record(&self, direction: Direction, host_us: u64, bytes: &[u8]) {
+        if !self.is_active() || bytes.is_empty() {
+            return;
+        }
+        let mut writer = self
+            .writer
+            .lock()
+            .expect("Another thread holding the mutex panicked");
+        let recording = match writer.as_mut() {
+            Some(recording) => recording,
+            None => return,
+        };
+        let chunk = SessionChunk {
+            elapsed_us: host_us.saturating_sub(recording.started_us),
+            direction,
+            bytes: bytes.to_vec(),
+        };
+        match write_chunk(&mut recording.writer, &chunk) {
+            Ok(()) => recording.chunks += 1,
+            Err(e) => {
+                log::error!("Stopping the recording, could not write it: {}", e);
+                writer.take();
+                self.active.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ReplayState {
+    finished: Mutex<bool>,
+    condvar: Condvar,
+    mismatches: AtomicUsize,
+    paused: AtomicBool,
+}
+
+//follows a replay after its transport was moved into a SwordFishComm
+#[derive(Clone)]
+pub struct ReplayHandle(Arc<ReplayState>);
+
+impl ReplayHandle {
+    //every received chunk of the session was delivered
+    pub fn is_finished(&self) -> bool {
+        *self.0.finished.lock().expect("Another thread holding the mutex panicked")
+    }
+
+    //returns false on timeout
+    pub fn wait_finished(&self, timeout: Duration) -> bool {
+        let finished = self.0.finished.lock().expect("Another thread holding the mutex panicked");
+        let (finished, _) = self
+            .0
+            .condvar
+            .wait_timeout_while(finished, timeout, |finished| !*finished)
+            .expect("Another thread holding the mutex panicked");
+        *finished
+    }
+
+    //the sent bytes that differ from the recorded ones
+    pub fn mismatches(&self) -> usize {
+        self.0.mismatches.load(Ordering::Relaxed)
+    }
+
+    //starts a replay created with paused(true), the clock starts with the next read
+    pub fn resume(&self) {
+        self.0.paused.store(false, Ordering::Relaxed);
+    }
+}
+
+//a transport that plays the received chunks of a session back, to be given to SwordFishComm::from_transport.
+//the chunks are delivered with the delays they were recorded with, divided by the speed.
+//with sync_on_tx a received chunk that was recorded after a sent one also waits for the host to send those bytes,
+//so answers do not arrive before their request whatever the speed.
+//the sent bytes are compared to the recorded ones, the differences are logged and counted (ReplayHandle::mismatches)
+pub struct ReplayTransport {
+    chunks: VecDeque<SessionChunk>,
+    speed: f64,
+    sync_on_tx: bool,
+    read_timeout: Duration,
+    //recording time and wall time of the last chunk played
+    last_elapsed_us: u64,
+    last_instant: Option<Instant>,
+    expected_tx: VecDeque<u8>, //recorded sent bytes the host has not sent yet
+    pending_tx: usize,         //recorded sent bytes of the chunks already played
+    written_tx: usize,
+    pending: Vec<u8>,
+    state: Arc<ReplayState>,
+}
+
+impl ReplayTransport {
+    pub fn new(session: Session) -> Self {
+        ReplayTransport {
+            expected_tx: session.stream(Direction::Tx).into(),
+            chunks: session.chunks.into(),
+            speed: 1.0,
+            sync_on_tx: false,
+            read_timeout: Duration::from_millis(1),
+            last_elapsed_us: 0,
+            last_instant: None,
+            pending_tx: 0,
+            written_tx: 0,
+            pending: Vec::new(),
+            state: Arc::new(ReplayState::default()),
+        }
+    }
+
+    //2.0 plays twice as fast, f64::INFINITY without any delay
+    pub fn speed(mut self, speed: f64) -> Result<Self> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(anyhow!("The replay speed must be positive, not {}", speed));
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    pub fn sync_on_tx(mut self, sync_on_tx: bool) -> Self {
+        self.sync_on_tx = sync_on_tx;
+        self
+    }
+
+    //nothing is played until ReplayHandle::resume, so the hooks of the comm can be added first
+    pub fn paused(self, paused: bool) -> Self {
+        self.state.paused.store(paused, Ordering::Relaxed);
+        self
+    }
+
+    pub fn handle(&self) -> ReplayHandle {
+        ReplayHandle(self.state.clone())
+    }
+
+    //when the next chunk is due, the clock starts with the first read
+    fn due(&mut self, chunk_elapsed_us: u64) -> Instant {
+        let last_instant = *self.last_instant.get_or_insert_with(Instant::now);
+        let delay_us = chunk_elapsed_us.saturating_sub(self.last_elapsed_us) as f64 / self.speed;
+        last_instant + Duration::from_secs_f64(delay_us / 1e6)
+    }
+
+    //plays the chunks that are due, returns the next received bytes if there are any
+    fn next_rx(&mut self) -> Option<Vec<u8>> {
+        if self.state.paused.load(Ordering::Relaxed) {
+            return None;
+        }
+        loop {
+            let (elapsed_us, direction, size) = match self.chunks.front() {
+                Some(chunk) => (chunk.elapsed_us, chunk.direction, chunk.bytes.len()),
+                None => {
+                    let mut finished = self.state.finished.lock().expect("Another thread holding the mutex panicked");
+                    if !*finished {
+                        *finished = true;
+                        self.state.condvar.notify_all();
+                    }
+                    return None;
+                }
+            };
+            let due = self.due(elapsed_us);
+            let now = Instant::now();
+            if direction == Direction::Tx {
+                if self.sync_on_tx {
+                    if self.written_tx < self.pending_tx + size {
+                        return None;
+                    }
+                    //the host is the clock here
+                    self.last_instant = Some(now);
+                } else if now < due {
+                    return None;
+                } else {
+                    self.last_instant = Some(due);
+                }
+                self.pending_tx += size;
+                self.last_elapsed_us = elapsed_us;
+                self.chunks.pop_front();
+                continue;
+            }
+            if now < due {
+                return None;
+            }
+            self.last_instant = Some(due);
+            self.last_elapsed_us = elapsed_us;
+            return self.chunks.pop_front().map(|chunk| chunk.bytes);
+        }
+    }
+
+    //how long a read can wait for the next chunk
+    fn wait(&mut self) -> Duration {
+        if self.state.paused.load(Ordering::Relaxed) {
+            return self.read_timeout;
+        }
+        let due = match self.chunks.front() {
+            Some(chunk) if !(chunk.direction == Direction::Tx && self.sync_on_tx) => self.due(chunk.elapsed_us),
+            _ => return self.read_timeout,
+        };
+        std::cmp::min(due.saturating_duration_since(Instant::now()), self.read_timeout)
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.next_rx() {
+                Some(bytes) => self.pending = bytes,
+                None => {
+                    std::thread::sleep(self.wait());
+                    match self.next_rx() {
+                        Some(bytes) => self.pending = bytes,
+                        None => return Err(io::ErrorKind::TimedOut.into()),
+                    }
+                }
+            }
+        }
+        let n = std::cmp::min(buf.len(), self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let expected: Vec<u8> = (0..buf.len()).map_while(|_| self.expected_tx.pop_front()).collect();
+        if expected[..] != buf[..expected.len()] || expected.len() < buf.len() {
+            self.state.mismatches.fetch_add(1, Ordering::Relaxed);
+            log::warn!(
+                "The replay sent {} where the session has {}",
+                to_hex(buf),
+                to_hex(&expected)
+            );
+        }
+        self.written_tx += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let chunk = |elapsed_us, direction, bytes: &[u8]| SessionChunk { elapsed_us, direction, bytes: bytes.to_vec() };
+        Session {
+            started_us: 1_700_000_000_000_000,
+            chunks: vec![
+                chunk(0, Direction::Tx, &[1, 2]),
+                chunk(20_000, Direction::Rx, &[0xde, 0xad]),
+                chunk(40_000, Direction::Rx, &[0xbe, 0xef]),
+            ],
+        }
+    }
+
+    fn read_all(replay: &mut ReplayTransport, n: usize) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 16];
+        while received.len() < n {
+            match replay.read(&mut buf) {
+                Ok(read) => received.extend_from_slice(&buf[..read]),
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn sessions_round_trip_through_text() {
+        let mut text = Vec::new();
+        session().write_to(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("20000 rx dead\n"));
+        assert_eq!(Session::read_from(text.as_bytes()).unwrap(), session());
+        assert!(Session::read_from("5 up 00".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn replay_keeps_the_timing_scaled_by_the_speed() {
+        let mut replay = ReplayTransport::new(session()).speed(2.0).unwrap();
+        let handle = replay.handle();
+        let start = Instant::now();
+        assert_eq!(read_all(&mut replay, 4), vec![0xde, 0xad, 0xbe, 0xef]);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(20) && elapsed < Duration::from_millis(200), "{:?}", elapsed);
+        assert!(!handle.is_finished());
+        assert!(replay.read(&mut [0u8; 4]).is_err());
+        assert!(handle.wait_finished(Duration::ZERO));
+        assert_eq!(handle.mismatches(), 0);
+    }
+
+    #[test]
+    fn replay_waits_for_the_host_to_send() {
+        let mut replay = ReplayTransport::new(session()).speed(f64::INFINITY).unwrap().sync_on_tx(true);
+        let handle = replay.handle();
+        assert!(replay.read(&mut [0u8; 4]).is_err());
+        replay.write_all(&[1, 3]).unwrap();
+        assert_eq!(read_all(&mut replay, 4), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(handle.mismatches(), 1);
+    }
+
+    #[test]
+    fn a_paused_replay_waits_for_resume() {
+        let mut replay = ReplayTransport::new(session()).speed(f64::INFINITY).unwrap().paused(true);
+        let handle = replay.handle();
+        assert!(replay.read(&mut [0u8; 4]).is_err());
+        handle.resume();
+        assert_eq!(read_all(&mut replay, 4), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(ReplayTransport::new(session()).speed(0.0).is_err());
+        assert!(ReplayTransport::new(session()).speed(f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,651 @@
+//a swordfish board in software, for tests and for trying tools without hardware
+//  let (simulator, comm) = SwordFishSimulator::connect();
+//the simulated device answers Ping, VersionData and TimeSync, pushes the streams started with StreamStart,
+//runs Calibrate and SelfTest as long-running operations, runs a bootloader for the dfu messages, keeps the parameters of params.toml,
+//rejects what it does not support with a Nack, and any opcode can be given a custom handler with SimulatedDevice::set_handler
+use crate::swordfish_comm::SwordFishComm;
+use crate::swordfish_concentrated_message::SwordFishConcentratedMessageBufferBuilder;
+use crate::swordfish_dfu::{crc32, DfuState, DfuStatusCode};
+use crate::swordfish_messages::{
+    Calibrate, DfuCommit, DfuEnterBootloader, DfuErase, DfuGetState, DfuStatus, DfuVerify,
+    DfuWriteBlock, Echo, ImuSample, OperationAbort, OperationProgress, OperationResult, ParamRead,
+    Nack, ParamWrite, Ping, SelfTest, StreamStart, StreamStop, TimeSync, VersionData,
+};
+use crate::swordfish_error::codes;
+use crate::swordfish_registry::MessageRegistry;
+use crate::swordfish_params::{ParamStatus, ParamTable};
+use crate::swordfish_transport::{memory_link, SwordFishTransport};
+use crate::{
+    BoundedString, BoundedVec, SwordFishConcentratedMessage, SwordFishMessageCategory, SwordFishMessageTrait,
+    CONCENTRATED_MESSAGE_TOTAL_SIZE,
+};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+//returns the messages the device sends back, nothing to stay silent
+pub type SimulatorHandler =
+    Box<dyn FnMut(&SwordFishConcentratedMessage) -> Vec<SwordFishConcentratedMessage> + Send>;
+
+//--------------SimulatedBootloader------------------//
+pub struct SimulatedBootloader {
+    pub slot_address: u32,
+    pub state: DfuState,
+    pub flash: Vec<u8>, //the application slot
+    pub written: usize, //end of the written bytes since the erase
+    pub running_crc: u32,
+    //false makes the committed image fail to boot, the bootloader rolls back to the previous one
+    pub image_boots: bool,
+    //stop answering after this many DfuWriteBlock messages, like a board that lost its connection
+    pub stall_after_blocks: Option<usize>,
+    //writes the block with this index (counted like blocks_received) but loses its answer
+    pub lose_answer_to_block: Option<usize>,
+    pub blocks_received: usize,
+}
+
+impl SimulatedBootloader {
+    pub fn new(slot_address: u32, slot_size: usize) -> Self {
+        SimulatedBootloader {
+            slot_address,
+            state: DfuState::Application,
+            flash: vec![0xff; slot_size],
+            written: 0,
+            running_crc: 0,
+            image_boots: true,
+            stall_after_blocks: None,
+            lose_answer_to_block: None,
+            blocks_received: 0,
+        }
+    }
+
+    //offset of [address, address + length) in the slot
+    fn slot_offset(&self, address: u32, length: usize) -> Option<usize> {
+        let offset = address.checked_sub(self.slot_address)? as usize;
+        if offset + length <= self.flash.len() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn status(&self, request: u8, status: DfuStatusCode, crc32: u32) -> DfuStatus {
+        DfuStatus::new(request, status as u8, self.state as u8, self.written as u32, crc32)
+    }
+
+    pub fn handle(&mut self, msg: &SwordFishConcentratedMessage) -> Option<DfuStatus> {
+        use DfuState::*;
+        let bad_state = |bootloader: &Self, request: u8| Some(bootloader.status(request, DfuStatusCode::BadState, 0));
+        match msg.opcode {
+            DfuEnterBootloader::OPCODE => {
+                let request = DfuEnterBootloader::from_concentrated(msg).ok()?;
+                if request.get_image_size() as usize > self.flash.len() {
+                    return Some(self.status(msg.opcode, DfuStatusCode::TooLarge, 0));
+                }
+                self.state = Bootloader;
+                Some(self.status(msg.opcode, DfuStatusCode::Ok, 0))
+            }
+            DfuErase::OPCODE => {
+                let request = DfuErase::from_concentrated(msg).ok()?;
+                if !matches!(self.state, Bootloader | Erased | Writing | Verified) {
+                    return bad_state(self, msg.opcode);
+                }
+                let length = request.get_length() as usize;
+                let offset = match self.slot_offset(request.get_address(), length) {
+                    Some(offset) => offset,
+                    None => return Some(self.status(msg.opcode, DfuStatusCode::BadAddress, 0)),
+                };
+                self.flash[offset..offset + length].fill(0xff);
+                self.written = 0;
+                self.state = Erased;
+                Some(self.status(msg.opcode, DfuStatusCode::Ok, 0))
+            }
+            DfuWriteBlock::OPCODE => {
+                let request = DfuWriteBlock::from_concentrated(msg).ok()?;
+                if self.stall_after_blocks.is_some_and(|n| self.blocks_received >= n) {
+                    return None;
+                }
+                let index = self.blocks_received;
+                self.blocks_received += 1;
+                if !matches!(self.state, Erased | Writing) {
+                    return bad_state(self, msg.opcode);
+                }
+                let data = request.get_data();
+                let offset = match self.slot_offset(request.get_address(), data.len()) {
+                    Some(offset) => offset,
+                    None => return Some(self.status(msg.opcode, DfuStatusCode::BadAddress, 0)),
+                };
+                //like real flash, a region can only be written once after the erase
+                let region = &mut self.flash[offset..offset + data.len()];
+                if region.iter().any(|b| *b != 0xff) {
+                    return Some(self.status(msg.opcode, DfuStatusCode::FlashError, 0));
+                }
+                region.copy_from_slice(&data);
+                self.written = std::cmp::max(self.written, offset + data.len());
+                self.state = Writing;
+                if self.lose_answer_to_block == Some(index) {
+                    return None;
+                }
+                Some(self.status(msg.opcode, DfuStatusCode::Ok, 0))
+            }
+            DfuVerify::OPCODE => {
+                let request = DfuVerify::from_concentrated(msg).ok()?;
+                if !matches!(self.state, Writing | Verified) {
+                    return bad_state(self, msg.opcode);
+                }
+                let length = request.get_length() as usize;
+                let offset = match self.slot_offset(request.get_address(), length) {
+                    Some(offset) => offset,
+                    None => return Some(self.status(msg.opcode, DfuStatusCode::BadAddress, 0)),
+                };
+                self.state = Verified;
+                Some(self.status(msg.opcode, DfuStatusCode::Ok, crc32(&self.flash[offset..offset + length])))
+            }
+            DfuCommit::OPCODE => {
+                let request = DfuCommit::from_concentrated(msg).ok()?;
+                if self.state != Verified {
+                    return bad_state(self, msg.opcode);
+                }
+                let image_size = std::cmp::min(request.get_image_size() as usize, self.flash.len());
+                let crc = crc32(&self.flash[..image_size]);
+                if crc != request.get_crc32() {
+                    return Some(self.status(msg.opcode, DfuStatusCode::CrcMismatch, crc));
+                }
+                let status = self.status(msg.opcode, DfuStatusCode::Ok, crc);
+                //the reboot, the new image confirms itself or the bootloader restores the previous one
+                if self.image_boots {
+                    self.running_crc = crc;
+                    self.state = Application;
+                } else {
+                    self.state = RolledBack;
+                }
+                self.written = 0;
+                Some(status)
+            }
+            DfuGetState::OPCODE => {
+                let crc = match self.state {
+                    Application | RolledBack => self.running_crc,
+                    _ => crc32(&self.flash[..self.written]),
+                };
+                Some(self.status(msg.opcode, DfuStatusCode::Ok, crc))
+            }
+            _ => None,
+        }
+    }
+}
+
+//--------------SimulatedParams------------------//
+//the parameters of params.toml, checked like the firmware does
+pub struct SimulatedParams {
+    pub table: ParamTable,
+    pub values: HashMap<u16, u64>,
+}
+
+impl Default for SimulatedParams {
+    fn default() -> Self {
+        let table = ParamTable::default();
+        let values = table
+            .iter()
+            .map(|param| (param.id, param.to_raw(param.default)))
+            .collect();
+        SimulatedParams { table, values }
+    }
+}
+
+impl SimulatedParams {
+    pub fn handle(&mut self, msg: &SwordFishConcentratedMessage) -> Option<SwordFishConcentratedMessage> {
+        match msg.opcode {
+            ParamRead::OPCODE => {
+                let request = ParamRead::from_concentrated(msg).ok()?;
+                let reply = match self.values.get(&request.get_id()) {
+                    Some(value) => ParamRead::new(request.get_id(), ParamStatus::Ok as u8, *value),
+                    None => ParamRead::new(request.get_id(), ParamStatus::UnknownId as u8, 0),
+                };
+                Some(reply.to_concentrated(msg.counter))
+            }
+            ParamWrite::OPCODE => {
+                let request = ParamWrite::from_concentrated(msg).ok()?;
+                let id = request.get_id();
+                let status = match self.table.get_by_id(id) {
+                    None => ParamStatus::UnknownId,
+                    Some(param) if param.read_only => ParamStatus::ReadOnly,
+                    Some(param) => match param.validate(param.from_raw(request.get_value())) {
+                        Ok(_) => {
+                            self.values.insert(id, request.get_value());
+                            ParamStatus::Ok
+                        }
+                        Err(_) => ParamStatus::OutOfRange,
+                    },
+                };
+                let value = self.values.get(&id).copied().unwrap_or(0);
+                Some(ParamWrite::new(id, status as u8, value).to_concentrated(msg.counter))
+            }
+            _ => None,
+        }
+    }
+}
+
+//--------------SimulatedClock------------------//
+//us since the device booted, running drift_ppm fast
+pub struct SimulatedClock {
+    pub boot: Instant,
+    pub offset_us: u64,
+    pub drift_ppm: f64,
+}
+
+impl Default for SimulatedClock {
+    fn default() -> Self {
+        SimulatedClock {
+            boot: Instant::now(),
+            offset_us: 0,
+            drift_ppm: 0.0,
+        }
+    }
+}
+
+impl SimulatedClock {
+    pub fn now_us(&self) -> u64 {
+        let elapsed = self.boot.elapsed().as_micros() as f64;
+        self.offset_us + (elapsed * (1.0 + self.drift_ppm * 1e-6)) as u64
+    }
+}
+
+//--------------SimulatedStreams------------------//
+//makes the payload of the n-th message of a stream
+pub type StreamGenerator = Box<dyn FnMut(u32) -> Vec<u8> + Send>;
+
+struct SimulatedStream {
+    period: Duration,
+    next: Instant,
+    sequence: u32,
+}
+
+//the streams started with StreamStart, ImuSample has made up data, other streams zeros
+pub struct SimulatedStreams {
+    active: HashMap<u8, SimulatedStream>,
+    generators: HashMap<u8, StreamGenerator>,
+    registry: MessageRegistry,
+}
+
+impl Default for SimulatedStreams {
+    fn default() -> Self {
+        let mut generators: HashMap<u8, StreamGenerator> = HashMap::new();
+        generators.insert(
+            ImuSample::OPCODE,
+            Box::new(|sequence| {
+                let phase = (sequence % 360) as f32 * std::f32::consts::PI / 180.0;
+                let a = (phase.sin() * 1000.0) as i16;
+                let g = (phase.cos() * 1000.0) as i16;
+                ImuSample::new(sequence, &[a, -a, 1000], &[g, 0, -g]).encode_payload()
+            }),
+        );
+        SimulatedStreams {
+            active: HashMap::new(),
+            generators,
+            registry: MessageRegistry::new(),
+        }
+    }
+}
+
+impl SimulatedStreams {
+    pub fn set_generator(&mut self, opcode: u8, generator: StreamGenerator) {
+        self.generators.insert(opcode, generator);
+    }
+
+    pub fn is_active(&self, opcode: u8) -> bool {
+        self.active.contains_key(&opcode)
+    }
+
+    fn start(&mut self, opcode: u8, rate_hz: u16) -> bool {
+        let is_stream = self
+            .registry
+            .get(opcode)
+            .is_some_and(|info| info.category == SwordFishMessageCategory::Stream);
+        if !is_stream || rate_hz == 0 {
+            return false;
+        }
+        let period = Duration::from_secs_f64(1.0 / rate_hz as f64);
+        self.active.insert(
+            opcode,
+            SimulatedStream {
+                period,
+                next: Instant::now(),
+                sequence: 0,
+            },
+        );
+        true
+    }
+
+    //the stream messages that are due
+    pub fn poll(&mut self) -> Vec<SwordFishConcentratedMessage> {
+        let now = Instant::now();
+        let mut msgs = Vec::new();
+        for (opcode, stream) in self.active.iter_mut() {
+            //a stalled simulator does not catch up with more than a burst
+            let mut burst = 0;
+            while stream.next <= now && burst < 64 {
+                let payload = match self.generators.get_mut(opcode) {
+                    Some(generator) => generator(stream.sequence),
+                    None => vec![0; self.registry.get(*opcode).map_or(0, |info| info.payload_size)],
+                };
+                msgs.push(SwordFishConcentratedMessage::new(stream.sequence as u16, *opcode, &payload));
+                stream.sequence = stream.sequence.wrapping_add(1);
+                stream.next += stream.period;
+                burst += 1;
+            }
+            if stream.next < now {
+                stream.next = now;
+            }
+        }
+        msgs
+    }
+}
+
+//--------------SimulatedOperations------------------//
+const OPERATION_FAILED: u8 = 1;
+const OPERATION_ABORTED: u8 = 2;
+
+struct SimulatedOperation {
+    counter: u16, //of the request, the progress and the result carry it
+    done_steps: u8,
+    next: Instant,
+    status: u8,
+    data: Vec<u8>,
+}
+
+//Calibrate and SelfTest report progress every step and end after steps steps
+pub struct SimulatedOperations {
+    pub step: Duration,
+    pub steps: u8,
+    //stop reporting after this many steps, like a device that hangs
+    pub stall_after: Option<u8>,
+    //the self tests that fail, SelfTest ends with status 1 and the failed bits in its data
+    pub self_test_failures: u32,
+    running: HashMap<u8, SimulatedOperation>,
+}
+
+impl Default for SimulatedOperations {
+    fn default() -> Self {
+        SimulatedOperations {
+            step: Duration::from_millis(20),
+            steps: 5,
+            stall_after: None,
+            self_test_failures: 0,
+            running: HashMap::new(),
+        }
+    }
+}
+
+impl SimulatedOperations {
+    pub fn is_running(&self, opcode: u8) -> bool {
+        self.running.contains_key(&opcode)
+    }
+
+    fn start(&mut self, msg: &SwordFishConcentratedMessage) {
+        let (status, data) = match msg.opcode {
+            Calibrate::OPCODE => match Calibrate::from_concentrated(msg) {
+                //the offsets found for the sensor
+                Ok(request) => (0, vec![request.get_sensor(), 0x12, 0x34]),
+                Err(_) => return,
+            },
+            SelfTest::OPCODE => match SelfTest::from_concentrated(msg) {
+                Ok(request) => {
+                    let failed = request.get_tests() & self.self_test_failures;
+                    let status = if failed == 0 { 0 } else { OPERATION_FAILED };
+                    (status, failed.to_le_bytes().to_vec())
+                }
+                Err(_) => return,
+            },
+            _ => return,
+        };
+        self.running.insert(
+            msg.opcode,
+            SimulatedOperation {
+                counter: msg.counter,
+                done_steps: 0,
+                next: Instant::now() + self.step,
+                status,
+                data,
+            },
+        );
+    }
+
+    fn abort(&mut self, opcode: u8) -> Option<SwordFishConcentratedMessage> {
+        let operation = self.running.remove(&opcode)?;
+        Some(OperationResult::new(opcode, OPERATION_ABORTED, &[]).expect("no data").to_concentrated(operation.counter))
+    }
+
+    //the progress and result messages that are due
+    pub fn poll(&mut self) -> Vec<SwordFishConcentratedMessage> {
+        let now = Instant::now();
+        let mut msgs = Vec::new();
+        let mut ended = Vec::new();
+        for (opcode, operation) in self.running.iter_mut() {
+            if operation.next > now || self.stall_after.is_some_and(|stall| operation.done_steps >= stall) {
+                continue;
+            }
+            operation.done_steps += 1;
+            operation.next = now + self.step;
+            if operation.done_steps < self.steps {
+                let percent = (operation.done_steps as u32 * 100 / self.steps as u32) as u8;
+                let progress = OperationProgress::new(*opcode, percent, operation.done_steps as u16, "running")
+                    .expect("the text fits");
+                msgs.push(progress.to_concentrated(operation.counter));
+            } else {
+                let result = OperationResult {
+                    operation: *opcode,
+                    status: operation.status,
+                    data: BoundedVec::truncated(&operation.data),
+                };
+                msgs.push(result.to_concentrated(operation.counter));
+                ended.push(*opcode);
+            }
+        }
+        for opcode in ended {
+            self.running.remove(&opcode);
+        }
+        msgs
+    }
+}
+
+//the answer of the device to a request it rejects, code is one of swordfish_error::codes
+pub fn nack(request: &SwordFishConcentratedMessage, code: u16, detail: &str) -> SwordFishConcentratedMessage {
+    Nack {
+        opcode: request.opcode,
+        counter: request.counter,
+        code,
+        detail: BoundedString::truncated(detail),
+    }
+    .to_concentrated(request.counter)
+}
+
+//--------------SimulatedDevice------------------//
+pub struct SimulatedDevice {
+    pub version_data: VersionData,
+    pub bootloader: SimulatedBootloader,
+    pub params: SimulatedParams,
+    pub clock: SimulatedClock,
+    pub streams: SimulatedStreams,
+    pub operations: SimulatedOperations,
+    handlers: HashMap<u8, SimulatorHandler>,
+}
+
+impl Default for SimulatedDevice {
+    fn default() -> Self {
+        SimulatedDevice {
+            version_data: VersionData::new(1, 0, 0, &[0x5f; 8]),
+            bootloader: SimulatedBootloader::new(0x0800_4000, 256 * 1024),
+            params: SimulatedParams::default(),
+            clock: SimulatedClock::default(),
+            streams: SimulatedStreams::default(),
+            operations: SimulatedOperations::default(),
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl SimulatedDevice {
+    //replaces the built in behaviour of the opcode
+    pub fn set_handler(&mut self, opcode: u8, handler: SimulatorHandler) {
+        self.handlers.insert(opcode, handler);
+    }
+
+    //what the device sends on its own: stream samples, operation progress
+    pub fn poll(&mut self) -> Vec<SwordFishConcentratedMessage> {
+        let mut msgs = self.streams.poll();
+        msgs.extend(self.operations.poll());
+        msgs
+    }
+
+    pub fn handle(&mut self, msg: &SwordFishConcentratedMessage) -> Vec<SwordFishConcentratedMessage> {
+        if let Some(handler) = self.handlers.get_mut(&msg.opcode) {
+            return handler(msg);
+        }
+        match msg.opcode {
+            Ping::OPCODE | Echo::OPCODE => vec![*msg],
+            VersionData::OPCODE => vec![self.version_data.to_concentrated(msg.counter)],
+            TimeSync::OPCODE => match TimeSync::from_concentrated(msg) {
+                Ok(request) => vec![TimeSync::new(request.get_host_time(), self.clock.now_us()).to_concentrated(msg.counter)],
+                Err(_) => vec![],
+            },
+            DfuEnterBootloader::OPCODE..=DfuGetState::OPCODE => match self.bootloader.handle(msg) {
+                Some(status) => vec![status.to_concentrated(msg.counter)],
+                None => vec![],
+            },
+            StreamStart::OPCODE => match StreamStart::from_concentrated(msg) {
+                Ok(request) if self.streams.start(request.get_stream(), request.get_rate_hz()) => vec![*msg],
+                Ok(_) => vec![nack(msg, codes::BAD_PARAMETER, "not a stream")],
+                Err(_) => vec![nack(msg, codes::BAD_LENGTH, "")],
+            },
+            StreamStop::OPCODE => match StreamStop::from_concentrated(msg) {
+                Ok(request) => {
+                    self.streams.active.remove(&request.get_stream());
+                    vec![*msg]
+                }
+                Err(_) => vec![],
+            },
+            Calibrate::OPCODE | SelfTest::OPCODE if self.operations.is_running(msg.opcode) => {
+                vec![nack(msg, codes::BUSY, "already running")]
+            }
+            Calibrate::OPCODE | SelfTest::OPCODE => {
+                self.operations.start(msg);
+                vec![]
+            }
+            OperationAbort::OPCODE => match OperationAbort::from_concentrated(msg) {
+                Ok(request) => std::iter::once(*msg)
+                    .chain(self.operations.abort(request.get_operation()))
+                    .collect(),
+                Err(_) => vec![],
+            },
+            ParamRead::OPCODE | ParamWrite::OPCODE => self.params.handle(msg).into_iter().collect(),
+            _ => vec![nack(msg, codes::UNSUPPORTED, "")],
+        }
+    }
+}
+
+//--------------SwordFishSimulator------------------//
+pub struct SwordFishSimulator {
+    device: Arc<Mutex<SimulatedDevice>>,
+    unsolicited: Sender<SwordFishConcentratedMessage>,
+    thread_handle: Option<JoinHandle<()>>,
+    thread_alive: Arc<AtomicBool>,
+}
+
+impl SwordFishSimulator {
+    //serves the device on the transport until it is closed or the simulator is dropped
+    pub fn spawn(mut port: Box<dyn SwordFishTransport>) -> Self {
+        let device = Arc::new(Mutex::new(SimulatedDevice::default()));
+        let thread_alive = Arc::new(AtomicBool::new(true));
+
+        let (unsolicited, unsolicited_receiver) = mpsc::channel::<SwordFishConcentratedMessage>();
+
+        let device_clone = device.clone();
+        let thread_alive_clone = thread_alive.clone();
+        let thread_handle = spawn(move || {
+            let mut read_buffer = [0; CONCENTRATED_MESSAGE_TOTAL_SIZE];
+            let mut builder = SwordFishConcentratedMessageBufferBuilder::for_host_frames();
+            while thread_alive_clone.load(Ordering::Relaxed) {
+                let mut outgoing: Vec<SwordFishConcentratedMessage> = unsolicited_receiver.try_iter().collect();
+                outgoing.extend(
+                    device_clone
+                        .lock()
+                        .expect("Another thread holding the mutex panicked")
+                        .poll(),
+                );
+                for msg in outgoing {
+                    if port.write_all(&msg.into_bytes()).is_err() {
+                        return;
+                    }
+                }
+                let n_bytes_read = match port.read(&mut read_buffer) {
+                    Ok(n_bytes_read) => n_bytes_read,
+                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                    Err(_) => break,
+                };
+                let mut next_msg = builder.append_buffer(&read_buffer[..n_bytes_read]);
+                while let Some(msg) = next_msg {
+                    let replies = device_clone
+                        .lock()
+                        .expect("Another thread holding the mutex panicked")
+                        .handle(&msg);
+                    for reply in replies {
+                        if port.write_all(&reply.into_bytes()).is_err() {
+                            return;
+                        }
+                    }
+                    next_msg = builder.next_message();
+                }
+            }
+        });
+
+        SwordFishSimulator {
+            device,
+            unsolicited,
+            thread_handle: Some(thread_handle),
+            thread_alive,
+        }
+    }
+
+    //a simulator and a SwordFishComm talking to it over an in-process link
+    pub fn connect() -> (SwordFishSimulator, SwordFishComm) {
+        let (host, device) = memory_link();
+        let simulator = SwordFishSimulator::spawn(Box::new(device));
+        (simulator, SwordFishComm::from_transport(Box::new(host)))
+    }
+
+    //a simulator behind a pseudo terminal, returns the port name that other programs open like a board's serial port
+    #[cfg(target_os = "linux")]
+    pub fn spawn_pty() -> std::io::Result<(SwordFishSimulator, String)> {
+        let pty = crate::swordfish_pty::PtyTransport::open()?;
+        let port_name = pty.port_name().to_string();
+        Ok((SwordFishSimulator::spawn(Box::new(pty)), port_name))
+    }
+
+    //a message the device sends on its own, like a DeviceLog
+    pub fn send(&self, msg: SwordFishConcentratedMessage) {
+        self.unsolicited
+            .send(msg)
+            .expect("The simulator thread stopped");
+    }
+
+    //to change the state of the device while it runs
+    pub fn device(&self) -> MutexGuard<'_, SimulatedDevice> {
+        self.device
+            .lock()
+            .expect("Another thread holding the mutex panicked")
+    }
+}
+
+impl Drop for SwordFishSimulator {
+    fn drop(&mut self) {
+        self.thread_alive.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.thread_handle.take() {
+            handle
+                .join()
+                .expect("The simulator thread could not be joined");
+        }
+    }
+}
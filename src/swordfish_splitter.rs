@@ -0,0 +1,153 @@
+//cuts the byte streams between a host and a device into frames, for the tools that forward them (the proxy,
+//the bridge, the daemon). the frames of the device start with SYNC_WORD_FROM_SWORDFISH and the ones of a host
+//with either sync word (see SYNC_WORDS_FROM_HOST), the bytes between frames and the frames with a bad checksum are kept, so nothing is lost on the way
+use crate::swordfish_concentrated_message::{HEADER_SIZE, SYNC_WORDS_FROM_DEVICE, SYNC_WORDS_FROM_HOST, SYNC_WORD_FROM_SWORDFISH};
+use crate::{SwordFishConcentratedMessage, MAX_PAYLOAD_SIZE};
+use std::time::{Duration, Instant};
+
+pub enum Segment {
+    Bytes(Vec<u8>),                                           //not part of a frame
+    Frame(Vec<u8>, Option<Box<SwordFishConcentratedMessage>>), //None when the checksum is wrong
+}
+
+//cuts a byte stream into frames without dropping anything, unlike SwordFishConcentratedMessageBufferBuilder
+pub struct FrameSplitter {
+    buffer: Vec<u8>,
+    last_push: Instant,
+    sync_words: &'static [[u8; 4]],
+}
+
+impl Default for FrameSplitter {
+    fn default() -> Self {
+        FrameSplitter::new()
+    }
+}
+
+impl FrameSplitter {
+    //cuts the frames of the device
+    pub fn new() -> Self {
+        FrameSplitter::with_sync_words(SYNC_WORDS_FROM_DEVICE)
+    }
+
+    //cuts the frames of a host
+    pub fn for_host_frames() -> Self {
+        FrameSplitter::with_sync_words(SYNC_WORDS_FROM_HOST)
+    }
+
+    pub fn with_sync_words(sync_words: &'static [[u8; 4]]) -> Self {
+        FrameSplitter {
+            buffer: Vec::new(),
+            last_push: Instant::now(),
+            sync_words,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Segment> {
+        self.last_push = Instant::now();
+        self.buffer.extend_from_slice(bytes);
+        let mut segments = Vec::new();
+        loop {
+            let start = match self
+                .buffer
+                .windows(4)
+                .position(|window| self.sync_words.iter().any(|sync_word| window == sync_word))
+            {
+                Some(start) => start,
+                None => {
+                    //the last bytes can be the start of a sync word
+                    let keep = std::cmp::min(self.buffer.len(), SYNC_WORD_FROM_SWORDFISH.len() - 1);
+                    let garbage: Vec<u8> = self.buffer.drain(..self.buffer.len() - keep).collect();
+                    if !garbage.is_empty() {
+                        segments.push(Segment::Bytes(garbage));
+                    }
+                    return segments;
+                }
+            };
+            if start > 0 {
+                segments.push(Segment::Bytes(self.buffer.drain(..start).collect()));
+            }
+            if self.buffer.len() < HEADER_SIZE {
+                return segments;
+            }
+            let length = u16::from_le_bytes([self.buffer[7], self.buffer[8]]) as usize;
+            if length > MAX_PAYLOAD_SIZE {
+                //not a frame, the sync word was in the data
+                segments.push(Segment::Bytes(self.buffer.drain(..1).collect()));
+                continue;
+            }
+            if self.buffer.len() < HEADER_SIZE + length + 1 {
+                return segments;
+            }
+            let frame: Vec<u8> = self.buffer.drain(..HEADER_SIZE + length + 1).collect();
+            let msg = SwordFishConcentratedMessage::parse_frame(&frame).map(Box::new);
+            segments.push(Segment::Frame(frame, msg));
+        }
+    }
+
+    //the bytes of a frame that never completed
+    pub fn take_stale(&mut self, timeout: Duration) -> Option<Vec<u8>> {
+        if self.buffer.is_empty() || self.last_push.elapsed() < timeout {
+            return None;
+        }
+        Some(std::mem::take(&mut self.buffer))
+    }
+}
+
+//the bytes start like a frame of either direction (the header and more), parse_frame tells if the checksum is right
+pub fn starts_like_a_frame(bytes: &[u8]) -> bool {
+    bytes.len() > HEADER_SIZE && SYNC_WORDS_FROM_HOST.iter().any(|sync_word| bytes.starts_with(sync_word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swordfish_messages::{Ping, VersionData};
+    use crate::SwordFishMessageTrait;
+
+    #[test]
+    fn streams_are_split_without_losing_bytes() {
+        let ping = Ping::default().to_concentrated(1).into_bytes().to_vec();
+        let mut bad = VersionData::default().to_concentrated(2).into_bytes().to_vec();
+        *bad.last_mut().unwrap() ^= 0xff;
+        let mut stream = vec![0x55, 0x66];
+        stream.extend_from_slice(&ping);
+        stream.extend_from_slice(&bad);
+        stream.extend_from_slice(&ping[..5]);
+
+        let mut splitter = FrameSplitter::new();
+        let mut forwarded = Vec::new();
+        let mut kinds = Vec::new();
+        for chunk in stream.chunks(3) {
+            for segment in splitter.push(chunk) {
+                match segment {
+                    Segment::Bytes(bytes) => {
+                        kinds.push("bytes");
+                        forwarded.extend(bytes);
+                    }
+                    Segment::Frame(bytes, msg) => {
+                        kinds.push(if msg.is_some() { "frame" } else { "bad" });
+                        forwarded.extend(bytes);
+                    }
+                }
+            }
+        }
+        assert_eq!(kinds, ["bytes", "frame", "bad"]);
+        assert_eq!(splitter.take_stale(Duration::ZERO).unwrap(), &ping[..5]);
+        forwarded.extend_from_slice(&ping[..5]);
+        assert_eq!(forwarded, stream);
+    }
+
+    #[test]
+    fn host_frames_start_with_either_sync_word() {
+        let mut frame = Ping::default().to_concentrated(1).into_bytes().to_vec();
+        frame[..4].copy_from_slice(&crate::swordfish_concentrated_message::_SYNC_WORD_TO_SWORDFISH);
+        match FrameSplitter::for_host_frames().push(&frame).as_slice() {
+            [Segment::Frame(bytes, Some(msg))] => {
+                assert_eq!(bytes, &frame);
+                assert_eq!(msg.opcode, Ping::OPCODE);
+            }
+            _ => panic!("the frame was not cut"),
+        }
+        assert!(FrameSplitter::new().push(&frame).iter().all(|segment| matches!(segment, Segment::Bytes(_))));
+    }
+}
@@ -0,0 +1,139 @@
+//buffers for messages of the Stream category, pushed by the device at a high rate
+//the read thread pushes every message into the ring buffer of its opcode, taking only the shared side
+//of a lock that set_capacity alone takes exclusively. when the buffer is full the oldest message is
+//overwritten and counted as overflowed.
+//the application drains batches whenever it likes:
+//  comm.start_stream(ImuSample::OPCODE, 1000)?;
+//  let samples: Vec<ImuSample> = comm.drain::<ImuSample>(256);
+use crate::swordfish_concentrated_message::SwordFishConcentratedMessage;
+use crate::SwordFishMessageTrait;
+use crossbeam_queue::ArrayQueue;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+
+pub const DEFAULT_STREAM_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamRecord {
+    pub msg: SwordFishConcentratedMessage,
+    pub host_us: u64, //when it was received, see swordfish_clock_sync::host_now_us
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    pub received: u64,
+    pub overflowed: u64, //overwritten before they were drained
+    pub buffered: usize,
+    pub capacity: usize,
+}
+
+pub struct StreamBuffer {
+    queue: RwLock<ArrayQueue<StreamRecord>>,
+    received: AtomicU64,
+    overflowed: AtomicU64,
+}
+
+impl StreamBuffer {
+    pub fn new(capacity: usize) -> Self {
+        StreamBuffer {
+            queue: RwLock::new(ArrayQueue::new(std::cmp::max(capacity, 1))),
+            received: AtomicU64::new(0),
+            overflowed: AtomicU64::new(0),
+        }
+    }
+
+    fn queue(&self) -> RwLockReadGuard<'_, ArrayQueue<StreamRecord>> {
+        self.queue.read().expect("Another thread holding the lock panicked")
+    }
+
+    pub fn push(&self, record: StreamRecord) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        if self.queue().force_push(record).is_some() {
+            self.overflowed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    //at most max records, oldest first
+    pub fn drain_records(&self, max: usize) -> Vec<StreamRecord> {
+        let queue = self.queue();
+        let mut records = Vec::with_capacity(std::cmp::min(max, queue.len()));
+        while records.len() < max {
+            match queue.pop() {
+                Some(record) => records.push(record),
+                None => break,
+            }
+        }
+        records
+    }
+
+    //messages that fail to decode are logged and skipped
+    pub fn drain<T: SwordFishMessageTrait>(&self, max: usize) -> Vec<T> {
+        self.drain_records(max)
+            .iter()
+            .filter_map(|record| match T::from_concentrated(&record.msg) {
+                Ok(msg) => Some(msg),
+                Err(e) => {
+                    log::warn!("Dropping stream message with opcode {}: {}", record.msg.opcode, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    //appends the payloads of at most max records to out, each padded with zeros to stride bytes,
+    //so fixed size records can be viewed as an array. returns the number of records
+    pub fn drain_payloads(&self, max: usize, stride: usize, out: &mut [u8]) -> usize {
+        let max = std::cmp::min(max, out.len() / std::cmp::max(stride, 1));
+        let queue = self.queue();
+        let mut n = 0;
+        while n < max {
+            let record = match queue.pop() {
+                Some(record) => record,
+                None => break,
+            };
+            let length = std::cmp::min(record.msg.length as usize, stride);
+            let slot = &mut out[n * stride..(n + 1) * stride];
+            slot[..length].copy_from_slice(&record.msg.payload[..length]);
+            slot[length..].fill(0);
+            n += 1;
+        }
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue().is_empty()
+    }
+
+    //keeps the newest records that fit, the others are counted as overflowed.
+    //the buffer stays the same, so handles from SwordFishComm::stream_buffer keep working
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut queue = self.queue.write().expect("Another thread holding the lock panicked");
+        let resized = ArrayQueue::new(std::cmp::max(capacity, 1));
+        while let Some(record) = queue.pop() {
+            if resized.force_push(record).is_some() {
+                self.overflowed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        *queue = resized;
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        let queue = self.queue();
+        StreamStats {
+            received: self.received.load(Ordering::Relaxed),
+            overflowed: self.overflowed.load(Ordering::Relaxed),
+            buffered: queue.len(),
+            capacity: queue.capacity(),
+        }
+    }
+}
+
+impl Default for StreamBuffer {
+    fn default() -> Self {
+        StreamBuffer::new(DEFAULT_STREAM_CAPACITY)
+    }
+}
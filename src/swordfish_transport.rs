@@ -0,0 +1,64 @@
+//the byte stream SwordFishComm talks over, a serial port by default
+//reads are expected to time out (std::io::ErrorKind::TimedOut) when no data is available,
+//like the serial port opened by SwordFishComm::new
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+pub trait SwordFishTransport: Read + Write + Send {}
+impl<T: Read + Write + Send> SwordFishTransport for T {}
+
+//one end of an in-process link, what is written to one end is read from the other
+//used to connect SwordFishComm to the simulator without hardware
+pub struct MemoryTransport {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    read_timeout: Duration,
+}
+
+pub fn memory_link() -> (MemoryTransport, MemoryTransport) {
+    let (a_tx, b_rx) = mpsc::channel();
+    let (b_tx, a_rx) = mpsc::channel();
+    (MemoryTransport::new(a_tx, a_rx), MemoryTransport::new(b_tx, b_rx))
+}
+
+impl MemoryTransport {
+    fn new(tx: Sender<Vec<u8>>, rx: Receiver<Vec<u8>>) -> Self {
+        MemoryTransport {
+            tx,
+            rx,
+            pending: Vec::new(),
+            read_timeout: Duration::from_millis(1),
+        }
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv_timeout(self.read_timeout) {
+                Ok(bytes) => self.pending = bytes,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::ErrorKind::BrokenPipe.into()),
+            }
+        }
+        let n = std::cmp::min(buf.len(), self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
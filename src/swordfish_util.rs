@@ -0,0 +1,69 @@
+//small helpers shared by the tools and the text formats of the library
+use anyhow::{anyhow, Result};
+use std::io::{BufRead, Read};
+
+//the longest line of a handshake
+const MAX_LINE_LENGTH: u64 = 512;
+
+//"0a1b2c", how tools show payloads
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+//"0000  48 69 00  |Hi.|", one line per bytes_per_line bytes with their offset and the printable ones as text
+pub fn hexdump(bytes: &[u8], bytes_per_line: usize) -> String {
+    let bytes_per_line = std::cmp::max(bytes_per_line, 1);
+    bytes
+        .chunks(bytes_per_line)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let text: String = chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("{:04x}  {:<width$}  |{}|", i * bytes_per_line, hex.join(" "), text, width = bytes_per_line * 3 - 1)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+//accepts "0a1b2c", "0x0a1b2c", "0a 1b 2c" and "0a:1b:2c"
+pub fn from_hex(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim();
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(anyhow!("Odd number of hex digits in {}", text));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| anyhow!("Invalid hex byte {}", pair))
+        })
+        .collect()
+}
+
+//a line of the handshake of swordfish_bridge or swordfish_daemon, without its newline
+pub(crate) fn read_line<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE_LENGTH).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(anyhow!("The connection was closed during the handshake"));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(to_hex(&[0x0a, 0xff, 0x00]), "0aff00");
+        assert_eq!(from_hex("0x0a ff:00").unwrap(), vec![0x0a, 0xff, 0x00]);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+    }
+}
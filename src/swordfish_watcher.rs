@@ -0,0 +1,290 @@
+//tells when a swordfish is plugged in or unplugged, so an application does not have to poll discover():
+//  let watcher = DeviceWatcher::new(DiscoveryConfig::default(), Box::new(|event| match event {
+//      DeviceEvent::Added(port) => println!("plugged {}", port.port_name),
+//      DeviceEvent::Removed(port) => println!("unplugged {}", port.port_name),
+//  }));
+//the ports are listed again whenever the PortSource reports a change: udev events on linux,
+//a timer everywhere else (or when udev is not available). the ports present at the start are reported as Added.
+//tests use a FakePortSource to plug and unplug ports by hand
+use crate::swordfish_discovery::{list_ports, probe, DiscoveryConfig, PortInfo};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_POLL_PERIOD: Duration = Duration::from_millis(500);
+//how long the watcher thread waits for a change before checking if it was stopped
+const WAIT_SLICE: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added(PortInfo),
+    Removed(PortInfo),
+}
+
+impl DeviceEvent {
+    pub fn port(&self) -> &PortInfo {
+        match self {
+            DeviceEvent::Added(port) | DeviceEvent::Removed(port) => port,
+        }
+    }
+
+    pub fn is_added(&self) -> bool {
+        matches!(self, DeviceEvent::Added(_))
+    }
+}
+
+pub type DeviceEventCallback = Box<dyn FnMut(&DeviceEvent) + Send>;
+
+//where the watcher gets the ports from
+pub trait PortSource {
+    fn ports(&mut self) -> Vec<PortInfo>;
+    //waits at most timeout, true if the ports may have changed since the last call
+    fn wait_for_change(&mut self, timeout: Duration) -> bool;
+}
+
+//lists the serial ports every period
+pub struct PollingPortSource {
+    period: Duration,
+    last_scan: Instant,
+}
+
+impl PollingPortSource {
+    pub fn new(period: Duration) -> Self {
+        PollingPortSource {
+            period,
+            last_scan: Instant::now(),
+        }
+    }
+}
+
+impl PortSource for PollingPortSource {
+    fn ports(&mut self) -> Vec<PortInfo> {
+        self.last_scan = Instant::now();
+        list_ports()
+    }
+
+    fn wait_for_change(&mut self, timeout: Duration) -> bool {
+        let next_scan = self.last_scan + self.period;
+        let now = Instant::now();
+        if now >= next_scan {
+            return true;
+        }
+        std::thread::sleep(std::cmp::min(timeout, next_scan - now));
+        Instant::now() >= next_scan
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod udev_source {
+    use super::PortSource;
+    use crate::swordfish_discovery::{list_ports, PortInfo};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    //lists the serial ports when udev reports a tty added or removed
+    pub struct UdevPortSource {
+        socket: libudev::MonitorSocket,
+        _context: libudev::Context,
+    }
+
+    impl UdevPortSource {
+        pub fn new() -> Result<Self, libudev::Error> {
+            let context = libudev::Context::new()?;
+            let mut monitor = libudev::Monitor::new(&context)?;
+            monitor.match_subsystem("tty")?;
+            Ok(UdevPortSource {
+                socket: monitor.listen()?,
+                _context: context,
+            })
+        }
+    }
+
+    impl PortSource for UdevPortSource {
+        fn ports(&mut self) -> Vec<PortInfo> {
+            list_ports()
+        }
+
+        fn wait_for_change(&mut self, timeout: Duration) -> bool {
+            let mut fds = [libc::pollfd {
+                fd: self.socket.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout.as_millis() as libc::c_int) };
+            if ready <= 0 {
+                return false;
+            }
+            let mut changed = false;
+            while let Some(event) = self.socket.receive_event() {
+                changed |= matches!(event.event_type(), libudev::EventType::Add | libudev::EventType::Remove);
+            }
+            changed
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use udev_source::UdevPortSource;
+
+//udev on linux, polling when it is not available
+pub fn system_port_source() -> Box<dyn PortSource> {
+    #[cfg(target_os = "linux")]
+    match UdevPortSource::new() {
+        Ok(source) => return Box::new(source),
+        Err(e) => log::warn!("udev is not available ({}), polling the serial ports", e),
+    }
+    Box::new(PollingPortSource::new(DEFAULT_POLL_PERIOD))
+}
+
+#[derive(Default)]
+struct FakePorts {
+    ports: Vec<PortInfo>,
+    changed: bool,
+}
+
+//ports plugged and unplugged by hand, clones share the same ports
+#[derive(Clone, Default)]
+pub struct FakePortSource {
+    shared: Arc<(Mutex<FakePorts>, Condvar)>,
+}
+
+impl FakePortSource {
+    pub fn new() -> Self {
+        FakePortSource::default()
+    }
+
+    pub fn plug(&self, port: PortInfo) {
+        self.change(|ports| {
+            ports.retain(|p| p.port_name != port.port_name);
+            ports.push(port);
+        });
+    }
+
+    pub fn unplug(&self, port_name: &str) {
+        self.change(|ports| ports.retain(|p| p.port_name != port_name));
+    }
+
+    fn change(&self, f: impl FnOnce(&mut Vec<PortInfo>)) {
+        let (state, condvar) = &*self.shared;
+        let mut state = state.lock().expect("Another thread holding the mutex panicked");
+        f(&mut state.ports);
+        state.changed = true;
+        condvar.notify_all();
+    }
+}
+
+impl PortSource for FakePortSource {
+    fn ports(&mut self) -> Vec<PortInfo> {
+        let mut state = self.shared.0.lock().expect("Another thread holding the mutex panicked");
+        state.changed = false;
+        state.ports.clone()
+    }
+
+    fn wait_for_change(&mut self, timeout: Duration) -> bool {
+        let (state, condvar) = &*self.shared;
+        let state = state.lock().expect("Another thread holding the mutex panicked");
+        let (state, _) = condvar
+            .wait_timeout_while(state, timeout, |state| !state.changed)
+            .expect("Another thread holding the mutex panicked");
+        state.changed
+    }
+}
+
+pub struct DeviceWatcher {
+    thread_handle: Option<JoinHandle<()>>,
+    thread_alive: Arc<AtomicBool>,
+}
+
+impl DeviceWatcher {
+    //watches the serial ports of the system
+    pub fn new(config: DiscoveryConfig, callback: DeviceEventCallback) -> Self {
+        //the udev handles can not move between threads, the source is made by the watcher thread
+        DeviceWatcher::spawn(config, Box::new(system_port_source), callback)
+    }
+
+    pub fn with_source(config: DiscoveryConfig, source: Box<dyn PortSource + Send>, callback: DeviceEventCallback) -> Self {
+        DeviceWatcher::spawn(config, Box::new(move || source as Box<dyn PortSource>), callback)
+    }
+
+    fn spawn(
+        config: DiscoveryConfig,
+        make_source: Box<dyn FnOnce() -> Box<dyn PortSource> + Send>,
+        mut callback: DeviceEventCallback,
+    ) -> Self {
+        let thread_alive = Arc::new(AtomicBool::new(true));
+        let thread_alive_clone = thread_alive.clone();
+        let thread_handle = spawn(move || {
+            let mut source = make_source();
+            let mut known: BTreeMap<String, PortInfo> = BTreeMap::new();
+            let mut rescan = true;
+            while thread_alive_clone.load(Ordering::Relaxed) {
+                if rescan {
+                    for event in scan(&config, source.ports(), &mut known) {
+                        callback(&event);
+                    }
+                }
+                rescan = source.wait_for_change(WAIT_SLICE);
+            }
+        });
+        DeviceWatcher {
+            thread_handle: Some(thread_handle),
+            thread_alive,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.thread_alive.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.thread_handle.take() {
+            handle
+                .join()
+                .expect("The watcher thread could not be joined");
+        }
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+//the events that turn known into the matching ports, a port that changed its descriptor is removed and added again
+fn scan(config: &DiscoveryConfig, ports: Vec<PortInfo>, known: &mut BTreeMap<String, PortInfo>) -> Vec<DeviceEvent> {
+    let current: BTreeMap<String, PortInfo> = config
+        .filter(ports)
+        .into_iter()
+        .map(|port| (port.port_name.clone(), port))
+        .collect();
+    let mut events = Vec::new();
+    let removed: Vec<String> = known
+        .iter()
+        .filter(|(name, port)| current.get(*name).is_none_or(|now| !same_device(now, port)))
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in removed {
+        if let Some(port) = known.remove(&name) {
+            events.push(DeviceEvent::Removed(port));
+        }
+    }
+    for (name, mut port) in current {
+        if known.contains_key(&name) {
+            continue;
+        }
+        //a port that does not answer yet is tried again with the next change
+        if let Some(timeout) = config.probe {
+            if !probe(&port.port_name, timeout) {
+                continue;
+            }
+            port.confirmed = true;
+        }
+        known.insert(name, port.clone());
+        events.push(DeviceEvent::Added(port));
+    }
+    events
+}
+
+fn same_device(a: &PortInfo, b: &PortInfo) -> bool {
+    a.vid == b.vid && a.pid == b.pid && a.serial_number == b.serial_number
+}
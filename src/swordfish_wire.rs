@@ -0,0 +1,302 @@
+//field-wise encoding of message payloads, used by the SwordFishMessage derive macro
+//numbers are little endian, variable length fields (strings, byte vectors, repeated items)
+//are sent as a u8 length prefix followed by the data, MAX_PAYLOAD_SIZE is below 256 so a u8 is enough
+use crate::swordfish_dynamic::DynamicFieldType;
+use crate::swordfish_registry::FieldType;
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::ops::Deref;
+
+pub trait SwordFishWireField: Sized + Default {
+    //the largest number of bytes the field can take on the wire
+    const MAX_SIZE: usize;
+    //true if the field has a length prefix and can take less than MAX_SIZE bytes
+    const VARIABLE_LENGTH: bool = false;
+
+    //the layout of the field, for the registry and the tools that decode messages they were not compiled with
+    fn field_type() -> FieldType;
+    fn wire_size(&self) -> usize;
+    fn encode(&self, buffer: &mut Vec<u8>);
+    //reads the field from the front of input and advances it
+    fn decode(input: &mut &[u8]) -> Result<Self>;
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if input.len() < n {
+        return Err(anyhow!(
+            "Payload too short, expected {} more bytes, got {}",
+            n,
+            input.len()
+        ));
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_wire_number {
+    ($($t:ty => $number:ident),*) => {$(
+        impl SwordFishWireField for $t {
+            const MAX_SIZE: usize = std::mem::size_of::<$t>();
+            fn field_type() -> FieldType {
+                FieldType::Number(DynamicFieldType::$number)
+            }
+            fn wire_size(&self) -> usize {
+                Self::MAX_SIZE
+            }
+            fn encode(&self, buffer: &mut Vec<u8>) {
+                buffer.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self> {
+                let bytes = take(input, Self::MAX_SIZE)?;
+                Ok(<$t>::from_le_bytes(bytes.try_into().expect("take returns exactly MAX_SIZE bytes")))
+            }
+        }
+    )*};
+}
+impl_wire_number!(u8 => U8, u16 => U16, u32 => U32, u64 => U64, i8 => I8, i16 => I16, i32 => I32, i64 => I64, f32 => F32, f64 => F64);
+
+impl<T: SwordFishWireField + Copy, const N: usize> SwordFishWireField for [T; N]
+where
+    [T; N]: Default,
+{
+    const MAX_SIZE: usize = T::MAX_SIZE * N;
+    const VARIABLE_LENGTH: bool = T::VARIABLE_LENGTH;
+    fn field_type() -> FieldType {
+        FieldType::Array(Box::new(T::field_type()), N)
+    }
+    fn wire_size(&self) -> usize {
+        self.iter().map(|item| item.wire_size()).sum()
+    }
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        for item in self.iter() {
+            item.encode(buffer);
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut arr = Self::default();
+        for item in arr.iter_mut() {
+            *item = T::decode(input)?;
+        }
+        Ok(arr)
+    }
+}
+
+fn check_length_prefix(len: usize, max: usize) -> Result<()> {
+    if len > max {
+        return Err(anyhow!("Field too long, maximum is {}, got {}", max, len));
+    }
+    Ok(())
+}
+
+//--------------BoundedString------------------//
+//utf-8 string of at most N bytes
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct BoundedString<const N: usize>(String);
+
+impl<const N: usize> BoundedString<N> {
+    pub fn new(s: &str) -> Result<Self> {
+        check_length_prefix(s.len(), N)?;
+        Ok(BoundedString(s.to_string()))
+    }
+
+    //cuts the string at the last char boundary that fits, like the array setters of the generated messages
+    pub fn truncated(s: &str) -> Self {
+        let mut end = std::cmp::min(s.len(), N);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        BoundedString(s[..end].to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<const N: usize> Deref for BoundedString<N> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<const N: usize> fmt::Debug for BoundedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<const N: usize> fmt::Display for BoundedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<const N: usize> TryFrom<&str> for BoundedString<N> {
+    type Error = anyhow::Error;
+    fn try_from(s: &str) -> Result<Self> {
+        BoundedString::new(s)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for BoundedString<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for BoundedString<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        BoundedString::new(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<const N: usize> SwordFishWireField for BoundedString<N> {
+    const MAX_SIZE: usize = 1 + N;
+    const VARIABLE_LENGTH: bool = true;
+    fn field_type() -> FieldType {
+        FieldType::String(N)
+    }
+    fn wire_size(&self) -> usize {
+        1 + self.0.len()
+    }
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.0.len() as u8);
+        buffer.extend_from_slice(self.0.as_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let len = u8::decode(input)? as usize;
+        check_length_prefix(len, N)?;
+        let bytes = take(input, len)?;
+        let s = std::str::from_utf8(bytes).map_err(|e| anyhow!("Invalid utf-8 in string field: {}", e))?;
+        Ok(BoundedString(s.to_string()))
+    }
+}
+
+//--------------BoundedVec------------------//
+//at most N repeated items, BoundedVec<u8, N> is used for byte arrays
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct BoundedVec<T, const N: usize>(Vec<T>);
+
+impl<T: Clone, const N: usize> BoundedVec<T, N> {
+    pub fn new(items: &[T]) -> Result<Self> {
+        check_length_prefix(items.len(), N)?;
+        Ok(BoundedVec(items.to_vec()))
+    }
+
+    //keeps the first N items, like the array setters of the generated messages
+    pub fn truncated(items: &[T]) -> Self {
+        let len = std::cmp::min(items.len(), N);
+        BoundedVec(items[..len].to_vec())
+    }
+
+    pub fn push(&mut self, item: T) -> Result<()> {
+        check_length_prefix(self.0.len() + 1, N)?;
+        self.0.push(item);
+        Ok(())
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.0
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T, const N: usize> Deref for BoundedVec<T, N> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for BoundedVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<T: Clone, const N: usize> TryFrom<&[T]> for BoundedVec<T, N> {
+    type Error = anyhow::Error;
+    fn try_from(items: &[T]) -> Result<Self> {
+        BoundedVec::new(items)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize> serde::Serialize for BoundedVec<T, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, const N: usize> serde::Deserialize<'de> for BoundedVec<T, N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        check_length_prefix(items.len(), N).map_err(serde::de::Error::custom)?;
+        Ok(BoundedVec(items))
+    }
+}
+
+impl<T: SwordFishWireField, const N: usize> SwordFishWireField for BoundedVec<T, N> {
+    const MAX_SIZE: usize = 1 + N * T::MAX_SIZE;
+    const VARIABLE_LENGTH: bool = true;
+    fn field_type() -> FieldType {
+        FieldType::Vec(Box::new(T::field_type()), N)
+    }
+    fn wire_size(&self) -> usize {
+        1 + self.0.iter().map(|item| item.wire_size()).sum::<usize>()
+    }
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.0.len() as u8);
+        for item in self.0.iter() {
+            item.encode(buffer);
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let len = u8::decode(input)? as usize;
+        check_length_prefix(len, N)?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        Ok(BoundedVec(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_length_round_trip() {
+        let name = BoundedString::<8>::new("board").unwrap();
+        let samples = BoundedVec::<i16, 4>::new(&[-1, 2, 300]).unwrap();
+        let mut buffer = Vec::new();
+        name.encode(&mut buffer);
+        samples.encode(&mut buffer);
+        assert_eq!(buffer.len(), name.wire_size() + samples.wire_size());
+
+        let mut input = &buffer[..];
+        assert_eq!(BoundedString::<8>::decode(&mut input).unwrap(), name);
+        assert_eq!(BoundedVec::<i16, 4>::decode(&mut input).unwrap(), samples);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn bad_lengths_are_rejected() {
+        assert!(BoundedString::<4>::new("too long").is_err());
+        assert_eq!(BoundedString::<4>::truncated("too long").as_str(), "too ");
+        //length prefix larger than the bound
+        assert!(BoundedVec::<u8, 2>::decode(&mut &[3u8, 1, 2, 3][..]).is_err());
+        //length prefix larger than the remaining payload
+        assert!(BoundedVec::<u8, 8>::decode(&mut &[3u8, 1, 2][..]).is_err());
+    }
+}
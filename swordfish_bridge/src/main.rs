@@ -0,0 +1,156 @@
+//swordfish-bridge, serves a board of the lab rack over tcp. it owns the serial port, and remote programs open
+//"tcp://token@host:5757" in place of the port, with SwordFishComm::new or --port of the other tools:
+//  SWORDFISH_BRIDGE_TOKEN=secret swordfish-bridge --port /dev/ttyUSB0 --listen 0.0.0.0:5757
+//  swordfish-bridge --simulator --token-file bridge.token
+//the first line printed is the address it listens on
+use clap::Parser;
+use serde_json::json;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_bridge::{SwordFishBridge, BRIDGE_TOKEN_VARIABLE, DEFAULT_BRIDGE_PORT};
+use swordfish_com::swordfish_comm::find_probable_swordfish_port;
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::{memory_link, SwordFishTransport};
+
+#[derive(Parser)]
+#[command(name = "swordfish-bridge", version, about = "Serve a swordfish board over tcp")]
+struct Args {
+    /// Serial port of the board, the first port that looks like a swordfish when not given
+    #[arg(short, long)]
+    port: Option<String>,
+    /// Serve the built-in simulator instead of a board
+    #[arg(long, conflicts_with = "port")]
+    simulator: bool,
+    /// Address to listen on
+    #[arg(short, long, default_value_t = format!("127.0.0.1:{}", DEFAULT_BRIDGE_PORT))]
+    listen: String,
+    /// The token clients must prove they know
+    #[arg(long, env = BRIDGE_TOKEN_VARIABLE, hide_env_values = true, conflicts_with = "token_file")]
+    token: Option<String>,
+    /// Read the token from the first line of a file
+    #[arg(long, value_name = "FILE")]
+    token_file: Option<PathBuf>,
+    /// Accept any client, without a token
+    #[arg(long, conflicts_with_all = ["token", "token_file"])]
+    insecure: bool,
+    /// Print json instead of text
+    #[arg(long)]
+    json: bool,
+    /// Stop after this many seconds
+    #[arg(short, long, value_name = "SECONDS")]
+    duration: Option<f64>,
+    /// -v for info, -vv for debug logs (on stderr)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+fn token(args: &Args) -> anyhow::Result<Option<String>> {
+    let token = match (&args.token, &args.token_file) {
+        (Some(token), _) => token.clone(),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_string(),
+        (None, None) if args.insecure => return Ok(None),
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "No token, pass --token, --token-file or {}, or --insecure to accept any client",
+                BRIDGE_TOKEN_VARIABLE
+            ))
+        }
+    };
+    if token.is_empty() {
+        return Err(anyhow::anyhow!("The token is empty"));
+    }
+    Ok(Some(token))
+}
+
+//the board or the simulator, its name for the clients, and the simulator that must live as long as the bridge
+fn open_device(args: &Args) -> anyhow::Result<(Box<dyn SwordFishTransport>, String, Option<SwordFishSimulator>)> {
+    if args.simulator {
+        let (device, simulator_port) = memory_link();
+        let simulator = SwordFishSimulator::spawn(Box::new(simulator_port));
+        return Ok((Box::new(device), "simulator".to_string(), Some(simulator)));
+    }
+    let port = match &args.port {
+        Some(port) => port.clone(),
+        None => find_probable_swordfish_port().ok_or_else(|| anyhow::anyhow!("No swordfish found, pass its port with --port"))?,
+    };
+    let device = serialport::new(&port, 115200)
+        .timeout(Duration::from_millis(1))
+        .open()
+        .map_err(|e| anyhow::anyhow!("Could not open {}: {}", port, e))?;
+    Ok((Box::new(device), port, None))
+}
+
+fn run(args: &Args) -> anyhow::Result<()> {
+    let token = token(args)?;
+    let (device, device_name, _simulator) = open_device(args)?;
+    let listener = TcpListener::bind(&args.listen).map_err(|e| anyhow::anyhow!("Could not listen on {}: {}", args.listen, e))?;
+    let bridge = SwordFishBridge::spawn(listener, device, token, &device_name)?;
+    match args.json {
+        true => println!("{}", json!({ "address": bridge.local_addr().to_string(), "device": device_name })),
+        false => println!("{}", bridge.local_addr()),
+    }
+    std::io::stdout().flush()?;
+
+    let deadline = args.duration.map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+    let mut client = None;
+    while bridge.is_running() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        let current = bridge.client();
+        if current != client {
+            match current {
+                Some(peer) => log::info!("{} has {}", peer, device_name),
+                None => log::info!("{} is free", device_name),
+            }
+            client = current;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    match bridge.is_running() {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!("{} was disconnected", device_name)),
+    }
+}
+
+//the library logs to the log crate, shown on stderr so it does not mix with the output
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{:<5} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let level = match args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("swordfish-bridge: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
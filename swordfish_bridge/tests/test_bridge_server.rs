@@ -0,0 +1,33 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::VersionData;
+use swordfish_com::SwordFishMessageTrait;
+
+#[test]
+fn the_served_board_is_opened_by_url() {
+    let mut bridge = Command::new(env!("CARGO_BIN_EXE_swordfish-bridge"))
+        .args(["--simulator", "--listen", "127.0.0.1:0", "--token", "secret", "-d", "10"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run swordfish-bridge");
+    let mut address = String::new();
+    BufReader::new(bridge.stdout.take().unwrap()).read_line(&mut address).unwrap();
+    let comm = SwordFishComm::new(&format!("tcp://secret@{}", address.trim())).unwrap();
+    let answer = comm.request(VersionData::default().to_concentrated(1)).unwrap();
+    drop(comm);
+    bridge.kill().unwrap();
+    bridge.wait().unwrap();
+    assert_eq!(answer, VersionData::new(1, 0, 0, &[0x5f; 8]).to_concentrated(1));
+}
+
+#[test]
+fn a_token_is_required() {
+    let output = Command::new(env!("CARGO_BIN_EXE_swordfish-bridge"))
+        .args(["--simulator", "--listen", "127.0.0.1:0"])
+        .env_remove("SWORDFISH_BRIDGE_TOKEN")
+        .output()
+        .expect("Could not run swordfish-bridge");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--insecure"));
+}
@@ -0,0 +1,535 @@
+//the swordfish command line tool, to work with a board without writing a program:
+//  swordfish ports                           the serial ports, the ones that look like a swordfish marked
+//  swordfish ping -n 10                      round trip times
+//  swordfish version                         the decoded VersionData
+//  swordfish send StreamStart 640a00         any message by name or opcode, with a hex payload
+//  swordfish monitor --stream ImuSample:100  the received messages, decoded
+//  swordfish dissector -o swordfish.lua      the wireshark plugin for captures (monitor --capture)
+//every command prints json instead with --json, and the exit code tells a script what went wrong (see exit_code).
+//--simulator runs the commands against the built-in simulator instead of a board,
+//--record saves the session to a file that --replay plays back in place of the board
+mod output;
+
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_bridge::redact_port_url;
+use swordfish_com::swordfish_capture::generate_dissector;
+use swordfish_com::swordfish_comm::{find_probable_swordfish_port, get_serial_ports, SwordFishComm};
+use swordfish_com::swordfish_discovery::{probe, DiscoveryConfig, PortInfo};
+use swordfish_com::swordfish_error::SwordFishError;
+use swordfish_com::swordfish_messages::{Ping, VersionData};
+use swordfish_com::swordfish_session::{ReplayTransport, Session};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_util::{from_hex, to_hex};
+use swordfish_com::{MessageRegistry, SwordFishConcentratedMessage, SwordFishMessageCategory, SwordFishMessageTrait, MAX_PAYLOAD_SIZE};
+
+//the exit codes of the tool, clap also exits with USAGE when the arguments are wrong
+mod exit_code {
+    pub const OK: u8 = 0;
+    pub const ERROR: u8 = 1; //anything not listed below
+    pub const USAGE: u8 = 2; //bad arguments, unknown message name, payload too long
+    pub const NO_DEVICE: u8 = 3; //no swordfish found, or the port could not be opened
+    pub const TIMEOUT: u8 = 4; //the device did not answer
+    pub const REJECTED: u8 = 5; //the device answered with a Nack
+}
+
+#[derive(Parser)]
+#[command(name = "swordfish", version, about = "Talk to a swordfish board from the shell")]
+struct Cli {
+    /// Serial port of the board, the first port that looks like a swordfish when not given
+    #[arg(short, long, global = true)]
+    port: Option<String>,
+    /// Talk to the built-in simulator instead of a board
+    #[arg(long, global = true, conflicts_with = "port")]
+    simulator: bool,
+    /// Record the raw bytes of the session with their timing to a file
+    #[arg(long, global = true, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Play a recorded session back instead of talking to a board
+    #[arg(long, global = true, value_name = "FILE", conflicts_with_all = ["port", "simulator"])]
+    replay: Option<PathBuf>,
+    /// Speed of --replay, 2 plays twice as fast
+    #[arg(long, global = true, default_value_t = 1.0)]
+    replay_speed: f64,
+    /// Print json instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    /// How long to wait for an answer, in ms
+    #[arg(short, long, global = true, default_value_t = 200)]
+    timeout: u64,
+    /// -v for info, -vv for debug logs (on stderr)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the serial ports, marking the ones that look like a swordfish
+    Ports {
+        /// Only the ports that look like a swordfish
+        #[arg(long)]
+        swordfish: bool,
+        /// Ping the candidates for this many ms and mark the ones that answer
+        #[arg(long, value_name = "MS")]
+        probe: Option<u64>,
+    },
+    /// Measure the round trip time of Ping messages
+    Ping {
+        /// Number of pings
+        #[arg(short = 'n', long, default_value_t = 4)]
+        count: u32,
+        /// Pause between two pings, in ms
+        #[arg(short, long, default_value_t = 100)]
+        interval: u64,
+    },
+    /// Read the firmware version of the board
+    Version,
+    /// Send one message and print the answer
+    Send {
+        /// Message name (e.g. ParamRead) or opcode
+        message: String,
+        /// Payload as hex, e.g. 0100 or "01 00"
+        #[arg(default_value = "")]
+        payload: String,
+        /// Counter of the message, the number of messages sent so far when not given
+        #[arg(long)]
+        counter: Option<u16>,
+    },
+    /// Print the messages received from the board
+    Monitor {
+        /// Only these messages (name or opcode), can be repeated
+        #[arg(short, long = "message", value_name = "MESSAGE")]
+        messages: Vec<String>,
+        /// Start a stream for the time of the monitor, NAME[:RATE_HZ], can be repeated
+        #[arg(long, value_name = "STREAM")]
+        stream: Vec<String>,
+        /// Stop after this many seconds
+        #[arg(short, long, value_name = "SECONDS")]
+        duration: Option<f64>,
+        /// Stop after this many messages, exits with the timeout code when --duration ends first
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+        /// Also record every frame sent and received to this pcapng file
+        #[arg(long, value_name = "FILE")]
+        capture: Option<PathBuf>,
+    },
+    /// Write the wireshark lua dissector for the captures
+    Dissector {
+        /// The file to write, stdout when not given
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+struct Failure {
+    exit_code: u8,
+    message: String,
+}
+
+impl Failure {
+    fn new(exit_code: u8, message: impl Into<String>) -> Self {
+        Failure {
+            exit_code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<SwordFishError> for Failure {
+    fn from(error: SwordFishError) -> Self {
+        let exit_code = match error {
+            SwordFishError::DeviceRejected { .. } => exit_code::REJECTED,
+            SwordFishError::Timeout { .. } => exit_code::TIMEOUT,
+            SwordFishError::NoAnswerExpected { .. } | SwordFishError::UnknownOpcode { .. } => exit_code::ERROR,
+        };
+        Failure::new(exit_code, error.to_string())
+    }
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<SwordFishError>() {
+            Ok(error) => Failure::from(error),
+            Err(error) => Failure::new(exit_code::ERROR, error.to_string()),
+        }
+    }
+}
+
+//the comm of a board, or of the simulator that lives as long as it
+struct Connection {
+    comm: SwordFishComm,
+    _simulator: Option<SwordFishSimulator>,
+}
+
+impl Connection {
+    fn open(cli: &Cli) -> Result<Self, Failure> {
+        let connection = Connection::connect(cli)?;
+        if let Some(path) = &cli.record {
+            connection
+                .comm
+                .start_recording(path)
+                .map_err(|e| Failure::new(exit_code::ERROR, format!("Could not record to {}: {}", path.display(), e)))?;
+        }
+        Ok(connection)
+    }
+
+    fn connect(cli: &Cli) -> Result<Self, Failure> {
+        if cli.simulator {
+            let (simulator, comm) = SwordFishSimulator::connect();
+            return Ok(Connection {
+                comm,
+                _simulator: Some(simulator),
+            });
+        }
+        if let Some(path) = &cli.replay {
+            let session = Session::load(path).map_err(|e| Failure::new(exit_code::USAGE, e.to_string()))?;
+            //the answers wait for their request, the commands run the same as when they were recorded
+            let replay = ReplayTransport::new(session)
+                .speed(cli.replay_speed)
+                .map_err(|e| Failure::new(exit_code::USAGE, e.to_string()))?
+                .sync_on_tx(true);
+            return Ok(Connection {
+                comm: SwordFishComm::from_transport(Box::new(replay)),
+                _simulator: None,
+            });
+        }
+        let port = match &cli.port {
+            Some(port) => port.clone(),
+            None => find_probable_swordfish_port()
+                .ok_or_else(|| Failure::new(exit_code::NO_DEVICE, "No swordfish found, pass its port with --port"))?,
+        };
+        log::info!("Using {}", redact_port_url(&port));
+        let comm = SwordFishComm::new(&port)
+            .map_err(|e| Failure::new(exit_code::NO_DEVICE, format!("Could not open {}: {}", redact_port_url(&port), e)))?;
+        Ok(Connection { comm, _simulator: None })
+    }
+
+    fn counter(&self) -> u16 {
+        self.comm.next_tx_counter()
+    }
+}
+
+//a message name of the registry or an opcode, decimal or 0x..
+fn resolve_opcode(registry: &MessageRegistry, message: &str) -> Result<u8, Failure> {
+    if let Some(info) = registry.find(message) {
+        return Ok(info.opcode);
+    }
+    let opcode = match message.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => message.parse().ok(),
+    };
+    match opcode {
+        Some(opcode) if registry.get(opcode).is_some() => Ok(opcode),
+        Some(opcode) => Err(Failure::new(exit_code::USAGE, format!("Opcode {} is not a known message", opcode))),
+        None => Err(Failure::new(exit_code::USAGE, format!("Unknown message {}", message))),
+    }
+}
+
+fn run(cli: &Cli) -> Result<u8, Failure> {
+    let timeout = Duration::from_millis(cli.timeout);
+    match &cli.command {
+        Command::Ports { swordfish, probe } => ports(cli, *swordfish, probe.map(Duration::from_millis)),
+        Command::Ping { count, interval } => ping(cli, &Connection::open(cli)?, *count, Duration::from_millis(*interval), timeout),
+        Command::Version => version(cli, &Connection::open(cli)?, timeout),
+        Command::Send { message, payload, counter } => send(cli, &Connection::open(cli)?, message, payload, *counter, timeout),
+        Command::Monitor { messages, stream, duration, count, capture } => monitor(
+            cli,
+            &Connection::open(cli)?,
+            messages,
+            stream,
+            duration.map(Duration::from_secs_f64),
+            *count,
+            capture.as_deref(),
+        ),
+        Command::Dissector { output } => dissector(output.as_deref()),
+    }
+}
+
+fn ports(cli: &Cli, swordfish_only: bool, probe_timeout: Option<Duration>) -> Result<u8, Failure> {
+    let config = DiscoveryConfig::default();
+    let mut ports: Vec<PortInfo> = get_serial_ports()
+        .ok_or_else(|| Failure::new(exit_code::ERROR, "Could not list the serial ports"))?
+        .into_iter()
+        .map(|mut port| {
+            port.matcher = config.match_port(&port).map(|name| name.to_string());
+            port
+        })
+        .filter(|port| !swordfish_only || port.matcher.is_some())
+        .collect();
+    if let Some(timeout) = probe_timeout {
+        for port in ports.iter_mut().filter(|port| port.matcher.is_some()) {
+            port.confirmed = probe(&port.port_name, timeout);
+        }
+        if swordfish_only {
+            ports.retain(|port| port.confirmed);
+        }
+    }
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&ports).expect("a port is plain data"));
+    } else {
+        for port in &ports {
+            let mut line = format!("{:<16} {:?}", port.port_name, port.kind);
+            if let (Some(vid), Some(pid)) = (port.vid, port.pid) {
+                line.push_str(&format!(" {:04x}:{:04x}", vid, pid));
+            }
+            for text in [&port.manufacturer, &port.product, &port.serial_number].into_iter().flatten() {
+                line.push_str(&format!(" {}", text));
+            }
+            if let Some(matcher) = &port.matcher {
+                line.push_str(&format!(" [swordfish: {}{}]", matcher, if port.confirmed { ", answers" } else { "" }));
+            }
+            println!("{}", line);
+        }
+    }
+    Ok(if ports.is_empty() { exit_code::NO_DEVICE } else { exit_code::OK })
+}
+
+fn ping(cli: &Cli, connection: &Connection, count: u32, interval: Duration, timeout: Duration) -> Result<u8, Failure> {
+    let mut rtts_ms: Vec<f64> = Vec::new();
+    for i in 0..count {
+        if i > 0 {
+            std::thread::sleep(interval);
+        }
+        let request = Ping::default().to_concentrated(connection.counter());
+        let start = Instant::now();
+        match connection.comm.request_with_timeout(request, timeout) {
+            Ok(_) => {
+                let rtt_ms = start.elapsed().as_secs_f64() * 1000.0;
+                if !cli.json {
+                    println!("reply #{}: time={:.3} ms", request.counter, rtt_ms);
+                }
+                rtts_ms.push(rtt_ms);
+            }
+            Err(SwordFishError::Timeout { .. }) => {
+                if !cli.json {
+                    println!("no reply #{}", request.counter);
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let received = rtts_ms.len();
+    let loss_percent = if count == 0 { 0.0 } else { 100.0 * (count as usize - received) as f64 / count as f64 };
+    let min = rtts_ms.iter().copied().reduce(f64::min);
+    let max = rtts_ms.iter().copied().reduce(f64::max);
+    let avg = (received > 0).then(|| rtts_ms.iter().sum::<f64>() / received as f64);
+    let stddev = avg.map(|avg| (rtts_ms.iter().map(|rtt| (rtt - avg).powi(2)).sum::<f64>() / received as f64).sqrt());
+    if cli.json {
+        println!(
+            "{}",
+            json!({
+                "sent": count,
+                "received": received,
+                "loss_percent": loss_percent,
+                "min_ms": min,
+                "avg_ms": avg,
+                "max_ms": max,
+                "stddev_ms": stddev,
+                "rtt_ms": rtts_ms,
+            })
+        );
+    } else {
+        println!("{} sent, {} received, {:.1}% loss", count, received, loss_percent);
+        if let (Some(min), Some(avg), Some(max), Some(stddev)) = (min, avg, max, stddev) {
+            println!("round trip min/avg/max/stddev = {:.3}/{:.3}/{:.3}/{:.3} ms", min, avg, max, stddev);
+        }
+    }
+    Ok(if received == 0 && count > 0 { exit_code::TIMEOUT } else { exit_code::OK })
+}
+
+fn version(cli: &Cli, connection: &Connection, timeout: Duration) -> Result<u8, Failure> {
+    let answer = connection
+        .comm
+        .request_with_timeout(VersionData::default().to_concentrated(connection.counter()), timeout)?;
+    let version = VersionData::from_concentrated(&answer)?;
+    if cli.json {
+        println!(
+            "{}",
+            json!({
+                "version": version.get_version(),
+                "subversion": version.get_subversion(),
+                "mcu_type": version.get_mcu_type(),
+                "uuid": to_hex(&version.get_uuid()),
+            })
+        );
+    } else {
+        println!("version   {}.{}", version.get_version(), version.get_subversion());
+        println!("mcu type  0x{:08x}", version.get_mcu_type());
+        println!("uuid      {}", to_hex(&version.get_uuid()));
+    }
+    Ok(exit_code::OK)
+}
+
+fn send(
+    cli: &Cli,
+    connection: &Connection,
+    message: &str,
+    payload: &str,
+    counter: Option<u16>,
+    timeout: Duration,
+) -> Result<u8, Failure> {
+    let registry = connection.comm.registry();
+    let opcode = resolve_opcode(&registry, message)?;
+    let payload = from_hex(payload).map_err(|e| Failure::new(exit_code::USAGE, e.to_string()))?;
+    if payload.len() > MAX_PAYLOAD_SIZE {
+        return Err(Failure::new(
+            exit_code::USAGE,
+            format!("The payload has {} bytes, at most {} fit in a message", payload.len(), MAX_PAYLOAD_SIZE),
+        ));
+    }
+    let request = SwordFishConcentratedMessage::new(counter.unwrap_or(connection.counter()), opcode, &payload);
+    match connection.comm.request_with_timeout(request, timeout) {
+        Ok(answer) => {
+            if cli.json {
+                println!("{}", output::message_json(&registry, &answer));
+            } else {
+                println!("{}", output::message_text(&registry, &answer));
+            }
+        }
+        //operations without a response, streams and responses are sent without waiting
+        Err(SwordFishError::NoAnswerExpected { .. }) => {
+            if cli.json {
+                println!("{}", json!({ "sent": output::message_json(&registry, &request) }));
+            } else {
+                println!("sent {}", output::message_text(&registry, &request));
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(exit_code::OK)
+}
+
+fn monitor(
+    cli: &Cli,
+    connection: &Connection,
+    messages: &[String],
+    streams: &[String],
+    duration: Option<Duration>,
+    count: Option<usize>,
+    capture: Option<&Path>,
+) -> Result<u8, Failure> {
+    let registry = connection.comm.registry();
+    if let Some(path) = capture {
+        connection.comm.start_capture(path)?;
+    }
+    let filter = messages
+        .iter()
+        .map(|message| resolve_opcode(&registry, message))
+        .collect::<Result<Vec<u8>, Failure>>()?;
+    let mut started = Vec::new();
+    for stream in streams {
+        let (name, rate_hz) = match stream.split_once(':') {
+            Some((name, rate_hz)) => (
+                name,
+                rate_hz
+                    .parse()
+                    .map_err(|_| Failure::new(exit_code::USAGE, format!("Bad stream rate {}", rate_hz)))?,
+            ),
+            None => (stream.as_str(), 100),
+        };
+        let opcode = resolve_opcode(&registry, name)?;
+        if registry.get(opcode).map(|info| info.category) != Some(SwordFishMessageCategory::Stream) {
+            return Err(Failure::new(exit_code::USAGE, format!("{} is not a stream", name)));
+        }
+        connection.comm.start_stream(opcode, rate_hz)?;
+        started.push(opcode);
+    }
+
+    let (sender, receiver) = mpsc::channel::<(Duration, SwordFishConcentratedMessage)>();
+    let start = Instant::now();
+    let hook_id = connection.comm.add_rx_hook(Box::new(move |msg, _| {
+        let _ = sender.send((start.elapsed(), *msg));
+    }));
+    let deadline = duration.map(|duration| start + duration);
+    let mut shown = 0;
+    while count.is_none_or(|count| shown < count) {
+        let received = match deadline {
+            Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok(),
+            None => receiver.recv().ok(),
+        };
+        let (time, msg) = match received {
+            Some(received) => received,
+            None => break,
+        };
+        if !filter.is_empty() && !filter.contains(&msg.opcode) {
+            continue;
+        }
+        if cli.json {
+            let mut value: Value = output::message_json(&registry, &msg);
+            value["time"] = json!(time.as_secs_f64());
+            println!("{}", value);
+        } else {
+            println!("{:>10.6} {}", time.as_secs_f64(), output::message_text(&registry, &msg));
+        }
+        shown += 1;
+    }
+    connection.comm.remove_rx_hook(hook_id);
+    for opcode in started {
+        if let Err(e) = connection.comm.stop_stream(opcode) {
+            log::warn!("Could not stop stream {}: {}", opcode, e);
+        }
+    }
+    if capture.is_some() {
+        let frames = connection.comm.stop_capture()?;
+        log::info!("Captured {} frames", frames);
+    }
+    Ok(if count.is_some_and(|count| shown < count) { exit_code::TIMEOUT } else { exit_code::OK })
+}
+
+fn dissector(output: Option<&Path>) -> Result<u8, Failure> {
+    let lua = generate_dissector(&MessageRegistry::new());
+    match output {
+        Some(path) => std::fs::write(path, lua)
+            .map_err(|e| Failure::new(exit_code::ERROR, format!("Could not write {}: {}", path.display(), e)))?,
+        None => print!("{}", lua),
+    }
+    Ok(exit_code::OK)
+}
+
+//the library logs to the log crate, shown on stderr so it does not mix with the output
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{:<5} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let level = match cli.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+    let exit_code = match run(&cli) {
+        Ok(exit_code) => exit_code,
+        Err(failure) => {
+            if cli.json {
+                println!("{}", json!({ "error": failure.message, "exit_code": failure.exit_code }));
+            } else {
+                eprintln!("swordfish: {}", failure.message);
+            }
+            failure.exit_code
+        }
+    };
+    ExitCode::from(exit_code)
+}
@@ -0,0 +1,46 @@
+//how the commands show messages, one line for people and one json object for scripts
+use serde_json::{json, Map, Value};
+use swordfish_com::swordfish_format::FrameFormatter;
+use swordfish_com::swordfish_util::to_hex;
+use swordfish_com::{MessageRegistry, SwordFishConcentratedMessage, MAX_PAYLOAD_SIZE};
+
+fn payload(msg: &SwordFishConcentratedMessage) -> &[u8] {
+    &msg.payload[..std::cmp::min(msg.length as usize, MAX_PAYLOAD_SIZE)]
+}
+
+//"VersionData (opcode 2, bounce) #17, 14 bytes, checksum ok version=1 subversion=0 mcu_type=0 uuid=5f5f5f5f5f5f5f5f",
+//with the payload in hex only for unknown opcodes
+pub fn message_text(registry: &MessageRegistry, msg: &SwordFishConcentratedMessage) -> String {
+    FrameFormatter {
+        single_line: true,
+        hexdump: registry.get(msg.opcode).is_none(),
+        ..FrameFormatter::new(registry)
+    }
+    .format(msg)
+}
+
+pub fn message_json(registry: &MessageRegistry, msg: &SwordFishConcentratedMessage) -> Value {
+    let info = registry.get(msg.opcode);
+    let mut value = json!({
+        "opcode": msg.opcode,
+        "name": info.map(|info| info.name.clone()),
+        "category": info.map(|info| info.category.name()),
+        "counter": msg.counter,
+        "length": msg.length,
+        "payload": to_hex(payload(msg)),
+        "fields": Value::Null,
+    });
+    if let Some(info) = info {
+        match info.decode_fields(payload(msg)) {
+            Ok(fields) => {
+                let fields: Map<String, Value> = fields
+                    .into_iter()
+                    .map(|(name, field)| (name, serde_json::to_value(field).expect("a field value is plain data")))
+                    .collect();
+                value["fields"] = Value::Object(fields);
+            }
+            Err(e) => value["error"] = json!(e.to_string()),
+        }
+    }
+    value
+}
@@ -0,0 +1,69 @@
+use serde_json::Value;
+use std::process::Command;
+
+//runs the tool against the built-in simulator, returns the exit code and stdout
+fn swordfish(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_swordfish"))
+        .arg("--simulator")
+        .args(args)
+        .output()
+        .expect("Could not run swordfish");
+    (output.status.code().expect("swordfish was killed"), String::from_utf8(output.stdout).unwrap())
+}
+
+fn json(stdout: &str) -> Value {
+    serde_json::from_str(stdout.trim()).expect("The output is not json")
+}
+
+#[test]
+fn answers_are_decoded() {
+    let (code, stdout) = swordfish(&["--json", "version"]);
+    assert_eq!(code, 0);
+    assert_eq!(json(&stdout)["uuid"], "5f5f5f5f5f5f5f5f");
+
+    let (code, stdout) = swordfish(&["--json", "ping", "-n", "2", "-i", "1"]);
+    assert_eq!(code, 0);
+    assert_eq!(json(&stdout)["received"], 2);
+
+    let (code, stdout) = swordfish(&["--json", "send", "StreamStart", "640a00"]);
+    assert_eq!(code, 0);
+    let answer = json(&stdout);
+    assert_eq!(answer["name"], "StreamStart");
+    assert_eq!(answer["fields"]["rate_hz"], 10);
+
+    let (code, stdout) = swordfish(&["monitor", "--stream", "ImuSample:500", "-n", "2", "-d", "1"]);
+    assert_eq!(code, 0);
+    assert_eq!(stdout.lines().filter(|line| line.contains("ImuSample (opcode 100, stream)")).count(), 2);
+
+    let (code, stdout) = swordfish(&["dissector"]);
+    assert_eq!(code, 0);
+    assert!(stdout.contains("Proto(\"swordfish\", \"SwordFish\")"));
+}
+
+#[test]
+fn failures_have_their_exit_code() {
+    //not a stream, the simulator answers with a Nack
+    let (code, stdout) = swordfish(&["--json", "send", "StreamStart", "020a00"]);
+    assert_eq!(code, 5);
+    assert!(json(&stdout)["error"].as_str().unwrap().contains("bad_parameter"));
+    //unknown message
+    assert_eq!(swordfish(&["send", "200"]).0, 2);
+    assert_eq!(swordfish(&["send", "Ping", "xyz"]).0, 2);
+    //a message that never arrives
+    assert_eq!(swordfish(&["monitor", "-m", "DeviceLog", "-n", "1", "-d", "0.05"]).0, 4);
+}
+
+#[test]
+fn recorded_sessions_replay_without_a_board() {
+    let session = std::env::temp_dir().join(format!("swordfish_cli_{}.session", std::process::id()));
+    let session = session.to_str().unwrap();
+    let (code, recorded) = swordfish(&["--record", session, "--json", "version"]);
+    assert_eq!(code, 0);
+    let replayed = Command::new(env!("CARGO_BIN_EXE_swordfish"))
+        .args(["--replay", session, "--replay-speed", "10", "--json", "version"])
+        .output()
+        .expect("Could not run swordfish");
+    std::fs::remove_file(session).unwrap();
+    assert_eq!(replayed.status.code(), Some(0));
+    assert_eq!(json(&String::from_utf8(replayed.stdout).unwrap()), json(&recorded));
+}
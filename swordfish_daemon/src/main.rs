@@ -0,0 +1,156 @@
+//swordfish-daemon, shares one board between the programs of the machine. it owns the serial port, and every
+//program opens "unix://" (the default socket) or "unix:///path/to/socket" in place of the port:
+//  swordfish-daemon --port /dev/ttyUSB0
+//  swordfish --port unix:// version
+//  swordfish-tui --port "unix://?subscribe=ImuSample"
+//the first line printed is the socket path
+use clap::Parser;
+use serde_json::json;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_comm::find_probable_swordfish_port;
+use swordfish_com::swordfish_daemon::{default_socket_path, ensure_private_directory, DaemonConfig, SwordFishDaemon};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::{memory_link, SwordFishTransport};
+use swordfish_com::MessageRegistry;
+
+#[derive(Parser)]
+#[command(name = "swordfish-daemon", version, about = "Share a swordfish board between the programs of this machine")]
+struct Args {
+    /// Serial port of the board, the first port that looks like a swordfish when not given
+    #[arg(short, long)]
+    port: Option<String>,
+    /// Serve the built-in simulator instead of a board
+    #[arg(long, conflicts_with = "port")]
+    simulator: bool,
+    /// Path of the unix socket, $XDG_RUNTIME_DIR/swordfish.sock when not given
+    #[arg(short, long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+    /// Release the firmware update lock of a client that sent nothing for this many seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 30.0)]
+    lock_timeout: f64,
+    /// Print json instead of text
+    #[arg(long)]
+    json: bool,
+    /// Stop after this many seconds
+    #[arg(short, long, value_name = "SECONDS")]
+    duration: Option<f64>,
+    /// -v for info, -vv for debug logs (on stderr)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+//the board or the simulator, its name for the clients, and the simulator that must live as long as the daemon
+fn open_device(args: &Args) -> anyhow::Result<(Box<dyn SwordFishTransport>, String, Option<SwordFishSimulator>)> {
+    if args.simulator {
+        let (device, simulator_port) = memory_link();
+        let simulator = SwordFishSimulator::spawn(Box::new(simulator_port));
+        return Ok((Box::new(device), "simulator".to_string(), Some(simulator)));
+    }
+    let port = match &args.port {
+        Some(port) => port.clone(),
+        None => find_probable_swordfish_port().ok_or_else(|| anyhow::anyhow!("No swordfish found, pass its port with --port"))?,
+    };
+    let device = serialport::new(&port, 115200)
+        .timeout(Duration::from_millis(1))
+        .open()
+        .map_err(|e| anyhow::anyhow!("Could not open {}: {}", port, e))?;
+    Ok((Box::new(device), port, None))
+}
+
+//a socket left behind by a daemon that did not stop cleanly is removed, a live one is not
+fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow::anyhow!("Another daemon listens on {}", path.display()));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path).map_err(|e| anyhow::anyhow!("Could not listen on {}: {}", path.display(), e))?;
+    //the user and the group of the daemon may use the board
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+    Ok(listener)
+}
+
+fn run(args: &Args) -> anyhow::Result<()> {
+    let path = match &args.socket {
+        Some(path) => path.clone(),
+        None => {
+            let path = default_socket_path();
+            if let Some(directory) = path.parent() {
+                ensure_private_directory(directory).map_err(|e| anyhow::anyhow!("{}: {}", directory.display(), e))?;
+            }
+            path
+        }
+    };
+    let (device, device_name, _simulator) = open_device(args)?;
+    let listener = bind(&path)?;
+    let config = DaemonConfig {
+        lock_timeout: Duration::from_secs_f64(args.lock_timeout),
+        ..DaemonConfig::default()
+    };
+    let daemon = SwordFishDaemon::spawn(listener, device, config, &MessageRegistry::new(), &device_name)?;
+    match args.json {
+        true => println!("{}", json!({ "socket": path, "device": device_name })),
+        false => println!("{}", path.display()),
+    }
+    std::io::stdout().flush()?;
+
+    let deadline = args.duration.map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+    let mut status = daemon.status();
+    while daemon.is_running() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        let current = daemon.status();
+        if current.clients != status.clients || current.exclusive_owner != status.exclusive_owner {
+            log::info!("Clients {:?}, locked by {:?}", current.clients, current.exclusive_owner);
+        }
+        status = current;
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let device_alive = daemon.is_running();
+    drop(daemon);
+    let _ = std::fs::remove_file(&path);
+    match device_alive {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!("{} was disconnected", device_name)),
+    }
+}
+
+//the library logs to the log crate, shown on stderr so it does not mix with the output
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{:<5} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let level = match args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("swordfish-daemon: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::VersionData;
+use swordfish_com::SwordFishMessageTrait;
+
+#[test]
+fn programs_share_the_board() {
+    let socket = std::env::temp_dir().join(format!("swordfish_daemon_{}.sock", std::process::id()));
+    let mut daemon = Command::new(env!("CARGO_BIN_EXE_swordfish-daemon"))
+        .args(["--simulator", "-d", "10", "--socket", socket.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run swordfish-daemon");
+    let mut path = String::new();
+    BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut path).unwrap();
+    assert_eq!(path.trim(), socket.to_str().unwrap());
+
+    //a second daemon does not take over the socket
+    let second = Command::new(env!("CARGO_BIN_EXE_swordfish-daemon"))
+        .args(["--simulator", "--socket", path.trim()])
+        .output()
+        .expect("Could not run swordfish-daemon");
+    assert!(!second.status.success());
+    assert!(String::from_utf8(second.stderr).unwrap().contains("Another daemon"));
+
+    let url = format!("unix://{}", path.trim());
+    let gui = SwordFishComm::new(&url).unwrap();
+    let logger = SwordFishComm::new(&url).unwrap();
+    assert!(gui.request(VersionData::default().to_concentrated(1)).is_ok());
+    assert!(logger.request(VersionData::default().to_concentrated(1)).is_ok());
+    drop((gui, logger));
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+    let _ = std::fs::remove_file(&socket);
+}
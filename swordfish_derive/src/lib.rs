@@ -0,0 +1,311 @@
+//derive macro for swordfish messages, see SwordFishMessageTrait in swordfish_com
+//usage:
+//  #[derive(Debug, Default, SwordFishMessage)]
+//  #[swordfish(opcode = 2, category = "bounce")]
+//  pub struct VersionData {...}
+//
+//categories are "bounce", "param", "response", "stream" and "operation",
+//operations can name the opcode of their response msg with `response = <opcode>`
+//
+//every message exports a symbol named after its opcode, so two messages with the same opcode fail the build,
+//or the link of the final binary when they are derived in different crates
+//
+//the payload is encoded field by field (see swordfish_wire), so every field has to implement
+//SwordFishWireField, and the largest possible payload is checked against MAX_PAYLOAD_SIZE at compile time
+//
+//fields appended in a later version of the message are marked with #[swordfish(since = <version>)],
+//they are filled with their default value when an older device sends a shorter payload.
+//unknown bytes after the last field (sent by a newer device) are ignored
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index, LitInt, LitStr};
+
+#[proc_macro_derive(SwordFishMessage, attributes(swordfish))]
+pub fn derive_swordfish_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct MessageAttributes {
+    opcode: u8,
+    category: String,
+    response: Option<u8>,
+}
+
+fn parse_message_attributes(input: &DeriveInput) -> syn::Result<MessageAttributes> {
+    let mut opcode: Option<u8> = None;
+    let mut category: Option<String> = None;
+    let mut response: Option<u8> = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("swordfish")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("opcode") {
+                opcode = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("category") {
+                category = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("response") {
+                response = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else {
+                return Err(meta.error("unknown swordfish attribute"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let opcode = opcode.ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, "missing #[swordfish(opcode = ...)]")
+    })?;
+    let category = category.ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, "missing #[swordfish(category = \"...\")]")
+    })?;
+    Ok(MessageAttributes {
+        opcode,
+        category,
+        response,
+    })
+}
+
+fn category_tokens(input: &DeriveInput, attrs: &MessageAttributes) -> syn::Result<TokenStream2> {
+    let category = quote!(::swordfish_com::SwordFishMessageCategory);
+    if attrs.response.is_some() && attrs.category != "operation" {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`response` is only valid for the \"operation\" category",
+        ));
+    }
+    match attrs.category.as_str() {
+        "bounce" => Ok(quote!(#category::Bounce)),
+        "param" => Ok(quote!(#category::Param)),
+        "response" => Ok(quote!(#category::Response)),
+        "stream" => Ok(quote!(#category::Stream)),
+        "operation" => match attrs.response {
+            Some(response) => Ok(quote!(#category::Operation(Some(#response)))),
+            None => Ok(quote!(#category::Operation(None))),
+        },
+        other => Err(syn::Error::new_spanned(
+            &input.ident,
+            format!(
+                "unknown category \"{}\", expected one of \"bounce\", \"param\", \"operation\", \"response\", \"stream\"",
+                other
+            ),
+        )),
+    }
+}
+
+fn is_packed(input: &DeriveInput) -> bool {
+    input.attrs.iter().filter(|a| a.path().is_ident("repr")).any(|a| {
+        let mut packed = false;
+        let _ = a.parse_nested_meta(|meta| {
+            if meta.path.is_ident("packed") {
+                packed = true;
+            }
+            if meta.input.peek(syn::token::Paren) {
+                let _content;
+                syn::parenthesized!(_content in meta.input);
+            }
+            Ok(())
+        });
+        packed
+    })
+}
+
+//the version a field was added in, 1 if it has no #[swordfish(since = ...)]
+fn field_since(field: &syn::Field) -> syn::Result<u8> {
+    let mut since = 1;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("swordfish")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("since") {
+                since = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                if since == 0 {
+                    return Err(meta.error("versions start at 1"));
+                }
+                Ok(())
+            } else {
+                Err(meta.error("unknown swordfish field attribute"))
+            }
+        })?;
+    }
+    Ok(since)
+}
+
+//encode_payload, decode_payload, get_payload_length, VERSION and FIELDS, field by field in declaration order
+fn codec_tokens(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let wire = quote!(::swordfish_com::swordfish_wire::SwordFishWireField);
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "SwordFishMessage can only be derived for structs",
+            ))
+        }
+    };
+
+    let members: Vec<TokenStream2> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|f| {
+                let ident = f.ident.as_ref().expect("named field");
+                quote!(#ident)
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote!(#index)
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+    let types: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+    let names: Vec<String> = members.iter().map(|m| m.to_string()).collect();
+
+    //optional fields can only be appended, a shorter payload must cut the message at a version boundary
+    let mut sinces = Vec::new();
+    for field in fields.iter() {
+        let since = field_since(field)?;
+        if sinces.last().is_some_and(|last| since < *last) {
+            return Err(syn::Error::new_spanned(
+                field,
+                "fields must be ordered by their `since` version, newer fields go last",
+            ));
+        }
+        sinces.push(since);
+    }
+    let version = sinces.iter().copied().max().unwrap_or(1);
+
+    let locals: Vec<syn::Ident> = (0..members.len())
+        .map(|i| quote::format_ident!("field_{}", i))
+        .collect();
+
+    //fields of packed structs can not be borrowed, they are copied out first
+    let field_refs: Vec<TokenStream2> = if is_packed(input) {
+        members.iter().map(|m| quote!(&{ self.#m })).collect()
+    } else {
+        members.iter().map(|m| quote!(&self.#m)).collect()
+    };
+
+    let construct = match fields {
+        Fields::Named(_) => quote!(Self { #(#members: #locals),* }),
+        Fields::Unnamed(_) => quote!(Self(#(#locals),*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    let decode_fields: Vec<TokenStream2> = locals
+        .iter()
+        .zip(types.iter())
+        .zip(sinces.iter())
+        .map(|((local, ty), since)| {
+            if *since > 1 {
+                //the device runs an older version that does not send this field
+                quote! {
+                    let #local = if input.is_empty() {
+                        <#ty as ::std::default::Default>::default()
+                    } else {
+                        <#ty as #wire>::decode(&mut input)?
+                    };
+                }
+            } else {
+                quote!(let #local = <#ty as #wire>::decode(&mut input)?;)
+            }
+        })
+        .collect();
+
+    Ok(quote! {
+        const VERSION: u8 = #version;
+        const FIELDS: &'static [::swordfish_com::SwordFishFieldInfo] = &[
+            #(::swordfish_com::SwordFishFieldInfo {
+                name: #names,
+                field_type: <#types as #wire>::field_type,
+                max_size: <#types as #wire>::MAX_SIZE,
+                variable_length: <#types as #wire>::VARIABLE_LENGTH,
+                since: #sinces,
+            },)*
+        ];
+
+        fn get_payload_length() -> usize {
+            0 #(+ <#types as #wire>::MAX_SIZE)*
+        }
+
+        fn encode_payload(&self) -> ::std::vec::Vec<u8> {
+            #[allow(unused_mut)]
+            let mut buffer = ::std::vec::Vec::with_capacity(<Self as ::swordfish_com::SwordFishMessageTrait>::get_payload_length());
+            #(#wire::encode(#field_refs, &mut buffer);)*
+            buffer
+        }
+
+        fn decode_payload(payload: &[u8]) -> ::swordfish_com::__private::anyhow::Result<Self> {
+            #[allow(unused_mut)]
+            let mut input = payload;
+            #(#decode_fields)*
+            //anything left was appended by a newer version of the message
+            Ok(#construct)
+        }
+    })
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let attrs = parse_message_attributes(input)?;
+    let category = category_tokens(input, &attrs)?;
+    let codec = codec_tokens(input)?;
+    let types: Vec<&syn::Type> = match &input.data {
+        Data::Struct(data) => data.fields.iter().map(|f| &f.ty).collect(),
+        _ => Vec::new(),
+    };
+    let name = &input.ident;
+    let opcode = attrs.opcode;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    //generic messages can not be checked in a const item
+    let size_check = if input.generics.params.is_empty() {
+        quote! {
+            const _: () = assert!(
+                0 #(+ <#types as ::swordfish_com::swordfish_wire::SwordFishWireField>::MAX_SIZE)* <= ::swordfish_com::MAX_PAYLOAD_SIZE,
+                "the largest payload of this message does not fit in MAX_PAYLOAD_SIZE"
+            );
+        }
+    } else {
+        quote!()
+    };
+
+    let opcode_symbol = format!("__swordfish_opcode_{}", opcode);
+    //the unit tests of swordfish_com also link the library itself through its dev-dependency, so its own
+    //messages would be there twice
+    let in_own_tests = match std::env::var("CARGO_CRATE_NAME").as_deref() {
+        Ok("swordfish_com") => quote!(#[cfg(not(test))]),
+        _ => quote!(),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::swordfish_com::SwordFishMessageTrait for #name #ty_generics #where_clause {
+            const OPCODE: u8 = #opcode;
+            const CATEGORY: ::swordfish_com::SwordFishMessageCategory = #category;
+            #codec
+        }
+
+        #size_check
+
+        #in_own_tests
+        const _: () = {
+            #[used]
+            #[unsafe(export_name = #opcode_symbol)]
+            static OPCODE_TAKEN: u8 = #opcode;
+        };
+
+        ::swordfish_com::__private::inventory::submit! {
+            ::swordfish_com::SwordFishMessageRegistration {
+                name: stringify!(#name),
+                opcode: #opcode,
+                category: #category,
+                version: <#name as ::swordfish_com::SwordFishMessageTrait>::VERSION,
+                fields: <#name as ::swordfish_com::SwordFishMessageTrait>::FIELDS,
+                payload_length: <#name as ::swordfish_com::SwordFishMessageTrait>::get_payload_length,
+            }
+        }
+    })
+}
@@ -0,0 +1,504 @@
+//the gateway serves a SwordFishComm to web pages and scripts, in json. plain http has the schema of the messages:
+//  GET /schema      MessageRegistry::json_schema, the fields object of every message
+//  GET /registry    MessageRegistry::to_json, opcodes, categories and field layouts
+//a websocket on any other path takes one json object per text message, and answers with the "id" it was given:
+//  {"type": "request", "id": 1, "message": "VersionData", "fields": {}, "timeout_ms": 200}
+//      -> {"type": "response", "id": 1, "message": {...}}, or {"type": "sent", ...} when no answer is expected
+//  {"type": "subscribe", "id": 2, "message": "ImuSample", "rate_hz": 100}
+//      -> {"type": "subscribed", "id": 2, ...}, then {"type": "message", "message": {...}} for every one received
+//  {"type": "unsubscribe", "id": 3, "message": "ImuSample"}
+//  {"type": "schema", "id": 4}
+//failures are {"type": "error", "id": 1, "error": "..."}, with the "code" of the Nack when the device rejected it.
+//messages are named like the registry, or given by opcode.
+//websockets opened by a page must come from an allowed origin, and pass the token as ws://host/?token=...
+//subscribed messages wait in a bounded queue per client, and are dropped when the client does not keep up
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_comm::{RxHook, SwordFishComm};
+use swordfish_com::swordfish_error::SwordFishError;
+use swordfish_com::swordfish_util::to_hex;
+use swordfish_com::{MessageInfo, MessageRegistry, SwordFishConcentratedMessage, SwordFishMessageCategory, MAX_PAYLOAD_SIZE};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_GATEWAY_PORT: u16 = 5758;
+pub const GATEWAY_TOKEN_VARIABLE: &str = "SWORDFISH_GATEWAY_TOKEN";
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(200);
+//so a command can not hold its connection thread and the comm for minutes
+const MAX_TIMEOUT_MS: u64 = 10_000;
+//the subscribed messages waiting for a client, about a second of a fast stream
+const CLIENT_QUEUE_SIZE: usize = 256;
+
+//who may open a websocket: pages from these origins, and clients that know the token when there is one.
+//clients that send no Origin, like scripts, are not pages and only need the token
+#[derive(Clone, Default)]
+pub struct GatewayAccess {
+    pub allowed_origins: Vec<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessageRef {
+    Opcode(u8),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Command {
+    Request {
+        message: MessageRef,
+        #[serde(default)]
+        fields: Value,
+        timeout_ms: Option<u64>,
+    },
+    Subscribe {
+        message: MessageRef,
+        rate_hz: Option<u16>,
+    },
+    Unsubscribe {
+        message: MessageRef,
+    },
+    Schema,
+}
+
+struct Client {
+    outbox: SyncSender<String>,
+    subscriptions: HashSet<u8>,
+    //the messages dropped because the queue of the client was full
+    dropped: usize,
+}
+
+type Clients = Arc<Mutex<HashMap<usize, Client>>>;
+
+struct GatewayShared {
+    comm: Arc<SwordFishComm>,
+    registry: MessageRegistry,
+    access: GatewayAccess,
+    clients: Clients,
+    //the streams the gateway started, with the clients that gave them a rate
+    streams: Mutex<HashMap<u8, HashSet<usize>>>,
+    next_client: AtomicUsize,
+    alive: AtomicBool,
+}
+
+pub struct Gateway {
+    shared: Arc<GatewayShared>,
+    local_addr: SocketAddr,
+    hook_id: usize,
+    accept_handle: Option<JoinHandle<()>>,
+}
+
+impl Gateway {
+    pub fn spawn(listener: TcpListener, comm: Arc<SwordFishComm>, access: GatewayAccess) -> anyhow::Result<Gateway> {
+        let local_addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let registry = comm.registry();
+        let clients = Clients::default();
+        let hook_id = comm.add_rx_hook(forward_subscribed(registry.clone(), clients.clone()));
+        let shared = Arc::new(GatewayShared {
+            comm,
+            registry,
+            access,
+            clients,
+            streams: Mutex::new(HashMap::new()),
+            next_client: AtomicUsize::new(0),
+            alive: AtomicBool::new(true),
+        });
+
+        let accept_shared = shared.clone();
+        let accept_handle = spawn(move || {
+            let mut connections: Vec<JoinHandle<()>> = Vec::new();
+            while accept_shared.alive.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        if let Err(e) = stream.set_nonblocking(false) {
+                            log::error!("{}", e);
+                            continue;
+                        }
+                        let shared = accept_shared.clone();
+                        connections.push(spawn(move || serve_connection(&shared, stream, peer)));
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(Duration::from_millis(10)),
+                    Err(e) => {
+                        log::error!("The gateway stopped accepting: {}", e);
+                        break;
+                    }
+                }
+                connections.retain(|connection| !connection.is_finished());
+            }
+            for connection in connections {
+                let _ = connection.join();
+            }
+        });
+        Ok(Gateway {
+            shared,
+            local_addr,
+            hook_id,
+            accept_handle: Some(accept_handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    //the websocket clients connected now
+    pub fn clients(&self) -> usize {
+        self.shared.clients.lock().expect("Another thread holding the mutex panicked").len()
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        self.shared.alive.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.accept_handle.take() {
+            handle.join().expect("The accept thread of the gateway could not be joined");
+        }
+        self.shared.comm.remove_rx_hook(self.hook_id);
+    }
+}
+
+//the opcode, name, category and counter of the message, with its fields keyed by name
+pub fn message_json(registry: &MessageRegistry, msg: &SwordFishConcentratedMessage) -> Value {
+    let payload = &msg.payload[..std::cmp::min(msg.length as usize, MAX_PAYLOAD_SIZE)];
+    let info = registry.get(msg.opcode);
+    let mut value = json!({
+        "opcode": msg.opcode,
+        "name": info.map(|info| info.name.clone()),
+        "category": info.map(|info| info.category.name()),
+        "counter": msg.counter,
+        "length": msg.length,
+        "payload": to_hex(payload),
+        "fields": Value::Null,
+    });
+    if let Some(info) = info {
+        match info.decode_json(payload) {
+            Ok(fields) => value["fields"] = fields,
+            Err(e) => value["error"] = json!(e.to_string()),
+        }
+    }
+    value
+}
+
+//sends every received message to the clients subscribed to its opcode, decoded once for all of them
+fn forward_subscribed(registry: MessageRegistry, clients: Clients) -> RxHook {
+    Box::new(move |msg, _| {
+        let mut clients = clients.lock().expect("Another thread holding the mutex panicked");
+        let mut text = None;
+        for client in clients.values_mut().filter(|client| client.subscriptions.contains(&msg.opcode)) {
+            let text = text.get_or_insert_with(|| json!({ "type": "message", "message": message_json(&registry, msg) }).to_string());
+            //a client that left is removed by its own thread
+            if let Err(TrySendError::Full(_)) = client.outbox.try_send(text.clone()) {
+                client.dropped += 1;
+            }
+        }
+    })
+}
+
+fn serve_connection(shared: &GatewayShared, stream: TcpStream, peer: SocketAddr) {
+    let result = match peek_head(&stream) {
+        Ok((head, _)) if is_websocket(&head) => serve_websocket(shared, stream, peer),
+        Ok((head, head_len)) => serve_http(shared, stream, &head, head_len),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log::debug!("{}: {}", peer, e);
+    }
+}
+
+//the request line and headers and their length with the blank line, peeked so the websocket handshake still reads them
+fn peek_head(stream: &TcpStream) -> anyhow::Result<(String, usize)> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = stream.peek(&mut buffer)?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Closed before sending a request"));
+        }
+        if let Some(end) = buffer[..n].windows(4).position(|window| window == b"\r\n\r\n") {
+            return Ok((String::from_utf8_lossy(&buffer[..end]).into_owned(), end + 4));
+        }
+        if n == buffer.len() || Instant::now() > deadline {
+            return Err(anyhow::anyhow!("No complete http request"));
+        }
+        sleep(Duration::from_millis(5));
+    }
+}
+
+fn is_websocket(head: &str) -> bool {
+    head.lines().skip(1).any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("upgrade:") && line.contains("websocket")
+    })
+}
+
+fn serve_http(shared: &GatewayShared, mut stream: TcpStream, head: &str, head_len: usize) -> anyhow::Result<()> {
+    stream.read_exact(&mut vec![0; head_len])?;
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("").split('?').next().unwrap_or("");
+    let (status, body) = match (method, path) {
+        ("GET", "/schema") => ("200 OK", serde_json::to_string_pretty(&shared.registry.json_schema())?),
+        ("GET", "/registry") => ("200 OK", shared.registry.to_json()),
+        ("GET", _) => ("404 Not Found", json!({ "error": format!("No {}, try /schema or /registry", path) }).to_string()),
+        _ => ("405 Method Not Allowed", json!({ "error": format!("{} is not supported", method) }).to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+//the error response of the handshake callback is the type tungstenite asks for
+#[allow(clippy::result_large_err)]
+fn serve_websocket(shared: &GatewayShared, stream: TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+    let mut websocket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| match check_access(&shared.access, request) {
+        Ok(()) => Ok(response),
+        Err((status, reason)) => {
+            log::warn!("{} refused: {}", peer, reason);
+            let mut refusal = ErrorResponse::new(Some(reason.to_string()));
+            *refusal.status_mut() = status;
+            Err(refusal)
+        }
+    })
+    .map_err(|e| anyhow::anyhow!("Websocket handshake failed: {}", e))?;
+    //short reads, so the subscribed messages are sent while the client is quiet
+    websocket.get_ref().set_read_timeout(Some(Duration::from_millis(10)))?;
+    let client = shared.next_client.fetch_add(1, Ordering::Relaxed);
+    let (outbox, inbox) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+    shared
+        .clients
+        .lock()
+        .expect("Another thread holding the mutex panicked")
+        .insert(client, Client { outbox, subscriptions: HashSet::new(), dropped: 0 });
+    log::info!("{} connected", peer);
+    let result = serve_commands(shared, client, &mut websocket, &inbox);
+    leave(shared, client);
+    log::info!("{} left", peer);
+    result
+}
+
+//the status and reason of the refusal when the websocket is not from an allowed origin or lacks the token
+fn check_access(access: &GatewayAccess, request: &Request) -> Result<(), (StatusCode, &'static str)> {
+    if let Some(origin) = request.headers().get("origin") {
+        let origin = origin.to_str().unwrap_or("");
+        if !access.allowed_origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin)) {
+            return Err((StatusCode::FORBIDDEN, "Origin not allowed"));
+        }
+    }
+    if let Some(token) = &access.token {
+        let given = request
+            .uri()
+            .query()
+            .unwrap_or("")
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+            .unwrap_or("");
+        if !same_token(given.as_bytes(), token.as_bytes()) {
+            return Err((StatusCode::UNAUTHORIZED, "Wrong or missing token"));
+        }
+    }
+    Ok(())
+}
+
+//compares every byte, so the time taken does not tell how much of the token was right
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+fn serve_commands(
+    shared: &GatewayShared,
+    client: usize,
+    websocket: &mut WebSocket<TcpStream>,
+    inbox: &Receiver<String>,
+) -> anyhow::Result<()> {
+    while shared.alive.load(Ordering::Relaxed) {
+        match websocket.read() {
+            Ok(Message::Text(text)) => {
+                let answer = handle_command(shared, client, text.as_str());
+                websocket.send(Message::text(answer.to_string()))?;
+            }
+            Ok(Message::Binary(_)) => {
+                let answer = json!({ "type": "error", "id": Value::Null, "error": "Commands are json text messages" });
+                websocket.send(Message::text(answer.to_string()))?;
+            }
+            //pings and closes are answered by tungstenite on the next read
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        while let Ok(text) = inbox.try_recv() {
+            websocket.send(Message::text(text))?;
+        }
+    }
+    let _ = websocket.close(None);
+    let _ = websocket.flush();
+    Ok(())
+}
+
+//the answer to one command, with its id
+fn handle_command(shared: &GatewayShared, client: usize, text: &str) -> Value {
+    let command: Value = match serde_json::from_str(text) {
+        Ok(command) => command,
+        Err(e) => return json!({ "type": "error", "id": Value::Null, "error": format!("Not json: {}", e) }),
+    };
+    let id = command.get("id").cloned().unwrap_or(Value::Null);
+    let result = serde_json::from_value::<Command>(command)
+        .map_err(|e| anyhow::anyhow!("Invalid command: {}", e))
+        .and_then(|command| run_command(shared, client, command));
+    let mut answer = match result {
+        Ok(answer) => answer,
+        Err(e) => {
+            let mut answer = json!({ "type": "error", "error": e.to_string() });
+            if let Some(SwordFishError::DeviceRejected { code, .. }) = e.downcast_ref::<SwordFishError>() {
+                answer["code"] = json!(code);
+            }
+            answer
+        }
+    };
+    answer["id"] = id;
+    answer
+}
+
+fn resolve<'a>(registry: &'a MessageRegistry, message: &MessageRef) -> anyhow::Result<&'a MessageInfo> {
+    match message {
+        MessageRef::Opcode(opcode) => registry.get(*opcode).ok_or_else(|| anyhow::anyhow!("Unknown opcode {}", opcode)),
+        MessageRef::Name(name) => registry.find(name).ok_or_else(|| anyhow::anyhow!("Unknown message {}", name)),
+    }
+}
+
+fn run_command(shared: &GatewayShared, client: usize, command: Command) -> anyhow::Result<Value> {
+    match command {
+        Command::Schema => Ok(json!({ "type": "schema", "schema": shared.registry.json_schema() })),
+        Command::Request { message, fields, timeout_ms } => {
+            let info = resolve(&shared.registry, &message)?;
+            let payload = info.encode_fields(&info.fields_from_json(&fields)?)?;
+            let request = SwordFishConcentratedMessage::new(shared.comm.next_tx_counter(), info.opcode, &payload);
+            if timeout_ms.is_some_and(|timeout_ms| timeout_ms > MAX_TIMEOUT_MS) {
+                return Err(anyhow::anyhow!("timeout_ms is at most {}", MAX_TIMEOUT_MS));
+            }
+            let timeout = timeout_ms.map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+            match shared.comm.request_with_timeout(request, timeout) {
+                Ok(answer) => Ok(json!({ "type": "response", "message": message_json(&shared.registry, &answer) })),
+                //operations without a response, streams and responses are sent without waiting
+                Err(SwordFishError::NoAnswerExpected { .. }) => {
+                    Ok(json!({ "type": "sent", "message": message_json(&shared.registry, &request) }))
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+        Command::Subscribe { message, rate_hz } => {
+            let info = resolve(&shared.registry, &message)?;
+            if let Some(rate_hz) = rate_hz {
+                if info.category != SwordFishMessageCategory::Stream {
+                    return Err(anyhow::anyhow!("{} is not a stream, only streams take a rate", info.name));
+                }
+                shared.comm.start_stream(info.opcode, rate_hz)?;
+                shared
+                    .streams
+                    .lock()
+                    .expect("Another thread holding the mutex panicked")
+                    .entry(info.opcode)
+                    .or_default()
+                    .insert(client);
+            }
+            if let Some(subscriber) = shared.clients.lock().expect("Another thread holding the mutex panicked").get_mut(&client) {
+                subscriber.subscriptions.insert(info.opcode);
+            }
+            Ok(json!({ "type": "subscribed", "message": info.name, "opcode": info.opcode }))
+        }
+        Command::Unsubscribe { message } => {
+            let info = resolve(&shared.registry, &message)?;
+            if let Some(subscriber) = shared.clients.lock().expect("Another thread holding the mutex panicked").get_mut(&client) {
+                subscriber.subscriptions.remove(&info.opcode);
+            }
+            release_stream(shared, client, info.opcode)?;
+            Ok(json!({ "type": "unsubscribed", "message": info.name, "opcode": info.opcode }))
+        }
+    }
+}
+
+//stops a stream the gateway started once no client gives it a rate any more
+fn release_stream(shared: &GatewayShared, client: usize, opcode: u8) -> anyhow::Result<()> {
+    let last = {
+        let mut streams = shared.streams.lock().expect("Another thread holding the mutex panicked");
+        let last = streams.get_mut(&opcode).is_some_and(|clients| clients.remove(&client) && clients.is_empty());
+        if last {
+            streams.remove(&opcode);
+        }
+        last
+    };
+    if last {
+        shared.comm.stop_stream(opcode)?;
+    }
+    Ok(())
+}
+
+fn leave(shared: &GatewayShared, client: usize) {
+    let left = shared.clients.lock().expect("Another thread holding the mutex panicked").remove(&client);
+    if let Some(left) = left.filter(|left| left.dropped > 0) {
+        log::warn!("Client {} did not keep up, {} messages were dropped", client, left.dropped);
+    }
+    let started: Vec<u8> = shared
+        .streams
+        .lock()
+        .expect("Another thread holding the mutex panicked")
+        .iter()
+        .filter(|(_, clients)| clients.contains(&client))
+        .map(|(opcode, _)| *opcode)
+        .collect();
+    for opcode in started {
+        if let Err(e) = release_stream(shared, client, opcode) {
+            log::warn!("Could not stop stream {}: {}", opcode, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn websocket_upgrades_are_told_from_plain_requests() {
+        assert!(is_websocket("GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: WebSocket\r\nConnection: Upgrade"));
+        assert!(!is_websocket("GET /schema HTTP/1.1\r\nHost: localhost"));
+        assert!(!is_websocket("GET /upgrade:websocket HTTP/1.1\r\nHost: localhost"));
+    }
+
+    #[test]
+    fn websockets_need_an_allowed_origin_and_the_token() {
+        let access = GatewayAccess { allowed_origins: vec!["http://localhost:8080".into()], token: Some("secret".into()) };
+        let request = |uri: &str, origin: Option<&str>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(origin) = origin {
+                request = request.header("Origin", origin);
+            }
+            request.body(()).unwrap()
+        };
+        assert!(check_access(&access, &request("/?token=secret", None)).is_ok());
+        assert!(check_access(&access, &request("/?a=1&token=secret", Some("http://LOCALHOST:8080"))).is_ok());
+        assert_eq!(check_access(&access, &request("/?token=secret", Some("http://evil.example"))).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert_eq!(check_access(&access, &request("/?token=secre", None)).unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert_eq!(check_access(&access, &request("/", None)).unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert!(check_access(&GatewayAccess::default(), &request("/", None)).is_ok());
+        assert!(check_access(&GatewayAccess::default(), &request("/", Some("null"))).is_err());
+    }
+}
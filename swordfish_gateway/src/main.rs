@@ -0,0 +1,147 @@
+//swordfish-gateway, serves a board to web pages and scripts as json over http and websocket (see gateway.rs):
+//  SWORDFISH_GATEWAY_TOKEN=secret swordfish-gateway --port /dev/ttyUSB0 --allow-origin http://localhost:8080
+//  swordfish-gateway --simulator --listen 127.0.0.1:0 --insecure
+//  curl http://127.0.0.1:5758/schema
+//--port also takes the tcp:// url of a bridge and the unix:// url of a daemon.
+//the first line printed is the address it listens on
+mod gateway;
+
+use clap::Parser;
+use gateway::{Gateway, GatewayAccess, DEFAULT_GATEWAY_PORT, GATEWAY_TOKEN_VARIABLE};
+use serde_json::json;
+use std::io::Write;
+use std::net::TcpListener;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_comm::{find_probable_swordfish_port, SwordFishComm};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+
+#[derive(Parser)]
+#[command(name = "swordfish-gateway", version, about = "Serve a swordfish board as json over http and websocket")]
+struct Args {
+    /// Serial port or url of the board, the first port that looks like a swordfish when not given
+    #[arg(short, long)]
+    port: Option<String>,
+    /// Serve the built-in simulator instead of a board
+    #[arg(long, conflicts_with = "port")]
+    simulator: bool,
+    /// Address to listen on
+    #[arg(short, long, default_value_t = format!("127.0.0.1:{}", DEFAULT_GATEWAY_PORT))]
+    listen: String,
+    /// Origin of the web pages that may open a websocket, like http://localhost:8080, can be repeated
+    #[arg(long, value_name = "ORIGIN")]
+    allow_origin: Vec<String>,
+    /// The token websocket clients pass as ws://host/?token=...
+    #[arg(long, env = GATEWAY_TOKEN_VARIABLE, hide_env_values = true)]
+    token: Option<String>,
+    /// Accept websocket clients without a token
+    #[arg(long, conflicts_with = "token")]
+    insecure: bool,
+    /// Print json instead of text
+    #[arg(long)]
+    json: bool,
+    /// Stop after this many seconds
+    #[arg(short, long, value_name = "SECONDS")]
+    duration: Option<f64>,
+    /// -v for info, -vv for debug logs (on stderr)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+fn access(args: &Args) -> anyhow::Result<GatewayAccess> {
+    let token = match &args.token {
+        Some(token) if token.is_empty() => return Err(anyhow::anyhow!("The token is empty")),
+        Some(token) => Some(token.clone()),
+        None if args.insecure => None,
+        None => {
+            return Err(anyhow::anyhow!(
+                "No token, pass --token or {}, or --insecure to accept any client",
+                GATEWAY_TOKEN_VARIABLE
+            ))
+        }
+    };
+    Ok(GatewayAccess { allowed_origins: args.allow_origin.clone(), token })
+}
+
+//the comm, and the simulator it talks to that must live as long as it
+fn connect(args: &Args) -> anyhow::Result<(Arc<SwordFishComm>, Option<SwordFishSimulator>)> {
+    if args.simulator {
+        let (simulator, comm) = SwordFishSimulator::connect();
+        return Ok((Arc::new(comm), Some(simulator)));
+    }
+    let port = match &args.port {
+        Some(port) => port.clone(),
+        None => find_probable_swordfish_port().ok_or_else(|| anyhow::anyhow!("No swordfish found, pass its port with --port"))?,
+    };
+    let comm = SwordFishComm::new(&port).map_err(|e| anyhow::anyhow!("Could not open {}: {}", port, e))?;
+    Ok((Arc::new(comm), None))
+}
+
+fn run(args: &Args) -> anyhow::Result<()> {
+    let access = access(args)?;
+    let (comm, _simulator) = connect(args)?;
+    let listener = TcpListener::bind(&args.listen).map_err(|e| anyhow::anyhow!("Could not listen on {}: {}", args.listen, e))?;
+    let gateway = Gateway::spawn(listener, comm, access)?;
+    let address = gateway.local_addr();
+    match args.json {
+        true => println!(
+            "{}",
+            json!({
+                "address": address.to_string(),
+                "schema": format!("http://{}/schema", address),
+                "websocket": format!("ws://{}/", address),
+            })
+        ),
+        false => println!("{}", address),
+    }
+    std::io::stdout().flush()?;
+
+    let deadline = args.duration.map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+    let mut clients = 0;
+    while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        let current = gateway.clients();
+        if current != clients {
+            log::info!("{} websocket clients", current);
+            clients = current;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+//the library logs to the log crate, shown on stderr so it does not mix with the output
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{:<5} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let level = match args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("swordfish-gateway: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
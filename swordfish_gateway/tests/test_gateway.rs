@@ -0,0 +1,115 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::Message;
+
+//the gateway in front of the simulator, and the address it listens on
+fn spawn_gateway(access: &[&str]) -> (Child, String) {
+    let mut gateway = Command::new(env!("CARGO_BIN_EXE_swordfish-gateway"))
+        .args(["--simulator", "--listen", "127.0.0.1:0", "-d", "20"])
+        .args(access)
+        .env_remove("SWORDFISH_GATEWAY_TOKEN")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run swordfish-gateway");
+    let mut address = String::new();
+    BufReader::new(gateway.stdout.take().unwrap()).read_line(&mut address).unwrap();
+    (gateway, address.trim().to_string())
+}
+
+fn http_get(address: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+//the next text message that is not a streamed one
+fn answer(websocket: &mut tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>, command: Value) -> Value {
+    websocket.send(Message::text(command.to_string())).unwrap();
+    loop {
+        let text = websocket.read().unwrap().into_text().unwrap();
+        let value: Value = serde_json::from_str(text.as_str()).unwrap();
+        if value["type"] != "message" {
+            return value;
+        }
+    }
+}
+
+#[test]
+fn the_schema_is_served_over_http() {
+    let (mut gateway, address) = spawn_gateway(&["--insecure"]);
+    let schema = http_get(&address, "/schema");
+    let missing = http_get(&address, "/nothing");
+    gateway.kill().unwrap();
+    gateway.wait().unwrap();
+
+    let (head, body) = schema.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"));
+    let schema: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(schema["$defs"]["VersionData"]["properties"]["uuid"]["type"], json!("string"));
+    assert!(missing.starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn requests_and_subscriptions_over_websocket() {
+    let (mut gateway, address) = spawn_gateway(&["--insecure"]);
+    let (mut websocket, _) = tungstenite::connect(format!("ws://{}/", address)).unwrap();
+
+    let version = answer(&mut websocket, json!({ "type": "request", "id": 1, "message": "VersionData" }));
+    assert_eq!(version["type"], json!("response"));
+    assert_eq!(version["id"], json!(1));
+    assert_eq!(version["message"]["fields"]["uuid"], json!("5f5f5f5f5f5f5f5f"));
+
+    let unknown = answer(&mut websocket, json!({ "type": "request", "id": "a", "message": "Teleport" }));
+    assert_eq!(unknown["type"], json!("error"));
+    assert_eq!(unknown["id"], json!("a"));
+    let bad_field = answer(&mut websocket, json!({ "type": "request", "id": 2, "message": "Ping", "fields": { "colour": 1 } }));
+    assert_eq!(bad_field["type"], json!("error"));
+
+    let subscribed = answer(&mut websocket, json!({ "type": "subscribe", "id": 3, "message": "ImuSample", "rate_hz": 100 }));
+    assert_eq!(subscribed["type"], json!("subscribed"));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut samples = 0;
+    while samples < 5 && Instant::now() < deadline {
+        let text = websocket.read().unwrap().into_text().unwrap();
+        let value: Value = serde_json::from_str(text.as_str()).unwrap();
+        if value["type"] == "message" {
+            assert_eq!(value["message"]["name"], json!("ImuSample"));
+            assert!(value["message"]["fields"].is_object());
+            samples += 1;
+        }
+    }
+    let unsubscribed = answer(&mut websocket, json!({ "type": "unsubscribe", "id": 4, "message": "ImuSample" }));
+    assert_eq!(unsubscribed["type"], json!("unsubscribed"));
+    let _ = websocket.close(None);
+    gateway.kill().unwrap();
+    gateway.wait().unwrap();
+    assert_eq!(samples, 5);
+}
+
+#[test]
+fn websockets_need_the_token_and_an_allowed_origin() {
+    let (mut gateway, address) = spawn_gateway(&["--token", "secret", "--allow-origin", "http://localhost:8080"]);
+    let without_token = tungstenite::connect(format!("ws://{}/", address));
+    let with_token = tungstenite::connect(format!("ws://{}/?token=secret", address));
+    let mut from_page = format!("ws://{}/?token=secret", address).into_client_request().unwrap();
+    from_page.headers_mut().insert("Origin", "http://localhost:8080".parse().unwrap());
+    let from_page = tungstenite::connect(from_page);
+    let mut from_elsewhere = format!("ws://{}/?token=secret", address).into_client_request().unwrap();
+    from_elsewhere.headers_mut().insert("Origin", "http://evil.example".parse().unwrap());
+    let from_elsewhere = tungstenite::connect(from_elsewhere);
+    let schema = http_get(&address, "/schema");
+    gateway.kill().unwrap();
+    gateway.wait().unwrap();
+
+    assert!(matches!(without_token, Err(tungstenite::Error::Http(response)) if response.status() == 401));
+    assert!(with_token.is_ok());
+    assert!(from_page.is_ok());
+    assert!(matches!(from_elsewhere, Err(tungstenite::Error::Http(response)) if response.status() == 403));
+    assert!(!schema.to_ascii_lowercase().contains("access-control-allow-origin"));
+}
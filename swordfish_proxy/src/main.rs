@@ -0,0 +1,268 @@
+//swordfish-proxy, sits between a board and the program that talks to it. the program opens the pseudo terminal
+//printed on the first line instead of the board, and every frame of both directions is shown on the way:
+//  swordfish-proxy --device /dev/ttyUSB0 --link /tmp/swordfish      the program opens /tmp/swordfish
+//  swordfish-proxy --simulator --rules faults.toml                  drop, delay, corrupt or rewrite frames (see ProxyConfig)
+//  swordfish-proxy --device /dev/ttyUSB0 --capture bench.pcapng     the frames as they were forwarded, for wireshark
+mod proxy;
+
+use clap::Parser;
+use proxy::{ProxyConfig, ProxyEvent, SegmentKind, SwordFishProxy};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_capture::Direction;
+use swordfish_com::swordfish_clock_sync::host_now_us;
+use swordfish_com::swordfish_comm::find_probable_swordfish_port;
+#[cfg(target_os = "linux")]
+use swordfish_com::swordfish_pty::PtyTransport;
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::{memory_link, SwordFishTransport};
+use swordfish_com::swordfish_util::to_hex;
+use swordfish_com::{MessageRegistry, SwordFishConcentratedMessage, MAX_PAYLOAD_SIZE};
+
+#[derive(Parser)]
+#[command(name = "swordfish-proxy", version, about = "Forward, decode and disturb the frames between a swordfish board and a program")]
+struct Args {
+    /// Serial port of the board, the first port that looks like a swordfish when not given
+    #[arg(short = 'D', long)]
+    device: Option<String>,
+    /// Forward to the built-in simulator instead of a board
+    #[arg(long, conflicts_with = "device")]
+    simulator: bool,
+    /// Also make the pseudo terminal reachable at this path (a symlink, removed on exit)
+    #[arg(long, value_name = "PATH")]
+    link: Option<PathBuf>,
+    /// Toml file with the rules and injections to apply
+    #[arg(short, long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+    /// Capture the forwarded frames to a pcapng file
+    #[arg(short, long, value_name = "FILE")]
+    capture: Option<PathBuf>,
+    /// Print json lines instead of text
+    #[arg(long)]
+    json: bool,
+    /// Only print the frames a rule acted on and the injected ones
+    #[arg(short, long)]
+    quiet: bool,
+    /// Stop after this many seconds
+    #[arg(short, long, value_name = "SECONDS")]
+    duration: Option<f64>,
+    /// -v for info, -vv for debug logs (on stderr)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+fn payload(msg: &SwordFishConcentratedMessage) -> &[u8] {
+    &msg.payload[..std::cmp::min(msg.length as usize, MAX_PAYLOAD_SIZE)]
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Tx => "tx",
+        Direction::Rx => "rx",
+    }
+}
+
+//what the proxy did to the segment, empty when it was forwarded as it was
+fn verdict(event: &ProxyEvent) -> String {
+    let rule = event.rule.map_or(String::new(), |rule| format!(" by rule {}", rule));
+    if event.kind == SegmentKind::Injected {
+        return "injected".to_string();
+    }
+    if let Some(delay) = event.delayed {
+        return format!("delayed {} ms{}", delay.as_millis(), rule);
+    }
+    match event.forwarded.as_slice() {
+        [] => format!("dropped{}", rule),
+        [forwarded] if *forwarded == event.original => String::new(),
+        [_] => format!("modified{}", rule),
+        _ => format!("duplicated{}", rule),
+    }
+}
+
+//"   1.234 tx Ping (0) #1", then what happened to it
+fn event_text(registry: &MessageRegistry, started_us: u64, event: &ProxyEvent) -> String {
+    let seconds = event.host_us.saturating_sub(started_us) as f64 / 1e6;
+    let mut text = format!("{:>9.3} {} ", seconds, direction_name(event.direction));
+    match (&event.kind, &event.msg) {
+        (SegmentKind::Garbage, _) => text.push_str(&format!("{} bytes outside a frame {}", event.original.len(), to_hex(&event.original))),
+        (SegmentKind::BadChecksum, _) => text.push_str(&format!("bad checksum {}", to_hex(&event.original))),
+        (_, None) => text.push_str(&to_hex(&event.original)),
+        (_, Some(msg)) => match registry.get(msg.opcode) {
+            Some(info) => {
+                text.push_str(&format!("{} ({}) #{}", info.name, msg.opcode, msg.counter));
+                match info.decode_fields(payload(msg)) {
+                    Ok(fields) => fields.iter().for_each(|(name, value)| text.push_str(&format!(" {}={}", name, value))),
+                    Err(e) => text.push_str(&format!(" payload={} ({})", to_hex(payload(msg)), e)),
+                }
+            }
+            None => text.push_str(&format!("opcode {} #{} payload={}", msg.opcode, msg.counter, to_hex(payload(msg)))),
+        },
+    }
+    let verdict = verdict(event);
+    if !verdict.is_empty() {
+        text.push_str(&format!("  [{}]", verdict));
+    }
+    text
+}
+
+fn event_json(registry: &MessageRegistry, event: &ProxyEvent) -> Value {
+    let kind = match event.kind {
+        SegmentKind::Frame => "frame",
+        SegmentKind::BadChecksum => "bad_checksum",
+        SegmentKind::Garbage => "garbage",
+        SegmentKind::Injected => "injected",
+    };
+    let info = event.msg.and_then(|msg| registry.get(msg.opcode));
+    json!({
+        "host_us": event.host_us,
+        "direction": direction_name(event.direction),
+        "kind": kind,
+        "opcode": event.msg.map(|msg| msg.opcode),
+        "name": info.map(|info| info.name.clone()),
+        "counter": event.msg.map(|msg| msg.counter),
+        "fields": event.msg.zip(info).and_then(|(msg, info)| info.decode_fields(payload(&msg)).ok()).map(|fields| {
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, serde_json::to_value(value).expect("a field value is plain data")))
+                .collect::<serde_json::Map<String, Value>>()
+        }),
+        "bytes": to_hex(&event.original),
+        "forwarded": event.forwarded.iter().map(|bytes| to_hex(bytes)).collect::<Vec<_>>(),
+        "delay_ms": event.delayed.map(|delay| delay.as_millis() as u64),
+        "rule": event.rule,
+        "action": verdict(event),
+    })
+}
+
+//the board or the simulator, and the simulator that must live as long as the proxy
+fn open_device(args: &Args) -> anyhow::Result<(Box<dyn SwordFishTransport>, Option<SwordFishSimulator>)> {
+    if args.simulator {
+        let (device, simulator_port) = memory_link();
+        return Ok((Box::new(device), Some(SwordFishSimulator::spawn(Box::new(simulator_port)))));
+    }
+    let port = match &args.device {
+        Some(port) => port.clone(),
+        None => find_probable_swordfish_port().ok_or_else(|| anyhow::anyhow!("No swordfish found, pass its port with --device"))?,
+    };
+    let device = serialport::new(&port, 115200)
+        .timeout(Duration::from_millis(1))
+        .open()
+        .map_err(|e| anyhow::anyhow!("Could not open {}: {}", port, e))?;
+    Ok((Box::new(device), None))
+}
+
+//the pseudo terminal the program opens in place of the board, and its name
+#[cfg(target_os = "linux")]
+fn open_host() -> anyhow::Result<(Box<dyn SwordFishTransport>, String)> {
+    let host = PtyTransport::open()?;
+    let port = host.port_name().to_string();
+    Ok((Box::new(host), port))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_host() -> anyhow::Result<(Box<dyn SwordFishTransport>, String)> {
+    Err(anyhow::anyhow!("The pseudo terminal of the proxy is only available on linux"))
+}
+
+#[cfg(unix)]
+fn link_port(port: &str, link: &Path) -> anyhow::Result<()> {
+    let _ = std::fs::remove_file(link);
+    std::os::unix::fs::symlink(port, link).map_err(|e| anyhow::anyhow!("Could not link {}: {}", link.display(), e))
+}
+
+#[cfg(not(unix))]
+fn link_port(_port: &str, _link: &Path) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("--link is only available on unix"))
+}
+
+fn run(args: &Args) -> anyhow::Result<()> {
+    let config = match &args.rules {
+        Some(path) => ProxyConfig::load(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?,
+        None => ProxyConfig::default(),
+    };
+    let registry = MessageRegistry::new();
+    let (device, _simulator) = open_device(args)?;
+    let (host, port) = open_host()?;
+
+    let started_us = host_now_us();
+    let (json, quiet, printed_registry) = (args.json, args.quiet, registry.clone());
+    let on_event = Box::new(move |event: &ProxyEvent| {
+        if quiet && event.rule.is_none() && event.kind != SegmentKind::Injected {
+            return;
+        }
+        let line = match json {
+            true => event_json(&printed_registry, event).to_string(),
+            false => event_text(&printed_registry, started_us, event),
+        };
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+    });
+    let proxy = SwordFishProxy::spawn(host, device, &config, &registry, Some(on_event))?;
+    if let Some(path) = &args.capture {
+        proxy.start_capture(path)?;
+    }
+    if let Some(link) = &args.link {
+        link_port(&port, link)?;
+    }
+    match args.json {
+        true => println!("{}", json!({ "port": port })),
+        false => println!("{}", port),
+    }
+    std::io::stdout().flush()?;
+
+    let deadline = args.duration.map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+    while proxy.is_running() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let device_alive = proxy.is_running();
+    if let Some(link) = &args.link {
+        let _ = std::fs::remove_file(link);
+    }
+    if args.capture.is_some() {
+        let frames = proxy.stop_capture()?;
+        log::info!("Captured {} frames", frames);
+    }
+    match device_alive {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!("The device was disconnected")),
+    }
+}
+
+//the library logs to the log crate, shown on stderr so it does not mix with the output
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{:<5} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let level = match args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("swordfish-proxy: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,551 @@
+//a man in the middle between a host program and a device, to watch or disturb what they say:
+//  let proxy = SwordFishProxy::spawn(Box::new(pty), Box::new(device_port), &ProxyConfig::load(path)?, &registry, None)?;
+//the bytes are forwarded both ways and cut into frames on the way (see swordfish_splitter), the frames of the host
+//start with either sync word.
+//bytes between frames and frames with a bad checksum go through untouched.
+//the rules of a ProxyConfig drop, delay, corrupt, duplicate or rewrite the frames they match, and injections add frames
+//of their own, for fault testing. every segment is reported to a callback and can be captured to pcapng (see swordfish_capture)
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_capture::{Direction, FrameCapture};
+use swordfish_com::swordfish_clock_sync::host_now_us;
+use swordfish_com::swordfish_registry::{FieldValue, MessageInfo, MessageRegistry};
+use swordfish_com::swordfish_splitter::{starts_like_a_frame, FrameSplitter, Segment};
+use swordfish_com::swordfish_transport::SwordFishTransport;
+use swordfish_com::swordfish_util::from_hex;
+use swordfish_com::{SwordFishConcentratedMessage, MAX_PAYLOAD_SIZE};
+
+//a frame that is not completed in this time is forwarded as it is
+const STALE_TIMEOUT: Duration = Duration::from_millis(50);
+
+//---------------------ProxyConfig---------------------
+//the rules and injections of a proxy, usually from a toml file:
+//  [[rule]]
+//  direction = "tx"          # tx is host to device, rx device to host, both when not given
+//  message = "ParamWrite"    # name or opcode, every message when not given
+//  action = "drop"           # drop, delay (ms = ..), corrupt, duplicate, set (fields = {..}), payload (hex = "..")
+//  every = 3                 # every 3rd matching frame, default every one
+//  limit = 1                 # at most this many times
+//
+//  [[inject]]
+//  direction = "rx"
+//  message = "DeviceLog"
+//  fields = { level = 2, text = "injected" }
+//  after_ms = 100
+//  period_ms = 1000          # once when not given
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    #[serde(rename = "rule")]
+    pub rules: Vec<ProxyRule>,
+    #[serde(rename = "inject")]
+    pub injections: Vec<Injection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProxyRule {
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default = "every_frame")]
+    pub every: u32,
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(flatten)]
+    pub action: RuleAction,
+}
+
+fn every_frame() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RuleAction {
+    Drop,
+    Delay { ms: u64 },
+    Corrupt, //flips the checksum
+    Duplicate,
+    Set { fields: BTreeMap<String, toml::Value> }, //the other fields keep their value
+    Payload { hex: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Injection {
+    pub direction: Direction,
+    pub message: String,
+    #[serde(default)]
+    pub fields: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    pub counter: u16,
+    #[serde(default)]
+    pub after_ms: u64,
+    #[serde(default)]
+    pub period_ms: Option<u64>,
+}
+
+impl ProxyConfig {
+    pub fn from_toml_str(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        ProxyConfig::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+}
+
+fn find_message<'a>(registry: &'a MessageRegistry, message: &str) -> Result<&'a MessageInfo> {
+    let by_opcode = message.parse::<u8>().ok().and_then(|opcode| registry.get(opcode));
+    by_opcode
+        .or_else(|| registry.find(message))
+        .ok_or_else(|| anyhow!("Unknown message {}", message))
+}
+
+//toml values spelled for FieldType::parse_value
+fn toml_text(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Array(items) => format!("[{}]", items.iter().map(toml_text).collect::<Vec<_>>().join(", ")),
+        toml::Value::Boolean(flag) => (*flag as u8).to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn typed_fields(info: &MessageInfo, fields: &BTreeMap<String, toml::Value>) -> Result<Vec<(String, FieldValue)>> {
+    fields
+        .iter()
+        .map(|(name, value)| {
+            let field = info.field(name).ok_or_else(|| anyhow!("{} has no field {}", info.name, name))?;
+            let value = field
+                .field_type()?
+                .parse_value(&toml_text(value))
+                .map_err(|e| anyhow!("Field {} of {}: {}", name, info.name, e))?;
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
+
+enum CompiledAction {
+    Drop,
+    Delay(Duration),
+    Corrupt,
+    Duplicate,
+    Set(MessageInfo, Vec<(String, FieldValue)>),
+    Payload(Vec<u8>),
+}
+
+struct CompiledRule {
+    direction: Option<Direction>,
+    opcode: Option<u8>,
+    every: u64,
+    limit: Option<u64>,
+    action: CompiledAction,
+    matched: u64,
+    applied: u64,
+}
+
+impl CompiledRule {
+    fn compile(rule: &ProxyRule, registry: &MessageRegistry) -> Result<Self> {
+        let info = rule.message.as_deref().map(|message| find_message(registry, message)).transpose()?;
+        let action = match &rule.action {
+            RuleAction::Drop => CompiledAction::Drop,
+            RuleAction::Delay { ms } => CompiledAction::Delay(Duration::from_millis(*ms)),
+            RuleAction::Corrupt => CompiledAction::Corrupt,
+            RuleAction::Duplicate => CompiledAction::Duplicate,
+            RuleAction::Set { fields } => {
+                let info = info.ok_or_else(|| anyhow!("A set rule needs a message"))?;
+                CompiledAction::Set(info.clone(), typed_fields(info, fields)?)
+            }
+            RuleAction::Payload { hex } => {
+                let payload = from_hex(hex)?;
+                if payload.len() > MAX_PAYLOAD_SIZE {
+                    return Err(anyhow!("The payload is {} bytes, at most {}", payload.len(), MAX_PAYLOAD_SIZE));
+                }
+                CompiledAction::Payload(payload)
+            }
+        };
+        if rule.every == 0 {
+            return Err(anyhow!("every must be at least 1"));
+        }
+        Ok(CompiledRule {
+            direction: rule.direction,
+            opcode: info.map(|info| info.opcode),
+            every: rule.every as u64,
+            limit: rule.limit.map(|limit| limit as u64),
+            action,
+            matched: 0,
+            applied: 0,
+        })
+    }
+
+    //counts the frame, true when the rule acts on it
+    fn triggers(&mut self, direction: Direction, msg: &SwordFishConcentratedMessage) -> bool {
+        if self.direction.is_some_and(|d| d != direction) || self.opcode.is_some_and(|opcode| opcode != msg.opcode) {
+            return false;
+        }
+        self.matched += 1;
+        if !self.matched.is_multiple_of(self.every) || self.limit.is_some_and(|limit| self.applied >= limit) {
+            return false;
+        }
+        self.applied += 1;
+        true
+    }
+}
+
+struct ScheduledInjection {
+    direction: Direction,
+    frame: Vec<u8>,
+    msg: SwordFishConcentratedMessage,
+    due: Instant,
+    period: Option<Duration>,
+}
+
+//---------------------SwordFishProxy---------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    Frame,
+    BadChecksum,
+    Garbage,  //bytes between frames
+    Injected, //made by the proxy
+}
+
+//what happened to a segment, given to the callback of the proxy
+#[derive(Debug, Clone)]
+pub struct ProxyEvent {
+    pub direction: Direction,
+    pub host_us: u64,
+    pub kind: SegmentKind,
+    pub original: Vec<u8>,
+    pub msg: Option<SwordFishConcentratedMessage>,
+    //what was sent on, twice for a duplicate. empty when dropped
+    pub forwarded: Vec<Vec<u8>>,
+    pub delayed: Option<Duration>,
+    pub rule: Option<usize>, //index of the rule that acted on it
+}
+
+pub type ProxyCallback = Box<dyn FnMut(&ProxyEvent) + Send>;
+
+struct ProxyState {
+    host: Box<dyn SwordFishTransport>,
+    device: Box<dyn SwordFishTransport>,
+    splitters: [FrameSplitter; 2],
+    rules: Vec<CompiledRule>,
+    scheduled: Vec<ScheduledInjection>,
+    delayed: Vec<(Instant, Direction, Vec<u8>)>,
+    capture: Arc<FrameCapture>,
+    on_event: Option<ProxyCallback>,
+}
+
+fn index(direction: Direction) -> usize {
+    match direction {
+        Direction::Tx => 0,
+        Direction::Rx => 1,
+    }
+}
+
+impl ProxyState {
+    //tx goes to the device, rx to the host
+    fn write(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        let port = match direction {
+            Direction::Tx => &mut self.device,
+            Direction::Rx => &mut self.host,
+        };
+        port.write_all(bytes)?;
+        port.flush()?;
+        //only what looks like a frame, a corrupted one is flagged
+        if starts_like_a_frame(bytes) {
+            let bad_checksum = SwordFishConcentratedMessage::parse_frame(bytes).is_none();
+            self.capture.record(direction, host_now_us(), bytes, bad_checksum);
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, event: ProxyEvent) {
+        if let Some(on_event) = self.on_event.as_mut() {
+            on_event(&event);
+        }
+    }
+
+    fn handle(&mut self, direction: Direction, segment: Segment) -> io::Result<()> {
+        let (kind, original, msg) = match segment {
+            Segment::Bytes(bytes) => (SegmentKind::Garbage, bytes, None),
+            Segment::Frame(bytes, None) => (SegmentKind::BadChecksum, bytes, None),
+            Segment::Frame(bytes, Some(msg)) => (SegmentKind::Frame, bytes, Some(*msg)),
+        };
+        let mut event = ProxyEvent {
+            direction,
+            host_us: host_now_us(),
+            kind,
+            original: original.clone(),
+            msg,
+            forwarded: vec![original],
+            delayed: None,
+            rule: None,
+        };
+        if let Some(msg) = msg {
+            let rule = self.rules.iter_mut().position(|rule| rule.triggers(direction, &msg));
+            if let Some(rule) = rule {
+                event.rule = Some(rule);
+                match &self.rules[rule].action {
+                    CompiledAction::Drop => event.forwarded.clear(),
+                    CompiledAction::Delay(delay) => {
+                        event.delayed = Some(*delay);
+                        let frame = event.forwarded.remove(0);
+                        self.delayed.push((Instant::now() + *delay, direction, frame));
+                    }
+                    CompiledAction::Corrupt => {
+                        if let Some(checksum) = event.forwarded[0].last_mut() {
+                            *checksum ^= 0xff;
+                        }
+                    }
+                    CompiledAction::Duplicate => event.forwarded.push(event.original.clone()),
+                    CompiledAction::Set(info, fields) => {
+                        let payload = &msg.payload[..msg.length as usize];
+                        let mut values = info.decode_fields(payload).unwrap_or_default();
+                        for (name, value) in fields {
+                            match values.iter_mut().find(|(field, _)| field == name) {
+                                Some((_, old)) => *old = value.clone(),
+                                None => values.push((name.clone(), value.clone())),
+                            }
+                        }
+                        match info.encode_fields(&values) {
+                            Ok(payload) => {
+                                let modified = SwordFishConcentratedMessage::new(msg.counter, msg.opcode, &payload);
+                                event.forwarded = vec![modified.into_bytes().to_vec()];
+                            }
+                            Err(e) => log::warn!("Rule {} could not rewrite {}: {}", rule, info.name, e),
+                        }
+                    }
+                    CompiledAction::Payload(payload) => {
+                        let modified = SwordFishConcentratedMessage::new(msg.counter, msg.opcode, payload);
+                        event.forwarded = vec![modified.into_bytes().to_vec()];
+                    }
+                }
+            }
+        }
+        for bytes in &event.forwarded {
+            self.write(direction, bytes)?;
+        }
+        self.report(event);
+        Ok(())
+    }
+
+    fn read(&mut self, direction: Direction, buffer: &mut [u8]) -> io::Result<()> {
+        let port = match direction {
+            Direction::Tx => &mut self.host,
+            Direction::Rx => &mut self.device,
+        };
+        let n = match port.read(buffer) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                if let Some(stale) = self.splitters[index(direction)].take_stale(STALE_TIMEOUT) {
+                    self.handle(direction, Segment::Bytes(stale))?;
+                }
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        for segment in self.splitters[index(direction)].push(&buffer[..n]) {
+            self.handle(direction, segment)?;
+        }
+        Ok(())
+    }
+
+    fn send_due(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        self.delayed.retain(|(at, direction, frame)| match *at <= now {
+            true => {
+                due.push((*direction, frame.clone()));
+                false
+            }
+            false => true,
+        });
+        for (direction, frame) in due {
+            self.write(direction, &frame)?;
+        }
+
+        let mut injections = Vec::new();
+        for scheduled in self.scheduled.iter_mut().filter(|scheduled| scheduled.due <= now) {
+            injections.push((scheduled.direction, scheduled.msg, scheduled.frame.clone()));
+            if let Some(period) = scheduled.period {
+                scheduled.due += period;
+            }
+        }
+        //the ones that are not periodic were sent once
+        self.scheduled.retain(|scheduled| scheduled.period.is_some() || scheduled.due > now);
+        for (direction, msg, frame) in injections {
+            self.write(direction, &frame)?;
+            self.report(ProxyEvent {
+                direction,
+                host_us: host_now_us(),
+                kind: SegmentKind::Injected,
+                original: frame.clone(),
+                msg: Some(msg),
+                forwarded: vec![frame],
+                delayed: None,
+                rule: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+pub struct SwordFishProxy {
+    capture: Arc<FrameCapture>,
+    thread_handle: Option<JoinHandle<()>>,
+    thread_alive: Arc<AtomicBool>,
+}
+
+impl SwordFishProxy {
+    //forwards between host and device until one of them is closed or the proxy is dropped.
+    //the reads of both transports should time out when there is no data, see swordfish_transport
+    pub fn spawn(
+        host: Box<dyn SwordFishTransport>,
+        device: Box<dyn SwordFishTransport>,
+        config: &ProxyConfig,
+        registry: &MessageRegistry,
+        on_event: Option<ProxyCallback>,
+    ) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| CompiledRule::compile(rule, registry).map_err(|e| anyhow!("Rule {}: {}", i, e)))
+            .collect::<Result<Vec<_>>>()?;
+        let start = Instant::now();
+        let scheduled = config
+            .injections
+            .iter()
+            .map(|injection| {
+                let info = find_message(registry, &injection.message)?;
+                let payload = info.encode_fields(&typed_fields(info, &injection.fields)?)?;
+                let msg = SwordFishConcentratedMessage::new(injection.counter, info.opcode, &payload);
+                Ok(ScheduledInjection {
+                    direction: injection.direction,
+                    frame: msg.into_bytes().to_vec(),
+                    msg,
+                    due: start + Duration::from_millis(injection.after_ms),
+                    period: injection.period_ms.map(Duration::from_millis),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| anyhow!("Injection: {}", e))?;
+
+        let capture = Arc::new(FrameCapture::default());
+        let thread_alive = Arc::new(AtomicBool::new(true));
+        let mut state = ProxyState {
+            host,
+            device,
+            splitters: [FrameSplitter::for_host_frames(), FrameSplitter::new()],
+            rules,
+            scheduled,
+            delayed: Vec::new(),
+            capture: capture.clone(),
+            on_event,
+        };
+        let thread_alive_clone = thread_alive.clone();
+        let thread_handle = spawn(move || {
+            let mut buffer = [0u8; 4096];
+            while thread_alive_clone.load(Ordering::Relaxed) {
+                let result = state
+                    .send_due()
+                    .and_then(|_| state.read(Direction::Tx, &mut buffer))
+                    .and_then(|_| state.read(Direction::Rx, &mut buffer));
+                if let Err(e) = result {
+                    log::error!("The proxy stopped: {}", e);
+                    thread_alive_clone.store(false, Ordering::Relaxed);
+                }
+            }
+        });
+        Ok(SwordFishProxy {
+            capture,
+            thread_handle: Some(thread_handle),
+            thread_alive,
+        })
+    }
+
+    //false once the host or the device was closed
+    pub fn is_running(&self) -> bool {
+        self.thread_alive.load(Ordering::Relaxed)
+    }
+
+    //records the frames as they leave the proxy, after the rules
+    pub fn start_capture(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.start_capture_to(Box::new(std::io::BufWriter::new(file)))
+    }
+
+    pub fn start_capture_to(&self, writer: Box<dyn Write + Send>) -> Result<()> {
+        Ok(self.capture.start(writer)?)
+    }
+
+    pub fn stop_capture(&self) -> Result<u64> {
+        Ok(self.capture.stop()?)
+    }
+}
+
+impl Drop for SwordFishProxy {
+    fn drop(&mut self) {
+        self.thread_alive.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.thread_handle.take() {
+            handle.join().expect("The proxy thread could not be joined");
+        }
+        if let Err(e) = self.capture.stop() {
+            log::error!("Could not finish the capture: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swordfish_com::swordfish_messages::VersionData;
+    use swordfish_com::SwordFishMessageTrait;
+
+    #[test]
+    fn rules_are_checked_against_the_registry() {
+        let registry = MessageRegistry::new();
+        let config = ProxyConfig::from_toml_str(
+            r#"
+            [[rule]]
+            direction = "rx"
+            message = "VersionData"
+            action = "set"
+            fields = { version = 9, uuid = "0102030405060708" }
+            every = 2
+
+            [[rule]]
+            message = "0"
+            action = "delay"
+            ms = 5
+
+            [[inject]]
+            direction = "tx"
+            message = "Ping"
+            period_ms = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rules[1].action, RuleAction::Delay { ms: 5 });
+        assert_eq!(config.rules[0].direction, Some(Direction::Rx));
+        let mut rule = CompiledRule::compile(&config.rules[0], &registry).unwrap();
+        let version = VersionData::default().to_concentrated(1);
+        assert!(!rule.triggers(Direction::Tx, &version));
+        assert!(!rule.triggers(Direction::Rx, &version));
+        assert!(rule.triggers(Direction::Rx, &version));
+
+        let unknown = ProxyConfig::from_toml_str("[[rule]]\nmessage = \"Nope\"\naction = \"drop\"").unwrap();
+        assert!(CompiledRule::compile(&unknown.rules[0], &registry).is_err());
+        let bad_field = ProxyConfig::from_toml_str("[[rule]]\nmessage = \"Ping\"\naction = \"set\"\nfields = { x = 1 }").unwrap();
+        assert!(CompiledRule::compile(&bad_field.rules[0], &registry).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//the proxy opens a pseudo terminal for the program
+#![cfg(target_os = "linux")]
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::{DeviceLog, Ping, VersionData};
+use swordfish_com::SwordFishMessageTrait;
+
+const RULES: &str = r#"
+[[rule]]
+direction = "tx"
+message = "Ping"
+action = "drop"
+limit = 1
+
+[[rule]]
+direction = "rx"
+message = "VersionData"
+action = "set"
+fields = { version = 9 }
+
+[[inject]]
+direction = "rx"
+message = "DeviceLog"
+fields = { level = 2, text = "from the proxy" }
+after_ms = 300
+"#;
+
+#[test]
+fn a_program_talks_through_the_proxy() {
+    let rules = std::env::temp_dir().join(format!("swordfish_proxy_{}.toml", std::process::id()));
+    std::fs::write(&rules, RULES).unwrap();
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_swordfish-proxy"))
+        .args(["--simulator", "--json", "-d", "10", "--rules", rules.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run swordfish-proxy");
+    let mut lines = BufReader::new(proxy.stdout.take().unwrap()).lines();
+    let port: Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    let comm = SwordFishComm::new(port["port"].as_str().unwrap()).unwrap();
+    let (sender, logs) = mpsc::channel();
+    comm.add_rx_hook(Box::new(move |msg, _| {
+        if msg.opcode == DeviceLog::OPCODE {
+            let _ = sender.send(*msg);
+        }
+    }));
+
+    let timeout = Duration::from_millis(200);
+    //the first ping is dropped, the second goes through
+    assert!(comm.request_with_timeout(Ping::default().to_concentrated(1), timeout).is_err());
+    assert!(comm.request_with_timeout(Ping::default().to_concentrated(2), timeout).is_ok());
+    let version = comm.request_with_timeout(VersionData::default().to_concentrated(3), timeout).unwrap();
+    assert_eq!(version, VersionData::new(9, 0, 0, &[0x5f; 8]).to_concentrated(3));
+    let log = logs.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert!(DeviceLog::from_concentrated(&log).unwrap().text.as_str().contains("from the proxy"));
+
+    drop(comm);
+    proxy.kill().unwrap();
+    proxy.wait().unwrap();
+    let _ = std::fs::remove_file(&rules);
+    let events: Vec<Value> = lines.map_while(Result::ok).map(|line| serde_json::from_str(&line).unwrap()).collect();
+    let actions: Vec<&str> = events.iter().filter_map(|event| event["action"].as_str()).filter(|action| !action.is_empty()).collect();
+    assert_eq!(actions, ["dropped by rule 0", "modified by rule 1", "injected"]);
+}
+
+#[test]
+fn host_frames_with_the_other_sync_word_are_decoded() {
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_swordfish-proxy"))
+        .args(["--simulator", "--json", "-d", "1"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run swordfish-proxy");
+    let mut lines = BufReader::new(proxy.stdout.take().unwrap()).lines();
+    let port: Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    let mut host = serialport::new(port["port"].as_str().unwrap(), 115_200)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .unwrap();
+    //a host that writes the sync word big endian, the frames of swordfish_com start with de ad be ef
+    let mut frame = Ping::default().to_concentrated(4).into_bytes().to_vec();
+    frame[..4].copy_from_slice(&[0xef, 0xbe, 0xad, 0xde]);
+    host.write_all(&frame).unwrap();
+
+    proxy.wait().unwrap();
+    let events: Vec<Value> = lines.map_while(Result::ok).map(|line| serde_json::from_str(&line).unwrap()).collect();
+    assert!(
+        events
+            .iter()
+            .any(|event| event["direction"] == "tx" && event["kind"] == "frame" && event["name"] == "Ping" && event["counter"] == 4),
+        "{:?}",
+        events
+    );
+}
@@ -0,0 +1,359 @@
+//the state of the monitor and what the keys do to it, drawn by ui.rs.
+//the comm feeds it through events (the rx/tx hooks, the ping thread and the answers of sent messages),
+//so it can be driven without a terminal
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use swordfish_com::swordfish_capture::Direction;
+use swordfish_com::swordfish_comm::LinkStats;
+use swordfish_com::swordfish_format::FrameFormatter;
+use swordfish_com::{FieldValue, MessageInfo, MessageRegistry, SwordFishConcentratedMessage};
+
+//oldest frames are dropped past this
+pub const MAX_FRAMES: usize = 10_000;
+//ping round trips kept for the sparkline
+pub const MAX_PINGS: usize = 200;
+
+pub struct FrameRow {
+    pub direction: Direction,
+    pub host_us: u64,
+    pub msg: SwordFishConcentratedMessage,
+}
+
+pub enum Event {
+    Frame(FrameRow),
+    Ping(Option<Duration>), //None when the ping got no answer
+    Answer(Result<SwordFishConcentratedMessage, String>),
+}
+
+//what the main loop does for the app
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    Quit,
+    Send(u8, Vec<u8>), //opcode and payload, the counter is the comm's
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpcodeCounter {
+    pub tx: u64,
+    pub rx: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Frames,
+    Form,
+}
+
+//the message to send, one text value per field
+pub struct SendForm {
+    pub message: usize, //index in the registry
+    pub values: Vec<String>,
+    pub selected: usize,
+}
+
+pub struct App {
+    pub registry: MessageRegistry,
+    pub frames: VecDeque<FrameRow>,
+    pub direction_filter: Option<Direction>,
+    pub opcode_filter: Option<u8>,
+    pub counters: BTreeMap<u8, OpcodeCounter>,
+    pub pings: VecDeque<u64>, //round trips in us
+    pub pings_lost: u64,
+    pub link: LinkStats,
+    pub scroll: usize, //frames from the newest, 0 follows the new frames
+    pub focus: Focus,
+    pub form: SendForm,
+    pub status: String,
+    pub started_us: u64,
+}
+
+//"VersionData (opcode 2, bounce) #17, 14 bytes, checksum ok version=1 ... uuid=5f5f5f5f5f5f5f5f", one row of the frames panel
+pub fn describe(registry: &MessageRegistry, msg: &SwordFishConcentratedMessage) -> String {
+    FrameFormatter {
+        single_line: true,
+        hexdump: registry.get(msg.opcode).is_none(),
+        ..FrameFormatter::new(registry)
+    }
+    .format(msg)
+}
+
+impl App {
+    pub fn new(registry: MessageRegistry, started_us: u64) -> Self {
+        let mut app = App {
+            registry,
+            frames: VecDeque::new(),
+            direction_filter: None,
+            opcode_filter: None,
+            counters: BTreeMap::new(),
+            pings: VecDeque::new(),
+            pings_lost: 0,
+            link: LinkStats::default(),
+            scroll: 0,
+            focus: Focus::Frames,
+            form: SendForm {
+                message: 0,
+                values: Vec::new(),
+                selected: 0,
+            },
+            status: String::new(),
+            started_us,
+        };
+        app.select_message(0);
+        app
+    }
+
+    pub fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Frame(row) => {
+                let counter = self.counters.entry(row.msg.opcode).or_default();
+                match row.direction {
+                    Direction::Tx => counter.tx += 1,
+                    Direction::Rx => counter.rx += 1,
+                }
+                //a scrolled list stays on the same frames
+                if self.scroll > 0 && self.matches(&row) {
+                    self.scroll += 1;
+                }
+                self.frames.push_back(row);
+                if self.frames.len() > MAX_FRAMES {
+                    self.frames.pop_front();
+                }
+            }
+            Event::Ping(Some(rtt)) => {
+                self.pings.push_back(rtt.as_micros() as u64);
+                if self.pings.len() > MAX_PINGS {
+                    self.pings.pop_front();
+                }
+            }
+            Event::Ping(None) => self.pings_lost += 1,
+            Event::Answer(Ok(answer)) => self.status = format!("answer: {}", describe(&self.registry, &answer)),
+            Event::Answer(Err(e)) => self.status = e,
+        }
+    }
+
+    pub fn matches(&self, row: &FrameRow) -> bool {
+        self.direction_filter.is_none_or(|direction| direction == row.direction)
+            && self.opcode_filter.is_none_or(|opcode| opcode == row.msg.opcode)
+    }
+
+    //the frames that pass the filters, oldest first
+    pub fn visible_frames(&self) -> Vec<&FrameRow> {
+        self.frames.iter().filter(|row| self.matches(row)).collect()
+    }
+
+    pub fn filter_text(&self) -> String {
+        let direction = match self.direction_filter {
+            None => "tx+rx",
+            Some(Direction::Tx) => "tx",
+            Some(Direction::Rx) => "rx",
+        };
+        let opcode = match self.opcode_filter.and_then(|opcode| self.registry.get(opcode)) {
+            Some(info) => info.name.clone(),
+            None => "all messages".to_string(),
+        };
+        format!("{}, {}", direction, opcode)
+    }
+
+    pub fn selected_message(&self) -> Option<&MessageInfo> {
+        self.registry.iter().nth(self.form.message)
+    }
+
+    fn select_message(&mut self, index: usize) {
+        self.form.message = index;
+        self.form.selected = 0;
+        self.form.values = match self.selected_message() {
+            Some(info) => {
+                let defaults = info.decode_fields(&vec![0; info.payload_size]).unwrap_or_default();
+                info.fields
+                    .iter()
+                    .map(|field| {
+                        defaults
+                            .iter()
+                            .find(|(name, _)| *name == field.name)
+                            .map_or(String::new(), |(_, value)| match value {
+                                FieldValue::Text(text) => text.clone(),
+                                value => value.to_string(),
+                            })
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+    }
+
+    //the payload of the form, or why it can not be sent
+    pub fn form_payload(&self) -> Result<(u8, Vec<u8>), String> {
+        let info = self.selected_message().ok_or("No message to send")?;
+        let values = info
+            .fields
+            .iter()
+            .zip(&self.form.values)
+            .map(|(field, text)| {
+                field
+                    .field_type()
+                    .and_then(|field_type| field_type.parse_value(text))
+                    .map(|value| (field.name.clone(), value))
+                    .map_err(|e| format!("{}: {}", field.name, e))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let payload = info.encode_fields(&values).map_err(|e| e.to_string())?;
+        Ok((info.opcode, payload))
+    }
+
+    fn cycle_opcode_filter(&mut self, forward: bool) {
+        let mut choices: Vec<Option<u8>> = vec![None];
+        choices.extend(self.registry.opcodes().into_iter().map(Some));
+        let position = choices.iter().position(|choice| *choice == self.opcode_filter).unwrap_or(0);
+        let next = match forward {
+            true => (position + 1) % choices.len(),
+            false => (position + choices.len() - 1) % choices.len(),
+        };
+        self.opcode_filter = choices[next];
+        self.scroll = 0;
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> Action {
+        match (self.focus, key) {
+            (_, Key::Ctrl('c')) => return Action::Quit,
+            (_, Key::Tab) => {
+                self.focus = match self.focus {
+                    Focus::Frames => Focus::Form,
+                    Focus::Form => Focus::Frames,
+                }
+            }
+            (Focus::Frames, Key::Char('q')) | (Focus::Frames, Key::Esc) => return Action::Quit,
+            (Focus::Frames, Key::Char('d')) => {
+                self.direction_filter = match self.direction_filter {
+                    None => Some(Direction::Tx),
+                    Some(Direction::Tx) => Some(Direction::Rx),
+                    Some(Direction::Rx) => None,
+                };
+                self.scroll = 0;
+            }
+            (Focus::Frames, Key::Char('o')) => self.cycle_opcode_filter(true),
+            (Focus::Frames, Key::Char('O')) => self.cycle_opcode_filter(false),
+            (Focus::Frames, Key::Char('c')) => {
+                self.frames.clear();
+                self.counters.clear();
+                self.scroll = 0;
+            }
+            (Focus::Frames, Key::Up) => self.scroll = std::cmp::min(self.scroll + 1, self.visible_frames().len().saturating_sub(1)),
+            (Focus::Frames, Key::Down) => self.scroll = self.scroll.saturating_sub(1),
+            (Focus::Frames, Key::PageUp) => self.scroll = std::cmp::min(self.scroll + 20, self.visible_frames().len().saturating_sub(1)),
+            (Focus::Frames, Key::PageDown) => self.scroll = self.scroll.saturating_sub(20),
+            (Focus::Frames, Key::End) => self.scroll = 0,
+            (Focus::Form, Key::Esc) => self.focus = Focus::Frames,
+            (Focus::Form, Key::Left) | (Focus::Form, Key::Right) => {
+                let n = self.registry.len();
+                if n > 0 {
+                    let next = match key {
+                        Key::Right => (self.form.message + 1) % n,
+                        _ => (self.form.message + n - 1) % n,
+                    };
+                    self.select_message(next);
+                }
+            }
+            (Focus::Form, Key::Up) => self.form.selected = self.form.selected.saturating_sub(1),
+            (Focus::Form, Key::Down) => {
+                self.form.selected = std::cmp::min(self.form.selected + 1, self.form.values.len().saturating_sub(1))
+            }
+            (Focus::Form, Key::Char(c)) => {
+                if let Some(value) = self.form.values.get_mut(self.form.selected) {
+                    value.push(c);
+                }
+            }
+            (Focus::Form, Key::Backspace) => {
+                if let Some(value) = self.form.values.get_mut(self.form.selected) {
+                    value.pop();
+                }
+            }
+            (Focus::Form, Key::Enter) => match self.form_payload() {
+                Ok((opcode, payload)) => {
+                    self.status = format!("sending {}", self.selected_message().map_or("", |info| info.name.as_str()));
+                    return Action::Send(opcode, payload);
+                }
+                Err(e) => self.status = e,
+            },
+            _ => {}
+        }
+        Action::None
+    }
+}
+
+//the keys the app knows, translated from the terminal events by main.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    End,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swordfish_com::swordfish_messages::{Ping, StreamStart};
+    use swordfish_com::SwordFishMessageTrait;
+
+    fn frame(direction: Direction, msg: SwordFishConcentratedMessage) -> Event {
+        Event::Frame(FrameRow { direction, host_us: 0, msg })
+    }
+
+    #[test]
+    fn frames_are_counted_and_filtered() {
+        let mut app = App::new(MessageRegistry::new(), 0);
+        app.handle_event(frame(Direction::Tx, Ping::default().to_concentrated(1)));
+        app.handle_event(frame(Direction::Rx, Ping::default().to_concentrated(1)));
+        app.handle_event(frame(Direction::Tx, StreamStart::new(100, 10).to_concentrated(2)));
+        assert_eq!(app.counters[&Ping::OPCODE], OpcodeCounter { tx: 1, rx: 1 });
+        assert_eq!(app.visible_frames().len(), 3);
+
+        app.handle_key(Key::Char('d'));
+        assert_eq!(app.visible_frames().len(), 2);
+        app.handle_key(Key::Char('d'));
+        assert_eq!(app.visible_frames().len(), 1);
+        app.handle_key(Key::Char('d'));
+        app.handle_key(Key::Char('o'));
+        assert_eq!(app.opcode_filter, Some(Ping::OPCODE));
+        assert_eq!(app.visible_frames().len(), 2);
+        app.handle_key(Key::Char('O'));
+        assert_eq!(app.opcode_filter, None);
+        assert_eq!(app.handle_key(Key::Char('q')), Action::Quit);
+    }
+
+    #[test]
+    fn the_form_encodes_the_typed_fields() {
+        let mut app = App::new(MessageRegistry::new(), 0);
+        app.handle_key(Key::Tab);
+        while app.selected_message().unwrap().opcode != StreamStart::OPCODE {
+            app.handle_key(Key::Right);
+        }
+        assert_eq!(app.form.values, vec!["0", "0"]);
+        app.handle_key(Key::Backspace);
+        for c in "100".chars() {
+            app.handle_key(Key::Char(c));
+        }
+        app.handle_key(Key::Down);
+        app.handle_key(Key::Char('5'));
+        let expected = StreamStart::new(100, 5).to_concentrated(0);
+        assert_eq!(app.handle_key(Key::Enter), Action::Send(StreamStart::OPCODE, expected.payload[..expected.length as usize].to_vec()));
+
+        app.handle_key(Key::Char('0'));
+        app.handle_key(Key::Char('0'));
+        app.handle_key(Key::Char('0'));
+        app.handle_key(Key::Char('0'));
+        app.handle_key(Key::Char('0'));
+        assert_eq!(app.handle_key(Key::Enter), Action::None);
+        assert!(app.status.contains("rate_hz"), "{}", app.status);
+    }
+}
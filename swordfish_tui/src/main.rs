@@ -0,0 +1,191 @@
+//swordfish-tui, a terminal monitor for the bench: the decoded frames in both directions (filtered by direction
+//and message), the counters of every message and of the link, the ping round trips, and a form to send any
+//message of the registry.
+//  swordfish-tui --port /dev/ttyUSB0
+//  swordfish-tui --simulator           the built-in simulator, in process
+//  swordfish-tui --pty-simulator       the simulator behind a pseudo terminal, opened like a board (linux)
+mod app;
+mod ui;
+
+use app::{Action, App, Event, FrameRow, Key};
+use clap::Parser;
+use ratatui::crossterm::event::{self, Event as TerminalEvent, KeyCode, KeyEventKind, KeyModifiers};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use swordfish_com::swordfish_bridge::redact_port_url;
+use swordfish_com::swordfish_capture::Direction;
+use swordfish_com::swordfish_clock_sync::host_now_us;
+use swordfish_com::swordfish_comm::{find_probable_swordfish_port, SwordFishComm};
+use swordfish_com::swordfish_messages::Ping;
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::{SwordFishConcentratedMessage, SwordFishMessageTrait};
+
+#[derive(Parser)]
+#[command(name = "swordfish-tui", version, about = "Watch and drive a swordfish board from the terminal")]
+struct Args {
+    /// Serial port of the board, the first port that looks like a swordfish when not given
+    #[arg(short, long)]
+    port: Option<String>,
+    /// Talk to the built-in simulator instead of a board
+    #[arg(long, conflicts_with_all = ["port", "pty_simulator"])]
+    simulator: bool,
+    /// Run the simulator behind a pseudo terminal and open it like a serial port, the in process one outside of linux
+    #[arg(long, conflicts_with = "port")]
+    pty_simulator: bool,
+    /// Ping period in ms, 0 to not ping
+    #[arg(long, default_value_t = 500)]
+    ping_ms: u64,
+    /// How long to wait for an answer, in ms
+    #[arg(short, long, default_value_t = 200)]
+    timeout: u64,
+}
+
+//the comm, and the simulator it talks to that must live as long as it
+fn connect(args: &Args) -> anyhow::Result<(Arc<SwordFishComm>, Option<SwordFishSimulator>)> {
+    //there are no pseudo terminals outside of linux, the simulator runs in process there
+    if args.simulator || (args.pty_simulator && !cfg!(target_os = "linux")) {
+        let (simulator, comm) = SwordFishSimulator::connect();
+        return Ok((Arc::new(comm), Some(simulator)));
+    }
+    let (simulator, port) = match (&args.port, args.pty_simulator) {
+        (_, true) => pty_simulator()?,
+        (Some(port), false) => (None, port.clone()),
+        (None, false) => (
+            None,
+            find_probable_swordfish_port().ok_or_else(|| anyhow::anyhow!("No swordfish found, pass its port with --port"))?,
+        ),
+    };
+    let comm = SwordFishComm::new(&port).map_err(|e| anyhow::anyhow!("Could not open {}: {}", redact_port_url(&port), e))?;
+    Ok((Arc::new(comm), simulator))
+}
+
+#[cfg(target_os = "linux")]
+fn pty_simulator() -> anyhow::Result<(Option<SwordFishSimulator>, String)> {
+    let (simulator, port) = SwordFishSimulator::spawn_pty()?;
+    Ok((Some(simulator), port))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pty_simulator() -> anyhow::Result<(Option<SwordFishSimulator>, String)> {
+    unreachable!("connect runs the simulator in process outside of linux")
+}
+
+//the frames of both directions go to the app through the comm's hooks
+fn hook_frames(comm: &SwordFishComm, events: &Sender<Event>) {
+    let rx_events = events.clone();
+    comm.add_rx_hook(Box::new(move |msg, timestamp| {
+        let host_us = timestamp.host.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_micros() as u64);
+        let _ = rx_events.send(Event::Frame(FrameRow {
+            direction: Direction::Rx,
+            host_us,
+            msg: *msg,
+        }));
+    }));
+    let tx_events = events.clone();
+    comm.add_tx_hook(Box::new(move |msg, host_us| {
+        let _ = tx_events.send(Event::Frame(FrameRow {
+            direction: Direction::Tx,
+            host_us,
+            msg: *msg,
+        }));
+    }));
+}
+
+fn spawn_pinger(comm: Arc<SwordFishComm>, events: Sender<Event>, period: Duration, timeout: Duration, alive: Arc<AtomicBool>) {
+    std::thread::spawn(move || {
+        while alive.load(Ordering::Relaxed) {
+            let start = Instant::now();
+            let msg = Ping::default().to_concentrated(comm.next_tx_counter());
+            let rtt = comm.request_with_timeout(msg, timeout).ok().map(|_| start.elapsed());
+            if events.send(Event::Ping(rtt)).is_err() {
+                return;
+            }
+            std::thread::sleep(period.saturating_sub(start.elapsed()));
+        }
+    });
+}
+
+//a request blocks until the answer, it runs beside the ui
+fn send(comm: &Arc<SwordFishComm>, events: &Sender<Event>, opcode: u8, payload: Vec<u8>, timeout: Duration) {
+    let comm = comm.clone();
+    let events = events.clone();
+    std::thread::spawn(move || {
+        let msg = SwordFishConcentratedMessage::new(comm.next_tx_counter(), opcode, &payload);
+        let answer = comm.request_with_timeout(msg, timeout).map_err(|e| e.to_string());
+        let _ = events.send(Event::Answer(answer));
+    });
+}
+
+fn key(event: &TerminalEvent) -> Option<Key> {
+    let key_event = match event {
+        TerminalEvent::Key(key_event) if key_event.kind != KeyEventKind::Release => key_event,
+        _ => return None,
+    };
+    Some(match key_event.code {
+        KeyCode::Char(c) if key_event.modifiers.contains(KeyModifiers::CONTROL) => Key::Ctrl(c),
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Tab | KeyCode::BackTab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::End => Key::End,
+        _ => return None,
+    })
+}
+
+fn run(args: &Args) -> anyhow::Result<()> {
+    let (comm, _simulator) = connect(args)?;
+    let timeout = Duration::from_millis(args.timeout);
+    let (events, received) = mpsc::channel();
+    hook_frames(&comm, &events);
+    let alive = Arc::new(AtomicBool::new(true));
+    if args.ping_ms > 0 {
+        spawn_pinger(comm.clone(), events.clone(), Duration::from_millis(args.ping_ms), timeout, alive.clone());
+    }
+
+    let mut app = App::new(comm.registry(), host_now_us());
+    let mut terminal = ratatui::init();
+    let result = (|| -> anyhow::Result<()> {
+        loop {
+            for event in received.try_iter() {
+                app.handle_event(event);
+            }
+            app.link = comm.link_stats();
+            terminal.draw(|frame| ui::draw(frame, &app))?;
+            if !event::poll(Duration::from_millis(50))? {
+                continue;
+            }
+            let action = match key(&event::read()?) {
+                Some(key) => app.handle_key(key),
+                None => Action::None,
+            };
+            match action {
+                Action::None => {}
+                Action::Quit => return Ok(()),
+                Action::Send(opcode, payload) => send(&comm, &events, opcode, payload, timeout),
+            }
+        }
+    })();
+    ratatui::restore();
+    alive.store(false, Ordering::Relaxed);
+    result
+}
+
+fn main() -> std::process::ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("swordfish-tui: {}", e);
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//draws the app: the frames on the left, the counters, the ping sparkline and the send form on the right
+use crate::app::{describe, App, Focus};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline};
+use ratatui::Frame;
+use swordfish_com::swordfish_capture::Direction;
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, status] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let [left, right] = Layout::horizontal([Constraint::Percentage(62), Constraint::Percentage(38)]).areas(main);
+    let [counters, ping, form] =
+        Layout::vertical([Constraint::Min(6), Constraint::Length(6), Constraint::Length(10)]).areas(right);
+    draw_frames(frame, app, left);
+    draw_counters(frame, app, counters);
+    draw_ping(frame, app, ping);
+    draw_form(frame, app, form);
+
+    let help = match app.focus {
+        Focus::Frames => "q quit  tab send form  d direction  o/O message  ↑↓ scroll  end follow  c clear",
+        Focus::Form => "tab/esc frames  ←→ message  ↑↓ field  type to edit  enter send",
+    };
+    let line = match app.status.is_empty() {
+        true => Line::from(help),
+        false => Line::from(vec![Span::styled(app.status.as_str(), Style::new().fg(Color::Yellow)), Span::raw("  "), Span::raw(help)]),
+    };
+    frame.render_widget(Paragraph::new(line), status);
+}
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = match focused {
+        true => Style::new().fg(Color::Cyan),
+        false => Style::new(),
+    };
+    Block::new().borders(Borders::ALL).border_style(style).title(title)
+}
+
+fn draw_frames(frame: &mut Frame, app: &App, area: Rect) {
+    let visible = app.visible_frames();
+    let height = area.height.saturating_sub(2) as usize;
+    let end = visible.len().saturating_sub(app.scroll);
+    let start = end.saturating_sub(height);
+    let items: Vec<ListItem> = visible[start..end]
+        .iter()
+        .map(|row| {
+            let seconds = row.host_us.saturating_sub(app.started_us) as f64 / 1e6;
+            let (arrow, color) = match row.direction {
+                Direction::Tx => ("tx", Color::Green),
+                Direction::Rx => ("rx", Color::Blue),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:>9.3} ", seconds), Style::new().fg(Color::DarkGray)),
+                Span::styled(format!("{} ", arrow), Style::new().fg(color).add_modifier(Modifier::BOLD)),
+                Span::raw(describe(&app.registry, &row.msg)),
+            ]))
+        })
+        .collect();
+    let following = match app.scroll {
+        0 => String::new(),
+        n => format!(", {} newer", n),
+    };
+    let title = format!(" frames ({}) {}{} ", app.filter_text(), visible.len(), following);
+    frame.render_widget(List::new(items).block(block(title, app.focus == Focus::Frames)), area);
+}
+
+fn draw_counters(frame: &mut Frame, app: &App, area: Rect) {
+    let link = &app.link;
+    let mut lines = vec![
+        Line::from(format!("tx {} frames {} B   rx {} frames {} B", link.tx_frames, link.tx_bytes, link.rx_frames, link.rx_bytes)),
+        Line::from(format!(
+            "bad checksum {}  write errors {}  timeouts {}  nacks {}",
+            link.bad_frames, link.write_errors, link.timeouts, link.rejected
+        )),
+        Line::from(Span::styled(format!("{:<20}{:>8}{:>8}", "message", "tx", "rx"), Style::new().add_modifier(Modifier::BOLD))),
+    ];
+    for (opcode, counter) in &app.counters {
+        let name = app.registry.get(*opcode).map_or(format!("opcode {}", opcode), |info| info.name.clone());
+        lines.push(Line::from(format!("{:<20}{:>8}{:>8}", name, counter.tx, counter.rx)));
+    }
+    frame.render_widget(Paragraph::new(lines).block(block(" counters ".to_string(), false)), area);
+}
+
+fn draw_ping(frame: &mut Frame, app: &App, area: Rect) {
+    let title = match app.pings.back() {
+        Some(last) => {
+            let max = app.pings.iter().max().copied().unwrap_or(0);
+            format!(" ping {:.2} ms (max {:.2}, lost {}) ", *last as f64 / 1e3, max as f64 / 1e3, app.pings_lost)
+        }
+        None => format!(" ping (lost {}) ", app.pings_lost),
+    };
+    //the newest on the right
+    let width = area.width.saturating_sub(2) as usize;
+    let data: Vec<u64> = app.pings.iter().skip(app.pings.len().saturating_sub(width)).copied().collect();
+    let sparkline = Sparkline::default()
+        .block(block(title, false))
+        .data(&data)
+        .style(Style::new().fg(Color::Magenta));
+    frame.render_widget(sparkline, area);
+}
+
+fn draw_form(frame: &mut Frame, app: &App, area: Rect) {
+    let focused = app.focus == Focus::Form;
+    let info = match app.selected_message() {
+        Some(info) => info,
+        None => {
+            frame.render_widget(Paragraph::new("no messages").block(block(" send ".to_string(), focused)), area);
+            return;
+        }
+    };
+    let mut lines = vec![Line::from(vec![
+        Span::raw("< "),
+        Span::styled(info.name.clone(), Style::new().add_modifier(Modifier::BOLD)),
+        Span::raw(format!(" ({}, {}) >", info.opcode, info.category.name())),
+    ])];
+    for (i, (field, value)) in info.fields.iter().zip(&app.form.values).enumerate() {
+        let style = match focused && i == app.form.selected {
+            true => Style::new().fg(Color::Black).bg(Color::Cyan),
+            false => Style::new(),
+        };
+        lines.push(Line::from(vec![
+            Span::raw(format!("{:>12}: ", field.name)),
+            Span::styled(value.clone(), style),
+            Span::styled(format!("  {}", field.type_name.replace(' ', "")), Style::new().fg(Color::DarkGray)),
+        ]));
+    }
+    frame.render_widget(Paragraph::new(lines).block(block(" send ".to_string(), focused)), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{Event, FrameRow};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::time::Duration;
+    use swordfish_com::swordfish_messages::Ping;
+    use swordfish_com::{MessageRegistry, SwordFishMessageTrait};
+
+    #[test]
+    fn every_panel_is_drawn() {
+        let mut app = App::new(MessageRegistry::new(), 0);
+        app.handle_event(Event::Frame(FrameRow {
+            direction: Direction::Rx,
+            host_us: 1_500_000,
+            msg: Ping::default().to_concentrated(7),
+        }));
+        app.handle_event(Event::Ping(Some(Duration::from_micros(1500))));
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("1.500 rx Ping (opcode 0, bounce) #7"));
+        assert!(screen.contains("ping 1.50 ms"));
+        assert!(screen.contains("counters"));
+        assert!(screen.contains("< Ping (0, bounce) >"));
+    }
+}
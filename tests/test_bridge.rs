@@ -0,0 +1,100 @@
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::time::Duration;
+use swordfish_com::swordfish_bridge::{SwordFishBridge, TcpTransport};
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::{DeviceLog, Ping, VersionData};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::memory_link;
+use swordfish_com::SwordFishMessageTrait;
+
+fn bridge(token: Option<&str>) -> (SwordFishSimulator, SwordFishBridge) {
+    let (device, simulator_port) = memory_link();
+    let simulator = SwordFishSimulator::spawn(Box::new(simulator_port));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let bridge = SwordFishBridge::spawn(listener, Box::new(device), token.map(str::to_string), "simulator").unwrap();
+    (simulator, bridge)
+}
+
+#[test]
+fn remote_comm_uses_the_same_api() {
+    let (simulator, bridge) = bridge(Some("secret"));
+    let comm = SwordFishComm::new(&format!("tcp://secret@{}", bridge.local_addr())).unwrap();
+    let answer = comm.request(VersionData::default().to_concentrated(1)).unwrap();
+    assert_eq!(answer, VersionData::new(1, 0, 0, &[0x5f; 8]).to_concentrated(1));
+    assert!(bridge.client().is_some());
+
+    //the frames the board sends on its own reach the hooks
+    let (sender, received) = mpsc::channel();
+    comm.add_rx_hook(Box::new(move |msg, _| {
+        let _ = sender.send(msg.opcode);
+    }));
+    simulator.send(DeviceLog::new(2, 1, 0, "remote").unwrap().to_concentrated(5));
+    assert_eq!(received.recv_timeout(Duration::from_secs(1)).unwrap(), DeviceLog::OPCODE);
+
+    //one session at a time
+    let busy = TcpTransport::connect(&bridge.local_addr().to_string(), Some("secret")).err().unwrap();
+    assert!(busy.to_string().contains("busy"));
+    drop(comm);
+    for _ in 0..100 {
+        if bridge.client().is_none() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let comm = SwordFishComm::connect_tcp(&bridge.local_addr().to_string(), Some("secret")).unwrap();
+    assert!(comm.request(Ping::default().to_concentrated(2)).is_ok());
+    assert_eq!(bridge.sessions(), 2);
+}
+
+#[test]
+fn sessions_need_the_token() {
+    let (_simulator, bridge) = bridge(Some("secret"));
+    let address = bridge.local_addr().to_string();
+    let refused = TcpTransport::connect(&address, Some("guess")).err().unwrap();
+    assert!(refused.to_string().contains("bad token"));
+    assert!(TcpTransport::connect(&address, None).is_err());
+    assert!(SwordFishComm::new(&format!("tcp://guess@{}", address)).is_err());
+    assert_eq!(bridge.sessions(), 0);
+}
+
+#[test]
+fn the_client_needs_the_bridge_to_know_the_token() {
+    use std::io::{BufRead, BufReader, Write};
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    //answers ok to anyone, without a proof of its own
+    let impostor = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(format!("swordfish-bridge 2 {}\n", "00".repeat(32)).as_bytes()).unwrap();
+        BufReader::new(stream.try_clone().unwrap()).read_line(&mut String::new()).unwrap();
+        stream.write_all(format!("ok {} simulator\n", "00".repeat(32)).as_bytes()).unwrap();
+    });
+    let refused = TcpTransport::connect(&address, Some("secret")).err().unwrap();
+    assert!(refused.to_string().contains("could not prove"));
+    impostor.join().unwrap();
+}
+
+#[test]
+fn pending_handshakes_are_limited() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpStream;
+    let (_simulator, bridge) = bridge(Some("secret"));
+    let pending: Vec<TcpStream> = (0..8)
+        .map(|_| {
+            let stream = TcpStream::connect(bridge.local_addr()).unwrap();
+            BufReader::new(stream.try_clone().unwrap()).read_line(&mut String::new()).unwrap();
+            stream
+        })
+        .collect();
+    let mut closed = TcpStream::connect(bridge.local_addr()).unwrap();
+    closed.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(closed.read(&mut [0u8; 64]).unwrap(), 0);
+    //the bridge sees them closed a moment later
+    drop(pending);
+    let opened = (0..100).any(|_| {
+        std::thread::sleep(Duration::from_millis(10));
+        SwordFishComm::connect_tcp(&bridge.local_addr().to_string(), Some("secret")).is_ok()
+    });
+    assert!(opened);
+}
@@ -0,0 +1,67 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swordfish_com::swordfish_capture::{read_frames, Direction};
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::{Ping, VersionData};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::memory_link;
+use swordfish_com::SwordFishMessageTrait;
+
+//a writer the test can read back after the comm is done with it
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn requests_and_answers_are_captured() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let buffer = SharedBuffer::default();
+    comm.start_capture_to(Box::new(buffer.clone())).unwrap();
+    comm.request(Ping::default().to_concentrated(1)).unwrap();
+    comm.request(VersionData::default().to_concentrated(2)).unwrap();
+    assert_eq!(comm.stop_capture().unwrap(), 4);
+    comm.request(Ping::default().to_concentrated(3)).unwrap();
+
+    let frames = read_frames(&buffer.0.lock().unwrap()[..]).unwrap();
+    let directions: Vec<Direction> = frames.iter().map(|frame| frame.direction).collect();
+    assert_eq!(directions, [Direction::Tx, Direction::Rx, Direction::Tx, Direction::Rx]);
+    assert_eq!(&frames[3].bytes[..], &*VersionData::new(1, 0, 0, &[0x5f; 8]).to_concentrated(2).into_bytes());
+    assert!(frames.windows(2).all(|pair| pair[0].host_us <= pair[1].host_us));
+}
+
+#[test]
+fn bad_checksum_frames_are_captured() {
+    let (host, mut device) = memory_link();
+    let comm = SwordFishComm::from_transport(Box::new(host));
+    let buffer = SharedBuffer::default();
+    comm.start_capture_to(Box::new(buffer.clone())).unwrap();
+
+    let good = Ping::default().to_concentrated(7).into_bytes();
+    let mut bad = good.to_vec();
+    *bad.last_mut().unwrap() ^= 0xff;
+    device.write_all(&bad).unwrap();
+    device.write_all(&good).unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(1);
+    while comm.get_rx_counter() == 0 && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    drop(comm);
+
+    let frames = read_frames(&buffer.0.lock().unwrap()[..]).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].bad_checksum);
+    assert_eq!(frames[0].bytes, bad);
+    assert!(!frames[1].bad_checksum);
+    assert_eq!(frames[1].direction, Direction::Rx);
+}
@@ -0,0 +1,105 @@
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::time::Duration;
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_daemon::{DaemonConfig, SwordFishDaemon};
+use swordfish_com::swordfish_error::{codes, SwordFishError};
+use swordfish_com::swordfish_messages::{Calibrate, DfuEnterBootloader, ImuSample, Ping, StreamStart, StreamStop, VersionData};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::memory_link;
+use swordfish_com::{MessageRegistry, SwordFishMessageTrait};
+
+fn daemon(name: &str) -> (SwordFishSimulator, SwordFishDaemon, PathBuf) {
+    let path = std::env::temp_dir().join(format!("swordfish_{}_{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (device, simulator_port) = memory_link();
+    let simulator = SwordFishSimulator::spawn(Box::new(simulator_port));
+    let listener = UnixListener::bind(&path).unwrap();
+    let daemon = SwordFishDaemon::spawn(listener, Box::new(device), DaemonConfig::default(), &MessageRegistry::new(), "simulator").unwrap();
+    (simulator, daemon, path)
+}
+
+fn wait_until(condition: impl Fn() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    panic!("The condition was not met in time");
+}
+
+#[test]
+fn answers_go_back_to_the_client_that_asked() {
+    let (_simulator, _daemon, path) = daemon("answers");
+    let url = format!("unix://{}", path.display());
+    let gui = SwordFishComm::new(&url).unwrap();
+    let logger = SwordFishComm::new(&url).unwrap();
+    //the same counters from both, each gets its own answer back
+    for counter in 1..20 {
+        let version = gui.request(VersionData::default().to_concentrated(counter)).unwrap();
+        assert_eq!(version.counter, counter);
+        let ping = logger.request(Ping::default().to_concentrated(counter)).unwrap();
+        assert_eq!((ping.opcode, ping.counter), (Ping::OPCODE, counter));
+    }
+    //a Nack has the counter of the client in its payload
+    match gui.request(StreamStart::new(2, 10).to_concentrated(7)) {
+        Err(SwordFishError::DeviceRejected { counter, .. }) => assert_eq!(counter, 7),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+}
+
+#[test]
+fn streams_fan_out_and_stop_with_their_last_client() {
+    let (simulator, daemon, path) = daemon("streams");
+    let runner = SwordFishComm::new(&format!("unix://{}", path.display())).unwrap();
+    let logger = SwordFishComm::new(&format!("unix://{}?subscribe=ImuSample", path.display())).unwrap();
+    let bystander = SwordFishComm::new(&format!("unix://{}", path.display())).unwrap();
+    runner.start_stream(ImuSample::OPCODE, 200).unwrap();
+    let received = std::cell::Cell::new(0);
+    wait_until(|| {
+        received.set(received.get() + logger.drain::<ImuSample>(100).len());
+        received.get() > 3
+    });
+    assert!(!runner.drain::<ImuSample>(100).is_empty());
+    assert!(bystander.drain::<ImuSample>(100).is_empty());
+
+    //the bystander did not start it, its stop does not reach the device
+    bystander.start_stream(ImuSample::OPCODE, 200).unwrap();
+    bystander.request(StreamStop::new(ImuSample::OPCODE).to_concentrated(1)).unwrap();
+    assert!(simulator.device().streams.is_active(ImuSample::OPCODE));
+    drop(runner);
+    drop(bystander);
+    wait_until(|| !simulator.device().streams.is_active(ImuSample::OPCODE));
+    wait_until(|| daemon.status().streams.is_empty());
+}
+
+#[test]
+fn a_firmware_update_locks_the_device() {
+    let (_simulator, daemon, path) = daemon("lock");
+    let url = format!("unix://{}", path.display());
+    let updater = SwordFishComm::new(&url).unwrap();
+    let gui = SwordFishComm::new(&url).unwrap();
+    updater.request(DfuEnterBootloader::new(1024).to_concentrated(1)).unwrap();
+    match gui.request(Ping::default().to_concentrated(1)) {
+        Err(SwordFishError::DeviceRejected { code, .. }) => assert_eq!(code, codes::BUSY),
+        other => panic!("expected busy, got {:?}", other),
+    }
+    assert!(updater.request(Ping::default().to_concentrated(2)).is_ok());
+    //the lock ends with its client
+    drop(updater);
+    wait_until(|| daemon.status().exclusive_owner.is_none());
+    assert!(gui.request(Ping::default().to_concentrated(2)).is_ok());
+}
+
+#[test]
+fn operations_keep_the_counter_of_the_client() {
+    let (_simulator, daemon, path) = daemon("operations");
+    let comm = SwordFishComm::new(&format!("unix://{}", path.display())).unwrap();
+    let first = comm.start_operation(Calibrate::new(0).to_concentrated(40), Duration::from_secs(1)).unwrap();
+    assert!(first.cancel());
+    let second = comm.start_operation(Calibrate::new(2).to_concentrated(41), Duration::from_secs(1)).unwrap();
+    assert_eq!(second.wait().unwrap().get_data(), vec![2, 0x12, 0x34]);
+    assert_eq!(second.progress().len(), 4);
+    wait_until(|| daemon.status().operations.is_empty());
+}
@@ -0,0 +1,66 @@
+use serde_json::json;
+use swordfish_com::swordfish_messages::create_swordfish_messages_hashmap;
+use swordfish_com::swordfish_registry::MessageRegistry;
+use swordfish_com::{BoundedString, SwordFishMessage, SwordFishMessageCategory, SwordFishMessageTrait};
+
+//messages derived outside swordfish_com, like an application would
+#[derive(Debug, PartialEq, Default, SwordFishMessage)]
+#[swordfish(opcode = 240, category = "bounce")]
+struct BoardName {
+    slot: u8,
+    name: BoundedString<16>,
+}
+
+#[derive(Debug, PartialEq, Default, SwordFishMessage)]
+#[swordfish(opcode = 241, category = "param")]
+struct FanSpeed {
+    rpm: u16,
+}
+
+type Rpm = u16;
+
+//field types spelled with paths and aliases, the layout comes from the types and not from how they are written
+#[derive(Debug, PartialEq, Default, SwordFishMessage)]
+#[swordfish(opcode = 242, category = "param")]
+struct PumpSettings {
+    name: ::swordfish_com::BoundedString<16>,
+    steps: swordfish_com::swordfish_wire::BoundedVec<u8, 8>,
+    total: std::primitive::u32,
+    rpm: Rpm,
+}
+
+#[test]
+fn messages_derived_in_another_crate_round_trip() {
+    let board = BoardName {
+        slot: 3,
+        name: BoundedString::new("left-arm").unwrap(),
+    };
+    let frame = board.to_concentrated(5);
+    assert_eq!(frame.opcode, 240);
+    assert_eq!(BoardName::from_concentrated(&frame).unwrap(), board);
+    assert_eq!(BoardName::CATEGORY, SwordFishMessageCategory::Bounce);
+    assert_eq!(BoardName::FIELDS[1].name, "name");
+}
+
+#[test]
+fn messages_derived_in_another_crate_are_registered() {
+    let map = create_swordfish_messages_hashmap();
+    assert_eq!(map[&BoardName::OPCODE].catagory, SwordFishMessageCategory::Bounce);
+    assert_eq!(map[&FanSpeed::OPCODE].catagory, SwordFishMessageCategory::Param);
+}
+
+#[test]
+fn the_layout_does_not_depend_on_how_types_are_written() {
+    let registry = MessageRegistry::new();
+    let info = registry.get(PumpSettings::OPCODE).unwrap();
+    let types: Vec<&str> = info.fields.iter().map(|field| field.type_name.as_str()).collect();
+    assert_eq!(types, ["BoundedString<16>", "BoundedVec<u8, 8>", "u32", "u16"]);
+    let schema = info.json_schema();
+    assert_eq!(schema["properties"]["name"], json!({ "type": "string", "maxLength": 16 }));
+    assert_eq!(schema["properties"]["rpm"]["type"], json!("integer"));
+    let fields = info.fields_from_json(&json!({ "name": "inlet", "steps": "0102", "total": 7, "rpm": 1200 })).unwrap();
+    let payload = info.encode_fields(&fields).unwrap();
+    let settings = PumpSettings::decode_payload(&payload).unwrap();
+    assert_eq!(settings.name.as_str(), "inlet");
+    assert_eq!(settings.rpm, 1200);
+}
@@ -0,0 +1,86 @@
+use swordfish_com::swordfish_dfu::{DfuStage, DfuState, FirmwareImage, FirmwareUpdater};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn test_image() -> FirmwareImage {
+    let data: Vec<u8> = (0..1000).map(|i| (i * 7 % 251) as u8).collect();
+    FirmwareImage::from_bin(&data, 0x0800_4000)
+}
+
+#[test]
+fn update_against_simulator() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    let image = test_image();
+    let stages = Arc::new(Mutex::new(Vec::new()));
+    let stages_clone = stages.clone();
+    FirmwareUpdater::new(&comm)
+        .on_progress(move |progress| stages_clone.lock().unwrap().push(progress))
+        .update(&image)
+        .expect("update failed");
+
+    let device = simulator.device();
+    assert_eq!(device.bootloader.state, DfuState::Application);
+    assert_eq!(device.bootloader.running_crc, image.crc32());
+    assert_eq!(&device.bootloader.flash[..image.len()], &image.data[..]);
+    let stages = stages.lock().unwrap();
+    assert_eq!(stages.first().unwrap().stage, DfuStage::EnterBootloader);
+    assert_eq!(stages.last().unwrap().stage, DfuStage::RollbackCheck);
+    assert!(stages
+        .iter()
+        .any(|p| p.stage == DfuStage::Write && p.done == image.len() && p.total == image.len()));
+}
+
+#[test]
+fn interrupted_update_is_resumed() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    let image = test_image();
+    simulator.device().bootloader.stall_after_blocks = Some(3);
+    let result = FirmwareUpdater::new(&comm)
+        .timeout(Duration::from_millis(50), 1)
+        .update(&image);
+    assert!(result.is_err());
+
+    simulator.device().bootloader.stall_after_blocks = None;
+    let first_write = Arc::new(Mutex::new(None));
+    let first_write_clone = first_write.clone();
+    FirmwareUpdater::new(&comm)
+        .on_progress(move |progress| {
+            if progress.stage == DfuStage::Write {
+                first_write_clone.lock().unwrap().get_or_insert(progress.done);
+            }
+        })
+        .update(&image)
+        .expect("resumed update failed");
+
+    assert_eq!(*first_write.lock().unwrap(), Some(3 * 128));
+    //8 blocks in total, the first 3 were not sent again
+    assert_eq!(simulator.device().bootloader.blocks_received, 8);
+    assert_eq!(simulator.device().bootloader.running_crc, image.crc32());
+}
+
+#[test]
+fn written_block_with_a_lost_answer_is_not_written_again() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    let image = test_image();
+    //the simulated flash refuses a second write of the block, like real flash
+    simulator.device().bootloader.lose_answer_to_block = Some(2);
+    FirmwareUpdater::new(&comm)
+        .timeout(Duration::from_millis(50), 2)
+        .update(&image)
+        .expect("update failed");
+
+    assert_eq!(simulator.device().bootloader.blocks_received, 8);
+    assert_eq!(simulator.device().bootloader.running_crc, image.crc32());
+}
+
+#[test]
+fn rollback_is_reported() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    simulator.device().bootloader.image_boots = false;
+    let result = FirmwareUpdater::new(&comm)
+        .reboot_timeout(Duration::from_millis(200))
+        .update(&test_image());
+    assert!(result.unwrap_err().to_string().contains("rolled back"));
+    assert_eq!(simulator.device().bootloader.state, DfuState::RolledBack);
+}
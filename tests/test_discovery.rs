@@ -0,0 +1,15 @@
+use std::time::Duration;
+use swordfish_com::swordfish_discovery::probe_transport;
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::swordfish_transport::memory_link;
+
+#[test]
+fn probe_by_ping() {
+    let (mut host, device) = memory_link();
+    let simulator = SwordFishSimulator::spawn(Box::new(device));
+    assert!(probe_transport(&mut host, Duration::from_millis(100)));
+
+    //a board that does not answer pings
+    simulator.device().set_handler(0, Box::new(|_| vec![]));
+    assert!(!probe_transport(&mut host, Duration::from_millis(50)));
+}
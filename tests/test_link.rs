@@ -0,0 +1,34 @@
+use std::sync::mpsc;
+use std::time::Duration;
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::{Ping, StreamStart, VersionData};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::SwordFishMessageTrait;
+
+#[cfg(target_os = "linux")]
+#[test]
+fn the_simulator_works_as_a_serial_port() {
+    let (_simulator, port_name) = SwordFishSimulator::spawn_pty().unwrap();
+    let comm = SwordFishComm::new(&port_name).unwrap();
+    let answer = comm.request(VersionData::default().to_concentrated(1)).unwrap();
+    assert_eq!(answer, VersionData::new(1, 0, 0, &[0x5f; 8]).to_concentrated(1));
+}
+
+#[test]
+fn link_stats_count_frames_and_failures() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let (sender, sent) = mpsc::channel();
+    comm.add_tx_hook(Box::new(move |msg, _| {
+        let _ = sender.send(msg.opcode);
+    }));
+    comm.request(Ping::default().to_concentrated(1)).unwrap();
+    //not a stream, rejected
+    assert!(comm.request(StreamStart::new(2, 10).to_concentrated(2)).is_err());
+    let stats = comm.link_stats();
+    assert_eq!((stats.tx_frames, stats.rx_frames, stats.rejected, stats.timeouts), (2, 2, 1, 0));
+    let ping = Ping::default().to_concentrated(1).into_bytes().len() as u64;
+    let stream_start = StreamStart::new(2, 10).to_concentrated(2).into_bytes().len() as u64;
+    assert_eq!(stats.tx_bytes, ping + stream_start);
+    assert_eq!(sent.recv_timeout(Duration::from_secs(1)).unwrap(), Ping::OPCODE);
+    assert_eq!(sent.recv_timeout(Duration::from_secs(1)).unwrap(), StreamStart::OPCODE);
+}
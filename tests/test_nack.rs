@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+use swordfish_com::swordfish_dynamic::MessageDescriptor;
+use swordfish_com::swordfish_error::{codes, error_code, SwordFishError};
+use swordfish_com::swordfish_messages::{Calibrate, Ping};
+use swordfish_com::swordfish_operation::OperationError;
+use swordfish_com::swordfish_simulator::{nack, SwordFishSimulator};
+use swordfish_com::{SwordFishConcentratedMessage, SwordFishMessageCategory, SwordFishMessageTrait};
+
+#[test]
+fn rejected_request_is_a_typed_error() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    //an opcode the host knows and the simulated firmware does not
+    comm.register_message(&MessageDescriptor::new("Future", 200, SwordFishMessageCategory::Bounce))
+        .unwrap();
+    let start = Instant::now();
+    let error = comm.request(SwordFishConcentratedMessage::new(7, 200, &[])).unwrap_err();
+    assert!(start.elapsed() < Duration::from_millis(150));
+    assert_eq!(
+        error,
+        SwordFishError::DeviceRejected { opcode: 200, counter: 7, code: codes::UNSUPPORTED, detail: String::new() }
+    );
+    assert!(error.to_string().contains("the firmware does not know this opcode"));
+    assert!(comm.send_msg(SwordFishConcentratedMessage::new(8, 200, &[])).is_none());
+}
+
+#[test]
+fn unknown_opcode_is_an_error_and_is_not_sent() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let error = comm.request(SwordFishConcentratedMessage::new(9, 201, &[])).unwrap_err();
+    assert_eq!(error, SwordFishError::UnknownOpcode { opcode: 201 });
+    assert!(comm.send_msg(SwordFishConcentratedMessage::new(10, 201, &[])).is_none());
+    assert_eq!(comm.link_stats().tx_frames, 0);
+}
+
+#[test]
+fn nack_of_another_request_is_ignored() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    simulator.device().set_handler(
+        Ping::OPCODE,
+        Box::new(|msg| {
+            let mut stale = *msg;
+            stale.counter = msg.counter.wrapping_sub(1);
+            vec![nack(&stale, codes::BUSY, "")]
+        }),
+    );
+    let error = comm.request(Ping::default().to_concentrated(5)).unwrap_err();
+    assert_eq!(error, SwordFishError::Timeout { opcode: Ping::OPCODE });
+}
+
+#[test]
+fn nack_of_another_request_does_not_replace_the_answer() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    simulator.device().set_handler(
+        Ping::OPCODE,
+        Box::new(|msg| {
+            let mut stale = *msg;
+            stale.counter = msg.counter.wrapping_sub(1);
+            vec![*msg, nack(&stale, codes::BUSY, "")]
+        }),
+    );
+    for counter in 1..20 {
+        assert_eq!(comm.request(Ping::default().to_concentrated(counter)).unwrap().counter, counter);
+    }
+}
+
+#[test]
+fn late_answer_to_another_request_is_ignored() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    simulator.device().set_handler(
+        Ping::OPCODE,
+        Box::new(|msg| {
+            let mut late = *msg;
+            late.counter = msg.counter.wrapping_sub(1);
+            vec![late]
+        }),
+    );
+    let error = comm
+        .request_with_timeout(Ping::default().to_concentrated(5), Duration::from_millis(100))
+        .unwrap_err();
+    assert_eq!(error, SwordFishError::Timeout { opcode: Ping::OPCODE });
+}
+
+#[test]
+fn unknown_error_code_names_have_no_code() {
+    assert_eq!(error_code("busy"), Some(codes::BUSY));
+    assert_eq!(error_code("no_such_code"), None);
+}
+
+#[test]
+fn rejected_operation() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    simulator
+        .device()
+        .set_handler(Calibrate::OPCODE, Box::new(|msg| vec![nack(msg, codes::WRONG_STATE, "motor running")]));
+    let handle = comm
+        .start_operation(Calibrate::new(0).to_concentrated(3), Duration::from_secs(1))
+        .unwrap();
+    match handle.wait() {
+        Err(OperationError::Rejected(SwordFishError::DeviceRejected { code, detail, .. })) => {
+            assert_eq!(code, codes::WRONG_STATE);
+            assert_eq!(detail, "motor running");
+        }
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::future::Future;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+use swordfish_com::swordfish_messages::{Calibrate, SelfTest};
+use swordfish_com::swordfish_operation::OperationError;
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::SwordFishMessageTrait;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+#[test]
+fn calibrate_blocking_with_progress() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let handle = comm.start_operation(Calibrate::new(3).to_concentrated(comm.next_tx_counter()), TIMEOUT).unwrap();
+    //one operation per opcode at a time
+    assert!(comm.start_operation(Calibrate::new(3).to_concentrated(comm.next_tx_counter()), TIMEOUT).is_err());
+    let result = handle.wait().unwrap();
+    assert_eq!(result.get_data(), vec![3, 0x12, 0x34]);
+    let percents: Vec<u8> = handle.progress().iter().map(|p| p.get_percent()).collect();
+    assert_eq!(percents, vec![20, 40, 60, 80]);
+    assert!(comm.running_operations().is_empty());
+}
+
+#[test]
+fn self_test_failure_with_callbacks() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    simulator.device().operations.self_test_failures = 0b100;
+    let handle = comm.start_operation(SelfTest::new(0b111).to_concentrated(comm.next_tx_counter()), TIMEOUT).unwrap();
+    let (progress_sender, progress_receiver) = mpsc::channel();
+    handle.on_progress(Box::new(move |p| progress_sender.send(p.get_stage()).unwrap()));
+    let (done_sender, done_receiver) = mpsc::channel();
+    handle.on_complete(Box::new(move |outcome| done_sender.send(outcome.clone()).unwrap()));
+    let outcome = done_receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(
+        outcome,
+        Err(OperationError::Failed { status: 1, data: 0b100u32.to_le_bytes().to_vec() })
+    );
+    assert_eq!(progress_receiver.try_iter().count(), 4);
+}
+
+struct ThreadWaker(std::thread::Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[test]
+fn calibrate_async() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let mut handle = comm.start_operation(Calibrate::new(1).to_concentrated(comm.next_tx_counter()), TIMEOUT).unwrap();
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let outcome = loop {
+        match std::pin::Pin::new(&mut handle).poll(&mut cx) {
+            Poll::Ready(outcome) => break outcome,
+            Poll::Pending => std::thread::park_timeout(Duration::from_millis(100)),
+        }
+    };
+    assert_eq!(outcome.unwrap().get_operation(), Calibrate::OPCODE);
+}
+
+#[test]
+fn cancel_and_timeout() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    let handle = comm.start_operation(Calibrate::new(0).to_concentrated(comm.next_tx_counter()), TIMEOUT).unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(handle.cancel());
+    assert_eq!(handle.wait(), Err(OperationError::Cancelled));
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(!simulator.device().operations.is_running(Calibrate::OPCODE));
+
+    //the device hangs after two steps, the timeout counts from the last progress
+    simulator.device().operations.stall_after = Some(2);
+    let handle = comm.start_operation(Calibrate::new(0).to_concentrated(comm.next_tx_counter()), Duration::from_millis(100)).unwrap();
+    assert_eq!(handle.wait(), Err(OperationError::Timeout));
+    assert_eq!(handle.progress().len(), 2);
+}
+
+#[test]
+fn the_result_of_a_cancelled_run_does_not_end_the_next_one() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let first = comm.start_operation(Calibrate::new(0).to_concentrated(comm.next_tx_counter()), TIMEOUT).unwrap();
+    assert!(first.cancel());
+    //started before the device answers the abort with an OperationResult
+    let second = comm.start_operation(Calibrate::new(5).to_concentrated(comm.next_tx_counter()), TIMEOUT).unwrap();
+    assert_eq!(second.wait().unwrap().get_data(), vec![5, 0x12, 0x34]);
+    assert_eq!(second.progress().len(), 4);
+}
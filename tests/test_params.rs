@@ -0,0 +1,44 @@
+use swordfish_com::swordfish_params::{ParamSet, ParamStore, ParamValue};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+
+#[test]
+fn get_and_set_against_simulator() {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let store = ParamStore::new(&comm);
+    assert_eq!(store.get_param("led_brightness").unwrap(), ParamValue::Integer(50));
+    store.set_param("led_brightness", 80).unwrap();
+    assert_eq!(store.get_param("led_brightness").unwrap(), ParamValue::Integer(80));
+    store.set_param("tx_power", -3.5).unwrap();
+    assert_eq!(store.get_param("tx_power").unwrap(), ParamValue::Float(-3.5));
+
+    //rejected on the host, nothing is sent
+    assert!(store.set_param("led_brightness", 101).is_err());
+    assert!(store.set_param("serial_number", 1).is_err());
+    assert!(store.set_param("no_such_param", 1).is_err());
+}
+
+#[test]
+fn profile_write_diff_and_file() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    let store = ParamStore::new(&comm);
+    let mut profile = store.table().defaults();
+    profile.set("sleep_timeout", 60);
+    profile.set("auto_sleep", false);
+    assert_eq!(store.diff(&profile).unwrap().len(), 2);
+
+    store.write_all(&profile).unwrap();
+    assert!(store.diff(&profile).unwrap().is_empty());
+
+    let path = std::env::temp_dir().join(format!("swordfish_profile_{}.toml", std::process::id()));
+    store.read_all().unwrap().save(&path).unwrap();
+    let loaded = ParamSet::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.get("sleep_timeout"), Some(ParamValue::Integer(60)));
+
+    //the device changed behind our back
+    simulator.device().params.values.insert(2, 10);
+    let diffs = store.diff(&loaded).unwrap();
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].name, "led_brightness");
+    assert_eq!(diffs[0].device, ParamValue::Integer(10));
+}
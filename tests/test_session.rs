@@ -0,0 +1,77 @@
+use std::io::{self, BufReader, Write};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swordfish_com::swordfish_capture::Direction;
+use swordfish_com::swordfish_comm::SwordFishComm;
+use swordfish_com::swordfish_messages::{ImuSample, Ping, VersionData};
+use swordfish_com::swordfish_session::{ReplayTransport, Session};
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::SwordFishMessageTrait;
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//the opcodes and counters the rx hooks of a comm see, in order
+fn received(comm: &SwordFishComm) -> mpsc::Receiver<(u8, u16)> {
+    let (sender, receiver) = mpsc::channel();
+    comm.add_rx_hook(Box::new(move |msg, _| {
+        let _ = sender.send((msg.opcode, msg.counter));
+    }));
+    receiver
+}
+
+fn record() -> (Session, Vec<(u8, u16)>) {
+    let (_simulator, comm) = SwordFishSimulator::connect();
+    let rx = received(&comm);
+    let buffer = SharedBuffer::default();
+    comm.start_recording_to(Box::new(buffer.clone())).unwrap();
+    comm.request(Ping::default().to_concentrated(1)).unwrap();
+    comm.request(VersionData::default().to_concentrated(2)).unwrap();
+    comm.start_stream(ImuSample::OPCODE, 500).unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+    comm.stop_stream(ImuSample::OPCODE).unwrap();
+    assert!(comm.stop_recording().unwrap() >= 4);
+    let seen = rx.try_iter().collect();
+    let bytes = buffer.0.lock().unwrap().clone();
+    (Session::read_from(BufReader::new(&bytes[..])).unwrap(), seen)
+}
+
+#[test]
+fn recorded_sessions_replay_the_same_messages() {
+    let (session, seen) = record();
+    assert!(seen.iter().any(|(opcode, _)| *opcode == ImuSample::OPCODE));
+    let ping = Ping::default().to_concentrated(1).into_bytes();
+    assert_eq!(&session.stream(Direction::Tx)[..ping.len()], &*ping);
+
+    //without sending anything, the hooks see what they saw live
+    let replay = ReplayTransport::new(session.clone()).speed(4.0).unwrap().paused(true);
+    let handle = replay.handle();
+    let comm = SwordFishComm::from_transport(Box::new(replay));
+    let rx = received(&comm);
+    handle.resume();
+    assert!(handle.wait_finished(Duration::from_secs(2)));
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), seen);
+    drop(comm);
+
+    //the same requests get the same answers, whatever the speed
+    let replay = ReplayTransport::new(session).speed(f64::INFINITY).unwrap().sync_on_tx(true);
+    let handle = replay.handle();
+    let comm = SwordFishComm::from_transport(Box::new(replay));
+    comm.request(Ping::default().to_concentrated(1)).unwrap();
+    let answer = comm.request(VersionData::default().to_concentrated(2)).unwrap();
+    assert_eq!(answer, VersionData::new(1, 0, 0, &[0x5f; 8]).to_concentrated(2));
+    assert_eq!(handle.mismatches(), 0);
+}
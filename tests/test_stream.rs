@@ -0,0 +1,34 @@
+use swordfish_com::swordfish_messages::ImuSample;
+use swordfish_com::swordfish_simulator::SwordFishSimulator;
+use swordfish_com::SwordFishMessageTrait;
+
+#[test]
+fn imu_stream_overflows_and_drains_in_order() {
+    let (simulator, comm) = SwordFishSimulator::connect();
+    //resized in place, a buffer taken before keeps receiving
+    let stream = comm.stream_buffer(ImuSample::OPCODE).unwrap();
+    comm.set_stream_capacity(ImuSample::OPCODE, 64).unwrap();
+    assert!(comm.set_stream_capacity(0, 64).is_err());
+    comm.start_stream(ImuSample::OPCODE, 1000).unwrap();
+    assert!(simulator.device().streams.is_active(ImuSample::OPCODE));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let stats = stream.stats();
+    assert_eq!(stats.capacity, 64);
+    assert!(stats.overflowed > 0, "{:?}", stats);
+    let samples = comm.drain::<ImuSample>(32);
+    assert_eq!(samples.len(), 32);
+    for pair in samples.windows(2) {
+        assert_eq!(pair[1].get_sequence(), pair[0].get_sequence() + 1);
+    }
+    //the oldest ones were overwritten
+    assert!(samples[0].get_sequence() > 0);
+
+    comm.stop_stream(ImuSample::OPCODE).unwrap();
+    assert!(!simulator.device().streams.is_active(ImuSample::OPCODE));
+    let stats = comm.stream_buffer(ImuSample::OPCODE).unwrap().stats();
+    assert!(stats.received >= stats.overflowed + stats.buffered as u64 + 32);
+
+    //not a stream
+    assert!(comm.start_stream(0, 10).is_err());
+}
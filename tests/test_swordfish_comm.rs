@@ -36,7 +36,7 @@ fn with_swordfish_opcode2() {
     while rx_counter < 10 {
         //send request for version data
         let request_concentrated_msg =
-            VersionData::default().to_concentrated(swordfish_comm.get_tx_counter() as u16);
+            VersionData::default().to_concentrated(swordfish_comm.next_tx_counter());
 
         let answer = swordfish_comm.send_msg(request_concentrated_msg);
         println!("sent the {} message", swordfish_comm.get_tx_counter());
@@ -62,3 +62,109 @@ fn with_swordfish_opcode2() {
     println!("rx_vec: {:?}", arc_rx_vec.read().unwrap());
     assert_eq!(rx_counter, 10);
 }
+
+#[test]
+fn with_simulator_opcode2() {
+    let (simulator, swordfish_comm) = swordfish_com::swordfish_simulator::SwordFishSimulator::connect();
+    simulator.device().version_data.set_mcu_type(0x1234);
+
+    for _ in 0..10 {
+        let request_concentrated_msg =
+            VersionData::default().to_concentrated(swordfish_comm.next_tx_counter());
+        let answer = swordfish_comm
+            .send_msg(request_concentrated_msg)
+            .expect("the simulator always answers");
+        let version_data = VersionData::from_concentrated(&answer).unwrap();
+        assert_eq!(version_data.get_mcu_type(), 0x1234);
+    }
+    assert_eq!(swordfish_comm.get_rx_counter(), 10);
+}
+
+#[test]
+fn time_sync_and_requests_never_share_a_counter() {
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use swordfish_com::swordfish_messages::TimeSync;
+    let (simulator, swordfish_comm) = swordfish_com::swordfish_simulator::SwordFishSimulator::connect();
+    //the device keeps the counter of every request and echoes it
+    let counters = Arc::new(Mutex::new(Vec::new()));
+    for opcode in [VersionData::OPCODE, TimeSync::OPCODE] {
+        let counters_clone = counters.clone();
+        simulator.device().set_handler(
+            opcode,
+            Box::new(move |msg| {
+                counters_clone.lock().unwrap().push(msg.counter);
+                vec![*msg]
+            }),
+        );
+    }
+
+    swordfish_comm.clock_sync().set_period(Some(Duration::from_millis(1)));
+    for _ in 0..50 {
+        let request = VersionData::default().to_concentrated(swordfish_comm.next_tx_counter());
+        swordfish_comm.send_msg(request).expect("the simulator always answers");
+    }
+    swordfish_comm.clock_sync().set_period(None);
+
+    let mut counters = counters.lock().unwrap().clone();
+    let sent = counters.len();
+    assert!(sent > 50, "no time sync was sent");
+    counters.sort();
+    counters.dedup();
+    assert_eq!(counters.len(), sent);
+}
+
+#[test]
+fn device_logs_from_simulator() {
+    use swordfish_com::swordfish_messages::DeviceLog;
+    let (simulator, swordfish_comm) = swordfish_com::swordfish_simulator::SwordFishSimulator::connect();
+    simulator.send(DeviceLog::new(1, 3, 250, "pll unlocked").unwrap().to_concentrated(0));
+    simulator.send(DeviceLog::new(3, 3, 260, "pll locked").unwrap().to_concentrated(1));
+
+    let time0 = std::time::Instant::now();
+    while swordfish_comm.device_log().recent(10).len() < 2 {
+        assert!(time0.elapsed().as_secs() < 2, "device logs did not arrive");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    let recent = swordfish_comm.device_log().recent(10);
+    assert_eq!(recent[0].level, log::Level::Error);
+    assert_eq!(recent[1].text, "pll locked");
+}
+
+#[test]
+fn clock_sync_with_simulator() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, SystemTime};
+    use swordfish_com::swordfish_messages::Ping;
+    let (simulator, swordfish_comm) = swordfish_com::swordfish_simulator::SwordFishSimulator::connect();
+    simulator.device().clock.offset_us = 5_000_000;
+    let device_now = || simulator.device().clock.now_us();
+
+    assert!(swordfish_comm.device_to_host_time(device_now()).is_none());
+    swordfish_comm.clock_sync().set_period(Some(Duration::from_millis(5)));
+    let time0 = std::time::Instant::now();
+    while swordfish_comm.clock_sync().estimate().map_or(0, |e| e.samples) < 5 {
+        assert!(time0.elapsed().as_secs() < 2, "no time sync answers");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    swordfish_comm.clock_sync().set_period(None);
+
+    let host = swordfish_comm.device_to_host_time(device_now()).unwrap();
+    let error = match SystemTime::now().duration_since(host) {
+        Ok(d) => d,
+        Err(e) => e.duration(),
+    };
+    assert!(error < Duration::from_millis(20), "{:?}", error);
+
+    //received messages carry the corrected timestamps
+    let stamped = std::sync::Arc::new(AtomicUsize::new(0));
+    let stamped_clone = stamped.clone();
+    let hook = swordfish_comm.add_rx_hook(Box::new(move |_msg, timestamp| {
+        if timestamp.corrected.is_some() && timestamp.device_us.is_some() {
+            stamped_clone.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+    swordfish_comm.send_msg(Ping::default().to_concentrated(0)).unwrap();
+    swordfish_comm.remove_rx_hook(hook);
+    assert_eq!(stamped.load(Ordering::Relaxed), 1);
+}
@@ -0,0 +1,43 @@
+use swordfish_com::{BoundedString, SwordFishConcentratedMessage, SwordFishMessage, SwordFishMessageTrait};
+
+//version 2 of a message appended `build`, version 3 appended `name`
+#[derive(Debug, PartialEq, Default, SwordFishMessage)]
+#[swordfish(opcode = 250, category = "bounce")]
+struct EvolvingData {
+    version: u8,
+    #[swordfish(since = 2)]
+    build: u16,
+    #[swordfish(since = 3)]
+    name: BoundedString<8>,
+}
+
+#[test]
+fn older_and_newer_payloads() {
+    assert_eq!(EvolvingData::VERSION, 3);
+    assert_eq!(EvolvingData::FIELDS[1].name, "build");
+    assert_eq!(EvolvingData::FIELDS[1].since, 2);
+
+    //a version 1 device only sends `version`
+    let old = SwordFishConcentratedMessage::new(0, EvolvingData::OPCODE, &[7]);
+    let decoded = EvolvingData::from_concentrated(&old).unwrap();
+    assert_eq!(decoded, EvolvingData { version: 7, ..Default::default() });
+
+    //a version 4 device appended bytes we do not know about
+    let mut payload = EvolvingData {
+        version: 7,
+        build: 300,
+        name: BoundedString::new("board").unwrap(),
+    }
+    .encode_payload();
+    let expected = EvolvingData::decode_payload(&payload).unwrap();
+    payload.extend_from_slice(&[1, 2, 3]);
+    let newer = SwordFishConcentratedMessage::new(0, EvolvingData::OPCODE, &payload);
+    assert_eq!(EvolvingData::from_concentrated(&newer).unwrap(), expected);
+
+    //a field cut in the middle is still an error
+    let cut = SwordFishConcentratedMessage::new(0, EvolvingData::OPCODE, &[7, 1]);
+    assert!(EvolvingData::from_concentrated(&cut).is_err());
+    //and so is a missing version 1 field
+    let empty = SwordFishConcentratedMessage::new(0, EvolvingData::OPCODE, &[]);
+    assert!(EvolvingData::from_concentrated(&empty).is_err());
+}
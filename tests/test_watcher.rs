@@ -0,0 +1,48 @@
+use std::sync::mpsc;
+use std::time::Duration;
+use swordfish_com::swordfish_discovery::{DiscoveryConfig, PortInfo, PortKind};
+use swordfish_com::swordfish_watcher::{DeviceEvent, DeviceWatcher, FakePortSource};
+
+fn port(port_name: &str, vid: u16, pid: u16, manufacturer: &str) -> PortInfo {
+    PortInfo {
+        port_name: port_name.to_string(),
+        kind: PortKind::Usb,
+        vid: Some(vid),
+        pid: Some(pid),
+        serial_number: Some("0001".to_string()),
+        manufacturer: Some(manufacturer.to_string()),
+        product: None,
+        matcher: None,
+        confirmed: false,
+    }
+}
+
+#[test]
+fn plug_and_unplug() {
+    let source = FakePortSource::new();
+    source.plug(port("/dev/ttyUSB0", 0x10C4, 0xEA60, "Silicon Labs"));
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = DeviceWatcher::with_source(
+        DiscoveryConfig::default(),
+        Box::new(source.clone()),
+        Box::new(move |event| sender.send(event.clone()).unwrap()),
+    );
+    let next = || receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+
+    //present at the start
+    let event = next();
+    assert!(event.is_added());
+    assert_eq!(event.port().matcher.as_deref(), Some("cp210x"));
+
+    //not a swordfish
+    source.plug(port("/dev/ttyACM0", 0x2341, 0x0043, "Arduino"));
+    source.plug(port("/dev/ttyUSB1", 0x0403, 0x6015, "FTDI"));
+    assert!(matches!(next(), DeviceEvent::Added(p) if p.port_name == "/dev/ttyUSB1"));
+
+    source.unplug("/dev/ttyUSB0");
+    assert!(matches!(next(), DeviceEvent::Removed(p) if p.port_name == "/dev/ttyUSB0"));
+
+    watcher.stop();
+    source.unplug("/dev/ttyUSB1");
+    assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+}